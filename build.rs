@@ -0,0 +1,40 @@
+//! Passes build metadata to the crate, see `netpulse::build_info`.
+//!
+//! - `NETPULSE_GIT_COMMIT`: the commit the crate is built from, if it is built from a git
+//!   checkout
+//! - `NETPULSE_BUILD_TIME`: seconds since the unix epoch, `SOURCE_DATE_EPOCH` if set, so that
+//!   builds can be reproduced
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // a missing path would rerun the script, and so rebuild the crate, on every build
+    for path in [".git/HEAD", ".git/refs"] {
+        if std::path::Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    if let Some(commit) = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+    {
+        println!("cargo:rustc-env=NETPULSE_GIT_COMMIT={}", commit.trim());
+    }
+
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    println!("cargo:rustc-env=NETPULSE_BUILD_TIME={built_at}");
+}
@@ -3,9 +3,17 @@
 //! # Analysis Features
 //!
 //! This module analyzes data from the [Store] to provide:
-//! - Outage detection and tracking
+//! - Outage detection, tracking and classification by [Severity]
+//! - [Correlation](correlate) of overlapping outages, to tell problems of the uplink from
+//!   problems of single targets
 //! - Success/failure statistics per check type
-//! - Latency analysis
+//! - Latency analysis, including a [histogram](latency_histogram) per section
+//! - Ranking of the targets by reliability and latency, and their current [streaks](current_streak)
+//! - Pass/fail of the configured [latency objectives](check_slos)
+//! - [Missed checks](audit_counts), from the expected and actual amount of checks
+//! - [Annotations] of outages, optionally left out of the success ratios
+//! - [Differences](diff) between two stores, and between two [periods](compare_windows) of one
+//! - A [markdown table](outages_markdown) of the outages, for incident reviews
 //! - Report generation
 //!
 //! The main entry point is the [analyze] function which generates
@@ -26,15 +34,70 @@
 //! The analysis report contains several sections:
 //! - General statistics (total checks, success rates)
 //! - HTTP-specific metrics
+//! - Target rankings
 //! - Outage analysis
 //! - Store metadata (hashes, versions)
 
-use crate::errors::AnalysisError;
-use crate::records::{Check, CheckFlag, CheckType};
+use crate::annotations::Annotations;
+use crate::config::{Config, LatencySlo, OutageConfig, ReportConfig};
+use crate::errors::{AnalysisError, StoreError};
+use crate::records::{Check, CheckDetail, CheckFlag, CheckType};
 use crate::store::Store;
+use crate::timezone::Timezone;
 
+use sha2::{Digest, Sha256};
+
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::{Display, Write};
 use std::hash::Hash;
+use std::io;
+use std::net::IpAddr;
+use std::ops::Range;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How bad an [Outage] is, determined by its duration and amount of failed checks.
+///
+/// The thresholds between the tiers are configured in [OutageConfig].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub enum Severity {
+    /// A short blip
+    Minor,
+    /// Reached the major thresholds of [OutageConfig]
+    Major,
+    /// Reached the critical thresholds of [OutageConfig]
+    Critical,
+}
+
+impl Severity {
+    /// All tiers, from least to most severe
+    pub const ALL: [Self; 3] = [Self::Minor, Self::Major, Self::Critical];
+
+    /// Classifies an outage that lasted `duration` and consisted of `checks` failed checks.
+    pub fn classify(duration: Duration, checks: usize, thresholds: &OutageConfig) -> Self {
+        let secs = duration.as_secs();
+        if secs >= thresholds.critical_after_secs || checks >= thresholds.critical_after_checks {
+            Self::Critical
+        } else if secs >= thresholds.major_after_secs || checks >= thresholds.major_after_checks {
+            Self::Major
+        } else {
+            Self::Minor
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Minor => "minor",
+                Self::Major => "major",
+                Self::Critical => "critical",
+            }
+        )
+    }
+}
 
 /// Represents a period of consecutive failed checks.
 ///
@@ -54,6 +117,14 @@ pub struct Outage<'check> {
     end: Option<&'check Check>,
     /// All checks that failed during this outage period
     all: Vec<&'check Check>,
+    /// How bad this outage is
+    severity: Severity,
+    /// Note from the [Annotations], if there is one for the start of this outage
+    annotation: Option<String>,
+    /// Whether a [critical target](Config::critical) failed in this outage
+    critical: bool,
+    /// Gaps without checks in this outage, see [unmonitored](Outage::unmonitored)
+    unmonitored: Vec<Range<SystemTime>>,
 }
 
 impl<'check> Outage<'check> {
@@ -64,360 +135,5153 @@ impl<'check> Outage<'check> {
     /// * `start` - The first failed check
     /// * `end` - Optional last failed check (None if ongoing)
     /// * `all_checks` - Slice of all failed checks in this period
+    /// * `thresholds` - Used to determine the [Severity]
     pub(crate) fn new(
         start: &'check Check,
         end: Option<&'check Check>,
         all_checks: &[&'check Check],
+        thresholds: &OutageConfig,
     ) -> Self {
-        Self {
+        let mut outage = Self {
             start,
             end,
             all: all_checks.to_vec(),
+            severity: Severity::Minor,
+            annotation: None,
+            critical: false,
+            unmonitored: Vec::new(),
+        };
+        outage.severity = Severity::classify(outage.duration(), outage.all.len(), thresholds);
+        if let Some(secs) = thresholds.unmonitored_after_secs {
+            outage.unmonitored = outage
+                .all
+                .windows(2)
+                .map(|pair| pair[0].timestamp_parsed()..pair[1].timestamp_parsed())
+                .filter(|gap| {
+                    gap.end
+                        .duration_since(gap.start)
+                        .is_ok_and(|d| d > Duration::from_secs(secs))
+                })
+                .collect();
+        }
+        outage
+    }
+
+    /// Returns the end of this outage, or now for an ongoing outage.
+    fn end_time(&self) -> SystemTime {
+        match self.end {
+            Some(end) => end.timestamp_parsed(),
+            None => SystemTime::now(),
+        }
+    }
+
+    /// Returns how long this outage lasted.
+    ///
+    /// For an ongoing outage, this is the time elapsed since its start. If the end is before the
+    /// start because of clock skew, this is zero, see [is_skewed](Outage::is_skewed).
+    pub fn duration(&self) -> Duration {
+        self.end_time()
+            .duration_since(self.start.timestamp_parsed())
+            .unwrap_or_default()
+    }
+
+    /// Returns whether the end of this outage is before its start.
+    ///
+    /// This happens when the system clock was set back during the outage, the checks are still
+    /// ordered by their sequence number.
+    pub fn is_skewed(&self) -> bool {
+        self.end_time() < self.start.timestamp_parsed()
+    }
+
+    /// Returns the [Severity] of this outage.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Returns the note on this outage from the [Annotations], if there is one.
+    pub fn annotation(&self) -> Option<&str> {
+        self.annotation.as_deref()
+    }
+
+    /// Takes the note on this outage from `annotations`, matched by the start of the outage.
+    pub(crate) fn annotate(&mut self, annotations: &Annotations) {
+        self.annotation = annotations
+            .get(self.start.timestamp_parsed())
+            .map(str::to_string);
+    }
+
+    /// Returns the gaps without checks in this outage, longer than
+    /// [unmonitored_after_secs](OutageConfig::unmonitored_after_secs).
+    ///
+    /// The outage spans these gaps, since no check succeeded in them, but whether the connection
+    /// was down during them is unknown. Empty if the option is not set.
+    pub fn unmonitored(&self) -> &[Range<SystemTime>] {
+        &self.unmonitored
+    }
+
+    /// Returns whether a [critical target](Config::critical) failed in this outage.
+    ///
+    /// Always false if no targets are configured as critical.
+    pub fn is_critical(&self) -> bool {
+        self.critical
+    }
+
+    /// Marks this outage as critical if one of its checks is against a
+    /// [critical target](Config::critical) of `config`.
+    pub(crate) fn mark_critical(&mut self, config: &Config) {
+        self.critical =
+            !config.critical.is_empty() && self.all.iter().any(|c| config.is_critical(c));
+    }
+
+    /// Returns a [Display] of this outage with its timestamps in `timezone`.
+    ///
+    /// The plain [Display] of an outage uses UTC.
+    pub fn display_in(&self, timezone: Timezone) -> OutageDisplay<'_, 'check> {
+        OutageDisplay {
+            outage: self,
+            timezone,
         }
     }
 }
 
 impl Display for Outage<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.end.is_some() {
+        write!(f, "{}", self.display_in(Timezone::Utc))
+    }
+}
+
+/// Displays an [Outage] with its timestamps in a [Timezone], see [Outage::display_in].
+#[derive(Debug, Clone, Copy)]
+pub struct OutageDisplay<'outage, 'check> {
+    outage: &'outage Outage<'check>,
+    timezone: Timezone,
+}
+
+impl Display for OutageDisplay<'_, '_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outage = self.outage;
+        if let Some(end) = outage.end {
             writeln!(
                 f,
                 "From {} To {}",
-                humantime::format_rfc3339_seconds(self.start.timestamp_parsed()),
-                humantime::format_rfc3339_seconds(self.end.unwrap().timestamp_parsed())
+                self.timezone.format(outage.start.timestamp_parsed()),
+                self.timezone.format(end.timestamp_parsed())
             )?;
         } else {
             writeln!(
                 f,
                 "From {} STILL ONGOING",
-                humantime::format_rfc3339_seconds(self.start.timestamp_parsed()),
+                self.timezone.format(outage.start.timestamp_parsed()),
+            )?;
+        }
+        if outage.is_skewed() {
+            writeln!(f, "Duration: <unknown due to clock skew>")?;
+        } else {
+            // an ongoing outage lasts until now, which is not a whole second
+            writeln!(
+                f,
+                "Duration: {}",
+                humantime::format_duration(Duration::from_secs(outage.duration().as_secs()))
             )?;
         }
-        writeln!(f, "Checks: {}", self.all.len())?;
+        writeln!(f, "Severity: {}", outage.severity)?;
+        if outage.critical {
+            writeln!(f, "Critical Target: yes")?;
+        }
+        writeln!(f, "Checks: {}", outage.all.len())?;
+        // soft failures got an answer, unlike the hard ones
+        let too_slow = outage
+            .all
+            .iter()
+            .filter(|check| check.is_too_slow())
+            .count();
+        if too_slow > 0 {
+            writeln!(f, "Too Slow: {too_slow}")?;
+        }
         writeln!(
             f,
             "Type: {}",
-            self.start.calc_type().unwrap_or(CheckType::Unknown)
+            outage.start.calc_type().unwrap_or(CheckType::Unknown)
         )?;
+        for gap in &outage.unmonitored {
+            writeln!(
+                f,
+                "Unmonitored: {} to {}",
+                self.timezone.format(gap.start),
+                self.timezone.format(gap.end)
+            )?;
+        }
+        if let Some(note) = &outage.annotation {
+            writeln!(f, "Note: {note}")?;
+        }
         Ok(())
     }
 }
 
-/// Display a formatted list of checks.
-///
-/// Each check is formatted with:
-/// - Index number
-/// - Indented check details
-/// - Nested line breaks preserved
-///
-/// # Arguments
+/// Health of the monitored connection, derived from the most recent checks.
 ///
-/// * `group` - Slice of check references to format
-/// * `f` - String buffer to write formatted output
-///
-/// # Errors
-///
-/// Returns [AnalysisError] if string formatting fails.
-pub fn display_group(group: &[&Check], f: &mut String) -> Result<(), std::fmt::Error> {
-    if group.is_empty() {
-        writeln!(f, "\t<Empty>")?;
-        return Ok(());
+/// Meant for integration with other monitoring systems, see [Health::exit_code].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Health {
+    /// The most recent check of every type succeeded
+    Healthy,
+    /// At least one check type is in an ongoing outage
+    Outage,
+    /// The store could not be read
+    Unreadable,
+    /// At least one of the configured [latency objectives](check_slos) is breached
+    SloBreached,
+}
+
+impl Health {
+    /// Determines the [Health] of the given [Store] from its [ongoing outages](ongoing_outages).
+    pub fn of_store(store: &Store) -> Self {
+        Self::of_store_with(store, &Config::default())
     }
-    for (cidx, check) in group.iter().enumerate() {
-        writeln!(f, "{cidx}:")?;
-        writeln!(f, "\t{}", check.to_string().replace("\n", "\n\t"))?;
+
+    /// Determines the [Health] of the given [Store] from its [ongoing outages](ongoing_outages)
+    /// and the [latency objectives](Config::slo) of `config`.
+    ///
+    /// An ongoing outage is worse than a breached objective. If [critical](Config::critical)
+    /// targets are configured, only their outages count.
+    pub fn of_store_with(store: &Store, config: &Config) -> Self {
+        // the severity does not matter here, only if there is an outage at all
+        let outage = if config.critical.is_empty() {
+            !ongoing_outages(store, &config.outages).is_empty()
+        } else {
+            let critical = store.filtered(|c| config.is_critical(c));
+            !ongoing_outages(&critical, &config.outages).is_empty()
+        };
+        if outage {
+            Self::Outage
+        } else if check_slos(store, &config.slo).iter().any(|r| !r.met()) {
+            Self::SloBreached
+        } else {
+            Self::Healthy
+        }
+    }
+
+    /// Determines the [Health] from the result of loading a [Store].
+    ///
+    /// Any error while loading results in [Health::Unreadable].
+    pub fn of_load(store: &Result<Store, StoreError>) -> Self {
+        Self::of_load_with(store, &Config::default())
+    }
+
+    /// Like [of_load](Health::of_load), but with [of_store_with](Health::of_store_with).
+    pub fn of_load_with(store: &Result<Store, StoreError>, config: &Config) -> Self {
+        match store {
+            Ok(store) => Self::of_store_with(store, config),
+            Err(_) => Self::Unreadable,
+        }
+    }
+
+    /// Returns the process exit code for this [Health].
+    ///
+    /// - `0` - [Health::Healthy]
+    /// - `1` - [Health::Outage]
+    /// - `2` - [Health::Unreadable]
+    /// - `4` - [Health::SloBreached]
+    ///
+    /// `3` is left out, the reader exits with it if there is no store at all.
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::Healthy => 0,
+            Self::Outage => 1,
+            Self::Unreadable => 2,
+            Self::SloBreached => 4,
+        }
     }
-    Ok(())
 }
 
-/// Generate a comprehensive analysis report for the given store.
-///
-/// The report includes:
-/// - General check statistics
-/// - HTTP-specific metrics
-/// - Outage analysis
-/// - Store metadata
-///
-/// # Errors
-///
-/// Returns [AnalysisError] if:
-/// - Report string formatting fails
-/// - Store hash calculation fails
-///
-/// # Example
-///
-/// ```rust,no_run
-/// use netpulse::{store::Store, analyze};
-///
-/// let store = Store::load().unwrap();
-/// let report = analyze::analyze(&store).unwrap();
-/// println!("{}", report);
-/// ```
-pub fn analyze(store: &Store) -> Result<String, AnalysisError> {
-    let mut f = String::new();
-    barrier(&mut f, "General")?;
-    generalized(store, &mut f)?;
-    barrier(&mut f, "HTTP")?;
-    generic_type_analyze(store, &mut f, CheckType::Http)?;
-    barrier(&mut f, "ICMPv4")?;
-    generic_type_analyze(store, &mut f, CheckType::IcmpV4)?;
-    barrier(&mut f, "ICMPv6")?;
-    generic_type_analyze(store, &mut f, CheckType::IcmpV6)?;
-    barrier(&mut f, "IPv4")?;
-    gereric_ip_analyze(store, &mut f, CheckFlag::IPv4)?;
-    barrier(&mut f, "IPv6")?;
-    gereric_ip_analyze(store, &mut f, CheckFlag::IPv6)?;
-    barrier(&mut f, "Outages")?;
-    outages(store, &mut f)?;
-    barrier(&mut f, "Store Metadata")?;
-    store_meta(store, &mut f)?;
+impl Display for Health {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Healthy => "healthy",
+                Self::Outage => "outage",
+                Self::Unreadable => "unreadable",
+                Self::SloBreached => "slo breached",
+            }
+        )
+    }
+}
 
-    Ok(f)
+/// Result of checking a [LatencySlo] against the checks in a store, see [check_slos].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SloResult {
+    /// The objective that was checked
+    pub slo: LatencySlo,
+    /// The latency at the percentile of the objective, [None] without successful checks
+    pub measured: Option<u64>,
 }
 
-/// Adds a section divider to the report with a title.
-///
-/// Creates a divider line of '=' characters with the title centered.
-///
-/// # Errors
-///
-/// Returns [AnalysisError] if string formatting fails.
-fn barrier(f: &mut String, title: &str) -> Result<(), AnalysisError> {
-    writeln!(f, "{:=<10}{:=<90}", "", format!(" {title} "))?;
-    Ok(())
+impl SloResult {
+    /// Returns whether the objective is met. Without any latencies, there is nothing to breach.
+    pub fn met(&self) -> bool {
+        self.measured
+            .is_none_or(|measured| measured <= self.slo.max_ms as u64)
+    }
 }
 
-/// Writes a key-value pair to the report in aligned columns.
-///
-/// Format: `<key>: <value>`
-fn key_value_write(
-    f: &mut String,
-    title: &str,
-    content: impl Display,
-) -> Result<(), std::fmt::Error> {
-    writeln!(f, "{:<20}: {:<78}", title, content.to_string())
+impl Display for SloResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.measured {
+            None => write!(f, "no data (max {}ms)", self.slo.max_ms),
+            Some(measured) if self.met() => {
+                write!(f, "met: {measured}ms <= {}ms", self.slo.max_ms)
+            }
+            Some(measured) => write!(f, "BREACHED: {measured}ms > {}ms", self.slo.max_ms),
+        }
+    }
+}
+
+/// Expected and actual amount of checks of a [CheckType], see [audit_counts].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct CountAudit {
+    /// The type of the checks
+    pub check_type: CheckType,
+    /// Time between two checks of a target, see [check_settings](Config::check_settings)
+    pub interval: Duration,
+    /// How many checks there would be if none were missed
+    pub expected: usize,
+    /// How many checks there are
+    pub actual: usize,
+}
+
+impl CountAudit {
+    /// Returns how many checks were missed.
+    pub fn missing(&self) -> usize {
+        self.expected.saturating_sub(self.actual)
+    }
+
+    /// Returns the share of the expected checks that were missed, between 0.0 and 1.0.
+    pub fn missing_ratio(&self) -> f64 {
+        if self.expected == 0 {
+            return 0.0;
+        }
+        self.missing() as f64 / self.expected as f64
+    }
+}
+
+impl Display for CountAudit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} expected, {} missing ({}%)",
+            self.actual,
+            self.expected,
+            self.missing(),
+            short_percent(self.missing_ratio())
+        )
+    }
 }
 
-/// Analyzes and formats outage information from the store.
+/// Estimates how many checks of each [CheckType] were missed, for example while the daemon was
+/// not running or the machine was asleep.
 ///
-/// Groups consecutive failed checks by check type and creates
-/// Outage records for reporting.
-fn outages(store: &Store, f: &mut String) -> Result<(), AnalysisError> {
-    let all_checks: Vec<&Check> = store.checks().iter().collect();
-    let mut outages: Vec<Outage> = Vec::new();
-    let fails_exist = all_checks
+/// Each target of a check type is expected to be checked every
+/// [interval](crate::config::CheckSettings::interval) of the type, as configured in `config`,
+/// from its first to its last check. Types without checks are left out. Targets that were
+/// [backed off](crate::backoff) are checked less often on purpose, which shows up as missing
+/// checks too.
+pub fn audit_counts(store: &Store, config: &Config) -> Vec<CountAudit> {
+    CheckType::all()
         .iter()
-        .fold(true, |fails_exist, c| fails_exist & !c.is_success());
-    if !fails_exist || all_checks.is_empty() {
-        writeln!(f, "None\n")?;
-        return Ok(());
+        .filter_map(|check_type| {
+            let interval = config.check_settings(*check_type).interval;
+            let period = interval.as_secs().max(1);
+            let mut spans: BTreeMap<IpAddr, (u64, u64, usize)> = BTreeMap::new();
+            for check in store.checks().iter().filter(|c| is_type(c, *check_type)) {
+                let time = check.timestamp();
+                let span = spans.entry(check.target()).or_insert((time, time, 0));
+                span.0 = span.0.min(time);
+                span.1 = span.1.max(time);
+                span.2 += 1;
+            }
+            if spans.is_empty() {
+                return None;
+            }
+            let expected = spans
+                .values()
+                .map(|(first, last, _)| ((last - first) / period) as usize + 1)
+                .sum();
+            let actual = spans.values().map(|(_, _, count)| count).sum();
+            Some(CountAudit {
+                check_type: *check_type,
+                interval,
+                expected,
+                actual,
+            })
+        })
+        .collect()
+}
+
+/// Returns the `p`th percentile of `latencies` with the nearest-rank method.
+///
+/// Returns [None] for no latencies. `p` is clamped to be between 1 and 100.
+pub fn percentile(latencies: &[u64], p: u8) -> Option<u64> {
+    if latencies.is_empty() {
+        return None;
     }
+    let mut sorted = latencies.to_vec();
+    sorted.sort_unstable();
+    let rank = (sorted.len() * p.clamp(1, 100) as usize).div_ceil(100);
+    Some(sorted[rank.max(1) - 1])
+}
+
+/// Checks each of `slos` against the latencies of the successful checks in `store`.
+pub fn check_slos(store: &Store, slos: &[LatencySlo]) -> Vec<SloResult> {
+    slos.iter()
+        .map(|slo| {
+            let latencies: Vec<u64> = store
+                .checks()
+                .iter()
+                .filter(|c| is_type(c, slo.check_type))
+                .filter_map(|c| c.latency())
+                .map(u64::from)
+                .collect();
+            SloResult {
+                slo: *slo,
+                measured: percentile(&latencies, slo.percentile),
+            }
+        })
+        .collect()
+}
 
+/// Finds all outages that are still ongoing.
+///
+/// For each [CheckType], the most recent checks are looked at. If the latest check of that type
+/// failed, all directly preceding failed checks of that type form an ongoing [Outage], if they are
+/// at least [min_failures](OutageConfig::min_failures).
+///
+/// The [Severity] of the outages is determined with `thresholds`. Failed checks made in a
+/// [maintenance window](crate::maintenance) are left out, unless
+/// [count_maintenance](OutageConfig::count_maintenance) is set.
+pub fn ongoing_outages<'store>(
+    store: &'store Store,
+    thresholds: &OutageConfig,
+) -> Vec<Outage<'store>> {
+    let all_checks: Vec<&Check> = store
+        .checks()
+        .iter()
+        .filter(|c| counts_for_outages(c, thresholds))
+        .collect();
+    let mut outages = Vec::new();
     for check_type in CheckType::all() {
         let checks: Vec<&&Check> = all_checks
             .iter()
             .filter(|c| c.calc_type().unwrap_or(CheckType::Unknown) == *check_type)
             .collect();
-
-        let fail_groups = fail_groups(&checks);
-        for group in fail_groups {
-            // writeln!(f, "Group {gidx}:")?;
-            // display_group(group, f)?;
-            if !group.is_empty() {
-                outages.push(Outage::new(
-                    checks.first().unwrap(),
-                    Some(checks.last().unwrap()),
-                    &group,
-                ));
-            }
+        // the same order as in fail_groups, the last of equal sequence numbers is the newest
+        let Some(newest) = checks.iter().max_by_key(|c| c.seq()) else {
+            continue;
+        };
+        let Some(failing) = fail_groups(&checks).pop() else {
+            continue;
+        };
+        let ongoing = failing
+            .last()
+            .is_some_and(|last| std::ptr::eq(*last, **newest));
+        if ongoing && failing.len() >= thresholds.min_failures {
+            outages.push(Outage::new(failing[0], None, &failing, thresholds));
         }
     }
+    outages
+}
 
-    for outage in outages {
-        writeln!(f, "{outage}")?;
-    }
-    Ok(())
+/// When the checks of one [CheckType] started failing and last recovered, see [failure_times].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct FailureTimes {
+    /// First failed check of the [ongoing outage](ongoing_outages), [None] if there is none
+    pub failing_since: Option<SystemTime>,
+    /// First successful check after the latest outage that ended, [None] if no outage ended yet
+    pub recovered_at: Option<SystemTime>,
 }
 
-/// Find groups of consecutive failed checks.
+/// Finds when the checks of `check_type` started failing and when they last recovered.
 ///
-/// Groups are formed when:
-/// - Checks are consecutive by index
-/// - All checks in group are failures
-/// - Gap between groups is > 1 check
-fn fail_groups<'check>(checks: &[&&'check Check]) -> Vec<Vec<&'check Check>> {
-    let failed_idxs: Vec<usize> = checks
+/// Both are derived from the outages as determined by `thresholds`: the current failure is the
+/// [ongoing outage](ongoing_outages) of that type, and the recovery is the first successful check
+/// of that type after the last failed check of the latest outage that is over.
+pub fn failure_times(
+    store: &Store,
+    check_type: CheckType,
+    thresholds: &OutageConfig,
+) -> FailureTimes {
+    failure_times_of(
+        store,
+        check_type,
+        &ongoing_outages(store, thresholds),
+        &find_outages(store, thresholds),
+    )
+}
+
+/// Like [failure_times], with the `ongoing` and all `outages` of the store already found, so
+/// they are only searched once for all check types.
+fn failure_times_of(
+    store: &Store,
+    check_type: CheckType,
+    ongoing: &[Outage],
+    outages: &[Outage],
+) -> FailureTimes {
+    let failing_since = ongoing
         .iter()
-        .enumerate()
-        .filter(|(_idx, c)| !c.is_success())
-        .map(|(idx, _c)| idx)
+        .find(|o| is_type(o.start, check_type))
+        .map(|o| o.start.timestamp_parsed());
+    let recoveries: Vec<&Check> = store
+        .checks()
+        .iter()
+        .filter(|c| is_type(c, check_type) && c.is_success())
         .collect();
-    if failed_idxs.is_empty() {
-        return Vec::new();
+    // the outages of a type are in the order of their sequence numbers
+    let recovered_at = outages
+        .iter()
+        .rev()
+        .filter(|o| is_type(o.start, check_type))
+        .filter_map(|o| o.end)
+        .find_map(|end| {
+            recoveries
+                .iter()
+                .filter(|c| c.seq() > end.seq())
+                .min_by_key(|c| c.seq())
+        })
+        .map(|c| c.timestamp_parsed());
+    FailureTimes {
+        failing_since,
+        recovered_at,
     }
-    let mut groups: Vec<Vec<&Check>> = Vec::new();
+}
 
-    let mut first = failed_idxs[0];
-    let mut last = first;
-    for idx in failed_idxs {
-        if idx == last + 1 {
-            last = idx;
-        } else {
-            let mut group: Vec<&Check> = Vec::new();
-            for check in checks.iter().take(last + 1).skip(first) {
-                group.push(*check);
-            }
-            groups.push(group);
+/// Lists the outages of the store as a markdown table, for pasting into incident reviews.
+///
+/// Uses the [default](Config::default) configuration, see [outages_markdown_with].
+pub fn outages_markdown(store: &Store) -> String {
+    outages_markdown_with(store, &Config::default())
+}
 
-            first = idx;
-        }
+/// Lists the outages of the store as a markdown table, oldest first.
+///
+/// The table has the columns start, end, duration, type, target and failure reason. Ongoing
+/// outages have `ongoing` as end, and their duration lasts until now. The timestamps are in the
+/// [timezone](ReportConfig::timezone) of the report. Without outages, only the header is there.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use netpulse::{analyze, store::Store};
+///
+/// let store = Store::load().unwrap();
+/// println!("{}", analyze::outages_markdown(&store));
+/// ```
+pub fn outages_markdown_with(store: &Store, config: &Config) -> String {
+    let timezone = Layout::of(&config.report).timezone;
+    let mut table = String::from(
+        "| Start | End | Duration | Type | Target | Failure Reason |\n\
+         |-------|-----|----------|------|--------|----------------|\n",
+    );
+    for outage in past_and_ongoing_outages(store, &config.outages) {
+        let end = match outage.end {
+            Some(end) => timezone.format(end.timestamp_parsed()),
+            None => "ongoing".to_string(),
+        };
+        let duration = if outage.is_skewed() {
+            "unknown".to_string()
+        } else {
+            humantime::format_duration(outage.duration()).to_string()
+        };
+        table.push_str(&format!(
+            "| {} | {end} | {duration} | {} | {} | {} |\n",
+            timezone.format(outage.start.timestamp_parsed()),
+            outage.start.calc_type().unwrap_or(CheckType::Unknown),
+            outage_targets(&outage).join(", "),
+            failure_reasons(&outage).join(", ")
+        ));
     }
+    table
+}
 
-    groups
+/// Lists the outages of the store as iCalendar, for incident tooling.
+///
+/// Uses the [default](Config::default) configuration, see [outages_ical_with].
+pub fn outages_ical(store: &Store) -> String {
+    outages_ical_with(store, &Config::default())
 }
 
-/// Analyze metrics for a specific check type.
+/// Lists the outages of the store as iCalendar (RFC 5545), with one `VEVENT` per outage,
+/// oldest first.
 ///
-/// Calculates and formats:
-/// - Total check count
-/// - Success/failure counts
-/// - Success ratio
-/// - First/last check timestamps
+/// The events start with the first failed check and end with the last one, in UTC. Ongoing
+/// outages, and outages that lasted no time, have no `DTEND`. The summary names the check type,
+/// the targets and the [Severity]. Long lines are folded at [ICAL_LINE_OCTETS].
 ///
-/// # Errors
+/// # Example
 ///
-/// Returns [AnalysisError] if formatting fails.
-fn analyze_check_type_set(
-    f: &mut String,
-    all: &[&Check],
-    successes: &[&Check],
-) -> Result<(), AnalysisError> {
-    if all.is_empty() {
-        writeln!(f, "None\n")?;
-        return Ok(());
+/// ```rust,no_run
+/// use netpulse::{analyze, store::Store};
+///
+/// let store = Store::load().unwrap();
+/// std::fs::write("/tmp/outages.ics", analyze::outages_ical(&store)).unwrap();
+/// ```
+pub fn outages_ical_with(store: &Store, config: &Config) -> String {
+    // iCalendar wants its timestamps without separators, like 19700101T000100Z
+    let timestamp = |time: SystemTime| {
+        humantime::format_rfc3339_seconds(time)
+            .to_string()
+            .replace(['-', ':'], "")
+    };
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!(
+            "PRODID:-//netpulse//netpulse {}//EN",
+            env!("CARGO_PKG_VERSION")
+        ),
+    ];
+    let now = timestamp(SystemTime::now());
+    for outage in past_and_ongoing_outages(store, &config.outages) {
+        let start = outage.start.timestamp_parsed();
+        let check_type = outage.start.calc_type().unwrap_or(CheckType::Unknown);
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!(
+            "UID:{}-{}@netpulse",
+            timestamp(start),
+            outage.start.seq()
+        ));
+        lines.push(format!("DTSTAMP:{now}"));
+        lines.push(format!("DTSTART:{}", timestamp(start)));
+        if let Some(end) = outage
+            .end
+            .map(Check::timestamp_parsed)
+            .filter(|e| *e > start)
+        {
+            lines.push(format!("DTEND:{}", timestamp(end)));
+        }
+        lines.push(format!(
+            "SUMMARY:{}",
+            ical_text(&format!(
+                "{}{check_type} outage of {} ({})",
+                if outage.end.is_none() { "ongoing " } else { "" },
+                outage_targets(&outage).join(", "),
+                outage.severity
+            ))
+        ));
+        lines.push(format!(
+            "DESCRIPTION:{}",
+            ical_text(&format!(
+                "{} failed checks: {}",
+                outage.all.len(),
+                failure_reasons(&outage).join(", ")
+            ))
+        ));
+        lines.push("END:VEVENT".to_string());
     }
-    key_value_write(f, "checks", format!("{:08}", all.len()))?;
-    key_value_write(f, "checks ok", format!("{:08}", successes.len()))?;
-    key_value_write(
-        f,
-        "checks bad",
-        format!("{:08}", all.len() - successes.len()),
-    )?;
-    key_value_write(
-        f,
-        "success ratio",
-        format!(
-            "{:03.02}%",
-            success_ratio(all.len(), successes.len()) * 100.0
-        ),
-    )?;
-    key_value_write(
-        f,
-        "first check at",
-        humantime::format_rfc3339_seconds(all.first().unwrap().timestamp_parsed()),
-    )?;
-    key_value_write(
-        f,
-        "last check at",
-        humantime::format_rfc3339_seconds(all.last().unwrap().timestamp_parsed()),
-    )?;
-    writeln!(f)?;
-    Ok(())
+    lines.push("END:VCALENDAR".to_string());
+    // lines end with CRLF in iCalendar
+    lines
+        .iter()
+        .map(|line| format!("{}\r\n", ical_fold(line)))
+        .collect()
 }
 
-/// Write general check statistics section of the report.
+/// Longest line of iCalendar in octets, without the line break
+pub const ICAL_LINE_OCTETS: usize = 75;
+
+/// Folds `line` into lines of at most [ICAL_LINE_OCTETS], as iCalendar demands.
 ///
-/// Includes metrics across all check types combined.
-fn generalized(store: &Store, f: &mut String) -> Result<(), AnalysisError> {
-    if store.checks().is_empty() {
-        writeln!(f, "Store has no checks yet\n")?;
-        return Ok(());
+/// Each continuation starts with a space, which counts to its length. Characters are never
+/// split.
+fn ical_fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > ICAL_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
     }
-    let all: Vec<&Check> = store.checks().iter().collect();
-    let successes: Vec<&Check> = store.checks().iter().filter(|c| c.is_success()).collect();
-    analyze_check_type_set(f, &all, &successes)?;
-    Ok(())
+    folded
+}
+
+/// Escapes `text` for a text value in iCalendar.
+fn ical_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Outages of at least this many targets at the same time are [Diagnosis::Upstream]
+pub const MIN_CORRELATED_TARGETS: usize = 2;
+
+/// Where the problem behind a [Correlation] of outages likely is.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub enum Diagnosis {
+    /// Only one target failed, likely a problem of that target or the way to it
+    Isolated,
+    /// At least [MIN_CORRELATED_TARGETS] targets failed at the same time, likely a problem of
+    /// the local network or the uplink
+    Upstream,
+}
+
+impl Display for Diagnosis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Isolated => "isolated",
+                Self::Upstream => "likely local/upstream",
+            }
+        )
+    }
+}
+
+/// Overlapping [outages](Outage), made by [correlate].
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Correlation {
+    /// Start of the first of the outages
+    pub start: SystemTime,
+    /// End of the last of the outages, [None] if one of them is ongoing
+    pub end: Option<SystemTime>,
+    /// The targets that failed, ordered
+    pub targets: Vec<IpAddr>,
+    /// How many outages overlapped
+    pub outages: usize,
+    /// What the amount of targets says about the cause
+    pub diagnosis: Diagnosis,
 }
 
-/// Write check statistics section of the report for `check_type`.
+/// Groups the `outages` that overlap in time, and diagnoses each group by the amount of failed
+/// targets, see [Diagnosis].
 ///
-/// Analyzes and formats statistics for IPv4/IPv6 checks.
+/// Outages of different check types overlap like outages of one type. The checks of one cycle
+/// are not made at the same instant, so outages that are at most `slack` apart are treated as
+/// overlapping. The groups are ordered by their start. Skewed outages, see
+/// [is_skewed](Outage::is_skewed), end when they start.
+pub fn correlate(outages: &[Outage], slack: Duration) -> Vec<Correlation> {
+    let mut outages: Vec<&Outage> = outages.iter().collect();
+    outages.sort_by_key(|o| o.start.timestamp_parsed());
+    let mut correlations: Vec<Correlation> = Vec::new();
+    // end of the current group, including ongoing outages
+    let mut group_end = UNIX_EPOCH;
+    for outage in outages {
+        let start = outage.start.timestamp_parsed();
+        let end = outage.end_time().max(start);
+        let mut targets: Vec<IpAddr> = outage.all.iter().map(|c| c.target()).collect();
+        match correlations.last_mut() {
+            Some(group) if start <= group_end + slack => {
+                group.end = match (group.end, outage.end) {
+                    (Some(group), Some(_)) => Some(group.max(end)),
+                    _ => None,
+                };
+                group.targets.append(&mut targets);
+                group.outages += 1;
+                group_end = group_end.max(end);
+            }
+            _ => {
+                correlations.push(Correlation {
+                    start,
+                    end: outage.end.map(|_| end),
+                    targets,
+                    outages: 1,
+                    diagnosis: Diagnosis::Isolated,
+                });
+                group_end = end;
+            }
+        }
+    }
+    for group in &mut correlations {
+        group.targets.sort();
+        group.targets.dedup();
+        if group.targets.len() >= MIN_CORRELATED_TARGETS {
+            group.diagnosis = Diagnosis::Upstream;
+        }
+    }
+    correlations
+}
+
+/// Groups the past and ongoing outages of the store that overlap, see [correlate].
 ///
-/// Collects all checks that used that IP and generates a statistical report including:
-/// - Total number of that IP checks
-/// - Success/failure counts
-/// - Success ratio
-/// - First/last check timestamps
+/// Outages at most half a [period](Store::period_seconds) apart overlap. The [Severity] of the
+/// outages is determined with `thresholds`.
+pub fn correlations(store: &Store, thresholds: &OutageConfig) -> Vec<Correlation> {
+    correlate(
+        &past_and_ongoing_outages(store, thresholds),
+        correlation_slack(store),
+    )
+}
+
+/// How far apart outages may be to overlap, see [correlate].
+fn correlation_slack(store: &Store) -> Duration {
+    Duration::from_secs(store.period_seconds()) / 2
+}
+
+/// Returns the past and ongoing outages, ordered by start.
+fn past_and_ongoing_outages<'store>(
+    store: &'store Store,
+    thresholds: &OutageConfig,
+) -> Vec<Outage<'store>> {
+    let mut outages = find_outages(store, thresholds);
+    for ongoing in ongoing_outages(store, thresholds) {
+        outages.retain(|o| o.start != ongoing.start);
+        outages.push(ongoing);
+    }
+    outages.sort_by_key(|o| (o.start.timestamp_parsed(), o.start.seq()));
+    outages
+}
+
+/// Returns the targets of the failed checks of `outage`, in the order they failed first.
+fn outage_targets(outage: &Outage) -> Vec<String> {
+    let mut targets: Vec<String> = Vec::new();
+    for check in &outage.all {
+        let target = check.target().to_string();
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+/// Returns why the checks of `outage` failed, see [failure_reason], each reason once.
+fn failure_reasons(outage: &Outage) -> Vec<String> {
+    let mut reasons: Vec<String> = Vec::new();
+    for check in &outage.all {
+        let reason = failure_reason(check);
+        if !reasons.contains(&reason) {
+            reasons.push(reason);
+        }
+    }
+    reasons
+}
+
+/// Describes why `check` failed, from its [details](Check::details).
+fn failure_reason(check: &Check) -> String {
+    // the other details of a soft failure look like a success
+    if let Some(CheckDetail::TooSlow { max, .. }) = check
+        .details()
+        .iter()
+        .find(|detail| matches!(detail, CheckDetail::TooSlow { .. }))
+    {
+        return format!("too slow, over {max} ms");
+    }
+    for detail in check.details() {
+        match detail {
+            CheckDetail::Http { status } => return format!("HTTP status {status}"),
+            CheckDetail::Udp { sent, received } => {
+                return format!("{received}/{sent} packets answered")
+            }
+            CheckDetail::BodyMismatch => return "body mismatch".to_string(),
+            CheckDetail::Unresolved { .. } => return "could not resolve".to_string(),
+            CheckDetail::Source {
+                source,
+                bound: false,
+            } => return format!("could not bind to {source}"),
+            _ => (),
+        }
+    }
+    "no response".to_string()
+}
+
+/// Calculates the time-weighted availability of the store, between 0.0 and 1.0.
 ///
-/// Checks with ambiguous or invalid IP flags are excluded and logged as errors.
+/// This is the share of the observed time, from the first to the last check, that was not
+/// covered by an [Outage]. Unlike the success ratio of the checks, it does not depend on how
+/// often checks were made. Outages of different check types that overlap count only once, and
+/// ongoing outages last until the last check.
 ///
-/// # Errors
+/// Returns [None] if the store does not cover any time, like with a single check.
+pub fn availability(store: &Store, thresholds: &OutageConfig) -> Option<f64> {
+    let (first, last) = (store.first_check_time()?, store.last_check_time()?);
+    let window = last.duration_since(first).ok().filter(|w| !w.is_zero())?;
+
+    let mut downtimes: Vec<(SystemTime, SystemTime)> = find_outages(store, thresholds)
+        .iter()
+        .chain(&ongoing_outages(store, thresholds))
+        .filter(|o| !o.is_skewed())
+        .map(|o| {
+            let end = o.end.map_or(last, Check::timestamp_parsed);
+            (o.start.timestamp_parsed(), end.min(last))
+        })
+        .collect();
+    downtimes.sort();
+    let mut down = Duration::ZERO;
+    // end of the downtime counted so far, overlapping outages are only counted after it
+    let mut counted_until = first;
+    for (start, end) in downtimes {
+        let start = start.max(counted_until);
+        if let Ok(duration) = end.duration_since(start) {
+            down += duration;
+            counted_until = end;
+        }
+    }
+    Some(1.0 - down.as_secs_f64() / window.as_secs_f64())
+}
+
+/// Lists the last `amount` checks of the store, one per line and the newest last, see
+/// [Store::tail].
 ///
-/// Returns [AnalysisError] if:
-/// - Report formatting fails
-/// - Check type analysis fails
+/// Each line has the time in `timezone`, the type and target of the check, whether it succeeded
+/// and its latency, like `2024-12-08T09:00:00Z  HTTP(S)     1.1.1.1          ok      12 ms`.
+pub fn tail(store: &Store, amount: usize, timezone: &Timezone) -> String {
+    let mut lines = String::new();
+    for check in store.tail(amount) {
+        // writing to a String can't fail
+        let _ = writeln!(
+            lines,
+            "{}  {:<TYPE_WIDTH$}  {:<16} {:<7} {}",
+            timezone.format(check.timestamp_parsed()),
+            check.calc_type().unwrap_or(CheckType::Unknown).to_string(),
+            check.target().to_string(),
+            if check.is_success() { "ok" } else { "failed" },
+            check
+                .latency()
+                .map_or("-".to_string(), |l| format!("{l} ms"))
+        );
+    }
+    lines
+}
+
+/// Summarizes the store in a single line, for status bars and the like.
+///
+/// Shows the overall success ratio, the [availability] if the store covers any time, the
+/// success ratio per kind of check and the amount of ongoing outages, like
+/// `netpulse: 99.8% ok, 99.9% up | http 100% | icmp 98% | 1 ongoing outage`. Kinds without
+/// checks are left out. An empty store results in `netpulse: no data`.
+pub fn one_line(store: &Store) -> String {
+    const KINDS: &[(&str, &[CheckType])] = &[
+        ("http", &[CheckType::Http]),
+        ("icmp", &[CheckType::IcmpV4, CheckType::IcmpV6]),
+        ("udp", &[CheckType::Udp]),
+        ("throughput", &[CheckType::Throughput]),
+    ];
+    let stats = store.stats();
+    if stats.total == 0 {
+        return "netpulse: no data".to_string();
+    }
+
+    // successes per kind, the totals are in the stats
+    let mut successes = [0usize; KINDS.len()];
+    for check in store.checks().iter().filter(|c| c.is_success()) {
+        let check_type = check.calc_type().unwrap_or(CheckType::Unknown);
+        if let Some(kind) = KINDS
+            .iter()
+            .position(|(_, types)| types.contains(&check_type))
+        {
+            successes[kind] += 1;
+        }
+    }
+
+    let mut line = format!(
+        "netpulse: {}% ok",
+        short_percent(success_ratio(stats.total, stats.ok))
+    );
+    if let Some(availability) = availability(store, &OutageConfig::default()) {
+        line.push_str(&format!(", {}% up", short_percent(availability)));
+    }
+    for ((name, types), ok) in KINDS.iter().zip(successes) {
+        let all: usize = types
+            .iter()
+            .map(|t| stats.by_type.get(t).copied().unwrap_or(0))
+            .sum();
+        if all > 0 {
+            line.push_str(&format!(
+                " | {name} {}%",
+                short_percent(success_ratio(all, ok))
+            ));
+        }
+    }
+    let outages = ongoing_outages(store, &OutageConfig::default()).len();
+    line.push_str(&match outages {
+        0 => " | no ongoing outage".to_string(),
+        1 => " | 1 ongoing outage".to_string(),
+        n => format!(" | {n} ongoing outages"),
+    });
+    line
+}
+
+/// Formats a ratio as percentage with at most one decimal, leaving out a trailing `.0`.
+fn short_percent(ratio: f64) -> String {
+    let percent = format!("{:.1}", ratio * 100.0);
+    match percent.strip_suffix(".0") {
+        Some(whole) => whole.to_string(),
+        None => percent,
+    }
+}
+
+/// How many targets are listed per ranking in the report
+pub const RANKING_LEN: usize = 3;
+/// How far back from the newest check [degradation_score] looks for the recent checks it
+/// compares to the older ones
+pub const DEGRADATION_WINDOW: Duration = Duration::from_secs(60 * 60);
+/// [degradation_score] needs at least this many recent checks, and as many older ones
+pub const DEGRADATION_MIN_CHECKS: usize = 4;
+/// Targets with a [degradation_score] of at least this are degrading
+pub const DEGRADING_SCORE: f64 = 0.5;
+/// Targets without a successful check for this long are stale, if nothing else is
+/// [configured](ReportConfig::stale_after_secs)
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(15 * 60);
+/// How many buckets the latency histograms in the report have at most
+pub const HISTOGRAM_BUCKETS: usize = 8;
+/// Length of the longest bar of the latency histograms in the report
+const HISTOGRAM_WIDTH: usize = 50;
+/// Width of the report in columns if none is [configured](ReportConfig::width)
+pub const DEFAULT_WIDTH: usize = 100;
+/// The report is never narrower than this
+pub const MIN_WIDTH: usize = 40;
+/// Environment variable to set the width of the report, see [report_width]
+pub const ENV_WIDTH: &str = "NETPULSE_REPORT_WIDTH";
+/// Width of the key column of the report
+const KEY_WIDTH: usize = 20;
+/// Width of the check type column of [tail] and [sparkline], the [Display] of the longest
+/// [CheckType]
+const TYPE_WIDTH: usize = 10;
+/// Bucket of a [sparkline] in which all checks succeeded
+pub const SPARK_UP: char = '█';
+/// Bucket of a [sparkline] in which some checks failed
+pub const SPARK_PARTIAL: char = '▄';
+/// Bucket of a [sparkline] in which all checks failed
+pub const SPARK_DOWN: char = '░';
+/// Bucket of a [sparkline] without checks
+pub const SPARK_EMPTY: char = ' ';
+/// An IP family of a target with at least this success ratio counts as healthy, see
+/// [FamilyReachability::failing]
+pub const FAMILY_HEALTHY_RATIO: f64 = 0.9;
+/// An IP family of a target with less than this success ratio counts as failing, see
+/// [FamilyReachability::failing]
+pub const FAMILY_FAILING_RATIO: f64 = 0.5;
+
+/// Sorts latencies into at most `buckets` buckets of equal width.
 ///
-/// # Warning Messages
+/// The buckets are contiguous and cover the range from the smallest to the largest latency, both
+/// included. Each bucket is returned with the amount of latencies in it, empty buckets included.
+/// If the data range is smaller than `buckets`, fewer buckets are returned.
 ///
-/// Prints warning to stderr if:
-/// - Check has both IPv4 and IPv6 flags set
-/// - Check has no IP version flags set
-fn gereric_ip_analyze(
-    store: &Store,
-    f: &mut String,
-    ip_check_flag: CheckFlag,
-) -> Result<(), AnalysisError> {
-    if ![CheckFlag::IPv4, CheckFlag::IPv6].contains(&ip_check_flag) {
-        panic!("check flag is not IPv4 or IPv6: {ip_check_flag:?}");
+/// Returns an empty [Vec] if `latencies` is empty or `buckets` is 0.
+pub fn latency_histogram(latencies: &[u64], buckets: usize) -> Vec<(Range<u64>, usize)> {
+    let (Some(min), Some(max)) = (latencies.iter().min(), latencies.iter().max()) else {
+        return Vec::new();
+    };
+    if buckets == 0 {
+        return Vec::new();
     }
-    let all: Vec<&Check> = store
-        .checks()
+    let span = max - min + 1;
+    let width = span.div_ceil(buckets as u64);
+    let len = span.div_ceil(width) as usize;
+
+    let mut histogram: Vec<(Range<u64>, usize)> = (0..len as u64)
+        .map(|i| (min + i * width..min + (i + 1) * width, 0))
+        .collect();
+    for latency in latencies {
+        histogram[((latency - min) / width) as usize].1 += 1;
+    }
+    histogram
+}
+
+/// Draws the uptime of each [CheckType] of the store as a row of `buckets` characters, one line
+/// per type with checks, like `HTTP(S)     ████▄░░███`.
+///
+/// See [type_sparkline] for the characters. The rows of all types span the same time, from the
+/// first to the last check of the store, so they line up.
+pub fn sparkline(store: &Store, buckets: usize) -> String {
+    CheckType::all()
         .iter()
-        .filter(|c| match c.ip_type() {
-            Ok(ip) => ip,
-            Err(err) => {
-                eprintln!("check '{}' has bad flags: {err}", c.get_hash());
-                return false;
-            }
-        } == CheckFlag::IPv4
-        )
+        .filter(|check_type| store.checks().iter().any(|c| is_type(c, **check_type)))
+        .map(|check_type| {
+            format!(
+                "{:<TYPE_WIDTH$}  {}\n",
+                check_type.to_string(),
+                type_sparkline(store, *check_type, buckets)
+            )
+        })
+        .collect()
+}
+
+/// Draws the uptime of the checks of `check_type` as a row of `buckets` characters.
+///
+/// The time from the first to the last check of the store, of any type, is split into `buckets`
+/// buckets of equal length. Each bucket is drawn as [SPARK_UP] if all its checks succeeded,
+/// [SPARK_PARTIAL] if some failed, [SPARK_DOWN] if all failed and [SPARK_EMPTY] if it has no
+/// checks of `check_type`.
+pub fn type_sparkline(store: &Store, check_type: CheckType, buckets: usize) -> String {
+    let Some((start, end)) = sparkline_window(store) else {
+        return SPARK_EMPTY.to_string().repeat(buckets);
+    };
+    let span = (end - start + 1) as u128;
+    // amount of checks and successful checks of each bucket
+    let mut counts = vec![(0usize, 0usize); buckets];
+    if buckets > 0 {
+        for check in store.checks().iter().filter(|c| is_type(c, check_type)) {
+            let bucket = (check.timestamp() - start) as u128 * buckets as u128 / span;
+            let count = &mut counts[bucket as usize];
+            count.0 += 1;
+            count.1 += usize::from(check.is_success());
+        }
+    }
+    counts
+        .into_iter()
+        .map(|(all, ok)| match (all, ok) {
+            (0, _) => SPARK_EMPTY,
+            (all, ok) if ok == all => SPARK_UP,
+            (_, 0) => SPARK_DOWN,
+            _ => SPARK_PARTIAL,
+        })
+        .collect()
+}
+
+/// Returns the timestamps of the first and the last check of the store, the time a [sparkline]
+/// spans.
+fn sparkline_window(store: &Store) -> Option<(u64, u64)> {
+    let times = store.checks().iter().map(Check::timestamp);
+    Some((times.clone().min()?, times.max()?))
+}
+
+/// Reliability and latency statistics of a single target, see [rank_targets].
+#[derive(Debug, PartialEq, Clone)]
+pub struct TargetRanking {
+    /// The target these statistics are for
+    pub target: IpAddr,
+    /// Amount of checks made against the target
+    pub checks: usize,
+    /// Amount of successful checks made against the target
+    pub successes: usize,
+    /// Mean latency of the successful checks in milliseconds, [None] if there are none
+    pub mean_latency: Option<f64>,
+}
+
+impl TargetRanking {
+    /// Calculates the [TargetRanking] of `target` from its checks.
+    fn new(target: IpAddr, checks: &[&Check]) -> Self {
+        let latencies: Vec<u16> = checks.iter().filter_map(|c| c.latency()).collect();
+        Self {
+            target,
+            checks: checks.len(),
+            successes: checks.iter().filter(|c| c.is_success()).count(),
+            mean_latency: (!latencies.is_empty())
+                .then(|| latencies.iter().map(|l| *l as f64).sum::<f64>() / latencies.len() as f64),
+        }
+    }
+
+    /// Ratio of successful checks, between 0.0 and 1.0.
+    pub fn success_ratio(&self) -> f64 {
+        success_ratio(self.checks, self.successes)
+    }
+}
+
+impl Display for TargetRanking {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({:03.02}% ok of {} checks, ",
+            self.target,
+            self.success_ratio() * 100.0,
+            self.checks
+        )?;
+        match self.mean_latency {
+            Some(lat) => write!(f, "mean latency {lat:.01} ms)"),
+            None => write!(f, "no latency)"),
+        }
+    }
+}
+
+/// Scores how much worse the newest checks are than the older ones, between 0.0 for no change
+/// or an improvement and 1.0 for much worse.
+///
+/// The checks of the last [DEGRADATION_WINDOW] before the newest check are compared to the
+/// older ones as baseline. The checks should be of one target and one [CheckType], as the
+/// latencies of different check types do not compare. The score adds up how much the failure ratio rose and by how much the mean
+/// latency of the successful checks rose relative to the baseline, so a doubled latency alone
+/// scores 1.0. Without [DEGRADATION_MIN_CHECKS] recent and baseline checks, the score is 0.0.
+///
+/// A rising score often comes before an outage, see [DEGRADING_SCORE].
+pub fn degradation_score(checks: &[&Check]) -> f64 {
+    let mut checks = checks.to_vec();
+    checks.sort_by_key(|c| (c.timestamp(), c.seq()));
+    let Some(newest) = checks.last().map(|c| c.timestamp()) else {
+        return 0.0;
+    };
+    let start = checks.partition_point(|c| c.timestamp() + DEGRADATION_WINDOW.as_secs() <= newest);
+    let (baseline, recent) = checks.split_at(start);
+    if recent.len() < DEGRADATION_MIN_CHECKS || baseline.len() < DEGRADATION_MIN_CHECKS {
+        return 0.0;
+    }
+    let failure_ratio = |checks: &[&Check]| {
+        checks.iter().filter(|c| !c.is_success()).count() as f64 / checks.len() as f64
+    };
+    let mean_latency = |checks: &[&Check]| {
+        let latencies: Vec<f64> = checks
+            .iter()
+            .filter_map(|c| c.latency())
+            .map(f64::from)
+            .collect();
+        (!latencies.is_empty()).then(|| latencies.iter().sum::<f64>() / latencies.len() as f64)
+    };
+    let failures = (failure_ratio(recent) - failure_ratio(baseline)).max(0.0);
+    let latency = match (mean_latency(baseline), mean_latency(recent)) {
+        (Some(baseline), Some(recent)) if baseline > 0.0 => {
+            ((recent - baseline) / baseline).clamp(0.0, 1.0)
+        }
+        _ => 0.0,
+    };
+    (failures + latency).min(1.0)
+}
+
+/// Returns the targets and check types with a [degradation_score] of at least
+/// [DEGRADING_SCORE] and their score, the highest score first.
+pub fn degrading_targets(store: &Store) -> Vec<(IpAddr, CheckType, f64)> {
+    let mut degrading: Vec<(IpAddr, CheckType, f64)> = group_by_target_and_type(store)
+        .into_iter()
+        .map(|(target, check_type, checks)| (target, check_type, degradation_score(&checks)))
+        .filter(|(_, _, score)| *score >= DEGRADING_SCORE)
         .collect();
-    let successes: Vec<&Check> = all.clone().into_iter().filter(|c| c.is_success()).collect();
-    analyze_check_type_set(f, &all, &successes)?;
-    Ok(())
+    degrading.sort_by(|a, b| b.2.total_cmp(&a.2));
+    degrading
 }
-/// Includes metrics across all check types combined.
-fn generic_type_analyze(
-    store: &Store,
-    f: &mut String,
-    check_type: CheckType,
-) -> Result<(), AnalysisError> {
-    let all: Vec<&Check> = store
-        .checks()
+
+/// The newest checks of a target and check type that all succeeded or all failed, see
+/// [current_streak].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Streak {
+    /// Whether the checks of the streak succeeded
+    pub success: bool,
+    /// Amount of checks in the streak, at least one
+    pub length: usize,
+}
+
+impl Display for Streak {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.success, self.length) {
+            (true, 1) => write!(f, "currently 1 success"),
+            (true, length) => write!(f, "currently {length} consecutive successes"),
+            (false, 1) => write!(f, "currently failing for 1 check"),
+            (false, length) => write!(f, "currently failing for {length} checks"),
+        }
+    }
+}
+
+/// Counts how many of the newest `checks` in a row had the same result as the newest one.
+///
+/// The checks are ordered by their [sequence number](Check::seq), since the timestamps may jump
+/// around with the system clock. A check with the other result ends the streak. Returns [None]
+/// for no checks.
+pub fn current_streak(checks: &[&Check]) -> Option<Streak> {
+    let mut checks = checks.to_vec();
+    checks.sort_by_key(|c| c.seq());
+    let success = checks.last()?.is_success();
+    let length = checks
         .iter()
-        .filter(|c| c.calc_type().unwrap_or(CheckType::Unknown) == check_type)
+        .rev()
+        .take_while(|c| c.is_success() == success)
+        .count();
+    Some(Streak { success, length })
+}
+
+/// Returns the [current streak](current_streak) of each target and check type.
+///
+/// The check types of a target are apart, so a target that answers HTTP but not ICMP has a
+/// streak of successes and one of failures.
+pub fn streaks(store: &Store) -> Vec<(IpAddr, CheckType, Streak)> {
+    group_by_target_and_type(store)
+        .into_iter()
+        .filter_map(|(target, check_type, checks)| {
+            Some((target, check_type, current_streak(&checks)?))
+        })
+        .collect()
+}
+
+/// Groups the checks of the store by their target.
+pub fn group_by_target(store: &Store) -> BTreeMap<IpAddr, Vec<&Check>> {
+    let mut groups: BTreeMap<IpAddr, Vec<&Check>> = BTreeMap::new();
+    for check in store.checks() {
+        groups.entry(check.target()).or_default().push(check);
+    }
+    groups
+}
+
+/// Groups the checks of the store by their target and then by their [CheckType], in the order
+/// of the targets and of the first check of each type.
+fn group_by_target_and_type(store: &Store) -> Vec<(IpAddr, CheckType, Vec<&Check>)> {
+    let mut groups = Vec::new();
+    for (target, checks) in group_by_target(store) {
+        let mut by_type: Vec<(CheckType, Vec<&Check>)> = Vec::new();
+        for check in checks {
+            let check_type = check.calc_type().unwrap_or(CheckType::Unknown);
+            match by_type.iter_mut().find(|(t, _)| *t == check_type) {
+                Some((_, checks)) => checks.push(check),
+                None => by_type.push((check_type, vec![check])),
+            }
+        }
+        groups.extend(
+            by_type
+                .into_iter()
+                .map(|(check_type, checks)| (target, check_type, checks)),
+        );
+    }
+    groups
+}
+
+/// Returns the hostname a check was made for, if its target was given as hostname.
+fn resolved_host(check: &Check) -> Option<&str> {
+    check.details().iter().find_map(|detail| match detail {
+        CheckDetail::Resolved { host } => Some(host.as_str()),
+        _ => None,
+    })
+}
+
+/// Lists the addresses each [configured hostname](crate::config::Config::hosts) resolved to.
+///
+/// For every hostname, the statistics of the checks against each address are calculated, which
+/// helps to find a bad backend behind a hostname with multiple addresses.
+pub fn resolved_hosts(store: &Store) -> BTreeMap<String, Vec<TargetRanking>> {
+    let mut hosts: BTreeMap<&str, BTreeMap<IpAddr, Vec<&Check>>> = BTreeMap::new();
+    for check in store.checks() {
+        if let Some(host) = resolved_host(check) {
+            hosts
+                .entry(host)
+                .or_default()
+                .entry(check.target())
+                .or_default()
+                .push(check);
+        }
+    }
+    hosts
+        .into_iter()
+        .map(|(host, targets)| {
+            let rankings = targets
+                .iter()
+                .map(|(target, checks)| TargetRanking::new(*target, checks))
+                .collect();
+            (host.to_string(), rankings)
+        })
+        .collect()
+}
+
+/// Ranks all targets of the store by their reliability.
+///
+/// The least reliable target, the one with the lowest success ratio, comes first. Targets with
+/// the same success ratio are ordered by their mean latency, slowest first.
+pub fn rank_targets(store: &Store) -> Vec<TargetRanking> {
+    let mut rankings: Vec<TargetRanking> = group_by_target(store)
+        .into_iter()
+        .map(|(target, checks)| TargetRanking::new(target, &checks))
         .collect();
-    let successes: Vec<&Check> = all.clone().into_iter().filter(|c| c.is_success()).collect();
-    analyze_check_type_set(f, &all, &successes)?;
-    Ok(())
+    rankings.sort_by(|a, b| {
+        a.success_ratio()
+            .total_cmp(&b.success_ratio())
+            .then_with(|| {
+                b.mean_latency
+                    .unwrap_or(f64::INFINITY)
+                    .total_cmp(&a.mean_latency.unwrap_or(f64::INFINITY))
+            })
+    });
+    rankings
 }
 
-/// Write store metadata section of the report.
+/// Reachability of a target over IPv4 and over IPv6, see [family_reachability].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct FamilyReachability {
+    /// The hostname the checks were made for, or the IP address if no hostname was used
+    pub target: String,
+    /// Amount of checks made over IPv4
+    pub v4_checks: usize,
+    /// Amount of successful checks made over IPv4
+    pub v4_successes: usize,
+    /// Amount of checks made over IPv6
+    pub v6_checks: usize,
+    /// Amount of successful checks made over IPv6
+    pub v6_successes: usize,
+}
+
+impl FamilyReachability {
+    /// Ratio of successful IPv4 checks, [None] if there are none.
+    pub fn v4_ratio(&self) -> Option<f64> {
+        (self.v4_checks > 0).then(|| success_ratio(self.v4_checks, self.v4_successes))
+    }
+
+    /// Ratio of successful IPv6 checks, [None] if there are none.
+    pub fn v6_ratio(&self) -> Option<f64> {
+        (self.v6_checks > 0).then(|| success_ratio(self.v6_checks, self.v6_successes))
+    }
+
+    /// Returns the failing family if one family is healthy and the other is failing.
+    ///
+    /// A family is healthy with a success ratio of at least [FAMILY_HEALTHY_RATIO] and failing
+    /// below [FAMILY_FAILING_RATIO]. Targets that were only checked over one family are never
+    /// asymmetric.
+    pub fn failing(&self) -> Option<CheckFlag> {
+        let (v4, v6) = (self.v4_ratio()?, self.v6_ratio()?);
+        if v4 >= FAMILY_HEALTHY_RATIO && v6 < FAMILY_FAILING_RATIO {
+            Some(CheckFlag::IPv6)
+        } else if v6 >= FAMILY_HEALTHY_RATIO && v4 < FAMILY_FAILING_RATIO {
+            Some(CheckFlag::IPv4)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for FamilyReachability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let family = |label: &str, ratio: Option<f64>, checks: usize| match ratio {
+            Some(ratio) => format!("{label} {:03.02}% ok of {checks}", ratio * 100.0),
+            None => format!("no {label}"),
+        };
+        write!(
+            f,
+            "{}, {}",
+            family("IPv4", self.v4_ratio(), self.v4_checks),
+            family("IPv6", self.v6_ratio(), self.v6_checks)
+        )?;
+        match self.failing() {
+            Some(CheckFlag::IPv4) => write!(f, " (IPv4 failing)"),
+            Some(_) => write!(f, " (IPv6 failing)"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Cross-references the IPv4 and IPv6 success ratios of each target.
 ///
-/// Includes:
-/// - Hash of in-memory data structure
-/// - Hash of store file on disk
-fn store_meta(store: &Store, f: &mut String) -> Result<(), AnalysisError> {
-    key_value_write(f, "Hash Datastructure", store.display_hash())?;
-    key_value_write(f, "Hash Store File", store.display_hash_of_file()?)?;
-    // TODO: write version of store in file and in memory
-    Ok(())
+/// Checks are grouped by the hostname they were [resolved](CheckDetail::Resolved) for, or by
+/// their IP address otherwise. Only hostnames can be checked over both families, so look at
+/// [failing](FamilyReachability::failing) for those to find a broken IPv4 or IPv6 setup.
+pub fn family_reachability(store: &Store) -> Vec<FamilyReachability> {
+    let mut targets: BTreeMap<String, FamilyReachability> = BTreeMap::new();
+    for check in store.checks() {
+        let target = match resolved_host(check) {
+            Some(host) => host.to_string(),
+            None => check.target().to_string(),
+        };
+        let entry = targets
+            .entry(target)
+            .or_insert_with_key(|target| FamilyReachability {
+                target: target.clone(),
+                ..Default::default()
+            });
+        let (checks, successes) = match check.ip_type() {
+            Ok(CheckFlag::IPv6) => (&mut entry.v6_checks, &mut entry.v6_successes),
+            _ => (&mut entry.v4_checks, &mut entry.v4_successes),
+        };
+        *checks += 1;
+        if check.is_success() {
+            *successes += 1;
+        }
+    }
+    targets.into_values().collect()
 }
 
-/// Calculate the success ratio of a subset compared to total.
+/// Takes a sample of about `size` checks of `store`, [None] if it has no more than `size`
+/// checks.
 ///
-/// Returns value between 0.0 and 1.0.
-#[inline]
-fn success_ratio(all_checks: usize, subset: usize) -> f64 {
-    subset as f64 / all_checks as f64
+/// A check is taken if its SHA-256 hash is a multiple of the sampling step, so the sample is the
+/// same on every run, with every build of netpulse, and does not follow the order of the checks.
+/// Taking every nth check instead could always hit the same target, since the checks of a cycle
+/// are stored one after the other.
+///
+/// Statistics like success ratios and latencies of the sample approximate those of the whole
+/// store. Outages can't be found in it, since most checks of an outage are left out.
+pub fn sample(store: &Store, size: usize) -> Option<Store> {
+    if store.len() <= size {
+        return None;
+    }
+    let step = store.len().div_ceil(size.max(1)) as u64;
+    Some(store.filtered(|check| {
+        // unlike the DefaultHasher, SHA-256 does not change between Rust versions
+        let bytes = bincode::serialize(check).expect("checks can be serialized");
+        let digest = Sha256::digest(bytes);
+        let hash = u64::from_be_bytes(digest[..8].try_into().expect("the digest is 32 bytes"));
+        hash % step == 0
+    }))
+}
+
+/// When a target last had a successful check, see [last_ok].
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct LastOk {
+    /// The hostname the checks were made for, or the IP address if no hostname was used
+    pub target: String,
+    /// Time of the newest successful check, [None] if no check ever succeeded
+    pub last_ok: Option<SystemTime>,
+    /// How long ago the newest successful check was made, [None] if no check ever succeeded
+    pub elapsed: Option<Duration>,
+    /// Whether the newest successful check is older than the threshold, or there is none
+    pub stale: bool,
+}
+
+impl Display for LastOk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(elapsed) = self.elapsed else {
+            return write!(f, "never");
+        };
+        write!(
+            f,
+            "{} ago",
+            humantime::format_duration(Duration::from_secs(elapsed.as_secs()))
+        )?;
+        if self.stale {
+            write!(f, " (stale)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the newest successful check of each target, and how long ago it was made.
+///
+/// Targets are named like in [family_reachability]. A target is stale if it had no successful
+/// check for longer than `stale_after`, or never had one.
+pub fn last_ok(store: &Store, stale_after: Duration) -> Vec<LastOk> {
+    last_ok_at(store, stale_after, SystemTime::now())
+}
+
+/// Like [last_ok], but measures the elapsed time up to `now`.
+pub fn last_ok_at(store: &Store, stale_after: Duration, now: SystemTime) -> Vec<LastOk> {
+    let mut targets: BTreeMap<String, Option<SystemTime>> = BTreeMap::new();
+    for check in store.checks() {
+        let target = match resolved_host(check) {
+            Some(host) => host.to_string(),
+            None => check.target().to_string(),
+        };
+        let newest = targets.entry(target).or_default();
+        if check.is_success() {
+            *newest = (*newest).max(Some(check.timestamp_parsed()));
+        }
+    }
+    targets
+        .into_iter()
+        .map(|(target, last_ok)| {
+            let elapsed = last_ok.map(|t| now.duration_since(t).unwrap_or_default());
+            LastOk {
+                target,
+                last_ok,
+                elapsed,
+                stale: elapsed.is_none_or(|e| e > stale_after),
+            }
+        })
+        .collect()
+}
+
+/// Display a formatted list of checks.
+///
+/// Each check is formatted with:
+/// - Index number
+/// - Indented check details
+/// - Nested line breaks preserved
+///
+/// # Arguments
+///
+/// * `group` - Slice of check references to format
+/// * `f` - String buffer to write formatted output
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if string formatting fails.
+pub fn display_group(group: &[&Check], f: &mut String) -> Result<(), std::fmt::Error> {
+    if group.is_empty() {
+        writeln!(f, "\t<Empty>")?;
+        return Ok(());
+    }
+    for (cidx, check) in group.iter().enumerate() {
+        writeln!(f, "{cidx}:")?;
+        writeln!(f, "\t{}", check.to_string().replace("\n", "\n\t"))?;
+    }
+    Ok(())
+}
+
+/// Generate a comprehensive analysis report for the given store, using the default [Config].
+///
+/// See [analyze_with].
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if:
+/// - Report string formatting fails
+/// - Store hash calculation fails
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use netpulse::{store::Store, analyze};
+///
+/// let store = Store::load().unwrap();
+/// let report = analyze::analyze(&store).unwrap();
+/// println!("{}", report);
+/// ```
+pub fn analyze(store: &Store) -> Result<String, AnalysisError> {
+    analyze_with(store, &Config::default())
+}
+
+/// Generate a comprehensive analysis report for the given store.
+///
+/// No annotations are shown, see [analyze_annotated] for those.
+///
+/// The report includes:
+/// - General check statistics
+/// - HTTP-specific metrics
+/// - Outage analysis
+/// - Store metadata
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if:
+/// - Report string formatting fails
+/// - Store hash calculation fails
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use netpulse::{config::Config, store::Store, analyze};
+///
+/// let store = Store::load().unwrap();
+/// let report = analyze::analyze_with(&store, &Config::load().unwrap()).unwrap();
+/// println!("{}", report);
+/// ```
+pub fn analyze_with(store: &Store, config: &Config) -> Result<String, AnalysisError> {
+    analyze_annotated(store, config, &Annotations::default())
+}
+
+/// Generate a comprehensive analysis report, showing `annotations` with their outages.
+///
+/// Like [analyze_with], which shows no annotations. The caller loads them, for example from
+/// their [default path](Annotations::path) with [Annotations::load]. If
+/// [exclude_annotated](crate::config::ReportConfig::exclude_annotated) is set, the checks of
+/// annotated outages are left out of all statistics but the outages themselves.
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if:
+/// - Report string formatting fails
+/// - Store hash calculation fails
+pub fn analyze_annotated(
+    store: &Store,
+    config: &Config,
+    annotations: &Annotations,
+) -> Result<String, AnalysisError> {
+    let mut buf = Vec::new();
+    write_report_annotated(store, config, annotations, &mut buf)?;
+    Ok(String::from_utf8(buf).expect("the report is written from strings"))
+}
+
+/// Writes the report of [analyze] to `w`, using the default [Config].
+///
+/// See [write_report_with].
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if the report can't be made or written to `w`.
+pub fn write_report(store: &Store, w: &mut impl io::Write) -> Result<(), AnalysisError> {
+    write_report_with(store, &Config::default(), w)
+}
+
+/// Writes the report of [analyze_with] to `w`, section by section.
+///
+/// Only the current section is kept in memory, so the report can be streamed to a file or
+/// socket while it is made. The bytes are the same as those of [analyze_with].
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if the report can't be made or written to `w`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use netpulse::{analyze, config::Config, store::Store};
+///
+/// let store = Store::load().unwrap();
+/// analyze::write_report_with(&store, &Config::load().unwrap(), &mut std::io::stdout()).unwrap();
+/// ```
+pub fn write_report_with(
+    store: &Store,
+    config: &Config,
+    w: &mut impl io::Write,
+) -> Result<(), AnalysisError> {
+    write_report_annotated(store, config, &Annotations::default(), w)
+}
+
+/// Writes the report of [analyze_annotated] to `w`, section by section, see
+/// [write_report_with].
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if the report can't be made or written to `w`.
+pub fn write_report_annotated(
+    store: &Store,
+    config: &Config,
+    annotations: &Annotations,
+    w: &mut impl io::Write,
+) -> Result<(), AnalysisError> {
+    let started = Instant::now();
+    let mut f = String::new();
+    let mut flush = |f: &mut String| -> Result<(), AnalysisError> {
+        w.write_all(f.as_bytes())?;
+        f.clear();
+        Ok(())
+    };
+    statistics_flushed(store, config, annotations, &mut f, &mut flush)?;
+    let layout = Layout::of(&config.report);
+    layout.barrier(&mut f, "Store Metadata")?;
+    store_meta(store, config, &mut f, layout)?;
+    // last, so that it covers the whole report
+    layout.key_value(&mut f, "Report Time", format!("{:.1?}", started.elapsed()))?;
+    flush(&mut f)
+}
+
+/// Writes all sections of the report except the store metadata, see [analyze_annotated].
+#[cfg(test)]
+fn statistics(
+    store: &Store,
+    config: &Config,
+    annotations: &Annotations,
+    f: &mut String,
+) -> Result<(), AnalysisError> {
+    statistics_flushed(store, config, annotations, f, &mut |_| Ok(()))
+}
+
+/// Like [statistics], but calls `flush` with `f` after every section.
+fn statistics_flushed(
+    store: &Store,
+    config: &Config,
+    annotations: &Annotations,
+    f: &mut String,
+    flush: &mut dyn FnMut(&mut String) -> Result<(), AnalysisError>,
+) -> Result<(), AnalysisError> {
+    let layout = Layout::of(&config.report);
+    let mut outages = past_and_ongoing_outages(store, &config.outages);
+    for outage in &mut outages {
+        outage.annotate(annotations);
+        outage.mark_critical(config);
+    }
+    // once per report, the outages are built again for several sections
+    let skewed = outages.iter().filter(|o| o.is_skewed()).count();
+    if skewed > 0 {
+        crate::logging::warn(format!(
+            "{skewed} outages end before they start, the clock was probably changed"
+        ));
+    }
+    let excluded: HashSet<&Check> = if config.report.exclude_annotated {
+        outages
+            .iter()
+            .filter(|o| o.annotation().is_some())
+            .flat_map(|o| o.all.iter().copied())
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    let maintenance: HashSet<&Check> = store
+        .checks()
+        .iter()
+        .filter(|c| !counts_for_outages(c, &config.outages))
+        .collect();
+    let counted = store.filtered(|c| !excluded.contains(c) && !maintenance.contains(c));
+    let sampled = config
+        .report
+        .sample_above
+        .and_then(|size| sample(&counted, size));
+    let stats = sampled.as_ref().unwrap_or(&counted);
+    let title = |title: &str| match sampled {
+        Some(_) => format!("{title} (sampled)"),
+        None => title.to_string(),
+    };
+
+    layout.barrier(f, &title("General"))?;
+    if sampled.is_some() {
+        layout.explained(
+            f,
+            "sampled",
+            format!("{:08} of {:08} checks", stats.len(), counted.len()),
+            "the statistics below are calculated over a sample of the checks",
+        )?;
+    }
+    if !excluded.is_empty() {
+        layout.explained(
+            f,
+            "annotated, excluded",
+            format!("{:08}", excluded.len()),
+            "checks of annotated outages, left out of the statistics",
+        )?;
+    }
+    if !maintenance.is_empty() {
+        layout.explained(
+            f,
+            "maintenance, excluded",
+            format!("{:08}", maintenance.len()),
+            "failed checks in maintenance windows, left out of the statistics",
+        )?;
+    }
+    generalized(stats, availability(&counted, &config.outages), f, layout)?;
+    flush(f)?;
+    subsets(stats, &counted, &config.outages, f, layout)?;
+    flush(f)?;
+    layout.barrier(f, &title("Targets"))?;
+    targets(stats, f, layout)?;
+    flush(f)?;
+    layout.barrier(f, "Last Ok")?;
+    last_ok_section(store, config.report.stale_after(), f, layout)?;
+    flush(f)?;
+    layout.barrier(f, "Early Warnings")?;
+    early_warnings(&counted, f, layout)?;
+    flush(f)?;
+    layout.barrier(f, &title("IP Families"))?;
+    families(stats, f, layout)?;
+    flush(f)?;
+    if !store.aggregates().is_empty() {
+        layout.barrier(f, "Downsampled")?;
+        downsampled(store, f, layout)?;
+        flush(f)?;
+    }
+    layout.barrier(f, "Missed Checks")?;
+    let audits = audit_counts(store, config);
+    if audits.is_empty() {
+        writeln!(f, "None")?;
+    } else {
+        for audit in audits {
+            layout.key_value(
+                f,
+                &audit.check_type.to_string(),
+                format!("{audit}, every {}s", audit.interval.as_secs()),
+            )?;
+        }
+        layout.explain(
+            f,
+            "one check per target every interval of its type from its first to its last check is expected",
+        )?;
+    }
+    writeln!(f)?;
+    flush(f)?;
+    if !config.slo.is_empty() {
+        layout.barrier(f, "Latency Objectives")?;
+        for result in check_slos(&counted, &config.slo) {
+            layout.key_value(
+                f,
+                &format!("{} p{}", result.slo.check_type, result.slo.percentile),
+                result,
+            )?;
+        }
+        layout.explain(
+            f,
+            "nearest-rank percentile of the latencies of the checks of the type",
+        )?;
+        writeln!(f)?;
+        flush(f)?;
+    }
+    layout.barrier(f, "Timeline")?;
+    timeline(&counted, f, layout)?;
+    flush(f)?;
+    layout.barrier(f, "Outages")?;
+    let transient = transient_failures(store, &config.outages);
+    write_outages(store, &outages, transient, &config.outages, f, layout)?;
+    flush(f)
+}
+
+/// Generate a report with the statistics of the checks grouped by the value of their tag `key`.
+///
+/// Uses the [default](Config::default) configuration, see [analyze_by_tag_with].
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if report formatting fails.
+pub fn analyze_by_tag(store: &Store, key: &str) -> Result<String, AnalysisError> {
+    analyze_by_tag_with(store, key, &Config::default())
+}
+
+/// Generate a report with the statistics of the checks grouped by the value of their tag `key`.
+///
+/// There is one section per value, titled like `region=eu`. Checks without the tag are shown in
+/// a last section titled `untagged`. See [Check::tags] for how checks get their tags.
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if report formatting fails.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use netpulse::{config::Config, store::Store, analyze};
+///
+/// let store = Store::load().unwrap();
+/// let report = analyze::analyze_by_tag_with(&store, "region", &Config::load().unwrap()).unwrap();
+/// println!("{}", report);
+/// ```
+pub fn analyze_by_tag_with(
+    store: &Store,
+    key: &str,
+    config: &Config,
+) -> Result<String, AnalysisError> {
+    let mut f = String::new();
+    if store.checks().is_empty() {
+        writeln!(f, "Store has no checks yet\n")?;
+        return Ok(f);
+    }
+
+    let mut groups: BTreeMap<&str, Vec<&Check>> = BTreeMap::new();
+    let mut untagged: Vec<&Check> = Vec::new();
+    for check in store.checks() {
+        match check.tag(key) {
+            Some(value) => groups.entry(value).or_default().push(check),
+            None => untagged.push(check),
+        }
+    }
+
+    let layout = Layout::of(&config.report);
+    let sections = groups
+        .into_iter()
+        .map(|(value, checks)| (format!("{key}={value}"), checks))
+        .chain([("untagged".to_string(), untagged)]);
+    for (label, all) in sections {
+        layout.barrier(&mut f, &label)?;
+        let successes: Vec<&Check> = all.iter().copied().filter(|c| c.is_success()).collect();
+        analyze_check_type_set(&mut f, &all, &successes, None, None, layout)?;
+    }
+    Ok(f)
+}
+
+/// Success ratio of a [CheckType] in two stores, see [DiffReport].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TypeDiff {
+    /// The type of the checks
+    pub check_type: CheckType,
+    /// Success ratio in the old store, [None] if it has no checks of this type
+    pub old_ratio: Option<f64>,
+    /// Success ratio in the new store, [None] if it has no checks of this type
+    pub new_ratio: Option<f64>,
+}
+
+impl TypeDiff {
+    /// Returns how much the success ratio changed, if both stores have checks of this type.
+    pub fn delta(&self) -> Option<f64> {
+        Some(self.new_ratio? - self.old_ratio?)
+    }
+}
+
+/// Differences between an old and a new [Store], see [diff].
+#[derive(Debug, PartialEq, Clone)]
+pub struct DiffReport<'new> {
+    /// Checks in the new store that are not in the old one
+    pub added: usize,
+    /// Checks in the old store that are not in the new one
+    pub removed: usize,
+    /// Success ratios of the check types that are in either store
+    pub types: Vec<TypeDiff>,
+    /// Outages in the new store that don't start like one in the old store
+    pub new_outages: Vec<Outage<'new>>,
+    /// Whether the time ranges of the stores don't overlap, so the comparison is of two
+    /// different periods rather than two versions of one store
+    pub disjoint: bool,
+}
+
+impl Display for DiffReport<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ratio = |ratio: Option<f64>| match ratio {
+            Some(ratio) => format!("{}%", short_percent(ratio)),
+            None => "-".to_string(),
+        };
+        if self.disjoint {
+            writeln!(f, "WARNING: the stores cover disjoint time windows")?;
+        }
+        writeln!(f, "{:<20}: {}", "checks added", self.added)?;
+        writeln!(f, "{:<20}: {}", "checks removed", self.removed)?;
+        for t in &self.types {
+            let delta = match t.delta() {
+                Some(delta) => format!(" ({delta:+.1} points)", delta = delta * 100.0),
+                None => String::new(),
+            };
+            writeln!(
+                f,
+                "{:<20}: {} -> {}{delta}",
+                t.check_type.to_string(),
+                ratio(t.old_ratio),
+                ratio(t.new_ratio)
+            )?;
+        }
+        writeln!(f, "{:<20}: {}", "new outages", self.new_outages.len())?;
+        for outage in &self.new_outages {
+            writeln!(f)?;
+            write!(f, "{outage}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares an `old` and a `new` store, like before and after a network change.
+///
+/// Uses the [default](OutageConfig::default) thresholds for the outages, see [diff_with].
+pub fn diff<'new>(old: &Store, new: &'new Store) -> DiffReport<'new> {
+    diff_with(old, new, &OutageConfig::default())
+}
+
+/// Compares an `old` and a `new` store, finding outages with `thresholds`.
+///
+/// Checks are compared as a whole, so a copy of a store made later has only added checks.
+/// Outages are matched by their [CheckType] and the time of their first check.
+pub fn diff_with<'new>(
+    old: &Store,
+    new: &'new Store,
+    thresholds: &OutageConfig,
+) -> DiffReport<'new> {
+    let old_checks: HashSet<&Check> = old.checks().iter().collect();
+    let new_checks: HashSet<&Check> = new.checks().iter().collect();
+    let added = new_checks.difference(&old_checks).count();
+    let removed = old_checks.difference(&new_checks).count();
+
+    let ratio_of = |store: &Store, check_type: CheckType| {
+        let all: Vec<&Check> = store
+            .checks()
+            .iter()
+            .filter(|c| is_type(c, check_type))
+            .collect();
+        let ok = all.iter().filter(|c| c.is_success()).count();
+        (!all.is_empty()).then(|| success_ratio(all.len(), ok))
+    };
+    let types = CheckType::all()
+        .iter()
+        .map(|check_type| TypeDiff {
+            check_type: *check_type,
+            old_ratio: ratio_of(old, *check_type),
+            new_ratio: ratio_of(new, *check_type),
+        })
+        .filter(|t| t.old_ratio.is_some() || t.new_ratio.is_some())
+        .collect();
+
+    let outage_key = |o: &Outage| {
+        (
+            o.start.calc_type().unwrap_or(CheckType::Unknown),
+            o.start.timestamp_parsed(),
+        )
+    };
+    let old_outages: HashSet<(CheckType, SystemTime)> = find_outages(old, thresholds)
+        .iter()
+        .map(outage_key)
+        .collect();
+    let new_outages = find_outages(new, thresholds)
+        .into_iter()
+        .filter(|o| !old_outages.contains(&outage_key(o)))
+        .collect();
+
+    let window = |store: &Store| Some((store.first_check_time()?, store.last_check_time()?));
+    let disjoint = match (window(old), window(new)) {
+        (Some((old_first, old_last)), Some((new_first, new_last))) => {
+            old_last < new_first || new_last < old_first
+        }
+        _ => false,
+    };
+
+    DiffReport {
+        added,
+        removed,
+        types,
+        new_outages,
+        disjoint,
+    }
+}
+
+/// How much of the previous window of [compare_windows] its checks have to cover for it to be
+/// used as baseline, from the first to the last check.
+pub const MIN_BASELINE_COVERAGE: f64 = 0.5;
+
+/// Figures of the checks in one window of a [WindowComparison].
+#[derive(Debug, PartialEq, Clone)]
+pub struct WindowStats {
+    /// Amount of checks in the window
+    pub checks: usize,
+    /// Share of successful checks, [None] without checks
+    pub success_ratio: Option<f64>,
+    /// Mean latency of the successful checks in milliseconds, [None] if there are none
+    pub mean_latency: Option<f64>,
+    /// Amount of outages in the window
+    pub outages: usize,
+}
+
+impl WindowStats {
+    /// Computes the figures of all checks of `store`, with outages found with `thresholds`.
+    fn of(store: &Store, thresholds: &OutageConfig) -> Self {
+        let checks = store.checks();
+        let successes = checks.iter().filter(|c| c.is_success()).count();
+        let latencies: Vec<f64> = checks
+            .iter()
+            .filter(|c| c.is_success())
+            .filter_map(|c| c.latency())
+            .map(f64::from)
+            .collect();
+        Self {
+            checks: checks.len(),
+            success_ratio: (!checks.is_empty()).then(|| success_ratio(checks.len(), successes)),
+            mean_latency: (!latencies.is_empty())
+                .then(|| latencies.iter().sum::<f64>() / latencies.len() as f64),
+            outages: find_outages(store, thresholds).len(),
+        }
+    }
+}
+
+/// The most recent window of a store compared to the window of the same length before it, see
+/// [compare_windows].
+#[derive(Debug, PartialEq, Clone)]
+pub struct WindowComparison {
+    /// Length of each window
+    pub window: Duration,
+    /// End of the current window
+    pub end: SystemTime,
+    /// The current window
+    pub current: WindowStats,
+    /// The previous window, [None] if it has too few checks to compare with, see
+    /// [MIN_BASELINE_COVERAGE]
+    pub previous: Option<WindowStats>,
+}
+
+impl WindowComparison {
+    /// Returns how much the success ratio changed, if both windows have one.
+    pub fn ratio_delta(&self) -> Option<f64> {
+        Some(self.current.success_ratio? - self.previous.as_ref()?.success_ratio?)
+    }
+
+    /// Returns how much the mean latency changed in milliseconds, if both windows have one.
+    pub fn latency_delta(&self) -> Option<f64> {
+        Some(self.current.mean_latency? - self.previous.as_ref()?.mean_latency?)
+    }
+
+    /// Returns how much the amount of outages changed, if there is a previous window.
+    pub fn outage_delta(&self) -> Option<i64> {
+        Some(self.current.outages as i64 - self.previous.as_ref()?.outages as i64)
+    }
+}
+
+impl WindowComparison {
+    /// Returns a [Display] of this comparison laid out like the report of `report`, with the
+    /// end of the window in its [timezone](ReportConfig::timezone).
+    ///
+    /// The plain [Display] of a comparison uses the [default](ReportConfig::default) layout.
+    pub fn display_in(&self, report: &ReportConfig) -> WindowComparisonDisplay<'_> {
+        WindowComparisonDisplay {
+            comparison: self,
+            layout: Layout::of(report),
+        }
+    }
+
+    /// Writes the figures of both windows and their deltas to `f`, laid out with `layout`.
+    fn write(&self, f: &mut String, layout: Layout) -> Result<(), std::fmt::Error> {
+        let ratio = |stats: &WindowStats| match stats.success_ratio {
+            Some(ratio) => format!("{}%", short_percent(ratio)),
+            None => "-".to_string(),
+        };
+        let latency = |stats: &WindowStats| match stats.mean_latency {
+            Some(latency) => format!("{latency:.1}ms"),
+            None => "-".to_string(),
+        };
+        let window = humantime::format_duration(self.window);
+        layout.key_value(
+            f,
+            "window",
+            format!(
+                "the last {window} until {}",
+                layout.timezone.format(self.end)
+            ),
+        )?;
+        let Some(previous) = &self.previous else {
+            writeln!(
+                f,
+                "insufficient baseline: the {window} before have too few checks to compare with"
+            )?;
+            layout.key_value(f, "checks", self.current.checks)?;
+            layout.key_value(f, "success ratio", ratio(&self.current))?;
+            layout.key_value(f, "mean latency", latency(&self.current))?;
+            layout.key_value(f, "outages", self.current.outages)?;
+            return Ok(());
+        };
+        let delta = |delta: Option<String>| delta.map(|d| format!(" ({d})")).unwrap_or_default();
+        layout.key_value(
+            f,
+            "checks",
+            format!("{} -> {}", previous.checks, self.current.checks),
+        )?;
+        layout.key_value(
+            f,
+            "success ratio",
+            format!(
+                "{} -> {}{}",
+                ratio(previous),
+                ratio(&self.current),
+                delta(
+                    self.ratio_delta()
+                        .map(|d| format!("{:+.1} points", d * 100.0))
+                )
+            ),
+        )?;
+        layout.key_value(
+            f,
+            "mean latency",
+            format!(
+                "{} -> {}{}",
+                latency(previous),
+                latency(&self.current),
+                delta(self.latency_delta().map(|d| format!("{d:+.1}ms")))
+            ),
+        )?;
+        layout.key_value(
+            f,
+            "outages",
+            format!(
+                "{} -> {}{}",
+                previous.outages,
+                self.current.outages,
+                delta(self.outage_delta().map(|d| format!("{d:+}")))
+            ),
+        )
+    }
+}
+
+impl Display for WindowComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display_in(&ReportConfig::default()))
+    }
+}
+
+/// Displays a [WindowComparison] laid out like a report, see [WindowComparison::display_in].
+#[derive(Debug, Clone, Copy)]
+pub struct WindowComparisonDisplay<'comparison> {
+    comparison: &'comparison WindowComparison,
+    layout: Layout,
+}
+
+impl Display for WindowComparisonDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut shown = String::new();
+        self.comparison.write(&mut shown, self.layout)?;
+        f.write_str(&shown)
+    }
+}
+
+/// Compares the checks of the `window` before `end` with those of the `window` before that,
+/// finding outages with `thresholds`.
+///
+/// Like this week with last week, for a `window` of 7 days. Outages are found in each window on
+/// its own, so an outage that spans the border of the windows is counted in both. The previous
+/// window is only used as baseline if its checks cover at least [MIN_BASELINE_COVERAGE] of it,
+/// so a store that started in the middle of it is not compared with a few days of data.
+pub fn compare_windows(
+    store: &Store,
+    window: Duration,
+    end: SystemTime,
+    thresholds: &OutageConfig,
+) -> WindowComparison {
+    let start = end.checked_sub(window).unwrap_or(UNIX_EPOCH);
+    let previous_start = start.checked_sub(window).unwrap_or(UNIX_EPOCH);
+    let current = store.filtered(|c| (start..=end).contains(&c.timestamp_parsed()));
+    let previous = store.filtered(|c| (previous_start..start).contains(&c.timestamp_parsed()));
+
+    let times = previous.checks().iter().map(|c| c.timestamp_parsed());
+    let covered = match (times.clone().min(), times.max()) {
+        (Some(first), Some(last)) => last.duration_since(first).unwrap_or_default(),
+        _ => Duration::ZERO,
+    };
+    let sufficient =
+        !window.is_zero() && covered.as_secs_f64() >= window.as_secs_f64() * MIN_BASELINE_COVERAGE;
+    WindowComparison {
+        window,
+        end,
+        current: WindowStats::of(&current, thresholds),
+        previous: sufficient.then(|| WindowStats::of(&previous, thresholds)),
+    }
+}
+
+/// Returns a [Store] with only the checks whose target matches `pattern`.
+///
+/// A check matches if its target IP address or the hostname it was
+/// [resolved](CheckDetail::Resolved) from matches. Patterns containing `*` or `?` are globs that
+/// must match the whole target, all other patterns match any target containing them.
+///
+/// # Errors
+///
+/// Returns [AnalysisError::NoMatchingTargets] if no check matches, so that the caller doesn't
+/// show a report full of empty sections.
+pub fn filter_targets(store: &Store, pattern: &str) -> Result<Store, AnalysisError> {
+    let filtered = store.filtered(|check| target_matches(check, pattern));
+    if filtered.checks().is_empty() {
+        return Err(AnalysisError::NoMatchingTargets(pattern.to_string()));
+    }
+    Ok(filtered)
+}
+
+/// Returns whether the target of `check` matches `pattern`, see [filter_targets].
+pub fn target_matches(check: &Check, pattern: &str) -> bool {
+    let matches = |name: &str| {
+        if pattern.contains(['*', '?']) {
+            glob_match(pattern.as_bytes(), name.as_bytes())
+        } else {
+            name.contains(pattern)
+        }
+    };
+    matches(&check.target().to_string())
+        || check.details().iter().any(|detail| match detail {
+            CheckDetail::Resolved { host } => matches(host),
+            _ => false,
+        })
+}
+
+/// Matches `text` against a glob `pattern`, where `*` matches any amount of characters and `?`
+/// matches exactly one.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` in the pattern and of the text where it started matching
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // let the last `*` match one more character
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Returns the width the report should have.
+///
+/// The first of these is used:
+/// 1. `flag`, given on the command line
+/// 2. The [ENV_WIDTH] environment variable
+/// 3. The [configured width](ReportConfig::width)
+/// 4. The [terminal_width], if stdout is a terminal narrower than [DEFAULT_WIDTH]
+/// 5. [DEFAULT_WIDTH]
+///
+/// The width is never less than [MIN_WIDTH].
+pub fn report_width(flag: Option<usize>, report: &ReportConfig) -> usize {
+    flag.or_else(|| std::env::var(ENV_WIDTH).ok()?.trim().parse().ok())
+        .or(report.width)
+        .unwrap_or_else(|| terminal_width().map_or(DEFAULT_WIDTH, |w| w.min(DEFAULT_WIDTH)))
+        .max(MIN_WIDTH)
+}
+
+/// Returns the width of the terminal in columns, if stdout is a terminal.
+pub fn terminal_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ only writes into the winsize struct we pass
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 {
+        return None;
+    }
+    (size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+/// How the report is laid out, from the [ReportConfig].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Layout {
+    /// Timezone in which timestamps are shown
+    timezone: Timezone,
+    /// Width of the report in columns, at least [MIN_WIDTH]
+    width: usize,
+    /// Whether figures are followed by how they were calculated, see [ReportConfig::explain]
+    explain: bool,
+}
+
+impl Layout {
+    /// Takes the layout from `report`, with [DEFAULT_WIDTH] if it has no width.
+    fn of(report: &ReportConfig) -> Self {
+        Self {
+            timezone: report.timezone,
+            width: report.width.unwrap_or(DEFAULT_WIDTH).max(MIN_WIDTH),
+            explain: report.explain,
+        }
+    }
+
+    /// Adds a section divider to the report with a title.
+    ///
+    /// Creates a divider line of '=' characters as wide as the report, with the title near the
+    /// start.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AnalysisError] if string formatting fails.
+    fn barrier(&self, f: &mut String, title: &str) -> Result<(), AnalysisError> {
+        writeln!(
+            f,
+            "{:=<10}{:=<rest$}",
+            "",
+            format!(" {title} "),
+            rest = self.width - 10
+        )?;
+        Ok(())
+    }
+
+    /// Writes a key-value pair to the report in aligned columns.
+    ///
+    /// Format: `<key>: <value>`, with the value padded to the width of the report.
+    fn key_value(
+        &self,
+        f: &mut String,
+        title: &str,
+        content: impl Display,
+    ) -> Result<(), std::fmt::Error> {
+        writeln!(
+            f,
+            "{:<KEY_WIDTH$}: {:<rest$}",
+            title,
+            content.to_string(),
+            rest = self.width - KEY_WIDTH - 2
+        )
+    }
+
+    /// Writes how the figures above were calculated, if the report is
+    /// [explained](ReportConfig::explain).
+    ///
+    /// Format: `<padding>  ^ <explanation>`, with the explanation below the values.
+    fn explain(&self, f: &mut String, explanation: impl Display) -> Result<(), std::fmt::Error> {
+        if !self.explain {
+            return Ok(());
+        }
+        writeln!(f, "{:<KEY_WIDTH$}  ^ {explanation}", "")
+    }
+
+    /// Writes a key-value pair like [key_value](Layout::key_value), followed by its
+    /// [explanation](Layout::explain).
+    fn explained(
+        &self,
+        f: &mut String,
+        title: &str,
+        content: impl Display,
+        explanation: impl Display,
+    ) -> Result<(), std::fmt::Error> {
+        self.key_value(f, title, content)?;
+        self.explain(f, explanation)
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::of(&ReportConfig::default())
+    }
+}
+
+/// Finds the outages in the store.
+///
+/// Groups consecutive failed checks by check type and creates
+/// Outage records for reporting.
+fn find_outages<'store>(store: &'store Store, thresholds: &OutageConfig) -> Vec<Outage<'store>> {
+    fail_groups_by_type(store, thresholds)
+        .into_iter()
+        .filter(|group| !group.is_empty() && group.len() >= thresholds.min_failures)
+        .map(|group| Outage::new(group[0], group.last().copied(), &group, thresholds))
+        .collect()
+}
+
+/// Counts the failed checks that are too few in a row to be an outage, see
+/// [min_failures](OutageConfig::min_failures).
+fn transient_failures(store: &Store, thresholds: &OutageConfig) -> usize {
+    fail_groups_by_type(store, thresholds)
+        .into_iter()
+        .filter(|group| group.len() < thresholds.min_failures)
+        .map(|group| group.len())
+        .sum()
+}
+
+/// Returns whether `check` is considered for outages, see
+/// [count_maintenance](OutageConfig::count_maintenance).
+fn counts_for_outages(check: &Check, thresholds: &OutageConfig) -> bool {
+    thresholds.count_maintenance || check.is_success() || !check.in_maintenance()
+}
+
+/// Finds the groups of consecutive failed checks of each [CheckType].
+///
+/// Failed checks in a maintenance window are skipped, see [counts_for_outages].
+fn fail_groups_by_type<'store>(
+    store: &'store Store,
+    thresholds: &OutageConfig,
+) -> Vec<Vec<&'store Check>> {
+    let all_checks: Vec<&Check> = store
+        .checks()
+        .iter()
+        .filter(|c| counts_for_outages(c, thresholds))
+        .collect();
+    let mut groups = Vec::new();
+    for check_type in CheckType::all() {
+        let checks: Vec<&&Check> = all_checks
+            .iter()
+            .filter(|c| c.calc_type().unwrap_or(CheckType::Unknown) == *check_type)
+            .collect();
+        groups.extend(fail_groups(&checks));
+    }
+    groups
+}
+
+/// Formats the outages of the store, preceded by the amount of outages per [Severity] and of
+/// `transient` failures, as determined by `thresholds`.
+fn write_outages(
+    store: &Store,
+    outages: &[Outage],
+    transient: usize,
+    thresholds: &OutageConfig,
+    f: &mut String,
+    layout: Layout,
+) -> Result<(), AnalysisError> {
+    let fails_exist = store.checks().iter().any(|c| !c.is_success());
+    if !fails_exist {
+        writeln!(f, "None\n")?;
+        return Ok(());
+    }
+
+    layout.explained(
+        f,
+        "total",
+        outages.len(),
+        format!(
+            "runs of at least {} failed checks of a check type in a row",
+            thresholds.min_failures
+        ),
+    )?;
+    for severity in Severity::ALL {
+        layout.key_value(
+            f,
+            &severity.to_string(),
+            outages.iter().filter(|o| o.severity() == severity).count(),
+        )?;
+    }
+    layout.explain(
+        f,
+        "outages by their duration and amount of failed checks, see [outages] of the config",
+    )?;
+    let correlations = correlate(outages, correlation_slack(store));
+    let upstream: Vec<&Correlation> = correlations
+        .iter()
+        .filter(|c| c.diagnosis == Diagnosis::Upstream)
+        .collect();
+    layout.key_value(f, "likely local", upstream.len())?;
+    layout.key_value(f, "isolated", correlations.len() - upstream.len())?;
+    layout.explain(
+        f,
+        format!(
+            "overlapping outages of at least {MIN_CORRELATED_TARGETS} targets are likely a problem of the local network or uplink"
+        ),
+    )?;
+    for correlation in upstream {
+        layout.key_value(
+            f,
+            "local/upstream",
+            format!(
+                "{} to {}, {} targets",
+                layout.timezone.format(correlation.start),
+                correlation
+                    .end
+                    .map_or("now".to_string(), |end| layout.timezone.format(end)),
+                correlation.targets.len()
+            ),
+        )?;
+    }
+    if transient > 0 {
+        layout.explained(
+            f,
+            "transient failures",
+            transient,
+            format!(
+                "failed checks in runs of fewer than {}",
+                thresholds.min_failures
+            ),
+        )?;
+    }
+    writeln!(f)?;
+    for outage in outages {
+        writeln!(f, "{}", outage.display_in(layout.timezone))?;
+    }
+    Ok(())
+}
+
+/// Writes the most and least reliable and the slowest and fastest targets.
+fn targets(store: &Store, f: &mut String, layout: Layout) -> Result<(), AnalysisError> {
+    let mut rankings = rank_targets(store);
+    if rankings.is_empty() {
+        writeln!(f, "None\n")?;
+        return Ok(());
+    }
+    let shown = RANKING_LEN.min(rankings.len());
+
+    for ranking in &rankings[..shown] {
+        layout.key_value(f, "least reliable", ranking)?;
+    }
+    for ranking in rankings.iter().rev().take(shown) {
+        layout.key_value(f, "most reliable", ranking)?;
+    }
+    layout.explain(
+        f,
+        format!("the {shown} targets with the lowest and highest ok / checks"),
+    )?;
+
+    rankings.retain(|r| r.mean_latency.is_some());
+    rankings.sort_by(|a, b| {
+        b.mean_latency
+            .unwrap_or_default()
+            .total_cmp(&a.mean_latency.unwrap_or_default())
+    });
+    let shown = RANKING_LEN.min(rankings.len());
+    for ranking in &rankings[..shown] {
+        layout.key_value(f, "slowest", ranking)?;
+    }
+    for ranking in rankings.iter().rev().take(shown) {
+        layout.key_value(f, "fastest", ranking)?;
+    }
+    layout.explain(
+        f,
+        format!("the {shown} targets with the highest and lowest mean latency"),
+    )?;
+    let hosts = resolved_hosts(store);
+    for (host, rankings) in &hosts {
+        for ranking in rankings {
+            layout.key_value(f, host, format!("resolved to {ranking}"))?;
+        }
+    }
+    if !hosts.is_empty() {
+        layout.explain(f, "the checks of each address a hostname was resolved to")?;
+    }
+    for (target, check_type, streak) in streaks(store) {
+        layout.key_value(f, &format!("{target} {check_type}"), streak)?;
+    }
+    layout.explain(
+        f,
+        "the newest checks of the target and type in a row with the same result",
+    )?;
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Write the targets that are [degrading](degrading_targets).
+fn early_warnings(store: &Store, f: &mut String, layout: Layout) -> Result<(), AnalysisError> {
+    let degrading = degrading_targets(store);
+    if degrading.is_empty() {
+        writeln!(f, "None\n")?;
+        return Ok(());
+    }
+    for (target, check_type, score) in degrading {
+        layout.key_value(
+            f,
+            &format!("{target} {check_type}"),
+            format!("degrading, score {score:.2}"),
+        )?;
+    }
+    layout.explain(
+        f,
+        format!(
+            "score >= {DEGRADING_SCORE}: rise of the failure ratio + relative rise of the mean \
+             latency, checks of the last {} against the older ones",
+            humantime::format_duration(DEGRADATION_WINDOW)
+        ),
+    )?;
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Write how long ago each target had its last successful check, see [last_ok].
+fn last_ok_section(
+    store: &Store,
+    stale_after: Duration,
+    f: &mut String,
+    layout: Layout,
+) -> Result<(), AnalysisError> {
+    let targets = last_ok(store, stale_after);
+    if targets.is_empty() {
+        writeln!(f, "None\n")?;
+        return Ok(());
+    }
+    for target in targets {
+        layout.key_value(f, &target.target, &target)?;
+    }
+    layout.explain(
+        f,
+        format!(
+            "time since the newest successful check, stale after {}",
+            humantime::format_duration(stale_after)
+        ),
+    )?;
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Write the IPv4 and IPv6 success ratios of the targets checked over both families.
+fn families(store: &Store, f: &mut String, layout: Layout) -> Result<(), AnalysisError> {
+    let dual: Vec<FamilyReachability> = family_reachability(store)
+        .into_iter()
+        .filter(|r| r.v4_checks > 0 && r.v6_checks > 0)
+        .collect();
+    if dual.is_empty() {
+        writeln!(f, "None\n")?;
+        return Ok(());
+    }
+    for reachability in dual {
+        layout.key_value(f, &reachability.target, &reachability)?;
+    }
+    layout.explain(
+        f,
+        format!(
+            "ok / checks of each family, failing below {:.0}% while the other is at least {:.0}%",
+            FAMILY_FAILING_RATIO * 100.0,
+            FAMILY_HEALTHY_RATIO * 100.0
+        ),
+    )?;
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Find groups of consecutive failed checks.
+///
+/// Groups are formed when:
+/// - Checks are consecutive by index
+/// - All checks in group are failures
+/// - Gap between groups is > 1 check
+fn fail_groups<'check>(checks: &[&&'check Check]) -> Vec<Vec<&'check Check>> {
+    // consecutive by sequence number, the timestamps may jump around with the system clock
+    let mut checks: Vec<&'check Check> = checks.iter().map(|c| **c).collect();
+    checks.sort_by_key(|c| c.seq());
+    let failed_idxs: Vec<usize> = checks
+        .iter()
+        .enumerate()
+        .filter(|(_idx, c)| !c.is_success())
+        .map(|(idx, _c)| idx)
+        .collect();
+    if failed_idxs.is_empty() {
+        return Vec::new();
+    }
+    let mut groups: Vec<Vec<&Check>> = Vec::new();
+
+    let mut first = failed_idxs[0];
+    let mut last = first;
+    for idx in failed_idxs.into_iter().skip(1) {
+        if idx == last + 1 {
+            last = idx;
+        } else {
+            groups.push(checks[first..=last].to_vec());
+            first = idx;
+            last = idx;
+        }
+    }
+    // the last group is not followed by a gap, like when the store ends with failures
+    groups.push(checks[first..=last].to_vec());
+
+    groups
+}
+
+/// Analyze metrics for a specific check type.
+///
+/// Calculates and formats:
+/// - Total check count
+/// - Success/failure counts
+/// - Success ratio
+/// - Time-weighted `availability`, if given, see [availability]
+/// - First/last check timestamps
+/// - When the checks started failing and last recovered, if `failures` are given, see
+///   [failure_times]
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if formatting fails.
+fn analyze_check_type_set(
+    f: &mut String,
+    all: &[&Check],
+    successes: &[&Check],
+    availability: Option<f64>,
+    failures: Option<FailureTimes>,
+    layout: Layout,
+) -> Result<(), AnalysisError> {
+    let times = all.iter().map(|c| c.timestamp_parsed());
+    let (Some(first), Some(last)) = (times.clone().min(), times.max()) else {
+        writeln!(f, "None\n")?;
+        return Ok(());
+    };
+    layout.explained(
+        f,
+        "checks",
+        format!("{:08}", all.len()),
+        "all checks of this section",
+    )?;
+    layout.explained(
+        f,
+        "checks ok",
+        format!("{:08}", successes.len()),
+        "checks that succeeded",
+    )?;
+    layout.explained(
+        f,
+        "checks bad",
+        format!("{:08}", all.len() - successes.len()),
+        "checks - checks ok",
+    )?;
+    let too_slow = all.iter().filter(|check| check.is_too_slow()).count();
+    if too_slow > 0 {
+        layout.explained(
+            f,
+            "checks too slow",
+            format!("{too_slow:08}"),
+            "checks bad that were answered, but too slowly",
+        )?;
+    }
+    layout.explained(
+        f,
+        "success ratio",
+        format!(
+            "{:03.02}%",
+            success_ratio(all.len(), successes.len()) * 100.0
+        ),
+        "checks ok / checks",
+    )?;
+    if let Some(availability) = availability {
+        layout.explained(
+            f,
+            "availability",
+            format!("{:03.02}%", availability * 100.0),
+            "1 - time in outages / time from the first to the last check",
+        )?;
+    }
+    layout.explained(
+        f,
+        "first check at",
+        layout.timezone.format(first),
+        "time of the oldest check",
+    )?;
+    layout.explained(
+        f,
+        "last check at",
+        layout.timezone.format(last),
+        "time of the newest check",
+    )?;
+    if let Some(failures) = failures {
+        layout.explained(
+            f,
+            "failing since",
+            failures
+                .failing_since
+                .map_or("no active failure".to_string(), |t| {
+                    layout.timezone.format(t)
+                }),
+            "first failed check of the ongoing outage",
+        )?;
+        layout.explained(
+            f,
+            "last recovered at",
+            failures
+                .recovered_at
+                .map_or("never".to_string(), |t| layout.timezone.format(t)),
+            "first successful check after the latest outage",
+        )?;
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Write general check statistics section of the report.
+///
+/// Includes metrics across all check types combined, and the [availability] with the outages
+/// determined by `thresholds`.
+fn generalized(
+    store: &Store,
+    availability: Option<f64>,
+    f: &mut String,
+    layout: Layout,
+) -> Result<(), AnalysisError> {
+    if store.checks().is_empty() {
+        writeln!(f, "Store has no checks yet\n")?;
+        return Ok(());
+    }
+    let all: Vec<&Check> = store.checks().iter().collect();
+    let successes: Vec<&Check> = store.checks().iter().filter(|c| c.is_success()).collect();
+    analyze_check_type_set(f, &all, &successes, availability, None, layout)?;
+    Ok(())
+}
+
+/// Write the totals of the [downsampled](Store::downsample) checks for each [CheckType].
+fn downsampled(store: &Store, f: &mut String, layout: Layout) -> Result<(), AnalysisError> {
+    let mut totals: Vec<(CheckType, u64, u64, usize)> = Vec::new();
+    for aggregate in store.aggregates() {
+        let idx = match totals
+            .iter()
+            .position(|(t, ..)| *t == aggregate.check_type())
+        {
+            Some(idx) => idx,
+            None => {
+                totals.push((aggregate.check_type(), 0, 0, 0));
+                totals.len() - 1
+            }
+        };
+        totals[idx].1 += aggregate.count();
+        totals[idx].2 += aggregate.successes();
+        totals[idx].3 += 1;
+    }
+    for (check_type, count, successes, buckets) in totals {
+        layout.key_value(
+            f,
+            &check_type.to_string(),
+            format!(
+                "{count} checks in {buckets} buckets, {:03.02}% ok",
+                success_ratio(count as usize, successes as usize) * 100.0
+            ),
+        )?;
+    }
+    layout.explain(
+        f,
+        "totals of the aggregates of the downsampled checks, ok / checks",
+    )?;
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Write the sections for each [CheckType] and IP version.
+///
+/// Checks whose type can't be determined are shown in a section of their own. The sections of
+/// the check types show the [failure_times] with the outages determined by `thresholds`. These
+/// are taken from `counted`, the store the statistics of `store` were sampled from, if they
+/// were, so that the timestamps are those of the actual first failure and recovery.
+fn subsets(
+    store: &Store,
+    counted: &Store,
+    thresholds: &OutageConfig,
+    f: &mut String,
+    layout: Layout,
+) -> Result<(), AnalysisError> {
+    let ongoing = ongoing_outages(counted, thresholds);
+    let outages = find_outages(counted, thresholds);
+    for (check_type, label) in [
+        (CheckType::Http, "HTTP"),
+        (CheckType::IcmpV4, "ICMPv4"),
+        (CheckType::IcmpV6, "ICMPv6"),
+        (CheckType::Udp, "UDP"),
+        (CheckType::Throughput, "Throughput"),
+        (CheckType::Unknown, "Unknown"),
+    ] {
+        let failures = failure_times_of(counted, check_type, &ongoing, &outages);
+        analyze_subset(
+            store,
+            f,
+            |c| is_type(c, check_type),
+            label,
+            Some(failures),
+            layout,
+        )?;
+        if check_type == CheckType::Http {
+            http_phases(store, f, layout)?;
+        }
+    }
+    analyze_subset(
+        store,
+        f,
+        |c| is_ip_type(c, CheckFlag::IPv4),
+        "IPv4",
+        None,
+        layout,
+    )?;
+    analyze_subset(
+        store,
+        f,
+        |c| is_ip_type(c, CheckFlag::IPv6),
+        "IPv6",
+        None,
+        layout,
+    )?;
+    Ok(())
+}
+
+/// Write a section of the report with the statistics of all checks matching `predicate`.
+///
+/// The section is titled with `label` and includes:
+/// - Total number of matching checks
+/// - Success/failure counts
+/// - Success ratio
+/// - First/last check timestamps
+/// - The `failures`, if given
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if report formatting fails.
+fn analyze_subset(
+    store: &Store,
+    f: &mut String,
+    predicate: impl Fn(&Check) -> bool,
+    label: &str,
+    failures: Option<FailureTimes>,
+    layout: Layout,
+) -> Result<(), AnalysisError> {
+    layout.barrier(f, label)?;
+    let all: Vec<&Check> = store.checks().iter().filter(|c| predicate(c)).collect();
+    let successes: Vec<&Check> = all.iter().copied().filter(|c| c.is_success()).collect();
+    analyze_check_type_set(f, &all, &successes, None, failures, layout)?;
+    let latencies: Vec<u64> = all
+        .iter()
+        .filter_map(|c| c.latency())
+        .map(u64::from)
+        .collect();
+    histogram(f, &latency_histogram(&latencies, HISTOGRAM_BUCKETS))?;
+    Ok(())
+}
+
+/// Write the mean duration of each phase of the HTTP checks that recorded them, see
+/// [CheckDetail::HttpPhases].
+///
+/// Writes nothing if no check recorded its phases.
+fn http_phases(store: &Store, f: &mut String, layout: Layout) -> Result<(), AnalysisError> {
+    // dns, connect, tls, ttfb and total
+    let mut sums = [0u64; 5];
+    let (mut timed, mut reused) = (0u64, 0u64);
+    for detail in store
+        .checks()
+        .iter()
+        .filter(|c| is_type(c, CheckType::Http))
+        .flat_map(|c| c.details())
+    {
+        if let CheckDetail::HttpPhases {
+            dns,
+            connect,
+            tls,
+            ttfb,
+            total,
+            reused: was_reused,
+        } = detail
+        {
+            for (sum, phase) in sums.iter_mut().zip([dns, connect, tls, ttfb, total]) {
+                *sum += u64::from(*phase);
+            }
+            timed += 1;
+            reused += u64::from(*was_reused);
+        }
+    }
+    if timed == 0 {
+        return Ok(());
+    }
+    layout.explained(
+        f,
+        "timed checks",
+        format!("{timed:08}, {reused} on reused connections"),
+        "checks that recorded their phases",
+    )?;
+    for (name, sum) in [
+        "mean DNS",
+        "mean connect",
+        "mean TLS",
+        "mean TTFB",
+        "mean total",
+    ]
+    .into_iter()
+    .zip(sums)
+    {
+        layout.key_value(
+            f,
+            name,
+            format!("{:.2}ms", sum as f64 / timed as f64 / 1000.0),
+        )?;
+    }
+    layout.explain(
+        f,
+        "mean of each phase over the timed checks, the phases add up to about the total",
+    )?;
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Writes a histogram as ASCII bars, one line per bucket.
+///
+/// Writes nothing for an empty histogram.
+fn histogram(f: &mut String, histogram: &[(Range<u64>, usize)]) -> Result<(), AnalysisError> {
+    let Some(most) = histogram.iter().map(|(_, count)| *count).max() else {
+        return Ok(());
+    };
+    writeln!(f, "latency histogram:")?;
+    for (range, count) in histogram {
+        let bar = count * HISTOGRAM_WIDTH / most.max(1);
+        writeln!(
+            f,
+            "{:>6} - {:<6}ms | {:<HISTOGRAM_WIDTH$} {count}",
+            range.start,
+            range.end - 1,
+            "#".repeat(bar)
+        )?;
+    }
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Writes the [sparkline] of each check type, as wide as the report.
+fn timeline(store: &Store, f: &mut String, layout: Layout) -> Result<(), AnalysisError> {
+    let Some((start, end)) = sparkline_window(store) else {
+        writeln!(f, "None\n")?;
+        return Ok(());
+    };
+    let buckets = layout.width - KEY_WIDTH - 2;
+    for check_type in CheckType::all() {
+        if store.checks().iter().any(|c| is_type(c, *check_type)) {
+            layout.key_value(
+                f,
+                &check_type.to_string(),
+                type_sparkline(store, *check_type, buckets),
+            )?;
+        }
+    }
+    let bucket = Duration::from_secs((end - start + 1).div_ceil(buckets as u64));
+    layout.explain(
+        f,
+        format!(
+            "{} to {}, {} per column",
+            layout
+                .timezone
+                .format(UNIX_EPOCH + Duration::from_secs(start)),
+            layout
+                .timezone
+                .format(UNIX_EPOCH + Duration::from_secs(end)),
+            humantime::format_duration(bucket)
+        ),
+    )?;
+    layout.explain(
+        f,
+        format!("{SPARK_UP} all checks ok, {SPARK_PARTIAL} some failed, {SPARK_DOWN} all failed"),
+    )?;
+    writeln!(f)?;
+    Ok(())
+}
+
+/// Returns whether `check` is of `check_type`, for use with [analyze_subset].
+fn is_type(check: &Check, check_type: CheckType) -> bool {
+    check.calc_type().unwrap_or(CheckType::Unknown) == check_type
+}
+
+/// Returns whether `check` used the IP version `ip_check_flag`, for use with [analyze_subset].
+///
+/// Checks with ambiguous or invalid IP flags never match, a warning is printed to stderr for
+/// them.
+fn is_ip_type(check: &Check, ip_check_flag: CheckFlag) -> bool {
+    match check.ip_type() {
+        Ok(ip) => ip == ip_check_flag,
+        Err(err) => {
+            eprintln!("check '{}' has bad flags: {err}", check.get_hash());
+            false
+        }
+    }
+}
+
+/// Write store metadata section of the report.
+///
+/// Includes:
+/// - Hash of in-memory data structure
+/// - Hash of store file on disk
+/// - Whether the store in memory matches the store file, see [Store::in_sync]
+/// - The [settings](Config::check_settings) of each check type in `config`
+///
+/// The store file is not read if [skip_store_file](ReportConfig::skip_store_file) is set.
+fn store_meta(
+    store: &Store,
+    config: &Config,
+    f: &mut String,
+    layout: Layout,
+) -> Result<(), AnalysisError> {
+    layout.explained(
+        f,
+        "Hash Datastructure",
+        store.display_hash(),
+        "hash of the checks in memory",
+    )?;
+    if config.report.skip_store_file {
+        layout.key_value(f, "Store File", "not used")?;
+    } else {
+        layout.explained(
+            f,
+            "Hash Store File",
+            store.display_hash_of_file()?,
+            "hash of the checks in the store file",
+        )?;
+        match store.in_sync() {
+            Some(in_sync) => layout.explained(
+                f,
+                "Store File",
+                if in_sync { "in sync" } else { "dirty" },
+                "whether the checks changed since they were loaded or saved",
+            )?,
+            None => layout.explained(
+                f,
+                "Store File",
+                "not compared",
+                "the checks are not those of the store file",
+            )?,
+        }
+    }
+    layout.explained(
+        f,
+        "Checks per Hour",
+        checks_per_hour(store).map_or("unknown".to_string(), |rate| format!("{rate:.1}")),
+        "checks / hours from the first to the last check",
+    )?;
+    let throughput = config
+        .throughput
+        .url
+        .is_some()
+        .then_some(CheckType::Throughput);
+    for check_type in CheckType::default_enabled()
+        .iter()
+        .copied()
+        .chain(throughput)
+    {
+        layout.explained(
+            f,
+            &format!("{check_type} Settings"),
+            config.check_settings(check_type),
+            "effective settings of new checks, from the configuration",
+        )?;
+    }
+    // TODO: write version of store in file and in memory
+    Ok(())
+}
+
+/// Calculates how many checks were made per hour on average, from the first to the last check.
+///
+/// Returns [None] if the store has no checks or all checks were made at the same time.
+pub fn checks_per_hour(store: &Store) -> Option<f64> {
+    let span = store
+        .last_check_time()?
+        .duration_since(store.first_check_time()?)
+        .ok()?;
+    if span.is_zero() {
+        return None;
+    }
+    Some(store.checks().len() as f64 / (span.as_secs_f64() / 3600.0))
+}
+
+/// Calculate the success ratio of a subset compared to total.
+///
+/// Returns value between 0.0 and 1.0.
+#[inline]
+fn success_ratio(all_checks: usize, subset: usize) -> f64 {
+    subset as f64 / all_checks as f64
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    fn http_check(secs: u64, ok: bool) -> Check {
+        let mut flags = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+        if ok {
+            flags |= CheckFlag::Success;
+        }
+        Check::new(
+            UNIX_EPOCH + Duration::from_secs(secs),
+            flags,
+            if ok { Some(20) } else { None },
+            "1.1.1.1".parse().unwrap(),
+        )
+    }
+
+    fn store_of(results: &[bool]) -> Store {
+        let mut store = Store::new();
+        for (idx, ok) in results.iter().enumerate() {
+            store.add_check(http_check(idx as u64 * 60, *ok));
+        }
+        store
+    }
+
+    #[test]
+    fn test_sample_approximates_store() {
+        // 9000 checks of 3 targets, each cycle checks all of them
+        let mut store = Store::new();
+        let targets: [IpAddr; 3] = [
+            "10.0.0.1".parse().unwrap(),
+            "10.0.0.2".parse().unwrap(),
+            "10.0.0.3".parse().unwrap(),
+        ];
+        for cycle in 0..3000u64 {
+            for (idx, target) in targets.iter().enumerate() {
+                // 10.0.0.1 fails every 10th cycle, 10.0.0.2 every other, 10.0.0.3 never
+                let ok = match idx {
+                    0 => cycle % 10 != 0,
+                    1 => cycle % 2 == 0,
+                    _ => true,
+                };
+                let mut flags = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+                if ok {
+                    flags |= CheckFlag::Success;
+                }
+                store.add_check(Check::new(
+                    UNIX_EPOCH + Duration::from_secs(cycle * 60),
+                    flags,
+                    ok.then_some(10 + (cycle % 40) as u16 + idx as u16 * 50),
+                    *target,
+                ));
+            }
+        }
+        assert!(sample(&store, store.len()).is_none());
+
+        let sampled = sample(&store, 1500).unwrap();
+        assert!((1200..1800).contains(&sampled.len()), "{}", sampled.len());
+        let full = rank_targets(&store);
+        let approx = rank_targets(&sampled);
+        assert_eq!(full.len(), approx.len());
+        for (full, approx) in full.iter().zip(&approx) {
+            assert_eq!(full.target, approx.target);
+            assert!((full.success_ratio() - approx.success_ratio()).abs() < 0.03);
+            let (full, approx) = (full.mean_latency.unwrap(), approx.mean_latency.unwrap());
+            assert!((full - approx).abs() / full < 0.05);
+        }
+        // the same sample every time
+        assert_eq!(sample(&store, 1500).unwrap(), sampled);
+        // and with every build, the hash does not depend on the version of Rust
+        assert_eq!(sampled.len(), 1485);
+
+        let mut config = Config::default();
+        config.report.sample_above = Some(1500);
+        let mut report = String::new();
+        statistics(&store, &config, &Annotations::default(), &mut report).unwrap();
+        assert!(report.contains("General (sampled)"));
+        assert!(report.contains(&format!(
+            "sampled             : {:08} of 00009000 checks",
+            sampled.len()
+        )));
+        // the availability is still calculated over all checks
+        assert!(report.contains(&format!(
+            "availability        : {:.2}%",
+            availability(&store, &config.outages).unwrap() * 100.0
+        )));
+    }
+
+    #[test]
+    fn test_streaks() {
+        // a single failure long ago, then a long run of successes
+        let mut store = store_of(&[true, false]);
+        for i in 0..1234 {
+            store.add_check(http_check(120 + i * 60, true));
+        }
+        let [(_, CheckType::Http, streak)] = streaks(&store)[..] else {
+            panic!("there is one target with one type")
+        };
+        assert_eq!(
+            streak,
+            Streak {
+                success: true,
+                length: 1234
+            }
+        );
+        assert_eq!(streak.to_string(), "currently 1234 consecutive successes");
+
+        // failing now, the successes before don't count
+        for i in 0..5 {
+            store.add_check(http_check(100_000 + i * 60, false));
+        }
+        let streak = streaks(&store)[0].2;
+        assert_eq!(
+            streak,
+            Streak {
+                success: false,
+                length: 5
+            }
+        );
+        assert_eq!(streak.to_string(), "currently failing for 5 checks");
+
+        // a successful check of another type neither ends nor joins the HTTP streak
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(200_000),
+            CheckFlag::TypeUdp | CheckFlag::IPv4 | CheckFlag::Success,
+            Some(5),
+            "1.1.1.1".parse().unwrap(),
+        ));
+        assert_eq!(
+            streaks(&store),
+            [
+                ("1.1.1.1".parse().unwrap(), CheckType::Http, streak),
+                (
+                    "1.1.1.1".parse().unwrap(),
+                    CheckType::Udp,
+                    Streak {
+                        success: true,
+                        length: 1
+                    }
+                )
+            ]
+        );
+
+        // the result of each change starts a new streak
+        let flapping = store_of(&[true, false, true, false, true]);
+        let checks: Vec<&Check> = flapping.checks().iter().collect();
+        assert_eq!(current_streak(&checks).unwrap().length, 1);
+        assert_eq!(
+            current_streak(&checks).unwrap().to_string(),
+            "currently 1 success"
+        );
+
+        // ordered by sequence number, not by time
+        let mut skewed = store_of(&[false, false]);
+        skewed.add_check(http_check(0, true));
+        let checks: Vec<&Check> = skewed.checks().iter().collect();
+        assert_eq!(
+            current_streak(&checks),
+            Some(Streak {
+                success: true,
+                length: 1
+            })
+        );
+
+        // a target without checks has no streak
+        assert_eq!(current_streak(&[]), None);
+        assert!(streaks(&Store::new()).is_empty());
+    }
+
+    #[test]
+    fn test_degradation_score() {
+        let check = |secs: u64, ok: bool, latency: u16| {
+            let mut flags = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+            if ok {
+                flags |= CheckFlag::Success;
+            }
+            Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                flags,
+                ok.then_some(latency),
+                "10.0.0.1".parse().unwrap(),
+            )
+        };
+        // 20ms and always ok, with a little jitter
+        let stable: Vec<Check> = (0..100)
+            .map(|i| check(i * 60, true, 19 + (i % 3) as u16))
+            .collect();
+        let stable_score = degradation_score(&stable.iter().collect::<Vec<_>>());
+        assert!(stable_score < 0.1, "{stable_score}");
+
+        // the last hour is slower and fails sometimes
+        let degrading: Vec<Check> = (0..100)
+            .map(|i| match i {
+                0..40 => check(i * 60, true, 20),
+                _ => check(i * 60, i % 3 != 0, 35),
+            })
+            .collect();
+        let mut shuffled: Vec<&Check> = degrading.iter().collect();
+        shuffled.reverse();
+        let score = degradation_score(&shuffled);
+        assert!(score >= DEGRADING_SCORE, "{score}");
+
+        // getting better is no degradation
+        let improving: Vec<Check> = (0..100)
+            .map(|i| match i {
+                0..25 => check(i * 60, i % 3 != 0, 35),
+                _ => check(i * 60, true, 20),
+            })
+            .collect();
+        assert_eq!(
+            degradation_score(&improving.iter().collect::<Vec<_>>()),
+            0.0
+        );
+        // all within the window, so there is no baseline
+        assert_eq!(degradation_score(&shuffled[..6]), 0.0);
+
+        // slower DNS checks of the same target that only started within the window do not
+        // make its HTTP checks degrade
+        let mut mixed: Vec<Check> = stable.clone();
+        mixed.extend((40..100).map(|i| {
+            Check::new(
+                UNIX_EPOCH + Duration::from_secs(i * 60),
+                CheckFlag::TypeDns | CheckFlag::IPv4 | CheckFlag::Success,
+                Some(80),
+                "10.0.0.1".parse().unwrap(),
+            )
+        }));
+        let mixed_store = {
+            let mut store = Store::new();
+            for check in mixed {
+                store.add_check(check);
+            }
+            store
+        };
+        assert!(degrading_targets(&mixed_store).is_empty());
+
+        let mut store = Store::new();
+        for check in degrading {
+            store.add_check(check);
+        }
+        assert_eq!(
+            degrading_targets(&store),
+            [("10.0.0.1".parse().unwrap(), CheckType::Http, score)]
+        );
+        let mut report = String::new();
+        statistics(
+            &store,
+            &Config::default(),
+            &Annotations::default(),
+            &mut report,
+        )
+        .unwrap();
+        assert!(report.contains(&format!(
+            "10.0.0.1 HTTP(S)    : degrading, score {score:.2}"
+        )));
+    }
+
+    #[test]
+    fn test_report_of_piped_store() {
+        let mut store = Store::new();
+        store.add_check(http_check(0, true));
+        store.add_check(http_check(60, false));
+        let raw = bincode::serialize(&store).unwrap();
+        let piped = Store::load_from_reader(raw.as_slice()).unwrap();
+
+        let mut config = Config::default();
+        config.report.skip_store_file = true;
+        let report = analyze_annotated(&piped, &config, &Annotations::default()).unwrap();
+        assert!(report.contains("checks              : 00000002"));
+        assert!(report.contains(&format!("Hash Datastructure  : {}", store.display_hash())));
+        assert!(report.contains("Store File          : not used"));
+        assert!(!report.contains("Hash Store File"));
+    }
+
+    #[test]
+    fn test_explained_report() {
+        let mut store = Store::new();
+        for (secs, ok) in [(0, true), (60, false), (120, false), (180, true)] {
+            store.add_check(http_check(secs, ok));
+        }
+        let mut config = Config::default();
+        config.report.skip_store_file = true;
+        config.outages.min_failures = 1;
+        let plain = analyze_annotated(&store, &config, &Annotations::default()).unwrap();
+        assert!(!plain.contains("  ^ "));
+
+        config.report.explain = true;
+        let explained = analyze_annotated(&store, &config, &Annotations::default()).unwrap();
+        let lines: Vec<&str> = explained.lines().collect();
+        let explanation = |key: &str| {
+            let idx = lines
+                .iter()
+                .position(|l| l.starts_with(&format!("{key:<KEY_WIDTH$}: ")))
+                .unwrap_or_else(|| panic!("no {key} in the report"));
+            lines[idx + 1]
+                .strip_prefix(&format!("{:<KEY_WIDTH$}  ^ ", ""))
+                .unwrap_or_else(|| panic!("{key} is not explained"))
+        };
+        for key in [
+            "checks",
+            "checks ok",
+            "checks bad",
+            "availability",
+            "first check at",
+            "last check at",
+            "failing since",
+            "last recovered at",
+            "total",
+            "Checks per Hour",
+        ] {
+            assert!(!explanation(key).is_empty());
+        }
+        assert_eq!(explanation("success ratio"), "checks ok / checks");
+        assert!(explanation("total").contains("at least 1 failed"));
+        // the figures themselves are the same
+        let figures: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|l| !l.starts_with(&format!("{:<KEY_WIDTH$}  ^ ", "")))
+            .filter(|l| !l.starts_with("Report Time"))
+            .collect();
+        let plain: Vec<&str> = plain
+            .lines()
+            .filter(|l| !l.starts_with("Report Time"))
+            .collect();
+        assert_eq!(figures, plain);
+    }
+
+    #[test]
+    fn test_check_settings_in_metadata() {
+        let mut store = Store::new();
+        store.add_check(http_check(0, true));
+        let mut config = Config::default();
+        config.report.skip_store_file = true;
+        config.checks.udp.interval_secs = Some(300);
+        config.checks.http.retries = Some(3);
+        let report = analyze_annotated(&store, &config, &Annotations::default()).unwrap();
+        let meta = report.split_once(" Store Metadata ").unwrap().1;
+        assert!(
+            meta.contains("HTTP(S) Settings    : timeout 10s, 3 retries, every 1m"),
+            "{meta}"
+        );
+        assert!(meta.contains("ICMPv4 Settings     : timeout 10s, 0 retries, every 1m"));
+        assert!(meta.contains("UDP Settings        : timeout 2s, 0 retries, every 5m"));
+        // throughput checks are only made with a URL
+        assert!(!meta.contains("Throughput Settings"));
+
+        config.throughput.url = Some("http://speed.example.com/10MB.bin".to_string());
+        let report = analyze_annotated(&store, &config, &Annotations::default()).unwrap();
+        assert!(report.contains("Throughput Settings : timeout 30s, 0 retries, every 1h"));
+    }
+
+    #[test]
+    fn test_too_slow_in_report() {
+        let mut store = store_of(&[true, true, false, true]);
+        let mut slow = http_check(240, true);
+        slow.fail_if_slower(10);
+        store.add_check(slow);
+        store.add_check(http_check(300, true));
+        let mut config = Config::default();
+        config.report.skip_store_file = true;
+        let report = analyze_annotated(&store, &config, &Annotations::default()).unwrap();
+        assert!(
+            report.contains("checks bad          : 00000002"),
+            "{report}"
+        );
+        assert!(
+            report.contains("checks too slow     : 00000001"),
+            "{report}"
+        );
+        assert_eq!(report.matches("Too Slow: 1\n").count(), 1, "{report}");
+        let table = outages_markdown_with(&store, &config);
+        assert!(table.contains("| too slow, over 10 ms |"), "{table}");
+        assert!(!table.contains("| HTTP status"), "{table}");
+
+        // without soft failures, the line is left out
+        let report =
+            analyze_annotated(&store_of(&[true, false]), &config, &Annotations::default()).unwrap();
+        assert!(!report.contains("checks too slow"));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        // an outage in the sixth minute
+        let store = store_of(&[true, true, true, true, true, false, true, true, true, true]);
+        let line = type_sparkline(&store, CheckType::Http, 10);
+        assert_eq!(line.chars().count(), 10);
+        assert_eq!(line, "█████░████");
+        assert_eq!(line.chars().nth(5), Some(SPARK_DOWN));
+        assert_eq!(sparkline(&store, 10), format!("HTTP(S)     {line}\n"));
+
+        for buckets in [0, 1, 3, 25, 80] {
+            assert_eq!(
+                type_sparkline(&store, CheckType::Http, buckets)
+                    .chars()
+                    .count(),
+                buckets
+            );
+        }
+        // coarse buckets mix the outage with successes
+        assert_eq!(type_sparkline(&store, CheckType::Http, 2), "█▄");
+        // more buckets than checks leave gaps
+        assert!(type_sparkline(&store, CheckType::Http, 25).contains(SPARK_EMPTY));
+        assert_eq!(type_sparkline(&store, CheckType::IcmpV4, 4), "    ");
+        assert_eq!(type_sparkline(&Store::new(), CheckType::Http, 3), "   ");
+
+        let mut config = Config::default();
+        config.report.skip_store_file = true;
+        let report = analyze_annotated(&store, &config, &Annotations::default()).unwrap();
+        let timeline = report.split_once(" Timeline ").unwrap().1;
+        assert!(timeline.contains("HTTP(S)             : █"), "{timeline}");
+        assert!(timeline.contains(SPARK_DOWN), "{timeline}");
+    }
+
+    #[test]
+    fn test_last_ok() {
+        let mut store = Store::new();
+        let target = |check: Check, ip: &str| {
+            Check::new(
+                check.timestamp_parsed(),
+                check.flags(),
+                check.latency(),
+                ip.parse().unwrap(),
+            )
+        };
+        // 10.0.0.1 was ok a minute ago, 10.0.0.2 an hour ago, 10.0.0.3 never
+        store.add_check(target(http_check(3540, true), "10.0.0.1"));
+        store.add_check(target(http_check(0, true), "10.0.0.2"));
+        store.add_check(target(http_check(3540, false), "10.0.0.2"));
+        store.add_check(target(http_check(3540, false), "10.0.0.3"));
+        let now = UNIX_EPOCH + Duration::from_secs(3600);
+
+        let targets = last_ok_at(&store, DEFAULT_STALE_AFTER, now);
+        assert_eq!(targets.len(), 3);
+        assert_eq!(
+            targets[0].last_ok,
+            Some(UNIX_EPOCH + Duration::from_secs(3540))
+        );
+        assert!(!targets[0].stale);
+        assert_eq!(targets[0].to_string(), "1m ago");
+        assert_eq!(targets[1].elapsed, Some(Duration::from_secs(3600)));
+        assert!(targets[1].stale);
+        assert_eq!(targets[1].to_string(), "1h ago (stale)");
+        assert_eq!(targets[2].last_ok, None);
+        assert!(targets[2].stale);
+        assert_eq!(targets[2].to_string(), "never");
+
+        // with a longer threshold, the hour old success is fresh enough
+        let targets = last_ok_at(&store, Duration::from_secs(7200), now);
+        assert!(!targets[1].stale);
+
+        let mut config = Config::default();
+        config.report.stale_after_secs = Some(30);
+        assert_eq!(config.report.stale_after(), Duration::from_secs(30));
+        let mut report = String::new();
+        statistics(&store, &config, &Annotations::default(), &mut report).unwrap();
+        assert!(report.contains("Last Ok"));
+        assert!(report.contains("10.0.0.3"));
+    }
+
+    #[test]
+    fn test_failure_times() {
+        // HTTP recovered at 180, UDP fails since 240
+        let mut store = store_of(&[true, false, false, true, true]);
+        for (secs, ok) in [
+            (60, true),
+            (120, false),
+            (180, true),
+            (240, false),
+            (300, false),
+        ] {
+            let mut flags = CheckFlag::TypeUdp | CheckFlag::IPv4;
+            if ok {
+                flags |= CheckFlag::Success;
+            }
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                flags,
+                None,
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+        let thresholds = OutageConfig::default();
+
+        let http = failure_times(&store, CheckType::Http, &thresholds);
+        assert_eq!(http.failing_since, None);
+        assert_eq!(
+            http.recovered_at,
+            Some(UNIX_EPOCH + Duration::from_secs(180))
+        );
+        let udp = failure_times(&store, CheckType::Udp, &thresholds);
+        assert_eq!(
+            udp.failing_since,
+            Some(UNIX_EPOCH + Duration::from_secs(240))
+        );
+        assert_eq!(
+            udp.recovered_at,
+            Some(UNIX_EPOCH + Duration::from_secs(180))
+        );
+        assert_eq!(
+            failure_times(&store, CheckType::IcmpV4, &thresholds),
+            FailureTimes::default()
+        );
+
+        let mut report = String::new();
+        subsets(&store, &store, &thresholds, &mut report, Layout::default()).unwrap();
+        let http = report.split_once(" HTTP ").unwrap().1;
+        assert!(http.contains("failing since       : no active failure"));
+        assert!(http.contains("last recovered at   : 1970-01-01T00:03:00Z"));
+        let udp = report.split_once(" UDP ").unwrap().1;
+        assert!(udp.contains("failing since       : 1970-01-01T00:04:00Z"));
+        // the IP families have no failure times
+        let v4 = report.split_once(" IPv4 ").unwrap().1;
+        assert!(!v4.contains("failing since"));
+
+        // the failure times come from the counted checks, not from the sampled ones
+        let mut sampled = Store::new();
+        for check in store.checks().iter().filter(|c| c.is_success()) {
+            sampled.add_check(check.clone());
+        }
+        let mut report = String::new();
+        subsets(
+            &sampled,
+            &store,
+            &thresholds,
+            &mut report,
+            Layout::default(),
+        )
+        .unwrap();
+        let udp = report.split_once(" UDP ").unwrap().1;
+        assert!(udp.contains("failing since       : 1970-01-01T00:04:00Z"));
+    }
+
+    #[test]
+    fn test_rank_targets() {
+        let mut store = Store::new();
+        let targets = ["10.0.0.1", "10.0.0.2", "10.0.0.3"];
+        // 10.0.0.1 always ok, 10.0.0.2 half ok, 10.0.0.3 never ok
+        for i in 0..4u64 {
+            for (tidx, target) in targets.iter().enumerate() {
+                let ok = match tidx {
+                    0 => true,
+                    1 => i % 2 == 0,
+                    _ => false,
+                };
+                let mut check = http_check(i * 60, ok);
+                check.set_target(target.parse().unwrap());
+                store.add_check(check);
+            }
+        }
+
+        let ranked: Vec<String> = rank_targets(&store)
+            .iter()
+            .map(|r| r.target.to_string())
+            .collect();
+        assert_eq!(ranked, ["10.0.0.3", "10.0.0.2", "10.0.0.1"]);
+        let worst = &rank_targets(&store)[0];
+        assert_eq!(worst.checks, 4);
+        assert_eq!(worst.successes, 0);
+        assert_eq!(worst.mean_latency, None);
+    }
+
+    #[test]
+    fn test_outage_severity() {
+        let thresholds = OutageConfig {
+            major_after_secs: 300,
+            critical_after_secs: 3600,
+            major_after_checks: 5,
+            critical_after_checks: 60,
+            min_failures: 1,
+            count_maintenance: false,
+            unmonitored_after_secs: None,
+        };
+        let outage_of = |checks: &[Check]| {
+            let refs: Vec<&Check> = checks.iter().collect();
+            Outage::new(refs[0], refs.last().copied(), &refs, &thresholds).severity()
+        };
+
+        // a 30 second blip
+        assert_eq!(
+            outage_of(&[http_check(0, false), http_check(30, false)]),
+            Severity::Minor
+        );
+        // 10 minutes, but only two checks
+        assert_eq!(
+            outage_of(&[http_check(0, false), http_check(600, false)]),
+            Severity::Major
+        );
+        // two hours
+        assert_eq!(
+            outage_of(&[http_check(0, false), http_check(7200, false)]),
+            Severity::Critical
+        );
+        // many checks in a short time
+        let burst: Vec<Check> = (0..5).map(|i| http_check(i, false)).collect();
+        assert_eq!(outage_of(&burst), Severity::Major);
+
+        // ongoing outages are measured until now
+        let two_hours_ago = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - 7200;
+        let ongoing = [http_check(two_hours_ago, false)];
+        let refs: Vec<&Check> = ongoing.iter().collect();
+        let outage = Outage::new(refs[0], None, &refs, &thresholds);
+        assert!(outage.duration() >= Duration::from_secs(7200));
+        assert_eq!(outage.severity(), Severity::Critical);
+        assert!(outage.to_string().contains("Severity: critical"));
+    }
+
+    #[test]
+    fn test_unmonitored_gaps() {
+        // the daemon was down for an hour during the outage
+        let mut store = Store::new();
+        for (secs, ok) in [
+            (0, true),
+            (60, false),
+            (120, false),
+            (3720, false),
+            (3780, false),
+            (3840, true),
+        ] {
+            store.add_check(http_check(secs, ok));
+        }
+        let mut thresholds = OutageConfig::default();
+        let outages = find_outages(&store, &thresholds);
+        assert_eq!(outages.len(), 1);
+        assert_eq!(outages[0].all.len(), 4);
+        assert!(outages[0].unmonitored().is_empty());
+
+        thresholds.unmonitored_after_secs = Some(120);
+        let outages = find_outages(&store, &thresholds);
+        assert_eq!(outages.len(), 1);
+        assert_eq!(outages[0].all.len(), 4);
+        assert_eq!(
+            outages[0].unmonitored(),
+            [UNIX_EPOCH + Duration::from_secs(120)..UNIX_EPOCH + Duration::from_secs(3720)]
+        );
+        assert!(outages[0]
+            .to_string()
+            .contains("Unmonitored: 1970-01-01T00:02:00Z to 1970-01-01T01:02:00Z"));
+    }
+
+    #[test]
+    fn test_min_failures() {
+        let mut thresholds = OutageConfig {
+            min_failures: 3,
+            ..Default::default()
+        };
+        // a lone failure, three in a row and another lone failure
+        let store = store_of(&[true, false, true, false, false, false, true, false, true]);
+        let outages = find_outages(&store, &thresholds);
+        assert_eq!(outages.len(), 1);
+        assert_eq!(outages[0].all.len(), 3);
+        assert_eq!(
+            outages[0].start.timestamp_parsed(),
+            UNIX_EPOCH + Duration::from_secs(180)
+        );
+        assert_eq!(transient_failures(&store, &thresholds), 2);
+
+        let mut report = String::new();
+        write_outages(
+            &store,
+            &outages,
+            2,
+            &thresholds,
+            &mut report,
+            Layout::default(),
+        )
+        .unwrap();
+        assert!(report.contains("total               : 1"));
+        assert!(report.contains("transient failures  : 2"));
+
+        // a lone ongoing failure is no outage yet
+        let store = store_of(&[true, true, false]);
+        assert!(ongoing_outages(&store, &thresholds).is_empty());
+        let store = store_of(&[true, false, false, false]);
+        assert_eq!(ongoing_outages(&store, &thresholds).len(), 1);
+
+        // every failure is an outage by default
+        thresholds.min_failures = 1;
+        let store = store_of(&[true, false, true, false, false, false, true, false, true]);
+        assert_eq!(find_outages(&store, &thresholds).len(), 3);
+        assert_eq!(transient_failures(&store, &thresholds), 0);
+    }
+
+    #[test]
+    fn test_outages_markdown() {
+        let mut store = store_of(&[true, false, false, true, false]);
+        let mut failed = http_check(300, false);
+        failed.add_detail(CheckDetail::Http { status: 503 });
+        store.add_check(failed);
+        let mut config = Config::default();
+        config.outages.min_failures = 2;
+
+        let table = outages_markdown_with(&store, &config);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            lines[0],
+            "| Start | End | Duration | Type | Target | Failure Reason |"
+        );
+        assert!(lines[1].starts_with("|---"));
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[2],
+            format!(
+                "| 1970-01-01T00:01:00Z | 1970-01-01T00:02:00Z | 1m | HTTP(S) | {} | no response |",
+                store.checks()[1].target()
+            )
+        );
+        assert!(lines[3].starts_with("| 1970-01-01T00:04:00Z | ongoing | "));
+        assert!(lines[3].ends_with(" | no response, HTTP status 503 |"));
+
+        let healthy = outages_markdown(&store_of(&[true, true]));
+        assert_eq!(healthy.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_correlated_outages() {
+        use flagset::FlagSet;
+
+        let check = |secs: u64, flags: FlagSet<CheckFlag>, target: &str| {
+            Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                flags,
+                flags.contains(CheckFlag::Success).then_some(12),
+                target.parse().unwrap(),
+            )
+        };
+        let http = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+        let icmp = CheckFlag::TypeIcmp | CheckFlag::IPv4;
+        let mut store = Store::new();
+        for cycle in 0..20 {
+            let secs = cycle * 60;
+            // minutes 5 to 7: everything fails, 15 to 16: only the ICMP checks of 9.9.9.9
+            let uplink_down = (5..8).contains(&cycle);
+            let remote_down = (15..17).contains(&cycle);
+            let ok = |down: bool, flags| match down {
+                true => flags,
+                false => flags | CheckFlag::Success,
+            };
+            store.add_check(check(secs, ok(uplink_down, http), "1.1.1.1"));
+            store.add_check(check(secs + 2, ok(uplink_down, http), "1.1.1.1"));
+            store.add_check(check(
+                secs + 4,
+                ok(uplink_down || remote_down, icmp),
+                "9.9.9.9",
+            ));
+        }
+        let thresholds = OutageConfig {
+            min_failures: 2,
+            ..Default::default()
+        };
+
+        let correlations = correlations(&store, &thresholds);
+        assert_eq!(correlations.len(), 2, "{correlations:?}");
+        let uplink = &correlations[0];
+        assert_eq!(uplink.diagnosis, Diagnosis::Upstream);
+        assert_eq!(uplink.outages, 2);
+        assert_eq!(uplink.start, UNIX_EPOCH + Duration::from_secs(300));
+        assert_eq!(uplink.end, Some(UNIX_EPOCH + Duration::from_secs(424)));
+        assert_eq!(
+            uplink.targets,
+            [
+                "1.1.1.1".parse::<IpAddr>().unwrap(),
+                "9.9.9.9".parse().unwrap()
+            ]
+        );
+
+        let remote = &correlations[1];
+        assert_eq!(remote.diagnosis, Diagnosis::Isolated);
+        assert_eq!(remote.outages, 1);
+        assert_eq!(remote.targets, ["9.9.9.9".parse::<IpAddr>().unwrap()]);
+        assert_eq!(remote.diagnosis.to_string(), "isolated");
+
+        let mut config = Config {
+            outages: thresholds,
+            ..Default::default()
+        };
+        config.report.skip_store_file = true;
+        let report = analyze_with(&store, &config).unwrap();
+        assert!(report.contains("likely local        : 1"), "{report}");
+        assert!(report.contains("isolated            : 1"), "{report}");
+        assert!(report.contains(
+            "local/upstream      : 1970-01-01T00:05:00Z to 1970-01-01T00:07:04Z, 2 targets"
+        ));
+    }
+
+    #[test]
+    fn test_outages_ical() {
+        let store = store_of(&[true, false, false, true, false]);
+        let ical = outages_ical(&store);
+        assert!(ical.ends_with("\r\n"));
+        let lines: Vec<&str> = ical.split("\r\n").filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines[0], "BEGIN:VCALENDAR");
+        assert_eq!(lines[1], "VERSION:2.0");
+        assert_eq!(*lines.last().unwrap(), "END:VCALENDAR");
+        assert_eq!(lines.iter().filter(|l| **l == "BEGIN:VEVENT").count(), 2);
+        assert_eq!(lines.iter().filter(|l| **l == "END:VEVENT").count(), 2);
+
+        let events: Vec<&str> = ical.split("BEGIN:VEVENT").skip(1).collect();
+        assert!(events[0].contains("DTSTART:19700101T000100Z\r\n"));
+        assert!(events[0].contains("DTEND:19700101T000200Z\r\n"));
+        assert!(events[0].contains(&format!(
+            "SUMMARY:HTTP(S) outage of {} (minor)\r\n",
+            store.checks()[1].target()
+        )));
+        assert!(events[0].contains("DESCRIPTION:2 failed checks: no response\r\n"));
+        // the ongoing outage has no end
+        assert!(events[1].contains("DTSTART:19700101T000400Z\r\n"));
+        assert!(!events[1].contains("DTEND"));
+        assert!(events[1].contains("SUMMARY:ongoing HTTP(S) outage"));
+
+        assert_eq!(ical_text("a, b; c"), "a\\, b\\; c");
+        assert!(lines.iter().all(|l| l.len() <= ICAL_LINE_OCTETS));
+
+        let short = "x".repeat(ICAL_LINE_OCTETS);
+        assert_eq!(ical_fold(&short), short);
+        let folded = ical_fold(&"x".repeat(2 * ICAL_LINE_OCTETS));
+        let parts: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0].len(), ICAL_LINE_OCTETS);
+        assert_eq!(parts[1], format!(" {}", "x".repeat(ICAL_LINE_OCTETS - 1)));
+        assert_eq!(parts[2], " x");
+        // unfolding gives back the line
+        assert_eq!(
+            folded.replace("\r\n ", ""),
+            "x".repeat(2 * ICAL_LINE_OCTETS)
+        );
+        // a character of several octets is not split
+        let folded = ical_fold(&format!("{}ü", "x".repeat(ICAL_LINE_OCTETS - 1)));
+        assert_eq!(
+            folded,
+            format!("{}\r\n ü", "x".repeat(ICAL_LINE_OCTETS - 1))
+        );
+        assert_eq!(
+            outages_ical(&store_of(&[true])).matches("VEVENT").count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_checks_per_hour() {
+        assert_eq!(checks_per_hour(&Store::new()), None);
+        assert_eq!(checks_per_hour(&store_of(&[true])), None);
+
+        // 121 checks, one per minute, over two hours
+        let store = store_of(&[true; 121]);
+        assert_eq!(checks_per_hour(&store), Some(60.5));
+    }
+
+    #[test]
+    fn test_maintenance_failures_are_no_outages() {
+        // failures at 01:00 (in the window) and at 02:00 (outside of it)
+        let mut store = Store::new();
+        for (secs, ok, maintenance) in [
+            (0, true, false),
+            (3600, false, true),
+            (3660, false, true),
+            (3720, true, true),
+            (7200, false, false),
+            (7260, true, false),
+            (7320, false, false),
+            (7380, true, false),
+        ] {
+            let mut check = http_check(secs, ok);
+            if maintenance {
+                check.add_detail(CheckDetail::Maintenance);
+            }
+            store.add_check(check);
+        }
+        let mut config = Config::default();
+        let starts = |config: &Config| -> Vec<u64> {
+            find_outages(&store, &config.outages)
+                .iter()
+                .map(|o| o.start.timestamp())
+                .collect()
+        };
+
+        assert!(!starts(&config).contains(&3600));
+        assert!(starts(&config).contains(&7200));
+        let mut report = String::new();
+        statistics(&store, &config, &Annotations::default(), &mut report).unwrap();
+        assert!(report.contains("maintenance, excluded: 00000002"));
+
+        config.outages.count_maintenance = true;
+        assert!(starts(&config).contains(&3600));
+
+        // an ongoing failure in the window is no ongoing outage
+        config.outages.count_maintenance = false;
+        let mut failing = http_check(7440, false);
+        failing.add_detail(CheckDetail::Maintenance);
+        store.add_check(failing);
+        assert!(ongoing_outages(&store, &config.outages).is_empty());
+    }
+
+    #[test]
+    fn test_compare_windows() {
+        const HOUR: u64 = 60 * 60;
+        const WEEK: u64 = 7 * 24 * HOUR;
+        let check = |secs: u64, ok: bool, latency: u16| {
+            let mut flags = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+            if ok {
+                flags |= CheckFlag::Success;
+            }
+            Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                flags,
+                ok.then_some(latency),
+                "1.1.1.1".parse().unwrap(),
+            )
+        };
+        // two weeks of hourly checks: all fine at 20ms last week, this week at 30ms with two
+        // outages of 3 and 5 checks
+        let mut store = Store::new();
+        for hour in 0..2 * WEEK / HOUR {
+            let secs = hour * HOUR;
+            let this_week = secs >= WEEK;
+            let failed = this_week && [10, 11, 12, 50, 51, 52, 53, 54].contains(&(hour % 168));
+            store.add_check(check(secs, !failed, if this_week { 30 } else { 20 }));
+        }
+        let end = UNIX_EPOCH + Duration::from_secs(2 * WEEK - 1);
+        let window = Duration::from_secs(WEEK);
+        let thresholds = OutageConfig::default();
+
+        let comparison = compare_windows(&store, window, end, &thresholds);
+        let previous = comparison.previous.as_ref().unwrap();
+        assert_eq!(previous.checks, 168);
+        assert_eq!(previous.success_ratio, Some(1.0));
+        assert_eq!(previous.mean_latency, Some(20.0));
+        assert_eq!(previous.outages, 0);
+        assert_eq!(comparison.current.checks, 168);
+        assert_eq!(comparison.current.mean_latency, Some(30.0));
+        assert_eq!(comparison.current.outages, 2);
+        assert!((comparison.ratio_delta().unwrap() + 8.0 / 168.0).abs() < 1e-9);
+        assert_eq!(comparison.latency_delta(), Some(10.0));
+        assert_eq!(comparison.outage_delta(), Some(2));
+        let shown = comparison.to_string();
+        let lines: Vec<&str> = shown.lines().map(str::trim_end).collect();
+        assert!(lines.contains(&"success ratio       : 100% -> 95.2% (-4.8 points)"));
+        assert!(lines.contains(&"mean latency        : 20.0ms -> 30.0ms (+10.0ms)"));
+        assert!(lines.contains(&"outages             : 0 -> 2 (+2)"));
+        assert!(lines.contains(&"window              : the last 7days until 1970-01-14T23:59:59Z"));
+        assert!(!shown.contains("insufficient baseline"));
+
+        // laid out like the report
+        let report = ReportConfig {
+            timezone: Timezone::Offset(2 * 60 * 60),
+            width: Some(60),
+            ..Default::default()
+        };
+        let shown = comparison.display_in(&report).to_string();
+        let checks = shown.lines().find(|l| l.starts_with("checks")).unwrap();
+        assert_eq!(checks.chars().count(), 60);
+        assert!(shown.contains(": the last 7days until 1970-01-15T01:59:59+02:00"));
+
+        // the store started two days before the end of last week
+        let late = store.filtered(|c| c.timestamp() >= WEEK - 2 * 24 * HOUR);
+        let comparison = compare_windows(&late, window, end, &thresholds);
+        assert_eq!(comparison.previous, None);
+        assert_eq!(comparison.ratio_delta(), None);
+        assert_eq!(comparison.current.outages, 2);
+        let shown = comparison.to_string();
+        assert!(shown.contains("insufficient baseline"));
+        assert!(shown
+            .lines()
+            .any(|line| line.trim_end() == "outages             : 2"));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = store_of(&[true, true, false, true, false]);
+        // the same checks, and four more with a new outage at 360s
+        let mut new = store_of(&[true, true, false, true, false, true, false, true, false]);
+        new.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(600),
+            CheckFlag::Success | CheckFlag::TypeUdp | CheckFlag::IPv4,
+            Some(12),
+            "1.1.1.1".parse().unwrap(),
+        ));
+
+        let diff = diff(&old, &new);
+        assert_eq!(diff.added, 5);
+        assert_eq!(diff.removed, 0);
+        assert!(!diff.disjoint);
+        assert_eq!(
+            diff.types,
+            [
+                TypeDiff {
+                    check_type: CheckType::Http,
+                    old_ratio: Some(3.0 / 5.0),
+                    new_ratio: Some(5.0 / 9.0),
+                },
+                TypeDiff {
+                    check_type: CheckType::Udp,
+                    old_ratio: None,
+                    new_ratio: Some(1.0),
+                }
+            ]
+        );
+        assert!((diff.types[0].delta().unwrap() - (5.0 / 9.0 - 0.6)).abs() < 1e-9);
+        assert_eq!(diff.types[1].delta(), None);
+        // the outage at 120s is in both stores
+        let starts: Vec<SystemTime> = diff
+            .new_outages
+            .iter()
+            .map(|o| o.start.timestamp_parsed())
+            .collect();
+        assert!(starts.contains(&(UNIX_EPOCH + Duration::from_secs(360))));
+        assert!(!starts.contains(&(UNIX_EPOCH + Duration::from_secs(120))));
+        let shown = diff.to_string();
+        assert!(shown.contains("HTTP(S)             : 60% -> 55.6% (-4.4 points)"));
+        assert!(shown.contains("UDP                 : - -> 100%"));
+
+        // a store of a later period
+        let mut later = Store::new();
+        later.add_check(http_check(3600, true));
+        let diff = super::diff(&old, &later);
+        assert!(diff.disjoint);
+        assert_eq!(diff.added, 1);
+        assert_eq!(diff.removed, 5);
+        assert!(diff.to_string().starts_with("WARNING"));
+    }
+
+    #[test]
+    fn test_skewed_outage() {
+        // the clock was set back by an hour during the outage
+        let checks = [http_check(7200, false), http_check(3600, false)];
+        let refs: Vec<&Check> = checks.iter().collect();
+        let outage = Outage::new(refs[0], Some(refs[1]), &refs, &OutageConfig::default());
+        assert!(outage.is_skewed());
+        assert_eq!(outage.duration(), Duration::ZERO);
+        assert_eq!(outage.severity(), Severity::Minor);
+        let shown = outage.to_string();
+        assert!(shown.contains("Duration: <unknown due to clock skew>"));
+        assert!(shown.starts_with("From 1970-01-01T02:00:00Z To 1970-01-01T01:00:00Z\n"));
+
+        let checks = [http_check(3600, false), http_check(7200, false)];
+        let refs: Vec<&Check> = checks.iter().collect();
+        let outage = Outage::new(refs[0], Some(refs[1]), &refs, &OutageConfig::default());
+        assert!(!outage.is_skewed());
+        assert!(outage.to_string().contains("Duration: 1h"));
+    }
+
+    #[test]
+    fn test_annotated_outage() {
+        let store = store_of(&[true, false, false, true, false, true]);
+        let mut annotations = Annotations::default();
+        annotations.add(UNIX_EPOCH + Duration::from_secs(60), "planned maintenance");
+        let mut config = Config::default();
+
+        let outages = find_outages(&store, &config.outages);
+        let mut outage = outages[0].clone();
+        assert_eq!(outage.annotation(), None);
+        outage.annotate(&annotations);
+        assert_eq!(outage.annotation(), Some("planned maintenance"));
+        assert!(outage.to_string().contains("Note: planned maintenance"));
+
+        // the outage is labeled, but still counts
+        let mut report = String::new();
+        statistics(&store, &config, &annotations, &mut report).unwrap();
+        assert_eq!(report.matches("Note: planned maintenance").count(), 1);
+        assert!(report.contains("success ratio       : 50.00%"));
+        assert!(!report.contains("annotated, excluded"));
+
+        // the two checks of the annotated outage are left out, the other failure counts
+        config.report.exclude_annotated = true;
+        let mut report = String::new();
+        statistics(&store, &config, &annotations, &mut report).unwrap();
+        assert_eq!(report.matches("Note: planned maintenance").count(), 1);
+        assert!(report.contains("annotated, excluded : 00000002"));
+        assert!(report.contains("success ratio       : 75.00%"));
+        assert!(!report.contains("success ratio       : 50.00%"));
+    }
+
+    #[test]
+    fn test_analyze_by_tag() {
+        let mut store = Store::new();
+        for (idx, (region, ok)) in [
+            (Some("eu"), true),
+            (Some("us"), false),
+            (Some("eu"), false),
+            (None, true),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut check = http_check(idx as u64 * 60, ok);
+            if let Some(region) = region {
+                check.set_tag("region", region);
+            }
+            check.set_tag("tier", "prod");
+            store.add_check(check);
+        }
+
+        let report = analyze_by_tag(&store, "region").unwrap();
+        // (section title, count lines of the section)
+        let mut sections: Vec<(&str, Vec<&str>)> = Vec::new();
+        for line in report.lines() {
+            if line.starts_with("==========") {
+                sections.push((line.trim_matches(['=', ' ']), Vec::new()));
+            } else if line.starts_with("checks") {
+                sections.last_mut().unwrap().1.push(line.trim_end());
+            }
+        }
+        assert_eq!(
+            sections,
+            [
+                (
+                    "region=eu",
+                    vec![
+                        "checks              : 00000002",
+                        "checks ok           : 00000001",
+                        "checks bad          : 00000001",
+                    ]
+                ),
+                (
+                    "region=us",
+                    vec![
+                        "checks              : 00000001",
+                        "checks ok           : 00000000",
+                        "checks bad          : 00000001",
+                    ]
+                ),
+                (
+                    "untagged",
+                    vec![
+                        "checks              : 00000001",
+                        "checks ok           : 00000001",
+                        "checks bad          : 00000000",
+                    ]
+                ),
+            ]
+        );
+
+        // every check has this tag, the untagged section is empty
+        let report = analyze_by_tag(&store, "tier").unwrap();
+        assert!(report.contains(" tier=prod "));
+        assert!(report.trim_end().ends_with("None"));
+    }
+
+    #[test]
+    fn test_filter_targets() {
+        let mut store = store_of(&[true, false]);
+        let mut v6 = http_check(120, true);
+        v6.set_target("2606:4700:4700::1111".parse().unwrap());
+        store.add_check(v6);
+        let mut resolved = http_check(180, true);
+        resolved.set_target("93.184.215.14".parse().unwrap());
+        resolved.add_detail(CheckDetail::Resolved {
+            host: "example.com".to_string(),
+        });
+        store.add_check(resolved);
+
+        let targets_of = |pattern: &str| -> Vec<String> {
+            filter_targets(&store, pattern)
+                .unwrap()
+                .checks()
+                .iter()
+                .map(|c| c.target().to_string())
+                .collect()
+        };
+        assert_eq!(targets_of("1.1.1"), ["1.1.1.1", "1.1.1.1"]);
+        assert_eq!(targets_of("2606:*"), ["2606:4700:4700::1111"]);
+        assert_eq!(targets_of("*.com"), ["93.184.215.14"]);
+        assert_eq!(targets_of("?.?.?.?"), ["1.1.1.1", "1.1.1.1"]);
+        assert_eq!(
+            targets_of("*1*1*"),
+            [
+                "1.1.1.1",
+                "1.1.1.1",
+                "2606:4700:4700::1111",
+                "93.184.215.14"
+            ]
+        );
+
+        let mut report = String::new();
+        targets(
+            &filter_targets(&store, "example").unwrap(),
+            &mut report,
+            Layout::default(),
+        )
+        .unwrap();
+        assert!(report.contains("93.184.215.14"));
+        assert!(!report.contains("1.1.1.1"));
+
+        // globs match the whole target
+        assert!(matches!(
+            filter_targets(&store, "1.1.*.2"),
+            Err(AnalysisError::NoMatchingTargets(pattern)) if pattern == "1.1.*.2"
+        ));
+        assert!(filter_targets(&store, "10.0.0.1").is_err());
+    }
+
+    #[test]
+    fn test_fail_groups_by_seq() {
+        // the clock jumped back by an hour after the second check
+        let times = [7200, 7260, 3600, 3660, 3720, 3780];
+        let results = [false, false, false, true, false, true];
+        let mut store = Store::new();
+        for (secs, ok) in times.into_iter().zip(results) {
+            store.add_check(http_check(secs, ok));
+        }
+        let seqs: Vec<u64> = store.checks().iter().map(|c| c.seq()).collect();
+        assert_eq!(seqs, [1, 2, 3, 4, 5, 6]);
+
+        // shuffled, like after merging stores
+        let mut checks: Vec<&Check> = store.checks().iter().collect();
+        checks.reverse();
+        checks.swap(0, 3);
+        let refs: Vec<&&Check> = checks.iter().collect();
+
+        let groups: Vec<Vec<u64>> = fail_groups(&refs)
+            .iter()
+            .map(|group| group.iter().map(|c| c.seq()).collect())
+            .collect();
+        assert_eq!(groups, [vec![1, 2, 3], vec![5]]);
+    }
+
+    #[test]
+    fn test_trailing_fail_group() {
+        let store = store_of(&[true, false, true, false, false, false]);
+        let checks: Vec<&Check> = store.checks().iter().collect();
+        let refs: Vec<&&Check> = checks.iter().collect();
+        let groups: Vec<Vec<u64>> = fail_groups(&refs)
+            .iter()
+            .map(|group| group.iter().map(|c| c.seq()).collect())
+            .collect();
+        assert_eq!(groups, [vec![2], vec![4, 5, 6]]);
+
+        let outages = find_outages(&store, &OutageConfig::default());
+        assert_eq!(outages.len(), 2);
+        assert_eq!(outages[1].all.len(), 3);
+    }
+
+    #[test]
+    fn test_report_timezone() {
+        let checks = [http_check(0, false), http_check(120, false)];
+        let refs: Vec<&Check> = checks.iter().collect();
+        let outage = Outage::new(refs[0], Some(refs[1]), &refs, &OutageConfig::default());
+        let plus_two = Timezone::Offset(2 * 3600);
+
+        assert!(outage
+            .to_string()
+            .starts_with("From 1970-01-01T00:00:00Z To 1970-01-01T00:02:00Z\n"));
+        assert_eq!(
+            outage.display_in(Timezone::Utc).to_string(),
+            outage.to_string()
+        );
+        assert!(outage
+            .display_in(plus_two)
+            .to_string()
+            .starts_with("From 1970-01-01T02:00:00+02:00 To 1970-01-01T02:02:00+02:00\n"));
+
+        let store = store_of(&[false, false]);
+        let mut report = String::new();
+        generalized(
+            &store,
+            availability(&store, &OutageConfig::default()),
+            &mut report,
+            Layout {
+                timezone: plus_two,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(report.contains("first check at      : 1970-01-01T02:00:00+02:00"));
+        assert!(report.contains("last check at       : 1970-01-01T02:01:00+02:00"));
+    }
+
+    #[test]
+    fn test_ongoing_outage_in_report() {
+        let store = store_of(&[true, false, false, true, false, false]);
+        let mut report = String::new();
+        statistics(
+            &store,
+            &Config::default(),
+            &Annotations::default(),
+            &mut report,
+        )
+        .unwrap();
+        assert!(report.contains("From 1970-01-01T00:01:00Z To 1970-01-01T00:02:00Z\n"));
+        // the failures reach the newest check
+        assert!(report.contains("From 1970-01-01T00:04:00Z STILL ONGOING\n"));
+        assert!(!report.contains("To 1970-01-01T00:05:00Z"));
+    }
+
+    #[test]
+    fn test_ongoing_severity_in_report() {
+        // two failed checks a minute apart each, but the second outage is still going on
+        let store = store_of(&[true, false, false, true, false, false]);
+        let mut report = String::new();
+        statistics(
+            &store,
+            &Config::default(),
+            &Annotations::default(),
+            &mut report,
+        )
+        .unwrap();
+        assert!(report.contains("minor               : 1 "));
+        assert!(report.contains("major               : 0 "));
+        assert!(report.contains("critical            : 1 "));
+        let (past, ongoing) = report.split_once("STILL ONGOING").unwrap();
+        assert!(past.contains("Severity: minor\n"));
+        // the time elapsed since 1970 is far above critical_after_secs
+        assert!(ongoing.contains("Severity: critical\n"));
+        assert!(!ongoing.contains("Severity: minor\n"));
+    }
+
+    /// Trims the padding of the report lines, to compare them against expected output
+    fn section(store: &Store, predicate: impl Fn(&Check) -> bool, label: &str) -> Vec<String> {
+        let mut f = String::new();
+        analyze_subset(store, &mut f, predicate, label, None, Layout::default()).unwrap();
+        f.lines().map(|l| l.trim_end().to_string()).collect()
+    }
+
+    #[test]
+    fn test_http_phases() {
+        let mut store = store_of(&[true, false]);
+        let mut f = String::new();
+        http_phases(&store, &mut f, Layout::default()).unwrap();
+        assert!(f.is_empty());
+
+        for (secs, reused) in [(120, false), (180, true)] {
+            let mut check = http_check(secs, true);
+            check.add_detail(CheckDetail::HttpPhases {
+                dns: if reused { 0 } else { 1000 },
+                connect: if reused { 0 } else { 3000 },
+                tls: if reused { 0 } else { 20_000 },
+                ttfb: 40_000,
+                total: if reused { 41_000 } else { 65_000 },
+                reused,
+            });
+            store.add_check(check);
+        }
+        http_phases(&store, &mut f, Layout::default()).unwrap();
+        let lines: Vec<&str> = f.lines().map(str::trim_end).collect();
+        assert_eq!(
+            lines,
+            [
+                "timed checks        : 00000002, 1 on reused connections",
+                "mean DNS            : 0.50ms",
+                "mean connect        : 1.50ms",
+                "mean TLS            : 10.00ms",
+                "mean TTFB           : 40.00ms",
+                "mean total          : 53.00ms",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_analyze_subset() {
+        let mut store = store_of(&[true, false, true]);
+        let mut v6 = Check::new(
+            UNIX_EPOCH + Duration::from_secs(600),
+            CheckFlag::TypeIcmp | CheckFlag::IPv6,
+            None,
+            "2606:4700:4700::1111".parse().unwrap(),
+        );
+        v6.add_flag(CheckFlag::Success);
+        store.add_check(v6);
+
+        let http = section(&store, |c| is_type(c, CheckType::Http), "HTTP");
+        assert_eq!(
+            http[..8],
+            [
+                format!("{:=<10}{:=<90}", "", " HTTP "),
+                "checks              : 00000003".to_string(),
+                "checks ok           : 00000002".to_string(),
+                "checks bad          : 00000001".to_string(),
+                "success ratio       : 66.67%".to_string(),
+                "first check at      : 1970-01-01T00:00:00Z".to_string(),
+                "last check at       : 1970-01-01T00:02:00Z".to_string(),
+                String::new(),
+            ]
+        );
+
+        let icmp = section(&store, |c| is_type(c, CheckType::IcmpV6), "ICMPv6");
+        assert_eq!(icmp[1], "checks              : 00000001");
+        assert_eq!(icmp[4], "success ratio       : 100.00%");
+        assert_eq!(
+            section(&store, |c| is_type(c, CheckType::IcmpV4), "ICMPv4")[1],
+            "None"
+        );
+
+        // the IPv6 section used to show the IPv4 checks
+        assert_eq!(
+            section(&store, |c| is_ip_type(c, CheckFlag::IPv6), "IPv6"),
+            icmp[1..].iter().cloned().fold(
+                vec![format!("{:=<10}{:=<90}", "", " IPv6 ")],
+                |mut v, l| {
+                    v.push(l);
+                    v
+                }
+            )
+        );
+        assert_eq!(
+            section(&store, |c| is_ip_type(c, CheckFlag::IPv4), "IPv4")[1..],
+            http[1..]
+        );
+    }
+
+    #[test]
+    fn test_tail() {
+        let store = store_of(&[true, false, true]);
+        let lines = |amount| {
+            tail(&store, amount, &Timezone::Utc)
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines(2),
+            [
+                "1970-01-01T00:01:00Z  HTTP(S)     1.1.1.1          failed  -",
+                "1970-01-01T00:02:00Z  HTTP(S)     1.1.1.1          ok      20 ms",
+            ]
+        );
+        // newest last, and all checks if there are fewer
+        let all = lines(20);
+        assert_eq!(all.len(), 3);
+        assert!(all[0].starts_with("1970-01-01T00:00:00Z "));
+        assert_eq!(all[1..], lines(2));
+        assert!(tail(&Store::new(), 20, &Timezone::Utc).is_empty());
+        assert!(tail(&store, 1, &Timezone::Offset(3600)).starts_with("1970-01-01T01:02:00+01:00 "));
+        // every type fits into its column
+        assert!(CheckType::all()
+            .iter()
+            .chain([&CheckType::Unknown])
+            .all(|t| t.to_string().len() <= TYPE_WIDTH));
+    }
+
+    #[test]
+    fn test_one_line() {
+        assert_eq!(one_line(&Store::new()), "netpulse: no data");
+
+        assert_eq!(
+            one_line(&store_of(&[true, true, true])),
+            "netpulse: 100% ok, 100% up | http 100% | no ongoing outage"
+        );
+
+        let mut store = store_of(&[true, true, false]);
+        for (secs, ok) in [(0, true), (60, true)] {
+            let mut flags = CheckFlag::TypeIcmp | CheckFlag::IPv4;
+            if ok {
+                flags |= CheckFlag::Success;
+            }
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                flags,
+                Some(5),
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+        assert_eq!(
+            one_line(&store),
+            "netpulse: 80% ok, 100% up | http 66.7% | icmp 100% | 1 ongoing outage"
+        );
+    }
+
+    #[test]
+    fn test_streamed_sections_match_report() {
+        let mut store = store_of(&[true, false, false, true, false, true]);
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            CheckFlag::TypeUdp | CheckFlag::IPv4,
+            None,
+            "1.1.1.1".parse().unwrap(),
+        ));
+        // an ongoing outage would last longer in the second report
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(120),
+            CheckFlag::TypeUdp | CheckFlag::IPv4 | CheckFlag::Success,
+            Some(5),
+            "1.1.1.1".parse().unwrap(),
+        ));
+        let config = Config::default();
+        let annotations = Annotations::default();
+
+        let mut buffered = String::new();
+        statistics(&store, &config, &annotations, &mut buffered).unwrap();
+
+        let mut streamed: Vec<u8> = Vec::new();
+        let mut sections = 0;
+        let mut section = String::new();
+        statistics_flushed(&store, &config, &annotations, &mut section, &mut |f| {
+            streamed.extend_from_slice(f.as_bytes());
+            f.clear();
+            sections += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert!(section.is_empty());
+        assert!(sections > 1);
+        assert_eq!(streamed, buffered.as_bytes());
+    }
+
+    #[test]
+    fn test_availability() {
+        assert_eq!(
+            availability(&store_of(&[true]), &OutageConfig::default()),
+            None
+        );
+
+        // the failures are half of the checks, but the outage between them lasts long
+        let mut store = Store::new();
+        for (secs, ok) in [
+            (0, true),
+            (60, false),
+            (600, false),
+            (660, true),
+            (720, true),
+            (780, false),
+        ] {
+            store.add_check(http_check(secs, ok));
+        }
+        let up = availability(&store, &OutageConfig::default()).unwrap();
+        assert!((up - 240.0 / 780.0).abs() < 1e-9);
+
+        let mut report = String::new();
+        generalized(
+            &store,
+            availability(&store, &OutageConfig::default()),
+            &mut report,
+            Layout::default(),
+        )
+        .unwrap();
+        assert!(report.contains("success ratio       : 50.00%"));
+        assert!(report.contains("availability        : 30.77%"));
+        assert_eq!(
+            one_line(&store),
+            "netpulse: 50% ok, 30.8% up | http 50% | 1 ongoing outage"
+        );
+
+        // overlapping outages of different types count once
+        for (secs, ok) in [
+            (0, true),
+            (300, false),
+            (700, false),
+            (900, true),
+            (950, false),
+        ] {
+            let mut flags = CheckFlag::TypeIcmp | CheckFlag::IPv4;
+            if ok {
+                flags |= CheckFlag::Success;
+            }
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                flags,
+                Some(5),
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+        let up = availability(&store, &OutageConfig::default()).unwrap();
+        // down from 60 to 700 and since 780
+        assert!((up - 140.0 / 950.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_unknown_section() {
+        let mut store = store_of(&[true]);
+        // no type flag at all
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            CheckFlag::IPv4,
+            None,
+            "1.1.1.1".parse().unwrap(),
+        ));
+        let mut report = String::new();
+        subsets(
+            &store,
+            &store,
+            &OutageConfig::default(),
+            &mut report,
+            Layout::default(),
+        )
+        .unwrap();
+        let unknown = report
+            .split_once(&format!("{:=<10}{:=<90}", "", " Unknown "))
+            .expect("the report has no Unknown section")
+            .1;
+        // the newline ending the barrier comes first
+        assert!(unknown
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("checks              : 00000001"));
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let latencies = [10, 12, 15, 19, 20, 25, 31, 39, 40, 40];
+        let histogram = latency_histogram(&latencies, 3);
+        assert_eq!(
+            histogram,
+            [(10..21, 5), (21..32, 2), (32..43, 3)],
+            "{histogram:?}"
+        );
+        assert_eq!(
+            histogram.iter().map(|(_, count)| count).sum::<usize>(),
+            latencies.len()
+        );
+        // buckets are contiguous
+        for pair in histogram.windows(2) {
+            assert_eq!(pair[0].0.end, pair[1].0.start);
+        }
+
+        // fewer distinct values than buckets
+        assert_eq!(latency_histogram(&[7, 7, 8], 8), [(7..8, 2), (8..9, 1)]);
+        assert!(latency_histogram(&[], 8).is_empty());
+        assert!(latency_histogram(&[1, 2], 0).is_empty());
+    }
+
+    #[test]
+    fn test_resolved_hosts() {
+        let mut store = store_of(&[true]);
+        for (target, ok) in [("10.0.0.1", true), ("10.0.0.2", false), ("10.0.0.1", true)] {
+            let mut check = http_check(60, ok);
+            check.set_target(target.parse().unwrap());
+            check.add_detail(CheckDetail::Resolved {
+                host: "example.com".to_string(),
+            });
+            store.add_check(check);
+        }
+
+        let hosts = resolved_hosts(&store);
+        assert_eq!(hosts.len(), 1);
+        let backends: Vec<(String, usize, usize)> = hosts["example.com"]
+            .iter()
+            .map(|r| (r.target.to_string(), r.checks, r.successes))
+            .collect();
+        assert_eq!(
+            backends,
+            [
+                ("10.0.0.1".to_string(), 2, 2),
+                ("10.0.0.2".to_string(), 1, 0)
+            ]
+        );
+        assert!(store.checks()[2].to_string().contains("Host: example.com"));
+
+        let mut report = String::new();
+        targets(&store, &mut report, Layout::default()).unwrap();
+        assert!(report.contains("example.com         : resolved to 10.0.0.2 (0.00% ok of 1 checks"));
+    }
+
+    #[test]
+    fn test_audit_counts() {
+        let mut store = Store::new();
+        // every minute for ten minutes, then nothing for ten minutes, then ten more minutes
+        for minute in (0..10).chain(20..30) {
+            store.add_check(http_check(minute * 60, true));
+        }
+        // a second target, without gaps
+        for minute in 0..30 {
+            let mut check = http_check(minute * 60, true);
+            check.set_target("10.0.0.1".parse().unwrap());
+            store.add_check(check);
+        }
+
+        let audits = audit_counts(&store, &Config::default());
+        assert_eq!(
+            audits,
+            [CountAudit {
+                check_type: CheckType::Http,
+                interval: Duration::from_secs(60),
+                expected: 60,
+                actual: 50,
+            }]
+        );
+        assert_eq!(audits[0].missing(), 10);
+        assert_eq!(
+            audits[0].to_string(),
+            "50 of 60 expected, 10 missing (16.7%)"
+        );
+
+        let mut report = String::new();
+        statistics(
+            &store,
+            &Config::default(),
+            &Annotations::default(),
+            &mut report,
+        )
+        .unwrap();
+        assert!(report
+            .contains("HTTP(S)             : 50 of 60 expected, 10 missing (16.7%), every 60s"));
+        assert!(audit_counts(&Store::new(), &Config::default()).is_empty());
+    }
+
+    #[test]
+    fn test_audit_counts_of_interval() {
+        let config = Config::from_toml("[checks.http]\ninterval_secs = 300").unwrap();
+        let mut store = Store::new();
+        // every five minutes for an hour, and a throughput check every hour
+        for minute in (0..60).step_by(5) {
+            store.add_check(http_check(minute * 60, true));
+        }
+        for hour in 0..3 {
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(hour * 3600),
+                CheckFlag::TypeThroughput | CheckFlag::Success | CheckFlag::IPv4,
+                Some(900),
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+
+        let audits = audit_counts(&store, &config);
+        assert_eq!(
+            audits,
+            [
+                CountAudit {
+                    check_type: CheckType::Http,
+                    interval: Duration::from_secs(300),
+                    expected: 12,
+                    actual: 12,
+                },
+                CountAudit {
+                    check_type: CheckType::Throughput,
+                    interval: Duration::from_secs(3600),
+                    expected: 3,
+                    actual: 3,
+                }
+            ]
+        );
+
+        let mut report = String::new();
+        statistics(&store, &config, &Annotations::default(), &mut report).unwrap();
+        assert!(report.contains("12 of 12 expected, 0 missing (0%), every 300s"));
+        assert!(report.contains("3 of 3 expected, 0 missing (0%), every 3600s"));
+    }
+
+    #[test]
+    fn test_latency_slos() {
+        assert_eq!(percentile(&[], 95), None);
+        assert_eq!(percentile(&[7], 50), Some(7));
+        let latencies: Vec<u64> = (1..=100).rev().collect();
+        assert_eq!(percentile(&latencies, 95), Some(95));
+        assert_eq!(percentile(&latencies, 100), Some(100));
+
+        // ICMPv4 mostly around 20ms, with a few slow ones; HTTP at 20ms
+        let mut store = store_of(&[true; 20]);
+        for (idx, latency) in (0..20).map(|i| if i < 2 { 90 } else { 20 }).enumerate() {
+            let check = Check::new(
+                UNIX_EPOCH + Duration::from_secs(idx as u64 * 60),
+                CheckFlag::Success | CheckFlag::TypeIcmp | CheckFlag::IPv4,
+                Some(latency),
+                "1.1.1.1".parse().unwrap(),
+            );
+            store.add_check(check);
+        }
+        let mut config = Config {
+            slo: vec![
+                LatencySlo {
+                    check_type: CheckType::Http,
+                    percentile: 95,
+                    max_ms: 50,
+                },
+                LatencySlo {
+                    check_type: CheckType::IcmpV4,
+                    percentile: 95,
+                    max_ms: 50,
+                },
+                LatencySlo {
+                    check_type: CheckType::Udp,
+                    percentile: 50,
+                    max_ms: 50,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let results = check_slos(&store, &config.slo);
+        assert_eq!(results[0].measured, Some(20));
+        assert!(results[0].met());
+        assert_eq!(results[1].measured, Some(90));
+        assert!(!results[1].met());
+        // no UDP checks at all
+        assert_eq!(results[2].measured, None);
+        assert!(results[2].met());
+
+        let mut report = String::new();
+        statistics(&store, &config, &Annotations::default(), &mut report).unwrap();
+        assert!(report.contains("HTTP(S) p95         : met: 20ms <= 50ms"));
+        assert!(report.contains("ICMPv4 p95          : BREACHED: 90ms > 50ms"));
+
+        assert_eq!(Health::of_store_with(&store, &config), Health::SloBreached);
+        assert_eq!(Health::SloBreached.exit_code(), 4);
+        config.slo.remove(1);
+        assert_eq!(Health::of_store_with(&store, &config), Health::Healthy);
+    }
+
+    #[test]
+    fn test_health_exit_codes() {
+        let healthy = store_of(&[true, false, true, true]);
+        assert_eq!(Health::of_store(&healthy), Health::Healthy);
+        assert_eq!(Health::of_store(&healthy).exit_code(), 0);
+
+        let outage = store_of(&[true, true, false, false]);
+        assert_eq!(Health::of_store(&outage), Health::Outage);
+        assert_eq!(Health::of_store(&outage).exit_code(), 1);
+        assert_eq!(
+            ongoing_outages(&outage, &OutageConfig::default())[0]
+                .all
+                .len(),
+            2
+        );
+
+        let unreadable = Health::of_load(&Err(StoreError::DoesNotExist));
+        assert_eq!(unreadable, Health::Unreadable);
+        assert_eq!(unreadable.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_critical_targets() {
+        // 10.0.0.1 is fine, 10.0.0.2 is down
+        let mut store = Store::new();
+        for (i, (target, ok)) in [
+            ("10.0.0.2", false),
+            ("10.0.0.2", false),
+            ("10.0.0.1", true),
+            ("10.0.0.2", false),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut check = http_check(i as u64 * 60, ok);
+            check.set_target(target.parse().unwrap());
+            store.add_check(check);
+        }
+        assert_eq!(Health::of_store(&store), Health::Outage);
+
+        let mut config = Config {
+            critical: vec!["10.0.0.1".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(Health::of_store_with(&store, &config), Health::Healthy);
+        assert_eq!(Health::of_store_with(&store, &config).exit_code(), 0);
+        // the outage of the non-critical target is still reported
+        let mut report = String::new();
+        statistics(&store, &config, &Annotations::default(), &mut report).unwrap();
+        assert!(report.contains("Severity: "));
+        assert!(!report.contains("Critical Target: yes"));
+
+        config.critical.push("10.0.0.2".to_string());
+        assert_eq!(Health::of_store_with(&store, &config), Health::Outage);
+        assert_eq!(Health::of_store_with(&store, &config).exit_code(), 1);
+        let mut report = String::new();
+        statistics(&store, &config, &Annotations::default(), &mut report).unwrap();
+        assert!(report.contains("Critical Target: yes"));
+    }
+
+    #[test]
+    fn test_asymmetric_families() {
+        let mut store = store_of(&[true]);
+        for (target, ok) in [
+            ("192.0.2.1", true),
+            ("2001:db8::1", false),
+            ("192.0.2.1", true),
+            ("2001:db8::1", false),
+        ] {
+            let target: IpAddr = target.parse().unwrap();
+            let mut check = http_check(60, ok);
+            if target.is_ipv6() {
+                *check.flags_mut() ^= CheckFlag::IPv4 | CheckFlag::IPv6;
+            }
+            check.set_target(target);
+            check.add_detail(CheckDetail::Resolved {
+                host: "example.com".to_string(),
+            });
+            store.add_check(check);
+        }
+
+        let reachability = family_reachability(&store);
+        let host = reachability
+            .iter()
+            .find(|r| r.target == "example.com")
+            .unwrap();
+        assert_eq!((host.v4_checks, host.v4_successes), (2, 2));
+        assert_eq!((host.v6_checks, host.v6_successes), (2, 0));
+        assert_eq!(host.failing(), Some(CheckFlag::IPv6));
+        // the plain target was only checked over IPv4
+        let plain = reachability.iter().find(|r| r.target == "1.1.1.1").unwrap();
+        assert_eq!(plain.failing(), None);
+
+        let mut report = String::new();
+        families(&store, &mut report, Layout::default()).unwrap();
+        assert!(report.contains("example.com"));
+        assert!(report.contains("IPv6 0.00% ok of 2 (IPv6 failing)"));
+        assert!(!report.contains("1.1.1.1"));
+    }
+
+    #[test]
+    fn test_report_width() {
+        let store = store_of(&[true, false, true]);
+        for width in [60, 120] {
+            let config = Config {
+                report: ReportConfig {
+                    width: Some(width),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut report = String::new();
+            statistics(&store, &config, &Annotations::default(), &mut report).unwrap();
+
+            let barriers: Vec<&str> = report.lines().filter(|l| l.starts_with("===")).collect();
+            assert!(!barriers.is_empty());
+            for barrier in barriers {
+                assert_eq!(barrier.chars().count(), width, "{barrier}");
+            }
+            let checks = report.lines().find(|l| l.starts_with("checks ")).unwrap();
+            assert_eq!(checks.chars().count(), width);
+        }
+
+        assert_eq!(report_width(Some(20), &ReportConfig::default()), MIN_WIDTH);
+        assert_eq!(
+            report_width(
+                None,
+                &ReportConfig {
+                    width: Some(120),
+                    ..Default::default()
+                }
+            ),
+            120
+        );
+    }
 }
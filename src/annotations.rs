@@ -0,0 +1,162 @@
+//! Notes on outages, like "planned maintenance".
+//!
+//! Annotations are kept in a sidecar file next to the store (`netpulse.annotations`), keyed by
+//! the start time of the outage they belong to, so the store itself stays untouched. The
+//! report shows them with the matching [Outages](crate::analyze::Outage), and with
+//! [exclude_annotated](crate::config::ReportConfig::exclude_annotated), the checks of annotated
+//! outages don't count against the success ratios.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use netpulse::annotations::{self, Annotations};
+//!
+//! let mut notes = Annotations::load().unwrap();
+//! notes.add(annotations::parse_start("2024-12-08T14:00:00Z").unwrap(), "planned maintenance");
+//! notes.save().unwrap();
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::AnnotationError;
+use crate::store::Store;
+
+/// Extension of the annotations file, which is named like the store file
+pub const EXTENSION: &str = "annotations";
+
+/// Notes on outages, keyed by the start of the outage in seconds since the unix epoch.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Annotations {
+    notes: BTreeMap<u64, String>,
+}
+
+impl Annotations {
+    /// Returns the path of the annotations file next to the [store file](Store::path).
+    pub fn path() -> PathBuf {
+        Self::path_of(&Store::path())
+    }
+
+    /// Returns the path of the annotations file next to the store file at `store_path`.
+    pub fn path_of(store_path: &Path) -> PathBuf {
+        store_path.with_extension(EXTENSION)
+    }
+
+    /// Loads the annotations from their [default path](Annotations::path).
+    ///
+    /// # Errors
+    ///
+    /// See [load_from](Annotations::load_from).
+    pub fn load() -> Result<Self, AnnotationError> {
+        Self::load_from(&Self::path())
+    }
+
+    /// Loads the annotations from the file at `path`.
+    ///
+    /// A missing file means that there are no annotations yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AnnotationError] if the file exists but can't be read or parsed.
+    pub fn load_from(path: &Path) -> Result<Self, AnnotationError> {
+        match fs::read_to_string(path) {
+            Ok(raw) => Ok(serde_json::from_str(&raw)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Saves the annotations to their [default path](Annotations::path).
+    ///
+    /// # Errors
+    ///
+    /// See [save_to](Annotations::save_to).
+    pub fn save(&self) -> Result<(), AnnotationError> {
+        self.save_to(&Self::path())
+    }
+
+    /// Saves the annotations to the file at `path`, replacing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AnnotationError] if the file can't be written.
+    pub fn save_to(&self, path: &Path) -> Result<(), AnnotationError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Annotates the outage starting at `start` with `note`, replacing an earlier note.
+    pub fn add(&mut self, start: SystemTime, note: impl Into<String>) {
+        self.notes.insert(secs(start), note.into());
+    }
+
+    /// Removes the note of the outage starting at `start`, returning it.
+    pub fn remove(&mut self, start: SystemTime) -> Option<String> {
+        self.notes.remove(&secs(start))
+    }
+
+    /// Returns the note of the outage starting at `start`.
+    pub fn get(&self, start: SystemTime) -> Option<&str> {
+        self.notes.get(&secs(start)).map(String::as_str)
+    }
+
+    /// Iterates over the outage starts and their notes, ordered by start.
+    pub fn iter(&self) -> impl Iterator<Item = (SystemTime, &str)> {
+        self.notes
+            .iter()
+            .map(|(start, note)| (UNIX_EPOCH + Duration::from_secs(*start), note.as_str()))
+    }
+
+    /// Returns whether there are no annotations.
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+}
+
+/// Parses the start of an outage, as shown in the report, like `2024-12-08T14:00:00Z`.
+///
+/// # Errors
+///
+/// Returns [AnnotationError::InvalidStart] if `raw` is not an RFC 3339 timestamp.
+pub fn parse_start(raw: &str) -> Result<SystemTime, AnnotationError> {
+    humantime::parse_rfc3339_weak(raw.trim())
+        .map_err(|_| AnnotationError::InvalidStart(raw.to_string()))
+}
+
+/// Seconds of `time` since the unix epoch, the precision of the check timestamps.
+fn secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_annotations_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Annotations::path_of(&dir.path().join("netpulse.store"));
+        assert_eq!(path, dir.path().join("netpulse.annotations"));
+        assert!(Annotations::load_from(&path).unwrap().is_empty());
+
+        let start = parse_start("2024-12-08T14:00:00Z").unwrap();
+        let mut notes = Annotations::default();
+        notes.add(start, "planned maintenance");
+        notes.save_to(&path).unwrap();
+
+        let notes = Annotations::load_from(&path).unwrap();
+        assert_eq!(notes.get(start), Some("planned maintenance"));
+        assert_eq!(notes.get(start + Duration::from_secs(1)), None);
+        assert_eq!(
+            notes.iter().collect::<Vec<_>>(),
+            [(start, "planned maintenance")]
+        );
+        assert!(parse_start("yesterday").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Exponential backoff for targets that keep failing.
+//!
+//! When a target is clearly down, checking it every period just fills the store with identical
+//! failures. With backoff [enabled](BackoffConfig::enabled), a target that failed
+//! [after_failures](BackoffConfig::after_failures) times in a row is only checked every second
+//! period, then every fourth period and so on, up to [max_factor](BackoffConfig::max_factor).
+//! The first success resets the interval.
+//!
+//! Checks that were made while a target was backed off have a
+//! [CheckDetail::Backoff](crate::records::CheckDetail::Backoff).
+//!
+//! The state is kept per [CheckType] and target, and only in memory.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use crate::config::BackoffConfig;
+use crate::records::{Check, CheckDetail, CheckType};
+
+/// Backoff state of a single target.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+struct TargetState {
+    /// Consecutive failed checks
+    failures: u32,
+    /// The target is checked every `factor` periods
+    factor: u32,
+    /// Periods since the target was last checked
+    waited: u32,
+}
+
+impl Default for TargetState {
+    fn default() -> Self {
+        Self {
+            failures: 0,
+            factor: 1,
+            waited: 0,
+        }
+    }
+}
+
+/// Decides which targets to check in a period, see the [module documentation](self).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Backoff {
+    /// Settings of the backoff
+    config: BackoffConfig,
+    /// State for each check type and target
+    states: HashMap<(CheckType, IpAddr), TargetState>,
+}
+
+impl Backoff {
+    /// Creates a new [Backoff] without any failures recorded.
+    pub fn new(config: &BackoffConfig) -> Self {
+        Self {
+            config: config.clone(),
+            states: HashMap::new(),
+        }
+    }
+
+    /// Returns whether `target` should be checked with `check_type` in this period.
+    ///
+    /// Must be called exactly once per period for each check type and target, since it counts
+    /// the periods.
+    pub fn due(&mut self, check_type: CheckType, target: IpAddr) -> bool {
+        let state = self.states.entry((check_type, target)).or_default();
+        state.waited += 1;
+        if state.waited >= state.factor {
+            state.waited = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the current interval of `target`, in periods.
+    pub fn factor(&self, check_type: CheckType, target: IpAddr) -> u32 {
+        self.states
+            .get(&(check_type, target))
+            .map_or(1, |state| state.factor)
+    }
+
+    /// Updates the state of the target of `check` with its result.
+    ///
+    /// If the target was backed off when the check was made, a [CheckDetail::Backoff] is added
+    /// to `check`.
+    pub fn record(&mut self, check: &mut Check) {
+        let check_type = check.calc_type().unwrap_or(CheckType::Unknown);
+        let state = self.states.entry((check_type, check.target())).or_default();
+        if state.factor > 1 {
+            check.add_detail(CheckDetail::Backoff {
+                factor: state.factor,
+            });
+        }
+
+        if check.is_success() {
+            *state = TargetState::default();
+            return;
+        }
+        state.failures = state.failures.saturating_add(1);
+        if self.config.enabled && state.failures >= self.config.after_failures {
+            state.factor = state
+                .factor
+                .saturating_mul(2)
+                .min(self.config.max_factor.max(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+
+    use crate::records::CheckFlag;
+
+    use super::*;
+
+    fn check(ok: bool) -> Check {
+        let mut flags = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+        if ok {
+            flags |= CheckFlag::Success;
+        }
+        Check::new(SystemTime::now(), flags, None, "10.0.0.1".parse().unwrap())
+    }
+
+    #[test]
+    fn test_backoff_grows_and_resets() {
+        let target: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut backoff = Backoff::new(&BackoffConfig {
+            enabled: true,
+            after_failures: 2,
+            max_factor: 4,
+        });
+
+        // drives the target through `periods` periods, failing or succeeding every check, and
+        // returns in which periods it was checked
+        let mut run = |periods: usize, ok: bool| -> Vec<usize> {
+            let mut checked = Vec::new();
+            for period in 0..periods {
+                if backoff.due(CheckType::Http, target) {
+                    backoff.record(&mut check(ok));
+                    checked.push(period);
+                }
+            }
+            checked
+        };
+
+        // after 2 failures every 2nd period, after the 3rd failure every 4th period (the cap)
+        assert_eq!(run(13, false), [0, 1, 3, 7, 11]);
+        // the next check is still backed off, but succeeds, after that every period again
+        assert_eq!(run(6, true), [2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_backoff_factor_and_detail() {
+        let target: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut backoff = Backoff::new(&BackoffConfig {
+            enabled: true,
+            after_failures: 1,
+            max_factor: 16,
+        });
+        let mut factors = Vec::new();
+        for _ in 0..6 {
+            backoff.record(&mut check(false));
+            factors.push(backoff.factor(CheckType::Http, target));
+        }
+        assert_eq!(factors, [2, 4, 8, 16, 16, 16]);
+
+        let mut failed = check(false);
+        backoff.record(&mut failed);
+        assert_eq!(failed.details(), [CheckDetail::Backoff { factor: 16 }]);
+
+        let mut ok = check(true);
+        backoff.record(&mut ok);
+        assert_eq!(backoff.factor(CheckType::Http, target), 1);
+        let mut next = check(true);
+        backoff.record(&mut next);
+        assert!(next.details().is_empty());
+    }
+
+    #[test]
+    fn test_backoff_disabled() {
+        let target: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut backoff = Backoff::new(&BackoffConfig::default());
+        for _ in 0..10 {
+            assert!(backoff.due(CheckType::Http, target));
+            backoff.record(&mut check(false));
+        }
+        assert_eq!(backoff.factor(CheckType::Http, target), 1);
+    }
+}
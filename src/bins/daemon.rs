@@ -2,14 +2,26 @@
 //!
 //! The daemon:
 //! - Loads or creates a [Store]
-//! - Runs checks every [period_seconds](netpulse::store::Store::period_seconds)
-//! - Handles graceful shutdown on SIGTERM
-//! - Maintains PID file at [DAEMON_PID_FILE]
+//! - Runs checks every [period_seconds](netpulse::store::Store::period_seconds), see [Schedule]
+//! - Handles graceful shutdown on SIGTERM and SIGINT
+//! - Maintains PID file at [pid_file](netpulse::pid_file)
+//! - Answers [control commands](netpulse::control) on
+//!   [control_socket](netpulse::control_socket)
+//! - Serves the [health endpoints](netpulse::health) over HTTP, if configured
+//! - Keeps the [recent checks](netpulse::recent) in memory, for the status of `netpulsed --info`
+//! - Sends outage events to the [syslog](netpulse::syslog), if enabled
+//!
+//! # Output
+//!
+//! Normally, the daemon writes human readable output to stdout. In the foreground mode
+//! (`--foreground`), stdout only gets one [CycleLog] JSON line per check cycle, everything else
+//! goes to stderr.
 //!
 //! # Signal Handling
 //!
 //! The daemon handles the following signals:
 //! - SIGTERM: Graceful shutdown, saves state and removes PID file
+//! - SIGINT: The same as SIGTERM, for Ctrl-C in the foreground
 //!
 //! # Cleanup
 //!
@@ -18,20 +30,88 @@
 //! 2. Removes its PID file
 //! 3. Logs any cleanup errors
 
+use std::fmt::Display;
 use std::sync::atomic::AtomicBool;
-use std::time::{self, Duration, UNIX_EPOCH};
+use std::sync::{Arc, Mutex};
+use std::time::{self, Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use netpulse::analyze::display_group;
+use netpulse::backoff::Backoff;
+use netpulse::cache::AnalysisCache;
+use netpulse::config::Config;
+use netpulse::control::ControlServer;
 use netpulse::errors::RunError;
-use netpulse::DAEMON_PID_FILE;
+use netpulse::logging::CycleLog;
+use netpulse::recent::RecentChecks;
+use netpulse::{control_socket, logging, pid_file};
 use nix::sys::signal::{self, SigHandler, Signal};
 
-use netpulse::store::Store;
+use netpulse::records::Check;
+use netpulse::store::{AutoSave, Store};
+use netpulse::syslog::{OutageTracker, Syslog};
 
-use crate::USES_DAEMON_SYSTEM;
+use crate::{JSON_LOG, USES_DAEMON_SYSTEM};
 
 static TERMINATE: AtomicBool = AtomicBool::new(false);
 
+/// Signals that make the daemon shut down gracefully, see [cleanup]
+const SHUTDOWN_SIGNALS: [Signal; 2] = [Signal::SIGTERM, Signal::SIGINT];
+
+/// How long the main loop sleeps at most, so that it notices a shutdown in time
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Deadlines of the check cycles.
+///
+/// The deadline of the `n`th cycle is `first + n * period`, no matter how long the cycles take,
+/// so the checks don't drift like they would when sleeping for the period after each cycle.
+/// Slots that have passed while a cycle was still running are skipped, see
+/// [advance](Schedule::advance).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Schedule {
+    /// Deadline of the first cycle
+    first: Instant,
+    period: Duration,
+    /// Number of the next cycle
+    cycle: u32,
+}
+
+impl Schedule {
+    /// Creates a schedule whose deadlines are at the multiples of `period` since the UNIX epoch,
+    /// with `now` being the wall clock time `wall`.
+    fn aligned(now: Instant, wall: SystemTime, period: Duration) -> Self {
+        let since_epoch = wall
+            .duration_since(UNIX_EPOCH)
+            .expect("time is before the UNIX_EPOCH");
+        let into_period = Duration::from_nanos((since_epoch.as_nanos() % period.as_nanos()) as u64);
+        let first = match into_period.is_zero() {
+            true => now,
+            false => now + (period - into_period),
+        };
+        Self {
+            first,
+            period,
+            cycle: 0,
+        }
+    }
+
+    /// Returns when the next cycle is due.
+    fn deadline(&self) -> Instant {
+        self.first + self.period * self.cycle
+    }
+
+    /// Moves on to the next cycle after a cycle was finished at `now`.
+    ///
+    /// If the cycle overran the slot of the next one, the schedule moves on to the first slot
+    /// after `now`. Returns how many slots were skipped.
+    fn advance(&mut self, now: Instant) -> u32 {
+        let elapsed = now.saturating_duration_since(self.first);
+        let next = (elapsed.as_nanos() / self.period.as_nanos()) as u32 + 1;
+        let skipped = next.saturating_sub(self.cycle + 1);
+        self.cycle = next.max(self.cycle + 1);
+        skipped
+    }
+}
+
 /// Main daemon process function.
 ///
 /// This function:
@@ -42,8 +122,18 @@ static TERMINATE: AtomicBool = AtomicBool::new(false);
 // TODO: better error handling, keep going even if everything goes boom
 pub(crate) fn daemon() {
     signal_hook();
-    println!("starting daemon...");
-    let mut store = match Store::load_or_create() {
+    info("starting daemon...");
+    let config = match Config::load() {
+        Err(e) => {
+            eprintln!("could not load the config: {e}");
+            if let Err(e) = cleanup_without_store() {
+                eprintln!("error while trying to cleanup: {e}");
+            }
+            std::process::exit(1)
+        }
+        Ok(c) => c,
+    };
+    let store = match Store::load_or_create_with(&config) {
         Err(e) => {
             eprintln!("{e}");
             if let Err(e) = cleanup_without_store() {
@@ -53,60 +143,183 @@ pub(crate) fn daemon() {
         }
         Ok(s) => s,
     };
-    println!("store loaded, entering main loop");
+    let mut backoff = Backoff::new(&config.backoff);
+    let mut events = match config.syslog.enabled {
+        false => None,
+        true => match Syslog::open(&config.syslog) {
+            Ok(syslog) => Some((syslog, OutageTracker::new())),
+            Err(err) => {
+                logging::error(format!("could not set up the syslog: {err}"));
+                None
+            }
+        },
+    };
+    let store = Arc::new(Mutex::new(store));
+    let recent = Arc::new(Mutex::new(RecentChecks::default()));
+    match ControlServer::bind(&control_socket()) {
+        Ok(server) => {
+            server
+                .with_cache(AnalysisCache::new(&Store::path(), config.clone()))
+                .with_recent(recent.clone())
+                .spawn(store.clone());
+        }
+        Err(err) => logging::error(format!(
+            "could not bind the control socket at {}: {err}",
+            control_socket().display()
+        )),
+    }
+    #[cfg(feature = "health-http")]
+    let metrics = match config.health.listen {
+        None => None,
+        Some(addr) => match netpulse::health::HealthServer::bind(addr, &config.health) {
+            Ok(server) => {
+                let metrics = Arc::new(netpulse::health::Metrics::default());
+                metrics.update(&store.lock().expect("the store lock is poisoned"));
+                server
+                    .with_recent(recent.clone())
+                    .with_metrics(metrics.clone())
+                    .spawn();
+                Some(metrics)
+            }
+            Err(err) => {
+                logging::error(format!(
+                    "could not bind the health endpoints at {addr}: {err}"
+                ));
+                None
+            }
+        },
+    };
+    info("store loaded, entering main loop");
+    let period = Duration::from_secs(
+        store
+            .lock()
+            .expect("the store lock is poisoned")
+            .period_seconds(),
+    );
+    let mut schedule = Schedule::aligned(Instant::now(), SystemTime::now(), period);
+    let mut autosave = AutoSave::new(&config.store, Instant::now());
     loop {
+        // the control socket may only see the store between check cycles
+        let mut store = store.lock().expect("the store lock is poisoned");
         if TERMINATE.load(std::sync::atomic::Ordering::Relaxed) {
-            println!("terminating the daemon");
-            if let Err(e) = cleanup(&store) {
+            info("terminating the daemon");
+            if let Err(e) = cleanup(&store, &config) {
                 eprintln!("could not clean up before terminating: {e:#?}");
             }
             std::process::exit(1);
         }
-        let time = time::SystemTime::now();
-        if time
-            .duration_since(UNIX_EPOCH)
-            .expect("time is before the UNIX_EPOCH")
-            .as_secs()
-            % store.period_seconds()
-            == 0
-        {
-            if let Err(err) = wakeup(&mut store) {
-                eprintln!("error in the wakeup turn: {err}");
+        if Instant::now() >= schedule.deadline() {
+            if let Err(err) = wakeup(
+                &mut store,
+                &config,
+                &mut backoff,
+                &mut autosave,
+                events.as_mut(),
+                &recent,
+            ) {
+                logging::error(format!("error in the wakeup turn: {err}"));
+            }
+            #[cfg(feature = "health-http")]
+            if let Some(metrics) = &metrics {
+                metrics.update(&store);
+            }
+            let skipped = schedule.advance(Instant::now());
+            if skipped > 0 {
+                logging::warn(format!(
+                    "the check cycle took longer than the period of {}s, skipping {skipped} cycle(s)",
+                    period.as_secs()
+                ));
             }
         }
-        std::thread::sleep(Duration::from_secs(1));
+        drop(store);
+        std::thread::sleep(
+            schedule
+                .deadline()
+                .saturating_duration_since(Instant::now())
+                .min(POLL_INTERVAL),
+        );
     }
 }
 
 /// Run a check iteration and update store.
 ///
 /// Called periodically by the daemon main loop to:
-/// - Run configured checks
+/// - Run configured checks, saving the store in between as `autosave` says
+/// - Send outage events to the syslog
+/// - Remember the checks in `recent`
 /// - Save results to store
 /// - Handle any check errors
 ///
 /// # Errors
 ///
 /// Returns [DaemonError] if store operations fail.
-fn wakeup(store: &mut Store) -> Result<(), RunError> {
-    println!("waking up!");
+fn wakeup(
+    store: &mut Store,
+    config: &Config,
+    backoff: &mut Backoff,
+    autosave: &mut AutoSave,
+    events: Option<&mut (Syslog, OutageTracker)>,
+    recent: &Mutex<RecentChecks>,
+) -> Result<(), RunError> {
+    info("waking up!");
 
-    let mut buf = String::new();
-    display_group(&store.make_checks(), &mut buf)?;
-    println!("{buf}");
+    let start = time::SystemTime::now();
+    let timer = Instant::now();
+    let old_len = store.checks().len();
+    store.make_checks_saving(config, backoff, autosave);
+    let checks: Vec<&Check> = store.checks()[old_len..].iter().collect();
+    recent
+        .lock()
+        .expect("the recent checks lock is poisoned")
+        .push_cycle(&checks);
+    if JSON_LOG.load(std::sync::atomic::Ordering::Relaxed) {
+        println!(
+            "{}",
+            CycleLog::new(start, timer.elapsed(), &checks).to_json_line()
+        );
+    } else {
+        let mut buf = String::new();
+        display_group(&checks, &mut buf)?;
+        println!("{buf}");
+    }
+    if let Some((syslog, tracker)) = events {
+        for event in tracker.update(&checks) {
+            if let Err(err) = syslog.send_event(&event) {
+                logging::error(format!("could not send to the syslog: {err}"));
+            }
+        }
+    }
 
-    if let Err(err) = store.save() {
-        eprintln!("error while saving to file: {err:}");
+    if let Err(err) = store.save_with(config, &checks) {
+        logging::error(format!("error while saving to file: {err:}"));
+    } else {
+        autosave.saved(Instant::now());
     }
 
-    println!("done!");
+    info("done!");
     Ok(())
 }
 
+/// Prints an informational message, to stderr if stdout is reserved for the JSON log.
+///
+/// Nothing is printed if the [log level](logging::Level) is below `info`.
+fn info(msg: impl Display) {
+    if !logging::enabled(logging::Level::Info) {
+        return;
+    }
+    if JSON_LOG.load(std::sync::atomic::Ordering::Relaxed) {
+        eprintln!("{msg}");
+    } else {
+        println!("{msg}");
+    }
+}
+
 fn signal_hook() {
-    unsafe {
-        signal::signal(Signal::SIGTERM, SigHandler::Handler(handle_sigterm))
-            .expect("failed to set up signal handler");
+    for shutdown in SHUTDOWN_SIGNALS {
+        unsafe {
+            signal::signal(shutdown, SigHandler::Handler(handle_shutdown))
+                .expect("failed to set up signal handler");
+        }
     }
 }
 
@@ -119,8 +332,10 @@ fn signal_hook() {
 /// # Errors
 ///
 /// Returns [DaemonError] if cleanup operations fail.
-fn cleanup(store: &Store) -> Result<(), RunError> {
-    if let Err(err) = store.save() {
+fn cleanup(store: &Store, config: &Config) -> Result<(), RunError> {
+    logging::flush();
+    let all: Vec<&Check> = store.checks().iter().collect();
+    if let Err(err) = store.save_with(config, &all) {
         eprintln!("error while saving to file: {err:#?}");
         return Err(err.into());
     }
@@ -131,9 +346,15 @@ fn cleanup(store: &Store) -> Result<(), RunError> {
 }
 
 fn cleanup_without_store() -> Result<(), RunError> {
+    if let Err(err) = std::fs::remove_file(control_socket()) {
+        if !matches!(err.kind(), std::io::ErrorKind::NotFound) {
+            eprintln!("Failed to remove the control socket: {err}");
+        }
+    }
+
     // stuff we only need to do if it's a manual daemon
     if USES_DAEMON_SYSTEM.load(std::sync::atomic::Ordering::Relaxed) {
-        if let Err(err) = std::fs::remove_file(DAEMON_PID_FILE) {
+        if let Err(err) = std::fs::remove_file(pid_file()) {
             if matches!(err.kind(), std::io::ErrorKind::NotFound) {
                 // yeah, idk, ignore?
             } else {
@@ -146,7 +367,52 @@ fn cleanup_without_store() -> Result<(), RunError> {
     Ok(())
 }
 
-/// Signal handler for things like SIGTERM
-extern "C" fn handle_sigterm(_: i32) {
+/// Signal handler for the [SHUTDOWN_SIGNALS]
+extern "C" fn handle_shutdown(_: i32) {
     TERMINATE.store(true, std::sync::atomic::Ordering::Relaxed);
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+
+    #[test]
+    fn test_shutdown_signals_terminate() {
+        // the handler is called directly, installing it would affect the whole test process
+        assert_eq!(SHUTDOWN_SIGNALS, [Signal::SIGTERM, Signal::SIGINT]);
+        for shutdown in SHUTDOWN_SIGNALS {
+            TERMINATE.store(false, Ordering::Relaxed);
+            handle_shutdown(shutdown as i32);
+            assert!(
+                TERMINATE.load(Ordering::Relaxed),
+                "{shutdown} did not request the shutdown"
+            );
+        }
+        TERMINATE.store(false, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_schedule_stays_aligned() {
+        let period = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut schedule = Schedule::aligned(start, UNIX_EPOCH + Duration::from_secs(90), period);
+        // the first cycle is at the next full minute
+        assert_eq!(schedule.deadline(), start + Duration::from_secs(30));
+        let first = schedule.deadline();
+
+        // cycles of varying lengths don't move the deadlines
+        for (cycle, work) in [1, 5, 3, 59, 0].into_iter().enumerate() {
+            let done = schedule.deadline() + Duration::from_secs(work);
+            assert_eq!(schedule.advance(done), 0);
+            assert_eq!(schedule.deadline(), first + period * (cycle as u32 + 1));
+        }
+
+        // a cycle overrunning the next two slots skips them
+        let late = schedule.deadline() + Duration::from_secs(130);
+        assert_eq!(schedule.advance(late), 2);
+        assert_eq!(schedule.deadline(), first + period * 8);
+        assert!(schedule.deadline() > late);
+    }
+}
@@ -10,12 +10,37 @@
 //! Without options, displays analysis of stored check results.
 //!
 //! Use the `--help` flag for more information about the usage.
+//!
+//! # Exit Codes
+//!
+//! Every failure mode has its own code, so scripts can tell them apart:
+//!
+//! - `0` - Success, or a healthy connection with `--check-health`
+//! - `1` - Any other error, or an ongoing outage with `--check-health`
+//! - `2` - The store can't be read, with `--check-health`
+//! - `3` - There is no store yet, because the daemon never ran, see [EXIT_NO_STORE]
+//! - `4` - A latency objective is breached, with `--check-health`
+//!
+//! See [Health::exit_code] for the codes of `--check-health`.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 use getopts::Options;
-use netpulse::analyze::{self, display_group};
-use netpulse::errors::RunError;
-use netpulse::records::Check;
-use netpulse::store::Store;
+use netpulse::analyze::{self, display_group, Health};
+use netpulse::annotations::{self, Annotations};
+use netpulse::build_info::BuildInfo;
+use netpulse::config::Config;
+use netpulse::control;
+use netpulse::errors::{RunError, StoreError};
+use netpulse::export::{self, Format, TextReporter};
+use netpulse::records::{Check, CheckType};
+use netpulse::store::{Retention, Store};
+use netpulse::timezone::Timezone;
+use netpulse::{control_socket, running_daemon};
+
+/// Exit code of the report if there is no store at [Store::path]
+const EXIT_NO_STORE: i32 = 3;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
@@ -23,7 +48,157 @@ fn main() {
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this help menu");
     opts.optflag("V", "version", "print the version");
+    opts.optflag(
+        "",
+        "json",
+        "with --version: print the version, git commit, build time and features as JSON",
+    );
     opts.optflag("t", "test", "test run all checks");
+    opts.optopt(
+        "p",
+        "probe",
+        "check a single IP address or hostname once with all check types and print the results",
+        "TARGET",
+    );
+    opts.optflag(
+        "",
+        "save",
+        "with --probe: append the results to the store (stop the daemon first, it would overwrite them)",
+    );
+    opts.optopt(
+        "",
+        "type",
+        "with --test or --probe: only run checks of this type (http, icmpv4, icmpv6, udp)",
+        "TYPE",
+    );
+    opts.optopt(
+        "",
+        "timezone",
+        "show the timestamps of the report in this timezone (utc, local or an offset like +02:00)",
+        "TZ",
+    );
+    opts.optopt(
+        "",
+        "target",
+        "only analyze checks of targets matching PATTERN (a substring, or a glob with * and ?)",
+        "PATTERN",
+    );
+    opts.optopt(
+        "",
+        "store",
+        "analyze the store file at PATH instead of the one of the daemon, or read it from stdin with -",
+        "PATH",
+    );
+    opts.optflag(
+        "",
+        "demo",
+        "analyze a bundled demo store with outages and varied latencies, instead of the store of the daemon",
+    );
+    opts.optflag(
+        "",
+        "no-cache",
+        "load the store directly, instead of asking the running daemon for a cached report",
+    );
+    opts.optopt(
+        "",
+        "by-tag",
+        "show the statistics grouped by the values of the tag KEY",
+        "KEY",
+    );
+    opts.optopt(
+        "",
+        "annotate",
+        "annotate the outage starting at START (like 2024-12-08T14:00:00Z) with the text of --note",
+        "START",
+    );
+    opts.optopt(
+        "",
+        "note",
+        "with --annotate: the note for the outage",
+        "TEXT",
+    );
+    opts.optflag("", "annotations", "list the annotated outages");
+    opts.optopt(
+        "",
+        "inspect",
+        "show the check with the hash HASH, with its captured response",
+        "HASH",
+    );
+    opts.optflag(
+        "",
+        "exclude-annotated",
+        "leave the checks of annotated outages out of the success ratios",
+    );
+    opts.optflag(
+        "",
+        "gc",
+        "remove old checks from the store, the daemon has to be stopped",
+    );
+    opts.optopt(
+        "",
+        "keep-days",
+        "with --gc: remove the checks older than DAYS",
+        "DAYS",
+    );
+    opts.optopt(
+        "",
+        "max-count",
+        "with --gc: keep at most COUNT checks, the newest ones",
+        "COUNT",
+    );
+    opts.optflag(
+        "",
+        "explain",
+        "follow each figure of the report with how it was calculated",
+    );
+    opts.optflag(
+        "",
+        "diff",
+        "compare two store files given as OLD NEW: added checks, success ratios and new outages",
+    );
+    opts.optopt(
+        "",
+        "compare-window",
+        "compare the last WINDOW (like 7d or 24h) with the WINDOW before it: success ratio, latency and outages",
+        "WINDOW",
+    );
+    opts.optopt(
+        "w",
+        "width",
+        "width of the report in columns (default: the terminal width, at most 100)",
+        "COLUMNS",
+    );
+    opts.optopt(
+        "o",
+        "output",
+        "write the report to PATH instead of stdout, replacing the file",
+        "PATH",
+    );
+    opts.optopt(
+        "f",
+        "format",
+        &format!(
+            "format of the report: {} (default: text)",
+            Format::NAMES.join(", ")
+        ),
+        "FORMAT",
+    );
+    opts.optopt(
+        "",
+        "tail",
+        "print the last N checks with their time, type, target and result, newest last",
+        "N",
+    );
+    opts.optflag(
+        "1",
+        "one-line",
+        "print a single line summary, for status bars and the like",
+    );
+    opts.optflag(
+        "c",
+        "check-health",
+        "exit with 0 if the latest checks are healthy, 1 on an ongoing outage, 2 if the store can't be read, 3 if there is no store yet and 4 if a latency objective is breached",
+    );
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(f) => {
@@ -36,11 +211,230 @@ fn main() {
     if matches.opt_present("help") {
         print_usage(program, opts);
     } else if matches.opt_present("version") {
-        println!("{} {}", env!("CARGO_BIN_NAME"), env!("CARGO_PKG_VERSION"))
+        if matches.opt_present("json") {
+            println!("{}", BuildInfo::current().to_json())
+        } else {
+            println!("{} {}", env!("CARGO_BIN_NAME"), env!("CARGO_PKG_VERSION"))
+        }
     } else if matches.opt_present("test") {
-        test_checks();
+        if let Err(e) = test_checks(type_opt(&matches)) {
+            eprintln!("Error while running the checks: {e}");
+            std::process::exit(1);
+        }
+    } else if let Some(target) = matches.opt_str("probe") {
+        if let Err(e) = probe(&target, type_opt(&matches), matches.opt_present("save")) {
+            eprintln!("Error while probing {target}: {e}");
+            std::process::exit(1);
+        }
+    } else if let Some(start) = matches.opt_str("annotate") {
+        let Some(note) = matches.opt_str("note") else {
+            eprintln!("--annotate needs a --note");
+            std::process::exit(1)
+        };
+        if let Err(e) = annotate(&start, &note) {
+            eprintln!("Could not annotate the outage: {e}");
+            std::process::exit(1);
+        }
+    } else if matches.opt_present("annotations") {
+        if let Err(e) = list_annotations() {
+            eprintln!("Could not list the annotations: {e}");
+            std::process::exit(1);
+        }
+    } else if let Some(hash) = matches.opt_str("inspect") {
+        if let Err(e) = inspect(&hash) {
+            eprintln!("Could not inspect the check: {e}");
+            std::process::exit(1);
+        }
+    } else if matches.opt_present("gc") {
+        if let Err(e) = gc(&retention_opt(&matches)) {
+            eprintln!("Could not remove the old checks: {e}");
+            std::process::exit(1);
+        }
+    } else if matches.opt_present("diff") {
+        let [old, new] = matches.free.as_slice() else {
+            eprintln!("--diff needs two store files: OLD NEW");
+            std::process::exit(1)
+        };
+        if let Err(e) = diff(Path::new(old), Path::new(new)) {
+            eprintln!("Could not compare the stores: {e}");
+            std::process::exit(1);
+        }
+    } else if let Some(window) = matches.opt_str("compare-window") {
+        let window = match humantime::parse_duration(&window) {
+            Ok(window) if !window.is_zero() => window,
+            Ok(_) => {
+                eprintln!("invalid --compare-window: the window must not be empty");
+                std::process::exit(1)
+            }
+            Err(e) => {
+                eprintln!("invalid --compare-window: {e}");
+                std::process::exit(1)
+            }
+        };
+        if let Err(e) = compare_window(window) {
+            eprintln!("Could not compare the windows: {e}");
+            std::process::exit(1);
+        }
+    } else if matches.opt_present("check-health") {
+        check_health();
+    } else if let Some(amount) = matches.opt_str("tail") {
+        let amount = match amount.parse::<usize>() {
+            Ok(amount) => amount,
+            Err(e) => {
+                eprintln!("invalid --tail: {e}");
+                std::process::exit(1)
+            }
+        };
+        tail(amount, timezone_opt(&matches));
+    } else if matches.opt_present("one-line") {
+        one_line();
     } else {
-        analysis();
+        let source = match matches.opt_str("store") {
+            _ if matches.opt_present("demo") => Source::Demo,
+            Some(path) if path == "-" => Source::Stdin,
+            Some(path) => {
+                std::env::set_var(netpulse::store::ENV_PATH, path);
+                Source::StoreFile
+            }
+            None => Source::StoreFile,
+        };
+        let timezone = timezone_opt(&matches);
+        let tag = matches.opt_str("by-tag");
+        let target = matches.opt_str("target");
+        let exclude_annotated = matches.opt_present("exclude-annotated");
+        let explain = matches.opt_present("explain");
+        let output = matches.opt_str("output").map(PathBuf::from);
+        let format = format_opt(&matches);
+        let width = width_opt(&matches);
+        // the daemon only knows the plain report
+        let plain = timezone.is_none()
+            && !matches.opt_present("store")
+            && source == Source::StoreFile
+            && tag.is_none()
+            && target.is_none()
+            && !exclude_annotated
+            && !explain
+            && format == Format::Text
+            && analyze::report_width(width, &Default::default()) == analyze::DEFAULT_WIDTH;
+        if plain && !matches.opt_present("no-cache") {
+            if let Ok(report) = control::request_report(&control_socket()) {
+                write_report(output.as_deref(), &format!("{report}\n"));
+                return;
+            }
+        }
+        if tag.is_some() && format != Format::Text {
+            eprintln!("--by-tag only works with the text format");
+            std::process::exit(1)
+        }
+        let mut config = match Config::load() {
+            Err(e) => {
+                eprintln!("The config could not be loaded: {e}");
+                std::process::exit(1)
+            }
+            Ok(c) => c,
+        };
+        if let Some(timezone) = timezone {
+            config.report.timezone = timezone;
+        }
+        if exclude_annotated {
+            config.report.exclude_annotated = true;
+        }
+        if explain {
+            config.report.explain = true;
+        }
+        config.report.width = Some(analyze::report_width(width, &config.report));
+        if source != Source::StoreFile {
+            config.report.skip_store_file = true;
+        }
+        analysis(config, tag, target, format, output.as_deref(), source);
+    }
+}
+
+/// Parses the `--timezone` option, exiting on an invalid value.
+fn timezone_opt(matches: &getopts::Matches) -> Option<Timezone> {
+    match matches.opt_str("timezone").map(|t| t.parse::<Timezone>()) {
+        None => None,
+        Some(Ok(t)) => Some(t),
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            std::process::exit(1)
+        }
+    }
+}
+
+/// Parses the `--format` option, exiting on an invalid value.
+fn format_opt(matches: &getopts::Matches) -> Format {
+    match matches.opt_str("format").map(|f| f.parse::<Format>()) {
+        None => Format::default(),
+        Some(Ok(f)) => f,
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            std::process::exit(1)
+        }
+    }
+}
+
+/// Parses the `--width` option, exiting on an invalid value.
+fn width_opt(matches: &getopts::Matches) -> Option<usize> {
+    match matches.opt_str("width").map(|w| w.parse::<usize>()) {
+        None => None,
+        Some(Ok(w)) => Some(w),
+        Some(Err(e)) => {
+            eprintln!("invalid width: {e}");
+            std::process::exit(1)
+        }
+    }
+}
+
+/// Reads the limits of `--gc`, exiting if they are invalid or missing.
+fn retention_opt(matches: &getopts::Matches) -> Retention {
+    let number = |name: &str| match matches.opt_str(name).map(|n| n.parse::<u64>()) {
+        None => None,
+        Some(Ok(n)) => Some(n),
+        Some(Err(e)) => {
+            eprintln!("invalid --{name}: {e}");
+            std::process::exit(1)
+        }
+    };
+    let max_age = number("keep-days").map(|days| match days.checked_mul(24 * 60 * 60) {
+        Some(secs) => Duration::from_secs(secs),
+        None => {
+            eprintln!("invalid --keep-days: {days} days are too many");
+            std::process::exit(1)
+        }
+    });
+    let retention = Retention {
+        max_age,
+        max_count: number("max-count").map(|count| count as usize),
+    };
+    if retention == Retention::default() {
+        eprintln!("--gc needs --keep-days, --max-count or both");
+        std::process::exit(1)
+    }
+    retention
+}
+
+/// Writes the report to `output`, or to stdout without one, exiting if that fails.
+fn write_report(output: Option<&Path>, report: &str) {
+    let Some(path) = output else {
+        print!("{report}");
+        return;
+    };
+    if let Err(e) = export::write_to(path, report) {
+        eprintln!("Could not write the report to {}: {e}", path.display());
+        std::process::exit(1)
+    }
+}
+
+/// Parses the `--type` option, exiting on an invalid value.
+fn type_opt(matches: &getopts::Matches) -> Option<CheckType> {
+    match matches.opt_str("type").map(|t| t.parse::<CheckType>()) {
+        None => None,
+        Some(Ok(t)) => Some(t),
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            std::process::exit(1)
+        }
     }
 }
 
@@ -49,29 +443,275 @@ fn print_usage(program: &str, opts: Options) {
     print!("{}", opts.usage(&brief));
 }
 
-fn test_checks() -> Result<(), RunError> {
+fn test_checks(check_type: Option<CheckType>) -> Result<(), RunError> {
     let mut checks = Vec::new();
     let mut buf = String::new();
-    Store::primitive_make_checks(&mut checks);
+    match check_type {
+        Some(check_type) => {
+            Store::primitive_make_checks_of(&mut checks, &[check_type], &Config::load()?)
+        }
+        None => Store::primitive_make_checks(&mut checks, &Config::load()?),
+    }
     let hack_checks: Vec<&Check> = checks.iter().collect();
     display_group(&hack_checks, &mut buf)?;
     println!("{buf}");
     Ok(())
 }
 
-fn analysis() {
-    let store = match Store::load() {
-        Err(e) => {
-            eprintln!("The store could not be loaded: {e}");
+fn probe(target: &str, check_type: Option<CheckType>, save: bool) -> Result<(), RunError> {
+    let check_types = match &check_type {
+        Some(check_type) => std::slice::from_ref(check_type),
+        None => CheckType::default_enabled(),
+    };
+    let config = Config::load()?;
+    let mut checks = Vec::new();
+    for result in Store::probe(target, check_types, &config) {
+        match result {
+            Ok(check) => checks.push(check),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+    let mut buf = String::new();
+    display_group(&checks.iter().collect::<Vec<&Check>>(), &mut buf)?;
+    println!("{buf}");
+
+    if save {
+        let mut store = Store::load_or_create_with(&config)?;
+        let amount = checks.len();
+        for check in checks {
+            store.add_check(check);
+        }
+        let added: Vec<&Check> = store.checks()[store.checks().len() - amount..]
+            .iter()
+            .collect();
+        store.save_with(&config, &added)?;
+        println!("Appended {amount} checks to the store");
+    }
+    Ok(())
+}
+
+fn annotate(start: &str, note: &str) -> Result<(), RunError> {
+    let start = annotations::parse_start(start)?;
+    let mut notes = Annotations::load()?;
+    notes.add(start, note);
+    notes.save()?;
+    println!(
+        "Annotated the outage starting at {}",
+        humantime::format_rfc3339_seconds(start)
+    );
+    Ok(())
+}
+
+fn list_annotations() -> Result<(), RunError> {
+    let notes = Annotations::load()?;
+    if notes.is_empty() {
+        println!("No annotated outages");
+    }
+    for (start, note) in notes.iter() {
+        println!("{}: {note}", humantime::format_rfc3339_seconds(start));
+    }
+    Ok(())
+}
+
+/// Prints the check with the hash `hash`, followed by its captured response if there is one.
+fn inspect(hash: &str) -> Result<(), RunError> {
+    let store = Store::load_all()?;
+    let Some(check) = store.check_by_hash(hash) else {
+        eprintln!("There is no check with the hash {hash}");
+        std::process::exit(1)
+    };
+    println!("{check}");
+    if let Some(data) = check.captured() {
+        println!("\n{}", String::from_utf8_lossy(data));
+    }
+    Ok(())
+}
+
+fn gc(retention: &Retention) -> Result<(), RunError> {
+    if let Some(pid) = running_daemon() {
+        eprintln!(
+            "netpulsed is running with pid {pid}, stop it first, or it saves the removed checks again"
+        );
+        std::process::exit(1)
+    }
+    let removed = Store::gc_locked(&Store::path(), retention, SystemTime::now())?;
+    println!("Removed {removed} checks");
+    Ok(())
+}
+
+fn diff(old: &Path, new: &Path) -> Result<(), RunError> {
+    let old = Store::load_from(old)?;
+    let new = Store::load_from(new)?;
+    let config = Config::load()?;
+    print!("{}", analyze::diff_with(&old, &new, &config.outages));
+    Ok(())
+}
+
+fn compare_window(window: Duration) -> Result<(), RunError> {
+    let store = Store::load_all()?;
+    let config = Config::load()?;
+    let comparison = analyze::compare_windows(&store, window, SystemTime::now(), &config.outages);
+    print!("{}", comparison.display_in(&config.report));
+    Ok(())
+}
+
+fn check_health() {
+    let store = Store::load_all();
+    if let Err(e) = &store {
+        eprintln!("The store could not be loaded: {e}");
+    }
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("The config could not be loaded, using the defaults: {e}");
+        Config::default()
+    });
+    let health = Health::of_load_with(&store, &config);
+    println!("{health}");
+    std::process::exit(health.exit_code())
+}
+
+fn tail(amount: usize, timezone: Option<Timezone>) {
+    let store = match Store::load_all() {
+        Err(e) => load_failed(&e),
+        Ok(store) => store,
+    };
+    let timezone = timezone.unwrap_or_else(|| {
+        Config::load()
+            .map(|config| config.report.timezone)
+            .unwrap_or_else(|e| {
+                eprintln!("The config could not be loaded, using UTC: {e}");
+                Timezone::Utc
+            })
+    });
+    if store.is_empty() {
+        println!("The store has no checks yet");
+    }
+    print!("{}", analyze::tail(&store, amount, &timezone));
+}
+
+fn one_line() {
+    match Store::load_all() {
+        Err(e) => load_failed(&e),
+        Ok(store) => println!("{}", analyze::one_line(&store)),
+    }
+}
+
+/// Prints why the store could not be loaded and exits.
+fn load_failed(err: &StoreError) -> ! {
+    let (msg, code) = load_failure(err, &Store::path());
+    eprintln!("{msg}");
+    std::process::exit(code)
+}
+
+/// Returns the message and exit code for a store that could not be loaded from `path`.
+///
+/// A missing store is common on the first run, so it gets a hint instead of the bare error.
+fn load_failure(err: &StoreError, path: &Path) -> (String, i32) {
+    match err {
+        StoreError::DoesNotExist => (
+            format!(
+                "no store found at {}; is the daemon running?",
+                path.display()
+            ),
+            EXIT_NO_STORE,
+        ),
+        _ => (format!("The store could not be loaded: {err}"), 1),
+    }
+}
+
+/// Where the store to analyze comes from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Source {
+    /// The store file of the daemon, or the one given with `--store`
+    StoreFile,
+    /// A store piped to stdin, with `--store -`
+    Stdin,
+    /// The bundled [demo](netpulse::demo) store, with `--demo`
+    Demo,
+}
+
+/// Prints the report of the store from `source` in `format`.
+///
+/// Without a [StoreFile](Source::StoreFile), neither the store file nor the annotations next to
+/// it are used.
+fn analysis(
+    config: Config,
+    tag: Option<String>,
+    target: Option<String>,
+    format: Format,
+    output: Option<&Path>,
+    source: Source,
+) {
+    let store = match source {
+        Source::StoreFile => Store::load_all(),
+        Source::Stdin => Store::load_from_reader(std::io::stdin().lock()),
+        Source::Demo => Ok(netpulse::demo::store()),
+    };
+    let mut store = match store {
+        Err(e) if source == Source::Stdin => {
+            eprintln!("The store could not be read from stdin: {e}");
             std::process::exit(1)
         }
+        Err(e) => load_failed(&e),
         Ok(s) => s,
     };
-    match analyze::analyze(&store) {
+    if let Some(pattern) = target {
+        store = match analyze::filter_targets(&store, &pattern) {
+            Err(e) => {
+                println!("{e}");
+                return;
+            }
+            Ok(s) => s,
+        };
+    }
+    let report = match (tag, format) {
+        (Some(key), _) => analyze::analyze_by_tag_with(&store, &key, &config),
+        (None, Format::Text) => {
+            let annotations = if source == Source::StoreFile {
+                match Annotations::load() {
+                    Err(e) => {
+                        eprintln!("The annotations could not be loaded: {e}");
+                        std::process::exit(1);
+                    }
+                    Ok(a) => a,
+                }
+            } else {
+                Annotations::default()
+            };
+            export::render_with(&TextReporter::new(&config).annotated(&annotations), &store)
+        }
+        (None, format) => export::render(&store, &config, format),
+    };
+    match report {
         Err(e) => {
             eprintln!("Error while making the analysis: {e}");
             std::process::exit(1);
         }
-        Ok(report) => println!("{report}"),
+        Ok(report) if format == Format::Text => write_report(output, &format!("{report}\n")),
+        Ok(report) => write_report(output, &report),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_missing_store_guidance() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("netpulse.store");
+        let err = Store::load_all_from(&path).unwrap_err();
+
+        let (msg, code) = load_failure(&err, &path);
+        assert_eq!(
+            msg,
+            format!(
+                "no store found at {}; is the daemon running?",
+                path.display()
+            )
+        );
+        assert_eq!(code, EXIT_NO_STORE);
+
+        let (_, code) = load_failure(&StoreError::UnsupportedVersion, &path);
+        assert_eq!(code, 1);
     }
 }
@@ -17,21 +17,33 @@
 //! # Files
 //!
 //! - PID file: `/var/run/netpulse/netpulsed.pid`
+//! - Control socket: `/run/netpulse/netpulse.sock`
 //! - Info log: `/var/log/netpulse/info.log`
 //! - Error log: `/var/log/netpulse/error.log`
+//!
+//! All of these can be overridden with environment variables, see [netpulse::pid_file] and
+//! friends. Together with `--user`, this allows running the daemon without root, for example in
+//! a rootless container.
 
 use core::panic;
 use std::fs::{self, File};
 use std::io::Write;
 use std::os::unix::fs::PermissionsExt;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::sync::atomic::AtomicBool;
 
 use daemonize::Daemonize;
 use getopts::Options;
+use netpulse::build_info::BuildInfo;
+use netpulse::control::{self, Command};
+use netpulse::crypt::StoreKey;
 use netpulse::errors::RunError;
+use netpulse::logging::{self, Rotation};
 use netpulse::store::Store;
-use netpulse::{DAEMON_LOG_ERR, DAEMON_LOG_INF, DAEMON_PID_FILE, DAEMON_USER};
+use netpulse::{
+    control_socket, log_err_file, log_inf_file, lookup_user, pid_file, process_liveness,
+    running_daemon, Liveness, DAEMON_USER,
+};
 use nix::errno::Errno;
 use nix::sys::signal::{self, Signal};
 use nix::unistd::Pid;
@@ -48,12 +60,23 @@ const SYSTEMD_SERVICE_PATH: &str = "/etc/systemd/system/netpulsed.service";
 /// `false` => no, we're doing it all manually
 static USES_DAEMON_SYSTEM: AtomicBool = AtomicBool::new(false);
 
+/// Whether the daemon logs one JSON line per check cycle to stdout
+///
+/// Set by `--foreground`. All other output of the daemon goes to stderr in that case, so that
+/// stdout only contains [CycleLog](netpulse::logging::CycleLog) lines.
+static JSON_LOG: AtomicBool = AtomicBool::new(false);
+
 fn main() -> Result<(), RunError> {
     let args: Vec<String> = std::env::args().collect();
     let program = &args[0];
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this help menu");
     opts.optflag("V", "version", "print the version");
+    opts.optflag(
+        "",
+        "json",
+        "with --version: print the version, git commit, build time and features as JSON",
+    );
     opts.optflag("s", "start", "start the netpulse daemon manually");
     opts.optflag(
         "u",
@@ -65,8 +88,29 @@ fn main() -> Result<(), RunError> {
         "daemon",
         "run directly as the daemon, do not setup a pidfile or drop privileges, for use when using a daemonizing system like systemd",
     );
+    opts.optflag(
+        "f",
+        "foreground",
+        "run directly as the daemon without a pidfile, logging one JSON line per check cycle to stdout, for containers and the like",
+    );
+    opts.optflag(
+        "",
+        "user",
+        "with --start, --end or --doctor: run as the current user, without root and without dropping privileges",
+    );
     opts.optflag("i", "info", "info about the running netpulse daemon");
+    opts.optflag(
+        "",
+        "doctor",
+        "check the config, directories, user and capabilities needed by the daemon",
+    );
     opts.optflag("e", "end", "stop the running netpulse daemon");
+    opts.optopt(
+        "",
+        "snapshot",
+        "make the running daemon write a consistent copy of the store to PATH, next to the store",
+        "PATH",
+    );
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(f) => {
@@ -79,18 +123,32 @@ fn main() -> Result<(), RunError> {
     if matches.opt_present("help") {
         print_usage(program, opts);
     } else if matches.opt_present("version") {
-        println!("{} {}", env!("CARGO_BIN_NAME"), env!("CARGO_PKG_VERSION"))
+        if matches.opt_present("json") {
+            println!("{}", BuildInfo::current().to_json())
+        } else {
+            println!("{} {}", env!("CARGO_BIN_NAME"), env!("CARGO_PKG_VERSION"))
+        }
     } else if matches.opt_present("start") {
-        startd();
+        startd(matches.opt_present("user"));
     } else if matches.opt_present("info") {
         infod();
+    } else if matches.opt_present("doctor") {
+        doctor(matches.opt_present("user"));
     } else if matches.opt_present("setup") {
         Store::setup()?;
         setup_systemd()?;
     } else if matches.opt_present("end") {
-        endd();
+        endd(matches.opt_present("user"));
+    } else if let Some(path) = matches.opt_str("snapshot") {
+        snapshotd(Path::new(&path))?;
     } else if matches.opt_present("daemon") {
         USES_DAEMON_SYSTEM.store(true, std::sync::atomic::Ordering::Release);
+        logging::init_error_log(None, Rotation::from_env());
+        daemon();
+    } else if matches.opt_present("foreground") {
+        USES_DAEMON_SYSTEM.store(true, std::sync::atomic::Ordering::Release);
+        JSON_LOG.store(true, std::sync::atomic::Ordering::Release);
+        logging::init_error_log(None, Rotation::from_env());
         daemon();
     } else {
         print_usage(program, opts);
@@ -135,10 +193,10 @@ fn setup_systemd() -> Result<(), RunError> {
 }
 
 fn getpid() -> Option<i32> {
-    if !fs::exists(DAEMON_PID_FILE).expect("couldn't check if the pid file exists") {
+    if !fs::exists(pid_file()).expect("couldn't check if the pid file exists") {
         None
     } else {
-        let pid_raw = fs::read_to_string(DAEMON_PID_FILE)
+        let pid_raw = fs::read_to_string(pid_file())
             .expect("pid file does not exist")
             .trim()
             .to_string();
@@ -149,31 +207,50 @@ fn getpid() -> Option<i32> {
                 return None;
             }
         };
+        // signals to 0 or below would go to a process group or every process
+        if pid <= 0 {
+            eprintln!("The pid file contains no valid pid: '{pid_raw}'");
+            return None;
+        }
         Some(pid)
     }
 }
 
 fn infod() {
     match getpid() {
-        Some(pid) => {
-            if pid_runs(pid) {
-                println!("netpulsed is running with pid {pid}")
-            } else {
-                println!(
-                    "the pid file exists with pid {pid}, but no process with that pid is running"
-                )
+        Some(pid) => match process_liveness(pid) {
+            Liveness::Running => {
+                println!("netpulsed is running with pid {pid}");
+                match control::request_status(&control_socket()) {
+                    Ok(status) => print!("{status}"),
+                    Err(err) => eprintln!("could not get the status of the daemon: {err}"),
+                }
             }
-        }
+            Liveness::RunningNotOurs => println!(
+                "netpulsed is running with pid {pid}, or the pid file is stale and that pid belongs to another user's process"
+            ),
+            Liveness::NotRunning => println!(
+                "the pid file exists with pid {pid}, but no process with that pid is running"
+            ),
+        },
         None => println!("netpulsed is not running"),
     }
 }
 
-fn pid_runs(pid: i32) -> bool {
-    fs::exists(format!("/proc/{pid}")).expect("could not check if the process exists")
+fn doctor(user_mode: bool) {
+    let findings = netpulse::doctor::diagnose(user_mode);
+    for finding in &findings {
+        println!("{finding}");
+    }
+    if !findings.iter().all(|f| f.passed()) {
+        std::process::exit(1)
+    }
 }
 
-fn endd() {
-    root_guard();
+fn endd(user_mode: bool) {
+    if !user_mode {
+        root_guard();
+    }
     let mut terminated = false;
     let pid: Pid = match getpid() {
         None => {
@@ -186,8 +263,6 @@ fn endd() {
     match signal::kill(pid, Signal::SIGTERM) {
         Ok(()) => {
             println!("Sent termination signal to netpulsed (pid: {pid})");
-            // Optionally: wait to confirm process ended
-            // Could check /proc/{pid} or try kill(0)
         }
         Err(e) => {
             match e {
@@ -205,7 +280,7 @@ fn endd() {
 
     let sent_sig = std::time::Instant::now();
     while !terminated && sent_sig.elapsed().as_secs() < 5 {
-        if pid_runs(pid.as_raw()) {
+        if process_liveness(pid.as_raw()) != Liveness::NotRunning {
             std::thread::sleep(std::time::Duration::from_millis(20));
         } else {
             terminated = true
@@ -222,14 +297,30 @@ fn endd() {
             }
         }
     }
-    if fs::exists(DAEMON_PID_FILE).expect("could not check if the pid file exists") {
-        eprintln!("The pid file ({DAEMON_PID_FILE}) still exists even though the daemon is not running, removing it");
-        if let Err(err) = fs::remove_file(DAEMON_PID_FILE) {
+    if fs::exists(pid_file()).expect("could not check if the pid file exists") {
+        eprintln!(
+            "The pid file ({}) still exists even though the daemon is not running, removing it",
+            pid_file().display()
+        );
+        if let Err(err) = fs::remove_file(pid_file()) {
             eprintln!("Could not remove the pid file: {err}")
         }
     }
 }
 
+fn snapshotd(path: &Path) -> Result<(), RunError> {
+    // the daemon has a different working directory
+    let path = std::path::absolute(path)?;
+    match control::request(&control_socket(), &Command::Snapshot(path)) {
+        Ok(answer) => println!("{answer}"),
+        Err(err) => {
+            eprintln!("Could not make a snapshot: {err}");
+            std::process::exit(1)
+        }
+    }
+    Ok(())
+}
+
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [options]", program);
     print!("{}", opts.usage(&brief));
@@ -242,33 +333,64 @@ pub(crate) fn root_guard() {
     }
 }
 
-fn startd() {
-    root_guard();
+/// Opens a logfile for appending, rotating it first if it has grown too large.
+///
+/// Creates the parent directory of the logfile if needed.
+fn open_logfile(path: &Path, rotation: &Rotation) -> std::io::Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    rotation.rotate_if_needed(path)?;
+    File::options().create(true).append(true).open(path)
+}
+
+fn startd(user_mode: bool) {
+    if !user_mode {
+        root_guard();
+    }
+    // daemonize would only fail on the locked pid file, with a less helpful message
+    if let Some(pid) = running_daemon() {
+        eprintln!("netpulsed already running with pid {pid}");
+        std::process::exit(1)
+    }
+    // the daemon drops to this user, so it has to exist before anything is created
+    let user = (!user_mode).then(|| {
+        lookup_user(DAEMON_USER).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(1)
+        })
+    });
+    // the key file may only be readable by root, so it is read before dropping privileges
+    if let Err(e) = StoreKey::preload() {
+        eprintln!("{e}");
+        std::process::exit(1)
+    }
     let path = Store::path();
     let parent_path = path.parent().expect("store file has no parent directory");
     println!("Parent: {parent_path:?}");
 
-    let pid_path = PathBuf::from(DAEMON_PID_FILE);
+    let pid_path = pid_file();
     let pid_parent_path = pid_path.parent().expect("pid file has no parent directory");
     println!("Pid Parent: {pid_parent_path:?}");
 
-    let logfile = File::create(DAEMON_LOG_INF).expect("could not open info logfile");
-    let errfile = File::create(DAEMON_LOG_ERR).expect("could not open error logfile");
-
-    let user = nix::unistd::User::from_name(DAEMON_USER)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-        .expect("could not get user for netpulse")
-        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "netpulse user not found"))
-        .expect("could not get user for netpulse");
+    let rotation = Rotation::from_env();
+    let logfile = open_logfile(&log_inf_file(), &rotation).expect("could not open info logfile");
+    let errfile = open_logfile(&log_err_file(), &rotation).expect("could not open error logfile");
 
     fs::create_dir_all(parent_path).expect("could not create the store directory");
     fs::create_dir_all(pid_parent_path).expect("could not create the pid directory");
-    std::os::unix::fs::chown(
-        pid_parent_path,
-        Some(user.uid.into()),
-        Some(user.gid.into()),
-    )
-    .expect("could not set permissions for the netpulse run directory");
+    if let Some(socket_parent_path) = control_socket().parent() {
+        fs::create_dir_all(socket_parent_path).expect("could not create the socket directory");
+    }
+
+    if let Some(user) = user {
+        std::os::unix::fs::chown(
+            pid_parent_path,
+            Some(user.uid.into()),
+            Some(user.gid.into()),
+        )
+        .expect("could not set permissions for the netpulse run directory");
+    }
 
     // NOTE: Daemonize is the defacto standard way of becoming a daemon in rust (besides extra
     // tools like systemd or writing it all yourself with nix or just the libc).
@@ -277,37 +399,37 @@ fn startd() {
     // things like ICMP (ping) messages.
     // I have implemented ICMP checks, but the daemon drops the CAP_NET_RAW capability and then is
     // no longer allowed to make the custom pings.
-    let daemonize = Daemonize::new()
-        .pid_file(pid_path)
-        .chown_pid_file(true)
+    let mut daemonize = Daemonize::new()
+        .pid_file(pid_path.clone())
+        .chown_pid_file(!user_mode)
         .working_directory(parent_path)
-        .user("netpulse")
-        .group("netpulse")
         .stdout(logfile)
         .stderr(errfile)
-        .privileged_action(|| -> Result<(), RunError> {
-            Store::setup()?;
-            Ok(())
-        })
         .umask(0o022); // rw-r--r--
+    if !user_mode {
+        daemonize = daemonize.user(DAEMON_USER).group(DAEMON_USER);
+    }
+    let daemonize = daemonize.privileged_action(move || -> Result<(), RunError> {
+        if !user_mode {
+            Store::setup()?;
+        }
+        Ok(())
+    });
 
     println!("daemon setup done");
 
     let outcome = daemonize.execute();
     match outcome {
-        daemonize::Outcome::Parent(result) => {
-            if result.is_ok() {
-                println!("netpulsed was started",);
-            } else {
-                eprintln!("error while starting netpulsed: {}", result.unwrap_err());
-            }
-        }
-        daemonize::Outcome::Child(result) => {
-            if result.is_ok() {
+        daemonize::Outcome::Parent(result) => match result {
+            Ok(_) => println!("netpulsed was started",),
+            Err(err) => eprintln!("error while starting netpulsed: {err}"),
+        },
+        daemonize::Outcome::Child(result) => match result {
+            Ok(_) => {
+                logging::init_error_log(Some(log_err_file()), rotation);
                 daemon();
-            } else {
-                panic!("error while starting the daemon: {}", result.unwrap_err())
             }
-        }
+            Err(err) => panic!("error while starting the daemon: {err}"),
+        },
     }
 }
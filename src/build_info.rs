@@ -0,0 +1,108 @@
+//! Metadata of the build, for `--version --json`.
+//!
+//! Bug reports should name the exact build. Besides the version, [BuildInfo] has the git commit
+//! and the time of the build, which are passed in by the build script, and the enabled cargo
+//! features.
+//!
+//! # Example
+//!
+//! ```rust
+//! use netpulse::build_info::BuildInfo;
+//!
+//! let info = BuildInfo::current();
+//! assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+//! println!("{}", info.to_json());
+//! ```
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::Serialize;
+
+/// Metadata of the running build, see [BuildInfo::current].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize)]
+pub struct BuildInfo {
+    /// Version of the crate
+    pub version: &'static str,
+    /// The git commit the crate was built from, [None] if it was not built from a git checkout
+    pub git_commit: Option<&'static str>,
+    /// When the crate was built, in RFC 3339
+    ///
+    /// `SOURCE_DATE_EPOCH` is used instead of the time of the build if it is set.
+    pub built_at: String,
+    /// The enabled cargo features, like `http`
+    pub features: Vec<&'static str>,
+}
+
+impl BuildInfo {
+    /// Returns the metadata of this build.
+    pub fn current() -> Self {
+        let built_at = env!("NETPULSE_BUILD_TIME")
+            .parse()
+            .map_or(UNIX_EPOCH, |secs| UNIX_EPOCH + Duration::from_secs(secs));
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: option_env!("NETPULSE_GIT_COMMIT"),
+            built_at: humantime::format_rfc3339_seconds(built_at).to_string(),
+            features: features(),
+        }
+    }
+
+    /// Renders the metadata as a JSON object.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("the build info can be serialized")
+    }
+}
+
+/// Returns the enabled cargo features.
+fn features() -> Vec<&'static str> {
+    [
+        ("compression", cfg!(feature = "compression")),
+        ("encryption", cfg!(feature = "encryption")),
+        ("health-http", cfg!(feature = "health-http")),
+        ("http", cfg!(feature = "http")),
+        ("ping", cfg!(feature = "ping")),
+        ("toml-store", cfg!(feature = "toml-store")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_json_has_version_and_features() {
+        let info = BuildInfo::current();
+        let json: serde_json::Value = serde_json::from_str(&info.to_json()).unwrap();
+
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        let features: Vec<&str> = json["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f.as_str().unwrap())
+            .collect();
+        assert_eq!(features.contains(&"http"), cfg!(feature = "http"));
+        assert_eq!(features.contains(&"ping"), cfg!(feature = "ping"));
+        assert_eq!(
+            features.contains(&"health-http"),
+            cfg!(feature = "health-http")
+        );
+        assert_eq!(
+            features.contains(&"toml-store"),
+            cfg!(feature = "toml-store")
+        );
+        assert_eq!(
+            features.contains(&"compression"),
+            cfg!(feature = "compression")
+        );
+        assert_eq!(
+            features.contains(&"encryption"),
+            cfg!(feature = "encryption")
+        );
+        assert!(json["git_commit"].is_string() || json["git_commit"].is_null());
+        assert!(humantime::parse_rfc3339(json["built_at"].as_str().unwrap()).unwrap() > UNIX_EPOCH);
+    }
+}
@@ -0,0 +1,164 @@
+//! Cached analysis of the store file.
+//!
+//! Loading and analyzing a large store takes a while, and `netpulse` would do that on every
+//! invocation. Instead, the daemon keeps an [AnalysisCache] and serves the report over the
+//! [control socket](crate::control) with [Command::Report](crate::control::Command::Report).
+//! The cache watches the modification time of the store file, its
+//! [shards](crate::store::ShardBy) and its [annotations](crate::annotations), and only loads
+//! and analyzes them again when they have changed.
+//!
+//! `netpulse` asks the daemon for the report first, and falls back to loading the store itself
+//! if no daemon answers.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::analyze;
+use crate::annotations::Annotations;
+use crate::config::Config;
+use crate::errors::{AnalysisError, StoreError};
+use crate::store::Store;
+
+/// A loaded store and its report, valid as long as the file was not modified.
+#[derive(Debug)]
+struct Cached {
+    /// Modification time of the file when it was loaded
+    modified: SystemTime,
+    /// The loaded store
+    store: Store,
+    /// The [report](analyze::analyze_with) of the store
+    report: String,
+}
+
+/// Keeps the store file and its report in memory, see the [module documentation](self).
+#[derive(Debug)]
+pub struct AnalysisCache {
+    /// Path of the store file
+    path: PathBuf,
+    /// Configuration for the report
+    config: Config,
+    /// The current state, [None] until the first report
+    cached: Option<Cached>,
+}
+
+impl AnalysisCache {
+    /// Creates an empty cache for the store file at `path`.
+    pub fn new(path: &Path, config: Config) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            config,
+            cached: None,
+        }
+    }
+
+    /// Returns the report of the store file, loading and analyzing it again if it has changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AnalysisError] if the file can't be read or the analysis fails. The cache is
+    /// left as it was in that case.
+    pub fn report(&mut self) -> Result<String, AnalysisError> {
+        // the latest change to the store file or any of its shards
+        let mut modified = None;
+        for file in Store::files_of(&self.path)? {
+            let file_modified = fs::metadata(&file)
+                .and_then(|meta| meta.modified())
+                .map_err(StoreError::from)?;
+            modified = modified.max(Some(file_modified));
+        }
+        let mut modified = modified.ok_or(StoreError::DoesNotExist)?;
+        let annotations_path = Annotations::path_of(&self.path);
+        // there may be no annotations yet
+        if let Ok(annotated) = fs::metadata(&annotations_path).and_then(|meta| meta.modified()) {
+            modified = modified.max(annotated);
+        }
+        match &self.cached {
+            Some(cached) if cached.modified == modified => (),
+            _ => {
+                let store = Store::load_all_from(&self.path)?;
+                let annotations = Annotations::load_from(&annotations_path)?;
+                let report = analyze::analyze_annotated(&store, &self.config, &annotations)?;
+                self.cached = Some(Cached {
+                    modified,
+                    store,
+                    report,
+                });
+            }
+        }
+        Ok(self
+            .cached
+            .as_ref()
+            .expect("the cache was just filled")
+            .report
+            .clone())
+    }
+
+    /// Returns the cached store, if a [report](AnalysisCache::report) was made already.
+    ///
+    /// The store may be outdated if the file was changed since.
+    pub fn store(&self) -> Option<&Store> {
+        self.cached.as_ref().map(|cached| &cached.store)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::records::{Check, CheckFlag};
+
+    use super::*;
+
+    #[test]
+    fn test_cache_invalidation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("netpulse.store");
+        // the metadata of the store file at the default path does not matter here
+        let mut config = Config::default();
+        config.report.skip_store_file = true;
+        let set_modified = |secs: u64| {
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+                .unwrap();
+        };
+        let check = Check::new(
+            UNIX_EPOCH,
+            CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            Some(12),
+            "1.1.1.1".parse().unwrap(),
+        );
+
+        let mut store = Store::new();
+        store.add_check(check.clone());
+        store.snapshot(&path).unwrap();
+        set_modified(1000);
+
+        let mut cache = AnalysisCache::new(&path, config);
+        assert!(cache.store().is_none());
+        let report = cache.report().unwrap();
+        assert!(report.contains("checks              : 00000001"));
+        assert_eq!(cache.store().unwrap().checks().len(), 1);
+
+        // the file changed, but its modification time did not: the cache is used
+        store.add_check(check.clone());
+        store.snapshot(&path).unwrap();
+        set_modified(1000);
+        assert_eq!(cache.report().unwrap(), report);
+        assert_eq!(cache.store().unwrap().checks().len(), 1);
+
+        // a new modification time invalidates the cache
+        set_modified(2000);
+        let report = cache.report().unwrap();
+        assert!(report.contains("checks              : 00000002"));
+        assert_eq!(cache.store().unwrap().checks().len(), 2);
+
+        // a missing file is an error, but does not clear the cache
+        fs::remove_file(&path).unwrap();
+        assert!(cache.report().is_err());
+        assert_eq!(cache.store().unwrap().checks().len(), 2);
+    }
+}
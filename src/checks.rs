@@ -3,12 +3,15 @@
 //! This module contains the actual check implementations for different protocols:
 //! - HTTP checks via HEAD requests
 //! - ICMP checks via ping
+//! - UDP checks via a series of DNS queries
+//! - Throughput checks via an HTTP download
 //! - DNS checks (planned)
 //!
 //! All check functions follow the pattern:
 //! - Take a target IP address
 //! - Perform the check with timeout
-//! - Return latency on success or error on failure
+//! - Return latency (and other measurements, like the HTTP status) on success or error on
+//!   failure
 //!
 //! # Feature Flags
 //!
@@ -25,14 +28,280 @@
 //! let addr: IpAddr = "1.1.1.1".parse().unwrap();
 //!
 //! // Perform HTTP check
-//! if let Ok(latency) = checks::check_http(addr) {
-//!     println!("HTTP latency: {}ms", latency);
+//! if let Ok(response) = checks::check_http(addr) {
+//!     println!("HTTP latency: {}ms", response.latency);
 //! }
 //! ```
-use std::net::IpAddr;
+#[cfg(feature = "http")]
+use std::cell::RefCell;
+#[cfg(feature = "http")]
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, TcpStream, ToSocketAddrs, UdpSocket,
+};
+use std::os::fd::{AsFd, AsRawFd};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use nix::net::if_::{if_indextoname, if_nametoindex};
+use nix::sys::socket::{setsockopt, socket, sockopt, SockFlag, SockProtocol, SockType};
 
+use crate::config::{AddressFamily, Dscp, ResolveConfig, ScopedAddr, Source};
+#[cfg(feature = "http")]
+use crate::config::{HttpConnection, HttpMethod, HttpVersion};
 use crate::errors::CheckError;
 use crate::TIMEOUT;
+#[cfg(feature = "http")]
+use crate::{config::Config, records::CheckType};
+
+/// Resolves `host` to its IP addresses with the resolver of the system.
+///
+/// An IP address given as `host` is returned as is, a [scoped](ScopedAddr) one without its zone.
+///
+/// # Errors
+///
+/// Returns [CheckError::Io] if the resolution fails and [CheckError::NoAddress] if there are no
+/// addresses for `host`.
+pub fn resolve(host: &str) -> Result<Vec<IpAddr>, CheckError> {
+    if let Some(scoped) = scoped(host) {
+        return Ok(vec![scoped.ip.into()]);
+    }
+    let addrs: Vec<IpAddr> = (host, 0).to_socket_addrs()?.map(|a| a.ip()).collect();
+    if addrs.is_empty() {
+        return Err(CheckError::NoAddress(host.to_string()));
+    }
+    Ok(addrs)
+}
+
+/// Resolves `host` to its IP addresses as `options` configure: with [resolve_at] if they name
+/// a DNS server, with [resolve] otherwise.
+///
+/// # Errors
+///
+/// See [resolve] and [resolve_at].
+pub fn resolve_with(host: &str, options: &ResolveConfig) -> Result<Vec<IpAddr>, CheckError> {
+    match options.resolver {
+        Some(resolver) => resolve_at(host, resolver, options.family),
+        None => resolve(host),
+    }
+}
+
+/// Parses `host` as [ScopedAddr], if it has a zone.
+pub(crate) fn scoped(host: &str) -> Option<ScopedAddr> {
+    host.contains('%').then(|| host.parse().ok()).flatten()
+}
+
+/// DNS record type of IPv4 addresses
+const DNS_TYPE_A: u16 = 1;
+/// DNS record type of IPv6 addresses
+const DNS_TYPE_AAAA: u16 = 28;
+
+/// Resolves `host` to its IP addresses of `family` by asking the DNS server at `resolver`,
+/// bypassing the resolver of the system.
+///
+/// An IP address given as `host` is returned as is, a [scoped](ScopedAddr) one without its
+/// zone. The DNS server needs to resolve recursively, like public resolvers do, since only the
+/// addresses in its answer are used. A truncated answer is asked for again over TCP. All
+/// queries together take at most [TIMEOUT].
+///
+/// # Errors
+///
+/// Returns [CheckError::Io] if the DNS server can't be reached in time or its answer is
+/// malformed, and [CheckError::NoAddress] if there are no addresses of `family` for `host`.
+pub fn resolve_at(
+    host: &str,
+    resolver: SocketAddr,
+    family: AddressFamily,
+) -> Result<Vec<IpAddr>, CheckError> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![ip]);
+    }
+    if let Some(scoped) = scoped(host) {
+        return Ok(vec![scoped.ip.into()]);
+    }
+    let record_types: &[u16] = match family {
+        AddressFamily::Any => &[DNS_TYPE_A, DNS_TYPE_AAAA],
+        AddressFamily::V4 => &[DNS_TYPE_A],
+        AddressFamily::V6 => &[DNS_TYPE_AAAA],
+    };
+    let deadline = Instant::now() + TIMEOUT;
+    let mut addrs = Vec::new();
+    for record_type in record_types {
+        addrs.extend(dns_lookup(host, *record_type, resolver, deadline)?);
+    }
+    if addrs.is_empty() {
+        return Err(CheckError::NoAddress(host.to_string()));
+    }
+    Ok(addrs)
+}
+
+/// Asks the DNS server at `resolver` for the records of `record_type` of `host` until
+/// `deadline`, and returns the addresses in the answer.
+///
+/// The query is sent over UDP, and again over TCP if the answer is truncated.
+fn dns_lookup(
+    host: &str,
+    record_type: u16,
+    resolver: SocketAddr,
+    deadline: Instant,
+) -> Result<Vec<IpAddr>, CheckError> {
+    let malformed = || std::io::Error::new(ErrorKind::InvalidData, "malformed DNS answer");
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    let id = (nanos as u16) ^ (std::process::id() as u16);
+    let mut query = vec![0u8; 12];
+    query[..2].copy_from_slice(&id.to_be_bytes());
+    query[2] = 0x01; // recursion desired
+    query[5] = 0x01; // one question
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(CheckError::NoAddress(host.to_string()));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.extend_from_slice(&[0x00, 0x01]); // class IN
+
+    let mut answer = dns_exchange_udp(&query, resolver, deadline)?;
+    if answer[2] & 0x02 != 0 {
+        // truncated, TCP has room for all of the answer
+        answer = dns_exchange_tcp(&query, resolver, deadline)?;
+    }
+    let answer = answer.as_slice();
+    let word = |pos: usize| -> Result<u16, std::io::Error> {
+        answer
+            .get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(malformed)
+    };
+    // a missing name is no error, just no addresses
+    match answer[3] & 0x0f {
+        0 | 3 => (),
+        rcode => {
+            return Err(
+                std::io::Error::other(format!("DNS server answered with error {rcode}")).into(),
+            )
+        }
+    }
+
+    let mut pos = 12;
+    for _ in 0..word(4)? {
+        pos = skip_dns_name(answer, pos).ok_or_else(malformed)? + 4;
+    }
+    let mut addrs = Vec::new();
+    for _ in 0..word(6)? {
+        pos = skip_dns_name(answer, pos).ok_or_else(malformed)?;
+        let (rtype, rdlen) = (word(pos)?, word(pos + 8)? as usize);
+        let rdata = answer
+            .get(pos + 10..pos + 10 + rdlen)
+            .ok_or_else(malformed)?;
+        match (rtype, rdlen) {
+            (DNS_TYPE_A, 4) => addrs.push(IpAddr::from(
+                <[u8; 4]>::try_from(rdata).expect("length is 4"),
+            )),
+            (DNS_TYPE_AAAA, 16) => addrs.push(IpAddr::from(
+                <[u8; 16]>::try_from(rdata).expect("length is 16"),
+            )),
+            // like the CNAMEs leading to the addresses
+            _ => (),
+        }
+        pos += 10 + rdlen;
+    }
+    Ok(addrs)
+}
+
+/// Returns the time left until `deadline`.
+///
+/// # Errors
+///
+/// Returns an error of the kind [TimedOut](ErrorKind::TimedOut) if `deadline` has passed.
+fn remaining(deadline: Instant) -> Result<Duration, std::io::Error> {
+    Some(deadline.saturating_duration_since(Instant::now()))
+        .filter(|left| !left.is_zero())
+        .ok_or_else(|| std::io::Error::new(ErrorKind::TimedOut, "no DNS answer in time"))
+}
+
+/// Sends the DNS `query` to `resolver` over UDP and returns the answer to it, waiting until
+/// `deadline` at most.
+///
+/// Answers to other queries are ignored.
+fn dns_exchange_udp(
+    query: &[u8],
+    resolver: SocketAddr,
+    deadline: Instant,
+) -> Result<Vec<u8>, std::io::Error> {
+    let local: SocketAddr = match resolver {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse().expect("static address is valid"),
+        SocketAddr::V6(_) => "[::]:0".parse().expect("static address is valid"),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(resolver)?;
+    socket.send(query)?;
+
+    let mut buf = [0u8; 1500];
+    loop {
+        socket.set_read_timeout(Some(remaining(deadline)?))?;
+        let len = socket.recv(&mut buf)?;
+        if len >= 12 && buf[..2] == query[..2] {
+            return Ok(buf[..len].to_vec());
+        }
+    }
+}
+
+/// Sends the DNS `query` to `resolver` over TCP and returns the answer, waiting until
+/// `deadline` at most.
+fn dns_exchange_tcp(
+    query: &[u8],
+    resolver: SocketAddr,
+    deadline: Instant,
+) -> Result<Vec<u8>, std::io::Error> {
+    let mut stream = TcpStream::connect_timeout(&resolver, remaining(deadline)?)?;
+    stream.set_write_timeout(Some(remaining(deadline)?))?;
+    // messages over TCP start with their length
+    let mut message = (query.len() as u16).to_be_bytes().to_vec();
+    message.extend_from_slice(query);
+    stream.write_all(&message)?;
+
+    let mut read_exact = |buf: &mut [u8]| -> Result<(), std::io::Error> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            stream.set_read_timeout(Some(remaining(deadline)?))?;
+            match stream.read(&mut buf[filled..])? {
+                0 => return Err(ErrorKind::UnexpectedEof.into()),
+                read => filled += read,
+            }
+        }
+        Ok(())
+    };
+    let mut len = [0u8; 2];
+    read_exact(&mut len)?;
+    let mut answer = vec![0u8; u16::from_be_bytes(len) as usize];
+    read_exact(&mut answer)?;
+    if answer.len() < 12 || answer[..2] != query[..2] {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "malformed DNS answer",
+        ));
+    }
+    Ok(answer)
+}
+
+/// Returns the position after the name starting at `pos` in a DNS message, [None] if the name
+/// does not end inside the message.
+fn skip_dns_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            // a pointer to an earlier name ends the name
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
 
 /// Performs an ICMP ping check to the specified IP address.
 ///
@@ -78,17 +347,265 @@ use crate::TIMEOUT;
 /// ```
 #[cfg(feature = "ping")]
 pub fn just_fucking_ping(remote: IpAddr) -> Result<u16, CheckError> {
+    ping_with_timeout(remote, TIMEOUT)
+}
+
+/// Pings `remote` like [just_fucking_ping], but waits up to `timeout` for the reply.
+///
+/// # Errors
+///
+/// See [just_fucking_ping].
+#[cfg(feature = "ping")]
+pub fn ping_with_timeout(remote: IpAddr, timeout: Duration) -> Result<u16, CheckError> {
     let now = std::time::Instant::now();
-    match ping::rawsock::ping(remote, Some(TIMEOUT), None, None, None, None) {
+    match ping::rawsock::ping(remote, Some(timeout), None, None, None, None) {
         Ok(_) => Ok(now.elapsed().as_millis() as u16),
         Err(e) => Err(e.into()),
     }
 }
 
-/// Performs an HTTP HEAD request to check connectivity to the specified IP address.
+/// ICMPv6 type of echo requests
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+/// ICMPv6 type of echo replies
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// Pings `remote` through the interface of its zone and returns the round-trip time in
+/// milliseconds.
+///
+/// [just_fucking_ping] can't send to a zone, so this sends the ICMPv6 echo request itself, from
+/// a raw socket bound to the interface with `SO_BINDTODEVICE`. Like every ICMP check, this needs
+/// `CAP_NET_RAW`.
+///
+/// # Errors
+///
+/// Returns [CheckError::Bind] if the interface of the zone is missing or down, and
+/// [CheckError::Io] if the socket can't be opened or no reply comes within `timeout`.
+pub fn ping_scoped(remote: &ScopedAddr, timeout: Duration) -> Result<u16, CheckError> {
+    let (socket, scope_id) = icmpv6_socket(remote)?;
+    let start = Instant::now();
+    let ident = (std::process::id() as u16).to_be_bytes();
+    let seq = (SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos() as u16)
+        .to_be_bytes();
+    // the kernel fills in the checksum of ICMPv6 sockets
+    let mut request = [0u8; 16];
+    request[0] = ICMPV6_ECHO_REQUEST;
+    request[4..6].copy_from_slice(&ident);
+    request[6..8].copy_from_slice(&seq);
+    request[8..].copy_from_slice(b"netpulse");
+    socket.send_to(&request, SocketAddrV6::new(remote.ip, 0, 0, scope_id))?;
+
+    let mut buf = [0u8; 1500];
+    loop {
+        let left = timeout.saturating_sub(start.elapsed());
+        if left.is_zero() {
+            return Err(std::io::Error::from(ErrorKind::TimedOut).into());
+        }
+        socket.set_read_timeout(Some(left))?;
+        // the socket gets every ICMPv6 message of the interface, like neighbor discovery
+        let (len, from) = socket.recv_from(&mut buf)?;
+        if from.ip() == IpAddr::V6(remote.ip)
+            && len >= 8
+            && buf[0] == ICMPV6_ECHO_REPLY
+            && buf[4..8] == request[4..8]
+        {
+            return Ok(start.elapsed().as_millis() as u16);
+        }
+    }
+}
+
+/// Opens a raw ICMPv6 socket bound to the interface of the zone of `remote`, and returns it
+/// with the scope id of the zone.
+///
+/// std has no raw sockets, but a [UdpSocket] only wraps `sendto` and `recvfrom`, which work
+/// the same on them. Received messages start with the ICMPv6 header, without the IPv6 header.
+fn icmpv6_socket(remote: &ScopedAddr) -> Result<(UdpSocket, u32), CheckError> {
+    let zone_error = |reason: String| CheckError::Bind {
+        local: remote.zone.clone(),
+        reason,
+    };
+    let (interface, scope_id) = zone_interface(&remote.zone).map_err(zone_error)?;
+    let socket = socket(
+        nix::sys::socket::AddressFamily::Inet6,
+        SockType::Raw,
+        SockFlag::SOCK_CLOEXEC,
+        SockProtocol::IcmpV6,
+    )
+    .map_err(std::io::Error::from)?;
+    interface_up(&socket, &interface).map_err(zone_error)?;
+    bind_device(&socket, &interface).map_err(|err| zone_error(err.to_string()))?;
+    Ok((UdpSocket::from(socket), scope_id))
+}
+
+/// Returns the name and index of the interface of `zone`, which is either of them.
+///
+/// # Errors
+///
+/// Describes why there is no such interface.
+fn zone_interface(zone: &str) -> Result<(String, u32), String> {
+    if let Ok(index) = zone.parse::<u32>() {
+        let name =
+            if_indextoname(index).map_err(|_| format!("no interface has the index {index}"))?;
+        return Ok((name.to_string_lossy().into_owned(), index));
+    }
+    match if_nametoindex(zone) {
+        Ok(index) => Ok((zone.to_string(), index)),
+        Err(errno) => Err(format!("no such interface: {errno}")),
+    }
+}
+
+/// How much of the response body of an [HTTP check](check_http_with) is read at most
+///
+/// The rest of the body is not downloaded.
+#[cfg(feature = "http")]
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Results of an [HTTP check](check_http).
+#[cfg(feature = "http")]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct HttpResponse {
+    /// Round-trip time in milliseconds
+    pub latency: u16,
+    /// Status code of the response
+    pub status: u16,
+    /// HTTP version of the response, [None] if its status line could not be parsed
+    pub version: Option<HttpVersion>,
+    /// Whether the body contained the expected text, [None] if no text was expected
+    pub body_matches: Option<bool>,
+    /// The head and the first bytes of the body of the response, as much as was asked to be
+    /// captured
+    pub raw: Vec<u8>,
+    /// How long each phase of the request took
+    pub phases: HttpPhases,
+}
+
+/// How long each phase of an [HTTP check](check_http_with) took, as measured by curl.
+///
+/// The phases follow each other, so together they take about as long as
+/// [total](HttpPhases::total). The rest of the total is spent on reading the response after its
+/// first byte. On a [reused](HttpPhases::reused) connection, the DNS lookup, connecting and the
+/// TLS handshake are skipped and take no time.
+#[cfg(feature = "http")]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct HttpPhases {
+    /// Resolving the host, almost nothing for an IP address
+    pub dns: Duration,
+    /// Connecting with TCP
+    pub connect: Duration,
+    /// The TLS handshake, zero for plain HTTP
+    pub tls: Duration,
+    /// Sending the request and waiting for the first byte of the response
+    pub ttfb: Duration,
+    /// The whole request, until the last byte of the response
+    pub total: Duration,
+    /// Whether an open connection of an earlier request was used
+    pub reused: bool,
+}
+
+#[cfg(feature = "http")]
+impl HttpPhases {
+    /// Reads the phases of the last transfer of `easy`.
+    ///
+    /// curl reports the time from the start of the request until the end of each phase, these
+    /// are turned into the durations of the phases.
+    fn of(easy: &curl::easy::Easy) -> Result<Self, curl::Error> {
+        let resolved = easy.namelookup_time()?;
+        let connected = easy.connect_time()?.max(resolved);
+        // without TLS, there is no handshake and curl reports zero
+        let handshaken = easy.appconnect_time()?.max(connected);
+        let first_byte = easy.starttransfer_time()?.max(handshaken);
+        Ok(Self {
+            dns: resolved,
+            connect: connected - resolved,
+            tls: handshaken - connected,
+            ttfb: first_byte - handshaken,
+            total: easy.total_time()?.max(first_byte),
+            reused: easy.num_connects()? == 0,
+        })
+    }
+}
+
+/// How an [HTTP check](check_http_with) is made, taken from the [Config] with
+/// [of](HttpOptions::of).
+///
+/// The [default](HttpOptions::default) is a GET request without headers and with [TIMEOUT], as
+/// made by [check_http].
+#[cfg(feature = "http")]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HttpOptions {
+    /// Method of the request
+    pub method: HttpMethod,
+    /// Additional headers, a `Host` header replaces the IP address that would be sent otherwise
+    pub headers: BTreeMap<String, String>,
+    /// HTTP version curl is asked to use, it may still fall back to an older one
+    pub version: Option<HttpVersion>,
+    /// Text the first [MAX_BODY_BYTES] of the body are searched for, see
+    /// [HttpResponse::body_matches]
+    pub expect_body: Option<String>,
+    /// How many bytes of the response are kept in [HttpResponse::raw]
+    pub capture: usize,
+    /// Where the request is sent from, see [Config::source]
+    pub source: Option<Source>,
+    /// Whether the connection is kept for the next request, see [HttpPhases::reused]
+    pub connection: HttpConnection,
+    /// The request fails if it takes longer than this
+    pub timeout: Duration,
+}
+
+#[cfg(feature = "http")]
+impl HttpOptions {
+    /// Takes the options of the HTTP checks of `remote` from `config`.
+    pub fn of(config: &Config, remote: IpAddr) -> Self {
+        let mut options = match config.http.target(remote) {
+            Some(target) => Self {
+                method: target.method,
+                headers: target.headers.clone(),
+                version: target.version,
+                expect_body: target.expect_body.clone(),
+                ..Default::default()
+            },
+            None => Self::default(),
+        };
+        options.capture = config.capture.limit();
+        options.source = config.source.get(&remote).cloned();
+        options.connection = config.http.connection;
+        options.timeout = config.check_settings(CheckType::Http).timeout;
+        options
+    }
+}
+
+#[cfg(feature = "http")]
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            method: HttpMethod::default(),
+            headers: BTreeMap::new(),
+            version: None,
+            expect_body: None,
+            capture: 0,
+            source: None,
+            connection: HttpConnection::Fresh,
+            timeout: TIMEOUT,
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+thread_local! {
+    /// Handles with an open connection for [HttpConnection::Reuse], keyed by URL
+    static CONNECTIONS: RefCell<std::collections::HashMap<String, curl::easy::Easy>> =
+        RefCell::default();
+}
+
+/// Performs an HTTP request to check connectivity to the specified IP address.
 ///
-/// Makes an HTTP/HTTPS HEAD request to measure response time. Uses curl under the hood
-/// and requires the `http` feature to be enabled.
+/// Makes an HTTP/HTTPS GET request to measure response time. Uses curl under the hood
+/// and requires the `http` feature to be enabled. Redirects are not followed.
+///
+/// Whether the status code of the response counts as success is not decided here, see
+/// [HttpConfig](crate::config::HttpConfig).
 ///
 /// # Arguments
 ///
@@ -96,7 +613,7 @@ pub fn just_fucking_ping(remote: IpAddr) -> Result<u16, CheckError> {
 ///
 /// # Returns
 ///
-/// * `Ok(u16)` - Round-trip time in milliseconds if request succeeds
+/// * `Ok(HttpResponse)` - Round-trip time in milliseconds and status code if a response came
 /// * `Err(CheckError)` - If request fails (timeout, connection refused, etc)
 ///
 /// # Errors
@@ -105,7 +622,6 @@ pub fn just_fucking_ping(remote: IpAddr) -> Result<u16, CheckError> {
 /// - DNS resolution fails
 /// - Connection fails or is refused
 /// - Request times out ([`TIMEOUT`])
-/// - HTTP response indicates error
 /// - URL construction fails
 ///
 /// # IPv6 Handling
@@ -121,22 +637,1074 @@ pub fn just_fucking_ping(remote: IpAddr) -> Result<u16, CheckError> {
 ///
 /// let addr: IpAddr = "1.1.1.1".parse().unwrap();
 /// match check_http(addr) {
-///     Ok(latency) => println!("HTTP latency: {}ms", latency),
+///     Ok(response) => println!("HTTP latency: {}ms, status {}", response.latency, response.status),
 ///     Err(e) => eprintln!("HTTP check failed: {}", e),
 /// }
 /// ```
 #[cfg(feature = "http")]
-pub fn check_http(remote: IpAddr) -> Result<u16, CheckError> {
+pub fn check_http(remote: IpAddr) -> Result<HttpResponse, CheckError> {
+    check_http_with(remote, &HttpOptions::default())
+}
+
+/// Performs an HTTP request as set in `options`, see [check_http].
+///
+/// The version that was used in the end is in [HttpResponse::version]. With
+/// [HttpConnection::Reuse], the connection is kept open for the next request to `remote` made
+/// by this thread.
+///
+/// # Errors
+///
+/// See [check_http]. Returns [CheckError::Bind] if the request can't be sent from the
+/// [source](HttpOptions::source).
+#[cfg(feature = "http")]
+pub fn check_http_with(remote: IpAddr, options: &HttpOptions) -> Result<HttpResponse, CheckError> {
+    request(
+        &match remote {
+            IpAddr::V4(_) => remote.to_string(),
+            IpAddr::V6(_) => format!("[{remote}]"),
+        },
+        options,
+    )
+}
+
+/// Makes a request to `url` as set in `options`, see [check_http_with].
+#[cfg(feature = "http")]
+fn request(url: &str, options: &HttpOptions) -> Result<HttpResponse, CheckError> {
+    let HttpOptions {
+        method,
+        headers,
+        version,
+        expect_body,
+        capture,
+        source,
+        connection,
+        timeout,
+    } = options;
+    let (expect_body, source) = (expect_body.as_deref(), source.as_ref());
+    let (capture, connection) = (*capture, *connection);
     let start = std::time::Instant::now();
+    let kept = match connection {
+        HttpConnection::Fresh => None,
+        HttpConnection::Reuse => CONNECTIONS.with_borrow_mut(|handles| handles.remove(url)),
+    };
+    let mut easy = match kept {
+        Some(mut easy) => {
+            // the options of the last request are cleared, its connection stays open
+            easy.reset();
+            easy
+        }
+        None => curl::easy::Easy::new(),
+    };
+
+    easy.url(url)?;
+    if let Some(source) = source {
+        bind_curl(&mut easy, source)?;
+    }
+    match method {
+        HttpMethod::Head => easy.nobody(true)?,
+        HttpMethod::Get => easy.get(true)?,
+    }
+    let mut list = curl::easy::List::new();
+    for (name, value) in headers {
+        list.append(&format!("{name}: {value}"))?;
+    }
+    easy.http_headers(list)?;
+    if let Some(version) = *version {
+        easy.http_version(match version {
+            HttpVersion::V1_0 => curl::easy::HttpVersion::V10,
+            HttpVersion::V1_1 => curl::easy::HttpVersion::V11,
+            HttpVersion::V2 => curl::easy::HttpVersion::V2,
+            HttpVersion::V3 => curl::easy::HttpVersion::V3,
+        })?;
+    }
+    easy.timeout(*timeout)?;
+    let mut negotiated = None;
+    let mut body = Vec::new();
+    // both the head and the body are captured
+    let raw = RefCell::new(Vec::new());
+    let keep = |data: &[u8]| {
+        let mut raw = raw.borrow_mut();
+        let take = data.len().min(capture - raw.len());
+        raw.extend_from_slice(&data[..take]);
+    };
+    let result = {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|data| {
+            keep(data);
+            // without an expected text, the body is not needed, only how long it took to get it
+            if expect_body.is_none() {
+                return Ok(data.len());
+            }
+            let take = data.len().min(MAX_BODY_BYTES - body.len());
+            body.extend_from_slice(&data[..take]);
+            // taking less than all of the data aborts the transfer once the body is capped
+            Ok(take)
+        })?;
+        // after an upgrade, the last status line is the one of the actual response
+        transfer.header_function(|header| {
+            keep(header);
+            if let Some(version) = std::str::from_utf8(header)
+                .ok()
+                .and_then(HttpVersion::from_status_line)
+            {
+                negotiated = Some(version);
+            }
+            true
+        })?;
+        transfer.perform()
+    };
+    match result {
+        Err(err) if err.is_write_error() && body.len() == MAX_BODY_BYTES => (),
+        result => result.map_err(|err| curl_error(err, source))?,
+    }
+
+    let response = HttpResponse {
+        latency: start.elapsed().as_millis() as u16,
+        status: easy.response_code()? as u16,
+        version: negotiated,
+        body_matches: expect_body.map(|expected| String::from_utf8_lossy(&body).contains(expected)),
+        raw: raw.into_inner(),
+        phases: HttpPhases::of(&easy)?,
+    };
+    if connection == HttpConnection::Reuse {
+        CONNECTIONS.with_borrow_mut(|handles| handles.insert(url.to_string(), easy));
+    }
+    Ok(response)
+}
+
+/// Results of a [throughput check](check_throughput).
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Throughput {
+    /// Amount of bytes that were downloaded
+    pub bytes: u64,
+    /// How long the request took, from connecting to the last byte
+    pub duration: Duration,
+    /// Time until the first byte of the response in milliseconds
+    pub latency: u16,
+    /// Status code of the response
+    pub status: u16,
+}
+
+impl Throughput {
+    /// Bytes downloaded per second.
+    pub fn bytes_per_sec(&self) -> f64 {
+        // a download takes some time, this only avoids dividing by zero
+        self.bytes as f64 / self.duration.max(Duration::from_micros(1)).as_secs_f64()
+    }
+
+    /// Megabytes (10^6 bytes) downloaded per second.
+    pub fn mb_per_sec(&self) -> f64 {
+        self.bytes_per_sec() / 1_000_000.0
+    }
+}
+
+/// Downloads `url` to measure the throughput, see [Throughput].
+///
+/// At most `max_bytes` are downloaded, the download is stopped there and the throughput is
+/// measured over the bytes that came until then. If `remote` is given, it is used instead of
+/// resolving the host of `url`. The download is made from `source` if given.
+///
+/// # Errors
+///
+/// Returns [CheckError] if the download fails or takes longer than `timeout`, and
+/// [CheckError::Bind] if it can't be made from `source`.
+#[cfg(feature = "http")]
+pub fn check_throughput(
+    url: &str,
+    remote: Option<IpAddr>,
+    max_bytes: u64,
+    timeout: Duration,
+    source: Option<&Source>,
+) -> Result<Throughput, CheckError> {
+    let start = Instant::now();
     let mut easy = curl::easy::Easy::new();
+    easy.url(url)?;
+    easy.timeout(timeout)?;
+    if let Some(source) = source {
+        bind_curl(&mut easy, source)?;
+    }
+    if let (Some(remote), Some((host, port))) = (remote, url_host(url)) {
+        if host.parse::<IpAddr>().is_err() {
+            let mut resolve = curl::easy::List::new();
+            resolve.append(&match remote {
+                IpAddr::V4(_) => format!("{host}:{port}:{remote}"),
+                IpAddr::V6(_) => format!("{host}:{port}:[{remote}]"),
+            })?;
+            easy.resolve(resolve)?;
+        }
+    }
+    let mut bytes = 0;
+    let result = {
+        let mut transfer = easy.transfer();
+        transfer.write_function(|data| {
+            let take = (data.len() as u64).min(max_bytes - bytes);
+            bytes += take;
+            // taking less than all of the data aborts the transfer once the cap is reached
+            Ok(take as usize)
+        })?;
+        transfer.perform()
+    };
+    match result {
+        Err(err) if err.is_write_error() && bytes == max_bytes => (),
+        result => result.map_err(|err| curl_error(err, source))?,
+    }
+
+    Ok(Throughput {
+        bytes,
+        duration: start.elapsed(),
+        latency: easy
+            .starttransfer_time()?
+            .as_millis()
+            .min(crate::TIMEOUT_MS as u128) as u16,
+        status: easy.response_code()? as u16,
+    })
+}
+
+/// Returns the host and port of an `http` or `https` `url`, [None] if it has none.
+///
+/// The port is the default of the scheme if the URL does not name one. IPv6 addresses are
+/// returned without their brackets.
+pub fn url_host(url: &str) -> Option<(&str, u16)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let default_port = match scheme.to_lowercase().as_str() {
+        "http" => 80,
+        "https" => 443,
+        _ => return None,
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, a)| a);
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(v6) => {
+            let (host, rest) = v6.split_once(']')?;
+            (host, rest.strip_prefix(':'))
+        }
+        None => match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => default_port,
+    };
+    (!host.is_empty()).then_some((host, port))
+}
+
+/// Results of a [UDP check](check_udp).
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct UdpStats {
+    /// Amount of datagrams that were sent
+    pub sent: u8,
+    /// Amount of answers that were received in time
+    pub received: u8,
+    /// Mean round-trip time of the answered datagrams in milliseconds
+    ///
+    /// [None] if no datagram was answered.
+    pub mean_latency: Option<u16>,
+}
+
+impl UdpStats {
+    /// Percentage of datagrams that were not answered, between 0 and 100.
+    pub fn loss_percent(&self) -> u8 {
+        if self.sent == 0 {
+            return 100;
+        }
+        (100 - (self.received as u16 * 100 / self.sent as u16)) as u8
+    }
+
+    /// Returns whether at least one datagram was answered and the
+    /// [loss](UdpStats::loss_percent) is below `max_percent`.
+    pub fn loss_below(&self, max_percent: u8) -> bool {
+        self.received > 0 && self.loss_percent() < max_percent
+    }
+}
+
+/// Performs a UDP check by sending `count` DNS queries to `remote` and waiting for the answers.
+///
+/// Each datagram is a minimal DNS query for the NS records of the root zone, with the sequence
+/// number as query id. Any answer that starts with the same id counts as answered, so a plain
+/// UDP echo server works as a target too.
+///
+/// The datagrams are sent one after the other, waiting up to `timeout` for each answer. Datagrams
+/// that are not answered in time count as lost.
+///
+/// # Errors
+///
+/// Returns `CheckError` if the socket can't be created or a datagram can't be sent. An
+/// unreachable port does not count as an error, but as loss.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use netpulse::checks::check_udp;
+///
+/// let stats = check_udp("1.1.1.1:53".parse()?, 5, std::time::Duration::from_secs(1))?;
+/// println!("lost {}% of the packets", stats.loss_percent());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn check_udp(remote: SocketAddr, count: u8, timeout: Duration) -> Result<UdpStats, CheckError> {
+    check_udp_with(remote, count, timeout, None, None)
+}
+
+/// Performs a [UDP check](check_udp) with the datagrams marked with `dscp`, see [set_dscp], and
+/// sent from `source`, see [bind_udp].
+///
+/// # Errors
+///
+/// See [check_udp]. Also returns `CheckError` if `dscp` can't be set, and [CheckError::Bind] if
+/// the datagrams can't be sent from `source`.
+pub fn check_udp_with(
+    remote: SocketAddr,
+    count: u8,
+    timeout: Duration,
+    dscp: Option<Dscp>,
+    source: Option<&Source>,
+) -> Result<UdpStats, CheckError> {
+    let socket = bind_udp(remote, source)?;
+    if let Some(dscp) = dscp {
+        set_dscp(&socket, remote.ip(), dscp)?;
+    }
+    socket.connect(remote)?;
+
+    let mut received: u8 = 0;
+    let mut latency_sum: u64 = 0;
+    let mut buf = [0u8; 512];
+    for seq in 0..count {
+        let id = (seq as u16).to_be_bytes();
+        let sent_at = Instant::now();
+        socket.send(&dns_query(id))?;
+
+        while let Some(remaining) = timeout.checked_sub(sent_at.elapsed()) {
+            if remaining.is_zero() {
+                break;
+            }
+            socket.set_read_timeout(Some(remaining))?;
+            match socket.recv(&mut buf) {
+                Ok(len) if len >= 2 && buf[..2] == id => {
+                    received += 1;
+                    latency_sum += sent_at.elapsed().as_millis() as u64;
+                    break;
+                }
+                // late answer to an earlier datagram, keep waiting
+                Ok(_) => continue,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionRefused
+                    ) =>
+                {
+                    break
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    Ok(UdpStats {
+        sent: count,
+        received,
+        mean_latency: (received > 0).then(|| (latency_sum / received as u64) as u16),
+    })
+}
+
+/// Creates a UDP socket for datagrams to `remote`, sent from `source` if given.
+///
+/// An [address](Source::Address) is bound with a port chosen by the system, an
+/// [interface](Source::Interface) with `SO_BINDTODEVICE`, after making sure that it is up.
+///
+/// # Errors
+///
+/// Returns [CheckError::Bind] if the socket can't be bound to `source`, like when the address
+/// is not local or of the other IP version, or the interface is missing or down.
+pub fn bind_udp(remote: SocketAddr, source: Option<&Source>) -> Result<UdpSocket, CheckError> {
+    let unspecified: IpAddr = match remote {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    match source {
+        None => Ok(UdpSocket::bind((unspecified, 0))?),
+        Some(source @ Source::Address(local)) => {
+            if local.is_ipv4() != remote.is_ipv4() {
+                return Err(bind_error(source, "the IP versions differ"));
+            }
+            UdpSocket::bind((*local, 0)).map_err(|err| bind_error(source, err))
+        }
+        Some(source @ Source::Interface(name)) => {
+            let socket = UdpSocket::bind((unspecified, 0))?;
+            interface_up(&socket, name).map_err(|reason| bind_error(source, reason))?;
+            bind_device(&socket, name).map_err(|err| bind_error(source, err))?;
+            Ok(socket)
+        }
+    }
+}
+
+/// Binds `socket` to the interface `name` with `SO_BINDTODEVICE`, so it only sends and
+/// receives through it.
+fn bind_device(socket: &impl AsFd, name: &str) -> std::io::Result<()> {
+    setsockopt(socket, sockopt::BindToDevice, &name.into())?;
+    Ok(())
+}
+
+/// Checks that the interface `name` exists and is up, asking through `socket`.
+///
+/// # Errors
+///
+/// Describes why the interface can't be used.
+fn interface_up(socket: &impl AsFd, name: &str) -> Result<(), String> {
+    if name.len() > Source::MAX_INTERFACE_LEN {
+        return Err("the name is too long".to_string());
+    }
+    // SAFETY: ifreq is plain old data, all zeroes is a valid value
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in request.ifr_name.iter_mut().zip(name.as_bytes()) {
+        *dst = *src as libc::c_char;
+    }
+    // SAFETY: the descriptor is borrowed from a live socket and request is a NUL terminated
+    // ifreq, as SIOCGIFFLAGS expects
+    let result = unsafe {
+        libc::ioctl(
+            socket.as_fd().as_raw_fd(),
+            libc::SIOCGIFFLAGS,
+            &mut request as *mut libc::ifreq,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    // SAFETY: SIOCGIFFLAGS filled in the flags
+    let flags = unsafe { request.ifr_ifru.ifru_flags };
+    if flags & libc::IFF_UP as libc::c_short == 0 {
+        return Err("the interface is down".to_string());
+    }
+    Ok(())
+}
+
+/// Makes the requests of `easy` go out from `source`.
+///
+/// # Errors
+///
+/// Returns [CheckError::Bind] if `source` is an interface that is missing or down.
+#[cfg(feature = "http")]
+fn bind_curl(easy: &mut curl::easy::Easy, source: &Source) -> Result<(), CheckError> {
+    match source {
+        Source::Address(local) => easy.interface(&format!("host!{local}"))?,
+        Source::Interface(name) => {
+            let probe = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+            interface_up(&probe, name).map_err(|reason| bind_error(source, reason))?;
+            easy.interface(&format!("if!{name}"))?
+        }
+    }
+    Ok(())
+}
+
+/// Turns a failed transfer into a [CheckError::Bind] if curl could not use the `source`.
+#[cfg(feature = "http")]
+fn curl_error(err: curl::Error, source: Option<&Source>) -> CheckError {
+    match source {
+        Some(source) if err.is_interface_failed() => bind_error(source, err),
+        _ => err.into(),
+    }
+}
+
+/// Creates a [CheckError::Bind] for `source`.
+fn bind_error(source: &Source, reason: impl std::fmt::Display) -> CheckError {
+    CheckError::Bind {
+        local: source.to_string(),
+        reason: reason.to_string(),
+    }
+}
 
-    easy.url(&match remote {
-        IpAddr::V4(_) => remote.to_string(),
-        IpAddr::V6(_) => format!("[{remote}]"),
-    })?;
-    easy.nobody(true)?; // HEAD request only
-    easy.timeout(TIMEOUT)?;
-    easy.perform()?;
+/// Marks the packets that `socket` sends to `remote` with `dscp`.
+///
+/// Sets `IP_TOS` for IPv4 and `IPV6_TCLASS` for IPv6, the ECN bits are left at zero. Only the
+/// sockets of [UDP checks](check_udp_with) are marked, see [Config::dscp](crate::config::Config::dscp).
+///
+/// # Errors
+///
+/// Returns `CheckError` if the socket option can't be set.
+pub fn set_dscp(socket: &impl AsFd, remote: IpAddr, dscp: Dscp) -> Result<(), CheckError> {
+    let value = libc::c_int::from(dscp.tos());
+    match remote {
+        IpAddr::V4(_) => setsockopt(socket, sockopt::IpTos, &value),
+        IpAddr::V6(_) => setsockopt(socket, sockopt::Ipv6TClass, &value),
+    }
+    .map_err(|errno| std::io::Error::from(errno).into())
+}
+
+/// Builds a minimal DNS query for the NS records of the root zone.
+fn dns_query(id: [u8; 2]) -> [u8; 17] {
+    [
+        id[0], id[1], // id
+        0x01, 0x00, // flags: recursion desired
+        0x00, 0x01, // one question
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // no answers, authorities or additionals
+        0x00, // root name
+        0x00, 0x02, // type NS
+        0x00, 0x01, // class IN
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::ResolveConfig;
+    use crate::records::CheckType;
+
+    use super::*;
+
+    /// Starts a local UDP echo server, that ignores every `drop_every`th datagram.
+    fn echo_server(drop_every: Option<usize>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            for n in 1.. {
+                let (len, peer) = socket.recv_from(&mut buf).unwrap();
+                if drop_every.is_some_and(|d| n % d == 0) {
+                    continue;
+                }
+                socket.send_to(&buf[..len], peer).unwrap();
+            }
+        });
+        addr
+    }
+
+    /// Starts a local HTTP server that accepts `connections` connections and answers each
+    /// request on them with `response` after `delay`, until the client hangs up.
+    ///
+    /// Returns the peer and head of each request once all connections are closed.
+    #[cfg(feature = "http")]
+    fn http_server(
+        connections: usize,
+        response: Vec<u8>,
+        delay: Duration,
+    ) -> (
+        SocketAddr,
+        std::thread::JoinHandle<Vec<(SocketAddr, String)>>,
+    ) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..connections {
+                let (mut stream, peer) = listener.accept().unwrap();
+                let mut buf = [0u8; 1024];
+                'connection: loop {
+                    let mut head = Vec::new();
+                    while !head.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buf) {
+                            Ok(0) | Err(_) => break 'connection,
+                            Ok(len) => head.extend_from_slice(&buf[..len]),
+                        }
+                    }
+                    std::thread::sleep(delay);
+                    // the client may hang up once it has read enough
+                    let _ = stream.write_all(&response);
+                    requests.push((peer, String::from_utf8(head).unwrap()));
+                }
+            }
+            requests
+        });
+        (addr, handle)
+    }
+
+    /// A response of the [http_server] with status 200 and `body`.
+    #[cfg(feature = "http")]
+    fn ok_with(body: &[u8]) -> Vec<u8> {
+        let mut response =
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_status() {
+        let (addr, _) = http_server(
+            1,
+            b"HTTP/1.1 302 Found\r\nLocation: /elsewhere\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            Duration::ZERO,
+        );
+        let options = HttpOptions {
+            method: HttpMethod::Head,
+            capture: 32,
+            ..Default::default()
+        };
+        let response = request(&format!("http://{addr}"), &options).unwrap();
+        assert_eq!(response.status, 302);
+        assert_eq!(response.version, Some(HttpVersion::V1_1));
+        // the capture is cut off after 32 bytes
+        assert_eq!(response.raw, b"HTTP/1.1 302 Found\r\nLocation: /e");
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_method_and_headers() {
+        /// Makes a request with `options` and returns the head the server received.
+        fn received(options: HttpOptions) -> String {
+            let (addr, server) = http_server(1, ok_with(b"hello"), Duration::ZERO);
+            request(&format!("http://{addr}"), &options).unwrap();
+            server.join().unwrap().remove(0).1
+        }
+
+        let head = HttpOptions {
+            method: HttpMethod::Head,
+            ..Default::default()
+        };
+        assert!(received(head.clone()).starts_with("HEAD / HTTP/1.1\r\n"));
+
+        let received_get = received(HttpOptions {
+            headers: BTreeMap::from([
+                ("Host".to_string(), "example.com".to_string()),
+                ("Authorization".to_string(), "Bearer secret".to_string()),
+            ]),
+            ..Default::default()
+        });
+        assert!(received_get.starts_with("GET / HTTP/1.1\r\n"));
+        assert!(received_get.contains("\r\nHost: example.com\r\n"));
+        assert!(received_get.contains("\r\nAuthorization: Bearer secret\r\n"));
+
+        let (addr, server) = http_server(1, ok_with(b"hello"), Duration::ZERO);
+        let v1_0 = HttpOptions {
+            version: Some(HttpVersion::V1_0),
+            ..head
+        };
+        let response = request(&format!("http://{addr}"), &v1_0).unwrap();
+        // the server answers with HTTP/1.1 anyway, which is what gets recorded
+        assert_eq!(response.version, Some(HttpVersion::V1_1));
+        assert!(server.join().unwrap()[0]
+            .1
+            .starts_with("HEAD / HTTP/1.0\r\n"));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_expected_body() {
+        let get = |body: &[u8], expected: &str| {
+            let (addr, _) = http_server(1, ok_with(body), Duration::ZERO);
+            let options = HttpOptions {
+                expect_body: Some(expected.to_string()),
+                ..Default::default()
+            };
+            request(&format!("http://{addr}"), &options).unwrap()
+        };
+
+        assert_eq!(
+            get(b"<h1>all systems operational</h1>", "operational").body_matches,
+            Some(true)
+        );
+        let response = get(b"<h1>502 Bad Gateway</h1>", "operational");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body_matches, Some(false));
+
+        // only the start of a large body is read, text after the cap is not found
+        let mut large = b"healthy".to_vec();
+        large.resize(MAX_BODY_BYTES * 4, b'x');
+        large.extend_from_slice(b"hidden");
+        assert_eq!(get(&large, "healthy").body_matches, Some(true));
+        assert_eq!(get(&large, "hidden").body_matches, Some(false));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_throughput() {
+        let payload = ok_with(&vec![b'x'; 256 * 1024]);
+
+        let (addr, _) = http_server(1, payload.clone(), Duration::ZERO);
+        let url = format!("http://localhost:{}/payload.bin", addr.port());
+        let throughput = check_throughput(
+            &url,
+            Some(addr.ip()),
+            1024 * 1024,
+            Duration::from_secs(5),
+            None,
+        )
+        .unwrap();
+        assert_eq!(throughput.status, 200);
+        assert_eq!(throughput.bytes, 256 * 1024);
+        assert_eq!(
+            throughput.mb_per_sec(),
+            256.0 * 1024.0 / throughput.duration.as_secs_f64() / 1_000_000.0
+        );
+
+        // the download stops at the cap
+        let (addr, _) = http_server(1, payload, Duration::ZERO);
+        let throughput = check_throughput(
+            &format!("http://{addr}/"),
+            None,
+            1000,
+            Duration::from_secs(5),
+            None,
+        )
+        .unwrap();
+        assert_eq!(throughput.bytes, 1000);
+
+        let measured = Throughput {
+            bytes: 5_000_000,
+            duration: Duration::from_millis(2500),
+            latency: 20,
+            status: 200,
+        };
+        assert_eq!(measured.bytes_per_sec(), 2_000_000.0);
+        assert_eq!(measured.mb_per_sec(), 2.0);
+    }
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(
+            url_host("http://speed.example.com/10MB.bin"),
+            Some(("speed.example.com", 80))
+        );
+        assert_eq!(
+            url_host("HTTPS://user@example.com:8443?size=1"),
+            Some(("example.com", 8443))
+        );
+        assert_eq!(url_host("http://[::1]:8080/"), Some(("::1", 8080)));
+        assert_eq!(
+            url_host("https://[2001:db8::1]"),
+            Some(("2001:db8::1", 443))
+        );
+        assert_eq!(url_host("ftp://example.com/file"), None);
+        assert_eq!(url_host("example.com/file"), None);
+        assert_eq!(url_host("http:///file"), None);
+        assert_eq!(url_host("http://example.com:port/"), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve("192.0.2.7").unwrap(),
+            ["192.0.2.7".parse::<IpAddr>().unwrap()]
+        );
+        assert!(resolve("localhost")
+            .unwrap()
+            .iter()
+            .all(|ip| ip.is_loopback()));
+    }
 
-    Ok(start.elapsed().as_millis() as u16)
+    /// Returns the answer to `query` of a DNS server that knows every name: A queries are
+    /// answered with `ip`, AAAA queries with no records.
+    fn dns_answer(query: &[u8], ip: [u8; 4]) -> Vec<u8> {
+        let is_a = query[query.len() - 4..query.len() - 2] == DNS_TYPE_A.to_be_bytes();
+        let mut answer = query.to_vec();
+        answer[2] = 0x81; // response, recursion desired
+        answer[3] = 0x80; // recursion available, no error
+        answer[7] = 0; // answer count
+        if is_a {
+            // a CNAME first, then the address
+            answer.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01]);
+            answer.extend_from_slice(&[0, 0, 0, 60, 0x00, 0x02, 0xc0, 0x0c]);
+            answer[7] = 2;
+            answer.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01]);
+            answer.extend_from_slice(&[0, 0, 0, 60, 0x00, 0x04]);
+            answer.extend_from_slice(&ip);
+        }
+        answer
+    }
+
+    /// Starts a local DNS server that answers queries with [dns_answer].
+    fn dns_server(ip: [u8; 4]) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            loop {
+                let (len, peer) = socket.recv_from(&mut buf).unwrap();
+                socket.send_to(&dns_answer(&buf[..len], ip), peer).unwrap();
+            }
+        });
+        addr
+    }
+
+    /// Starts a local DNS server that answers queries over UDP truncated and without records,
+    /// and over TCP with [dns_answer].
+    fn truncating_dns_server(ip: [u8; 4]) -> SocketAddr {
+        // the TCP port of the same number could be taken
+        let (socket, listener) = loop {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            if let Ok(listener) = std::net::TcpListener::bind(socket.local_addr().unwrap()) {
+                break (socket, listener);
+            }
+        };
+        let addr = socket.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            loop {
+                let (len, peer) = socket.recv_from(&mut buf).unwrap();
+                let mut answer = buf[..len].to_vec();
+                answer[2] = 0x83; // response, truncated, recursion desired
+                answer[3] = 0x80;
+                socket.send_to(&answer, peer).unwrap();
+            }
+        });
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut len = [0u8; 2];
+                stream.read_exact(&mut len).unwrap();
+                let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+                stream.read_exact(&mut query).unwrap();
+                let answer = dns_answer(&query, ip);
+                stream
+                    .write_all(&(answer.len() as u16).to_be_bytes())
+                    .unwrap();
+                stream.write_all(&answer).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_resolve_at() {
+        // an address the resolver of the system would not return for the name
+        let resolver = dns_server([192, 0, 2, 7]);
+        let expected: IpAddr = "192.0.2.7".parse().unwrap();
+        assert_eq!(
+            resolve_at("netpulse.test", resolver, AddressFamily::V4).unwrap(),
+            [expected]
+        );
+        assert_eq!(
+            resolve_at("netpulse.test", resolver, AddressFamily::Any).unwrap(),
+            [expected]
+        );
+        assert!(matches!(
+            resolve_at("netpulse.test", resolver, AddressFamily::V6),
+            Err(CheckError::NoAddress(_))
+        ));
+        let options = ResolveConfig {
+            family: AddressFamily::Any,
+            resolver: Some(resolver),
+        };
+        assert_eq!(
+            CheckType::Http
+                .resolve_with("netpulse.test", &options)
+                .unwrap(),
+            expected
+        );
+        // IP addresses are not resolved
+        assert_eq!(
+            resolve_at("::1", resolver, AddressFamily::V4).unwrap(),
+            ["::1".parse::<IpAddr>().unwrap()]
+        );
+
+        // a truncated answer is asked for again over TCP
+        let resolver = truncating_dns_server([192, 0, 2, 8]);
+        assert_eq!(
+            resolve_at("netpulse.test", resolver, AddressFamily::Any).unwrap(),
+            ["192.0.2.8".parse::<IpAddr>().unwrap()]
+        );
+    }
+
+    /// Reads the TOS or traffic class byte of `socket`.
+    fn tos_of(socket: &UdpSocket, ipv6: bool) -> libc::c_int {
+        match ipv6 {
+            false => nix::sys::socket::getsockopt(socket, sockopt::IpTos),
+            true => nix::sys::socket::getsockopt(socket, sockopt::Ipv6TClass),
+        }
+        .unwrap()
+    }
+
+    /// Returns the name of the interface `socket` is bound to with `SO_BINDTODEVICE`.
+    fn bound_device(socket: &impl AsFd) -> String {
+        let name = nix::sys::socket::getsockopt(socket, sockopt::BindToDevice).unwrap();
+        // the kernel counts the terminating NUL into the length
+        name.to_string_lossy().trim_end_matches('\0').to_string()
+    }
+
+    #[test]
+    fn test_icmpv6_socket_binds_zone() {
+        let scoped: ScopedAddr = "fe80::1%lo".parse().unwrap();
+        let (socket, scope_id) = match icmpv6_socket(&scoped) {
+            Err(CheckError::Io { source }) if source.kind() == ErrorKind::PermissionDenied => {
+                eprintln!("no CAP_NET_RAW, skipping: {source}");
+                return;
+            }
+            result => result.unwrap(),
+        };
+        assert_eq!(bound_device(&socket), "lo");
+        let (_, lo_index) = zone_interface("lo").unwrap();
+        assert_eq!(scope_id, lo_index);
+
+        // the index of the interface works as zone too
+        let by_index: ScopedAddr = format!("fe80::1%{lo_index}").parse().unwrap();
+        let (socket, scope_id) = icmpv6_socket(&by_index).unwrap();
+        assert_eq!(bound_device(&socket), "lo");
+        assert_eq!(scope_id, lo_index);
+
+        for zone in ["nosuchif0", "4000000000"] {
+            let missing: ScopedAddr = format!("fe80::1%{zone}").parse().unwrap();
+            match ping_scoped(&missing, TIMEOUT) {
+                Err(CheckError::Bind { local, .. }) => assert_eq!(local, zone),
+                other => panic!("{missing} should fail to bind: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bind_udp() {
+        let remote: SocketAddr = "127.0.0.1:53".parse().unwrap();
+        let local = Source::Address("127.0.0.1".parse().unwrap());
+        let socket = bind_udp(remote, Some(&local)).unwrap();
+        assert_eq!(
+            socket.local_addr().unwrap().ip(),
+            "127.0.0.1".parse::<IpAddr>().unwrap()
+        );
+
+        let socket = bind_udp(remote, Some(&Source::Interface("lo".to_string()))).unwrap();
+        assert_eq!(bound_device(&socket), "lo");
+
+        for source in [
+            // not an address of this host
+            Source::Address("192.0.2.1".parse().unwrap()),
+            Source::Address("::1".parse().unwrap()),
+            Source::Interface("nosuchif0".to_string()),
+        ] {
+            match bind_udp(remote, Some(&source)) {
+                Err(CheckError::Bind { local, .. }) => assert_eq!(local, source.to_string()),
+                other => panic!("{source} should fail to bind: {other:?}"),
+            }
+        }
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_phases() {
+        const DELAY: Duration = Duration::from_millis(50);
+
+        /// Counts the connections the requests of the [http_server] came through.
+        fn connections(requests: Vec<(SocketAddr, String)>) -> usize {
+            let peers: std::collections::HashSet<SocketAddr> =
+                requests.into_iter().map(|(peer, _)| peer).collect();
+            peers.len()
+        }
+        let serve = |connections| {
+            http_server(
+                connections,
+                b"HTTP/1.1 204 No Content\r\n\r\n".to_vec(),
+                DELAY,
+            )
+        };
+        let head = |addr: SocketAddr, connection| {
+            let options = HttpOptions {
+                method: HttpMethod::Head,
+                connection,
+                ..Default::default()
+            };
+            request(&format!("http://{addr}/"), &options).unwrap()
+        };
+
+        let (addr, server) = serve(2);
+        for _ in 0..2 {
+            let phases = head(addr, HttpConnection::Fresh).phases;
+            assert!(!phases.reused);
+            assert!(phases.ttfb >= DELAY, "{phases:?}");
+            // plain HTTP has no handshake
+            assert_eq!(phases.tls, Duration::ZERO);
+            let sum = phases.dns + phases.connect + phases.tls + phases.ttfb;
+            assert!(sum <= phases.total, "{phases:?}");
+            assert!(phases.total - sum < Duration::from_millis(20), "{phases:?}");
+        }
+        // each fresh check had a connection of its own
+        assert_eq!(connections(server.join().unwrap()), 2);
+
+        let (addr, server) = serve(1);
+        let cold = head(addr, HttpConnection::Reuse).phases;
+        assert!(!cold.reused);
+        let warm = head(addr, HttpConnection::Reuse).phases;
+        assert!(warm.reused, "{warm:?}");
+        assert!(warm.ttfb >= DELAY);
+        assert!(warm.connect < Duration::from_millis(1));
+        // closes the kept connection, so the server stops
+        CONNECTIONS.with_borrow_mut(|handles| handles.clear());
+        assert_eq!(connections(server.join().unwrap()), 1);
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_source() {
+        let (addr, server) = http_server(
+            1,
+            b"HTTP/1.1 204 No Content\r\n\r\n".to_vec(),
+            Duration::ZERO,
+        );
+        let local = HttpOptions {
+            method: HttpMethod::Head,
+            source: Some(Source::Address("127.0.0.1".parse().unwrap())),
+            ..Default::default()
+        };
+        let response = request(&format!("http://{addr}"), &local).unwrap();
+        assert_eq!(response.status, 204);
+        assert_eq!(
+            server.join().unwrap()[0].0.ip(),
+            "127.0.0.1".parse::<IpAddr>().unwrap()
+        );
+
+        let unusable = HttpOptions {
+            source: Some(Source::Address("192.0.2.1".parse().unwrap())),
+            ..local
+        };
+        let err = request(&format!("http://{addr}"), &unusable).unwrap_err();
+        assert!(matches!(err, CheckError::Bind { .. }), "{err}");
+    }
+
+    #[test]
+    fn test_set_dscp() {
+        let ef = Dscp::new(46).unwrap();
+        let v4 = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert_eq!(tos_of(&v4, false), 0);
+        set_dscp(&v4, "127.0.0.1".parse().unwrap(), ef).unwrap();
+        assert_eq!(tos_of(&v4, false), 0xb8);
+        // the sandbox may have no IPv6
+        if let Ok(v6) = UdpSocket::bind("[::1]:0") {
+            set_dscp(&v6, "::1".parse().unwrap(), ef).unwrap();
+            assert_eq!(tos_of(&v6, true), 0xb8);
+        }
+
+        // the value is recorded in the UDP checks of the target
+        let server = echo_server(None);
+        let mut config = crate::config::Config::default();
+        config.udp.port = server.port();
+        config.udp.count = 1;
+        config.dscp.insert(server.ip(), ef);
+        let check = CheckType::Udp.make_with(server.ip(), &config);
+        assert!(check.is_success());
+        assert!(check
+            .details()
+            .contains(&crate::records::CheckDetail::Dscp { value: 46 }));
+        config.dscp.clear();
+        let check = CheckType::Udp.make_with(server.ip(), &config);
+        assert!(!check
+            .details()
+            .iter()
+            .any(|d| matches!(d, crate::records::CheckDetail::Dscp { .. })));
+    }
+
+    #[test]
+    fn test_udp_no_loss() {
+        let stats = check_udp(echo_server(None), 5, Duration::from_millis(500)).unwrap();
+        assert_eq!(stats.sent, 5);
+        assert_eq!(stats.received, 5);
+        assert_eq!(stats.loss_percent(), 0);
+        assert!(stats.mean_latency.is_some());
+    }
+
+    #[test]
+    fn test_udp_loss() {
+        let stats = check_udp(echo_server(Some(2)), 4, Duration::from_millis(200)).unwrap();
+        assert_eq!(stats.received, 2);
+        assert_eq!(stats.loss_percent(), 50);
+        // the loss has to be below the threshold, not at it
+        assert!(!stats.loss_below(50));
+        assert!(stats.loss_below(51));
+
+        // nobody listens on this port anymore
+        let silent = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let stats = check_udp(silent, 3, Duration::from_millis(200)).unwrap();
+        assert_eq!(stats.received, 0);
+        assert_eq!(stats.loss_percent(), 100);
+        assert!(!stats.loss_below(100));
+        assert_eq!(stats.mean_latency, None);
+    }
 }
@@ -0,0 +1,1651 @@
+//! Configuration of netpulse.
+//!
+//! The configuration is read from a TOML file at [CONFIG_PATH], or from the path in the
+//! [ENV_CONFIG] environment variable. If the file does not exist, the [default](Config::default)
+//! configuration is used. Every field is optional, missing fields take their default value.
+//!
+//! # Example
+//!
+//! ```toml
+//! hosts = ["example.com"]
+//!
+//! [udp]
+//! count = 10
+//! max_loss_percent = 10
+//!
+//! [outages]
+//! critical_after_secs = 1800
+//!
+//! [backoff]
+//! enabled = true
+//!
+//! [report]
+//! timezone = "+02:00"
+//!
+//! [tags."1.1.1.1"]
+//! region = "eu"
+//! tier = "prod"
+//!
+//! [[http.targets]]
+//! target = "1.1.1.1"
+//! accept = [200, 204, 302]
+//! method = "GET"
+//! headers = { Host = "one.one.one.one" }
+//!
+//! [[slo]]
+//! check_type = "icmpv4"
+//! percentile = 95
+//! max_ms = 50
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ConfigError;
+use crate::maintenance::MaintenanceWindow;
+use crate::predicate::Predicate;
+use crate::records::{Check, CheckDetail, CheckType};
+use crate::store::ShardBy;
+use crate::syslog::SyslogFormat;
+use crate::timezone::Timezone;
+
+/// Default location of the configuration file
+pub const CONFIG_PATH: &str = "/etc/netpulse.toml";
+
+/// Environment variable name for overriding the configuration file path
+///
+/// If set, its value will be used instead of [CONFIG_PATH].
+pub const ENV_CONFIG: &str = "NETPULSE_CONFIG";
+
+/// Configuration of netpulse, see the [module documentation](self).
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Hostnames that are checked in addition to the [TARGETS](crate::records::TARGETS)
+    ///
+    /// They are resolved before every check, the address that was used is recorded as
+    /// [target](crate::records::Check::target) of the check.
+    ///
+    /// Link-local IPv6 addresses are given with their zone, like `fe80::1%eth0`, see
+    /// [ScopedAddr]. They only get [ICMPv6 checks](crate::records::CheckType::IcmpV6).
+    pub hosts: Vec<String>,
+    /// Settings for [HTTP checks](crate::records::CheckType::Http)
+    pub http: HttpConfig,
+    /// Settings for [UDP checks](crate::records::CheckType::Udp)
+    pub udp: UdpConfig,
+    /// Settings for [throughput checks](crate::records::CheckType::Throughput)
+    pub throughput: ThroughputConfig,
+    /// Settings for the classification of [outages](crate::analyze::Outage)
+    pub outages: OutageConfig,
+    /// Settings for checking failing targets less often, see [backoff](crate::backoff)
+    pub backoff: BackoffConfig,
+    /// Settings for retrying failed checks before recording them
+    pub confirm: ConfirmConfig,
+    /// Settings for the reports of `netpulse`
+    pub report: ReportConfig,
+    /// Tags of the targets, keyed by IP address or hostname as in [hosts](Config::hosts)
+    ///
+    /// The tags are added to every [Check](crate::records::Check) of that target, see
+    /// [Check::tags](crate::records::Check::tags).
+    pub tags: BTreeMap<String, BTreeMap<String, String>>,
+    /// Settings for sending outage events to the syslog, see [syslog](crate::syslog)
+    pub syslog: SyslogConfig,
+    /// Settings for the [Store](crate::store::Store) file
+    pub store: StoreConfig,
+    /// Latency objectives, checked in the report and by
+    /// [Health](crate::analyze::Health::of_store_with)
+    pub slo: Vec<LatencySlo>,
+    /// Daily [maintenance windows](crate::maintenance), the checks made in them are marked
+    pub maintenance: Vec<MaintenanceWindow>,
+    /// Critical targets, by IP address or hostname as in [hosts](Config::hosts)
+    ///
+    /// If set, only outages of these targets make the
+    /// [Health](crate::analyze::Health::of_store_with) an outage, and the report marks them.
+    /// Outages of the other targets are still recorded and reported. If empty, all targets are
+    /// critical.
+    pub critical: Vec<String>,
+    /// How the [hosts](Config::hosts) are resolved, keyed by hostname
+    pub resolve: BTreeMap<String, ResolveConfig>,
+    /// DSCP values the probes to a target are marked with, keyed by IP address
+    ///
+    /// Only [UDP checks](crate::records::CheckType::Udp) are marked: ICMP and HTTP checks are
+    /// made by libraries that don't expose their sockets. Hostnames are not supported as keys,
+    /// use the address the host resolves to. The value that was used is recorded as
+    /// [CheckDetail::Dscp](crate::records::CheckDetail::Dscp).
+    pub dscp: BTreeMap<IpAddr, Dscp>,
+    /// Local address or network interface the probes to a target are sent from, keyed by IP
+    /// address
+    ///
+    /// For hosts with multiple uplinks. Only [UDP](crate::records::CheckType::Udp),
+    /// [HTTP](crate::records::CheckType::Http) and
+    /// [throughput](crate::records::CheckType::Throughput) checks are bound, ICMP checks don't
+    /// expose their sockets. The source that was used is recorded as
+    /// [CheckDetail::Source](crate::records::CheckDetail::Source).
+    pub source: BTreeMap<IpAddr, Source>,
+    /// Settings for capturing the raw responses of failed checks, for `netpulse --inspect`
+    pub capture: CaptureConfig,
+    /// Settings for the HTTP health endpoints of the daemon, see [health](crate::health)
+    pub health: HealthConfig,
+    /// Conditions deciding the success of the checks of a target instead of the defaults,
+    /// keyed by IP address or hostname as in [hosts](Config::hosts), see
+    /// [predicate](crate::predicate)
+    pub success: BTreeMap<String, Predicate>,
+    /// Highest acceptable latency of the checks of a target in milliseconds, keyed by IP
+    /// address or hostname as in [hosts](Config::hosts)
+    ///
+    /// A successful check that took longer is recorded as soft failure with a
+    /// [CheckDetail::TooSlow](crate::records::CheckDetail::TooSlow). Throughput checks are left
+    /// alone, their latency is the time of the whole download.
+    pub max_latency_ms: BTreeMap<String, u16>,
+    /// Timeout, retries and interval of each check type, see [check_settings](Config::check_settings)
+    pub checks: ChecksConfig,
+}
+
+/// Settings for [HTTP checks](crate::records::CheckType::Http).
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Settings for specific targets
+    pub targets: Vec<HttpTargetConfig>,
+    /// Whether each check opens a new connection, or reuses the one of the last check
+    pub connection: HttpConnection,
+    /// Record how long each phase of the requests took, as
+    /// [CheckDetail::HttpPhases](crate::records::CheckDetail::HttpPhases)
+    pub phases: bool,
+}
+
+/// Settings for [HTTP checks](crate::records::CheckType::Http) of a single target.
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpTargetConfig {
+    /// The target these settings are for
+    pub target: IpAddr,
+    /// Status codes that count as success, every 2xx and 3xx status if not set, see
+    /// [DEFAULT_ACCEPT]
+    #[serde(default)]
+    pub accept: Option<Vec<u16>>,
+    /// Method of the requests, `GET` by default
+    #[serde(default)]
+    pub method: HttpMethod,
+    /// Additional headers of the requests, like `Host` or `Authorization`
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// HTTP version the requests should use, any version by default
+    ///
+    /// If the server answers with another version, the check fails.
+    #[serde(default)]
+    pub version: Option<HttpVersion>,
+    /// Text the response body must contain for the check to succeed
+    ///
+    /// Only the first [MAX_BODY_BYTES](crate::checks::MAX_BODY_BYTES) of the body are searched.
+    /// Needs the `GET` [method](HttpTargetConfig::method), a `HEAD` response has no body.
+    #[serde(default)]
+    pub expect_body: Option<String>,
+}
+
+/// Status codes that count as success if no [accept](HttpTargetConfig::accept) list is set:
+/// every 2xx and 3xx status
+///
+/// Redirects are not followed, so a target that redirects, like `http://1.1.1.1`, is up.
+pub const DEFAULT_ACCEPT: Range<u16> = 200..400;
+
+/// How the requests of [HTTP checks](crate::records::CheckType::Http) are connected.
+///
+/// Written in lowercase in the configuration, like `"reuse"`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpConnection {
+    /// Resolve, connect and set up TLS for every check, so the latency includes all of it
+    #[default]
+    Fresh,
+    /// Keep the connection to a target open between checks, so the latency is mostly the
+    /// response time of the server
+    ///
+    /// A connection is only kept after a successful request, and a new one is opened if the
+    /// server closed it in the meantime.
+    Reuse,
+}
+
+/// Method of the requests of [HTTP checks](crate::records::CheckType::Http).
+///
+/// Written in uppercase in the configuration, like `"GET"`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    /// Only ask for the headers of the response
+    Head,
+    /// Ask for the whole response, the body is discarded
+    #[default]
+    Get,
+}
+
+impl Display for HttpMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Head => "HEAD",
+                Self::Get => "GET",
+            }
+        )
+    }
+}
+
+/// Version of HTTP, for [HTTP checks](crate::records::CheckType::Http).
+///
+/// Written like in the status line of a response in the configuration, like `"2"` or `"1.1"`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+pub enum HttpVersion {
+    /// HTTP/1.0
+    #[serde(rename = "1.0")]
+    V1_0,
+    /// HTTP/1.1
+    #[serde(rename = "1.1")]
+    V1_1,
+    /// HTTP/2, negotiated with ALPN for HTTPS or with an upgrade from HTTP/1.1 otherwise
+    #[serde(rename = "2")]
+    V2,
+    /// HTTP/3 over QUIC, only for HTTPS
+    #[serde(rename = "3")]
+    V3,
+}
+
+impl HttpVersion {
+    /// Parses the version of a status line of a response, like `HTTP/2 200`.
+    pub fn from_status_line(line: &str) -> Option<Self> {
+        let (protocol, _) = line.trim().split_once(' ')?;
+        Some(match protocol.strip_prefix("HTTP/")? {
+            "1.0" => Self::V1_0,
+            "1.1" => Self::V1_1,
+            "2" | "2.0" => Self::V2,
+            "3" | "3.0" => Self::V3,
+            _ => return None,
+        })
+    }
+}
+
+impl Display for HttpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::V1_0 => "1.0",
+                Self::V1_1 => "1.1",
+                Self::V2 => "2",
+                Self::V3 => "3",
+            }
+        )
+    }
+}
+
+impl HttpConfig {
+    /// Returns the settings for `target`, if [targets](HttpConfig::targets) has an entry for it.
+    pub fn target(&self, target: IpAddr) -> Option<&HttpTargetConfig> {
+        self.targets.iter().find(|t| t.target == target)
+    }
+
+    /// Returns whether a response with `status` from `target` counts as success.
+    ///
+    /// If [targets](HttpConfig::targets) has an entry for `target` with an
+    /// [accept](HttpTargetConfig::accept) list, that is used, otherwise the status has to be in
+    /// [DEFAULT_ACCEPT].
+    pub fn accepts(&self, target: IpAddr, status: u16) -> bool {
+        match self.target(target).and_then(|t| t.accept.as_ref()) {
+            Some(accept) => accept.contains(&status),
+            None => DEFAULT_ACCEPT.contains(&status),
+        }
+    }
+
+    /// Returns whether a response in the HTTP `version` from `target` counts as success.
+    ///
+    /// Every version is accepted, unless [targets](HttpConfig::targets) has an entry for
+    /// `target` with a [version](HttpTargetConfig::version). A response without a known version
+    /// is only accepted if no version is required.
+    pub fn accepts_version(&self, target: IpAddr, version: Option<HttpVersion>) -> bool {
+        match self.target(target).and_then(|t| t.version) {
+            Some(required) => version == Some(required),
+            None => true,
+        }
+    }
+}
+
+/// A latency objective: the `percentile` of the latencies of `check_type` must be at most
+/// `max_ms`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LatencySlo {
+    /// Type of the checks whose latency is measured, like `icmpv4`
+    #[serde(with = "check_type_name")]
+    pub check_type: CheckType,
+    /// Percentile of the latencies, between 1 and 100
+    pub percentile: u8,
+    /// Highest allowed latency at the percentile, in milliseconds
+    pub max_ms: u32,
+}
+
+/// IP version of the addresses a hostname may resolve to, see [ResolveConfig].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressFamily {
+    /// IPv4 and IPv6 addresses
+    #[default]
+    Any,
+    /// Only IPv4 addresses
+    V4,
+    /// Only IPv6 addresses
+    V6,
+}
+
+impl AddressFamily {
+    /// Returns whether `ip` is of this family.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        match self {
+            Self::Any => true,
+            Self::V4 => ip.is_ipv4(),
+            Self::V6 => ip.is_ipv6(),
+        }
+    }
+}
+
+/// A Differentiated Services Code Point, between 0 and [Dscp::MAX], see [Config::dscp].
+///
+/// It is the upper six bits of the IPv4 TOS or IPv6 traffic class byte, like `46` for
+/// expedited forwarding.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub struct Dscp(u8);
+
+impl Dscp {
+    /// The highest code point
+    pub const MAX: u8 = 63;
+
+    /// Creates a code point, [None] if `value` is above [Dscp::MAX].
+    pub fn new(value: u8) -> Option<Self> {
+        (value <= Self::MAX).then_some(Self(value))
+    }
+
+    /// The code point itself.
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+
+    /// The TOS or traffic class byte with this code point, without ECN bits.
+    pub fn tos(&self) -> u8 {
+        self.0 << 2
+    }
+}
+
+impl TryFrom<u8> for Dscp {
+    type Error = ConfigError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::new(value).ok_or_else(|| {
+            ConfigError::Invalid(format!(
+                "invalid DSCP value {value}, expected 0 to {}",
+                Self::MAX
+            ))
+        })
+    }
+}
+
+impl From<Dscp> for u8 {
+    fn from(value: Dscp) -> Self {
+        value.0
+    }
+}
+
+/// Where the probes to a target are sent from, see [Config::source].
+///
+/// Written as an IP address, like `"192.0.2.10"`, or the name of a network interface, like
+/// `"eth1"`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Source {
+    /// A local address, the port is chosen by the system
+    Address(IpAddr),
+    /// A network interface
+    Interface(String),
+}
+
+impl Source {
+    /// The longest name of an interface, without the trailing NUL
+    pub const MAX_INTERFACE_LEN: usize = libc::IFNAMSIZ - 1;
+}
+
+impl TryFrom<String> for Source {
+    type Error = ConfigError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if let Ok(addr) = value.parse() {
+            return Ok(Self::Address(addr));
+        }
+        if value.is_empty()
+            || value.len() > Self::MAX_INTERFACE_LEN
+            || value.contains(|c: char| c == '/' || c == ':' || c.is_whitespace())
+        {
+            return Err(ConfigError::Invalid(format!(
+                "invalid source {value:?}, expected an IP address or an interface name"
+            )));
+        }
+        Ok(Self::Interface(value))
+    }
+}
+
+impl From<Source> for String {
+    fn from(value: Source) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Address(addr) => write!(f, "{addr}"),
+            Self::Interface(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// An IPv6 address with the zone it is reached through, like `fe80::1%eth0`.
+///
+/// Link-local addresses are only unique on their link, so the zone names the interface of the
+/// link, or its index, like `fe80::1%2`. The address is recorded as
+/// [target](crate::records::Check::target) of the checks, and the zone as
+/// [CheckDetail::Zone](crate::records::CheckDetail::Zone).
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct ScopedAddr {
+    /// The address
+    pub ip: Ipv6Addr,
+    /// Name or index of the interface
+    pub zone: String,
+}
+
+impl ScopedAddr {
+    /// Returns whether `ip` needs a zone to be reached, because it is a unicast link-local
+    /// address in `fe80::/10`.
+    pub fn needs_zone(ip: IpAddr) -> bool {
+        matches!(ip, IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80)
+    }
+}
+
+impl FromStr for ScopedAddr {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| ConfigError::Invalid(format!("{s}: {reason}"));
+        let Some((ip, zone)) = s.split_once('%') else {
+            return Err(match s.parse::<IpAddr>() {
+                Ok(ip) if Self::needs_zone(ip) => {
+                    invalid(&format!("a link-local address needs a zone, like {s}%eth0"))
+                }
+                _ => invalid("expected an IPv6 address with a zone, like fe80::1%eth0"),
+            });
+        };
+        let ip = match ip.parse::<IpAddr>() {
+            Ok(IpAddr::V6(ip)) => ip,
+            Ok(IpAddr::V4(_)) => return Err(invalid("only IPv6 addresses have a zone")),
+            Err(_) => return Err(invalid("not an IP address")),
+        };
+        if zone.is_empty() {
+            return Err(invalid("the zone is empty"));
+        }
+        if zone.len() > Source::MAX_INTERFACE_LEN
+            || zone.contains(|c: char| c == '/' || c == '%' || c.is_whitespace())
+        {
+            return Err(invalid("the zone is not an interface name or index"));
+        }
+        Ok(Self {
+            ip,
+            zone: zone.to_string(),
+        })
+    }
+}
+
+impl Display for ScopedAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}%{}", self.ip, self.zone)
+    }
+}
+
+impl Display for Dscp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// How a [host](Config::hosts) is resolved, to rule out the DNS setup of the system.
+///
+/// The address that was used is recorded as [target](crate::records::Check::target) of each
+/// check, with the flag of its IP version.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResolveConfig {
+    /// Only addresses of this IP version are used, checks that need the other version are
+    /// skipped
+    pub family: AddressFamily,
+    /// DNS server that is asked instead of the resolver of the system, like `"1.1.1.1"` or
+    /// `"[2606:4700:4700::1111]:53"`
+    #[serde(with = "resolver_addr")]
+    pub resolver: Option<SocketAddr>,
+}
+
+/// (De)serializes the address of a DNS server, with port 53 if none is given.
+mod resolver_addr {
+    use std::net::{IpAddr, SocketAddr};
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(addr: &Option<SocketAddr>, s: S) -> Result<S::Ok, S::Error> {
+        match addr {
+            Some(addr) => s.serialize_str(&addr.to_string()),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<SocketAddr>, D::Error> {
+        let raw = String::deserialize(d)?;
+        raw.parse::<SocketAddr>()
+            .or_else(|_| raw.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid DNS server address '{raw}'")))
+    }
+}
+
+/// (De)serializes a [CheckType] by its lowercase name, like `icmpv4`.
+mod check_type_name {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::records::CheckType;
+
+    pub fn serialize<S: Serializer>(check_type: &CheckType, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&check_type.to_string().to_lowercase())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<CheckType, D::Error> {
+        String::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Settings for [UDP checks](crate::records::CheckType::Udp).
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UdpConfig {
+    /// How many datagrams to send per check
+    pub count: u8,
+    /// The check only succeeds if less than this percentage of datagrams is lost
+    pub max_loss_percent: u8,
+    /// Port on the targets to send the datagrams to
+    pub port: u16,
+    /// How long to wait for the answer to each datagram, in milliseconds
+    pub timeout_ms: u16,
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        Self {
+            count: 5,
+            max_loss_percent: 20,
+            port: 53,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+impl UdpConfig {
+    /// Returns [timeout_ms](UdpConfig::timeout_ms) as [Duration].
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms as u64)
+    }
+}
+
+/// Settings for [throughput checks](crate::records::CheckType::Throughput).
+///
+/// Throughput checks download [url](ThroughputConfig::url), so they are only made if it is set,
+/// and only every [interval_secs](ThroughputConfig::interval_secs).
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThroughputConfig {
+    /// URL of a file to download, like `http://speed.example.com/10MB.bin`
+    pub url: Option<String>,
+    /// How many bytes are downloaded at most, the download is stopped there
+    pub max_bytes: u64,
+    /// How long the download may take at most, in seconds
+    pub timeout_secs: u64,
+    /// The check fails if less than this many kilobytes per second are downloaded
+    pub min_kb_per_sec: u64,
+    /// Seconds between two throughput checks
+    pub interval_secs: u64,
+}
+
+impl Default for ThroughputConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            max_bytes: 10 * 1024 * 1024,
+            timeout_secs: 30,
+            min_kb_per_sec: 0,
+            interval_secs: 3600,
+        }
+    }
+}
+
+impl ThroughputConfig {
+    /// Returns [timeout_secs](ThroughputConfig::timeout_secs) as [Duration].
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    /// Returns whether a throughput of `bytes_per_sec` is enough, see
+    /// [min_kb_per_sec](ThroughputConfig::min_kb_per_sec).
+    pub fn accepts(&self, bytes_per_sec: f64) -> bool {
+        bytes_per_sec >= self.min_kb_per_sec as f64 * 1000.0
+    }
+}
+
+/// Thresholds for the [Severity](crate::analyze::Severity) of an
+/// [Outage](crate::analyze::Outage).
+///
+/// An outage reaches a tier if either its duration or its amount of failed checks reaches the
+/// threshold of that tier. Fewer than [min_failures](OutageConfig::min_failures) failed checks
+/// in a row are no outage at all, but transient failures.
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutageConfig {
+    /// Outages lasting at least this many seconds are major
+    pub major_after_secs: u64,
+    /// Outages lasting at least this many seconds are critical
+    pub critical_after_secs: u64,
+    /// Outages with at least this many failed checks are major
+    pub major_after_checks: usize,
+    /// Outages with at least this many failed checks are critical
+    pub critical_after_checks: usize,
+    /// Failed checks in a row needed for an outage, fewer are reported as transient failures
+    pub min_failures: usize,
+    /// Count failed checks made in a [maintenance window](crate::maintenance) for the outages and
+    /// the success ratios of the report
+    pub count_maintenance: bool,
+    /// Gaps without checks longer than this many seconds in an outage are marked as
+    /// [unmonitored](crate::analyze::Outage::unmonitored), like when the daemon was down during
+    /// the outage. Not marked by default
+    pub unmonitored_after_secs: Option<u64>,
+}
+
+impl Default for OutageConfig {
+    fn default() -> Self {
+        Self {
+            major_after_secs: 5 * 60,
+            critical_after_secs: 60 * 60,
+            major_after_checks: 5,
+            critical_after_checks: 60,
+            min_failures: 1,
+            count_maintenance: false,
+            unmonitored_after_secs: None,
+        }
+    }
+}
+
+/// Settings for the [Backoff](crate::backoff::Backoff) of failing targets.
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackoffConfig {
+    /// Whether failing targets are checked less often
+    pub enabled: bool,
+    /// After this many consecutive failures, the interval of a target starts doubling
+    pub after_failures: u32,
+    /// The interval grows up to this many periods
+    pub max_factor: u32,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            after_failures: 3,
+            max_factor: 16,
+        }
+    }
+}
+
+/// Settings for confirming failures: a failed check is retried right away, and only recorded as
+/// failed if all retries fail too.
+///
+/// A check that succeeds on a retry is recorded as success with a
+/// [CheckDetail::Retried](crate::records::CheckDetail::Retried).
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfirmConfig {
+    /// Whether failed checks are retried
+    pub enabled: bool,
+    /// How often a failed check is retried at most
+    pub retries: u8,
+}
+
+impl Default for ConfirmConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retries: 2,
+        }
+    }
+}
+
+/// Settings for capturing the raw responses of failed checks, like the head of an HTTP response.
+///
+/// The captured data is stored with the check as
+/// [CheckDetail::Captured](crate::records::CheckDetail::Captured) and shown by
+/// `netpulse --inspect HASH`. Responses may contain private data and take up space in the store,
+/// so capturing is off by default and capped at [max_bytes](CaptureConfig::max_bytes).
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// Whether the responses of failed checks are captured
+    pub enabled: bool,
+    /// How many bytes of a response are captured at most, up to [MAX_CAPTURE_BYTES]
+    pub max_bytes: usize,
+}
+
+/// Upper limit of [max_bytes](CaptureConfig::max_bytes)
+pub const MAX_CAPTURE_BYTES: usize = 16 * 1024;
+
+impl CaptureConfig {
+    /// Returns how many bytes of a response are captured, 0 if capturing is off.
+    pub fn limit(&self) -> usize {
+        match self.enabled {
+            true => self.max_bytes,
+            false => 0,
+        }
+    }
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes: 1024,
+        }
+    }
+}
+
+/// Settings for the reports of `netpulse`.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportConfig {
+    /// Timezone in which timestamps are shown, the store always uses UTC
+    pub timezone: Timezone,
+    /// Leave the checks of [annotated](crate::annotations) outages out of the success ratios
+    pub exclude_annotated: bool,
+    /// Width of the report in columns, [DEFAULT_WIDTH](crate::analyze::DEFAULT_WIDTH) if not set
+    ///
+    /// `netpulse` fits the report into the terminal if this is not set, see
+    /// [report_width](crate::analyze::report_width).
+    pub width: Option<usize>,
+    /// Targets without a successful check for this many seconds are flagged as stale, see
+    /// [last_ok](crate::analyze::last_ok)
+    ///
+    /// [DEFAULT_STALE_AFTER](crate::analyze::DEFAULT_STALE_AFTER) if not set.
+    pub stale_after_secs: Option<u64>,
+    /// If the store has more checks than this, the statistics of the report are calculated
+    /// over a [sample](crate::analyze::sample) of about this many checks
+    ///
+    /// Outages, the availability, missed checks and latency objectives always use all checks.
+    /// Off by default.
+    pub sample_above: Option<usize>,
+    /// Leave the hash and state of the store file at [Store::path](crate::store::Store::path)
+    /// out of the report, for stores that were not loaded from it
+    pub skip_store_file: bool,
+    /// Follow each figure of the report with a line describing how it was calculated
+    pub explain: bool,
+}
+
+impl ReportConfig {
+    /// How long a target may go without a successful check before it is stale.
+    pub fn stale_after(&self) -> Duration {
+        self.stale_after_secs
+            .map_or(crate::analyze::DEFAULT_STALE_AFTER, Duration::from_secs)
+    }
+}
+
+/// Settings for the [Store](crate::store::Store) file.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    /// How the checks are split into multiple files
+    pub shard_by: ShardBy,
+    /// Skip checks that duplicate a check made at most this many seconds before, see
+    /// [add_check_with](crate::store::Store::add_check_with)
+    ///
+    /// Off by default, since every added check is compared to the recent ones.
+    pub dedup_window_secs: Option<u64>,
+    /// Save the new checks at least this often while a check cycle is running, in seconds, see
+    /// [AutoSave](crate::store::AutoSave)
+    ///
+    /// The store is always saved at the end of a cycle.
+    pub autosave_secs: Option<u64>,
+    /// Save the store after this many new checks while a check cycle is running, see
+    /// [AutoSave](crate::store::AutoSave)
+    pub autosave_checks: Option<usize>,
+}
+
+/// Settings for sending [outage events](crate::syslog::OutageEvent) to the syslog.
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyslogConfig {
+    /// Whether the daemon sends outage events to the syslog
+    pub enabled: bool,
+    /// Path of the syslog socket
+    pub socket: PathBuf,
+    /// Format of the messages
+    pub format: SyslogFormat,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            socket: PathBuf::from("/dev/log"),
+            format: SyslogFormat::default(),
+        }
+    }
+}
+
+/// Settings for the HTTP health endpoints of the daemon, see [health](crate::health).
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Address and port the daemon serves the endpoints on, like `127.0.0.1:9464`
+    ///
+    /// Off if unset. Needs the `health-http` feature.
+    pub listen: Option<SocketAddr>,
+    /// `/readyz` fails if the last check cycle is older than this many seconds, or had no
+    /// successful check
+    pub stale_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            // three missed cycles
+            stale_secs: 180,
+        }
+    }
+}
+
+/// Timeout, retries and interval of each of the [default check types](CheckType::default_enabled).
+///
+/// Unset values take the built-in defaults, see [Config::check_settings].
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChecksConfig {
+    /// Settings for [HTTP checks](crate::records::CheckType::Http)
+    pub http: CheckTypeConfig,
+    /// Settings for [ICMPv4 checks](crate::records::CheckType::IcmpV4)
+    pub icmpv4: CheckTypeConfig,
+    /// Settings for [ICMPv6 checks](crate::records::CheckType::IcmpV6)
+    pub icmpv6: CheckTypeConfig,
+    /// Settings for [UDP checks](crate::records::CheckType::Udp)
+    pub udp: CheckTypeConfig,
+}
+
+impl ChecksConfig {
+    /// Returns the settings of `check_type` and its name in the configuration file, [None] for
+    /// the types that have none.
+    fn get(&self, check_type: CheckType) -> Option<(&'static str, &CheckTypeConfig)> {
+        match check_type {
+            CheckType::Http => Some(("http", &self.http)),
+            CheckType::IcmpV4 => Some(("icmpv4", &self.icmpv4)),
+            CheckType::IcmpV6 => Some(("icmpv6", &self.icmpv6)),
+            CheckType::Udp => Some(("udp", &self.udp)),
+            _ => None,
+        }
+    }
+}
+
+/// Settings of a single check type, see [ChecksConfig].
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckTypeConfig {
+    /// How long to wait for an answer, in milliseconds
+    ///
+    /// For UDP checks, this is the wait for each datagram and replaces
+    /// [udp.timeout_ms](UdpConfig::timeout_ms).
+    pub timeout_ms: Option<u16>,
+    /// How often a failed check is retried at most, replaces [confirm](Config::confirm) for this
+    /// type
+    ///
+    /// `0` turns retrying off for this type.
+    pub retries: Option<u8>,
+    /// Seconds between two checks, at least the [period](crate::store::PERIOD_SECS) of the daemon
+    pub interval_secs: Option<u64>,
+}
+
+/// The settings a check type is made with, see [Config::check_settings].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CheckSettings {
+    /// How long to wait for an answer
+    pub timeout: Duration,
+    /// How often a failed check is retried at most
+    pub retries: u8,
+    /// Time between two checks
+    pub interval: Duration,
+}
+
+impl Display for CheckSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "timeout {}, {} retries, every {}",
+            humantime::format_duration(self.timeout),
+            self.retries,
+            humantime::format_duration(self.interval)
+        )
+    }
+}
+
+impl Config {
+    /// Returns the settings checks of `check_type` are made with.
+    ///
+    /// The values set in [checks](Config::checks) win. Otherwise:
+    ///
+    /// - the timeout is [TIMEOUT](crate::TIMEOUT), [udp.timeout_ms](UdpConfig::timeout_ms) for
+    ///   UDP checks and [throughput.timeout_secs](ThroughputConfig::timeout_secs) for throughput
+    ///   checks
+    /// - failed checks are retried [confirm.retries](ConfirmConfig::retries) times if
+    ///   [confirm](Config::confirm) is enabled, throughput checks are never retried
+    /// - the interval is the [period](crate::store::PERIOD_SECS) of the daemon, and
+    ///   [throughput.interval_secs](ThroughputConfig::interval_secs) for throughput checks
+    pub fn check_settings(&self, check_type: CheckType) -> CheckSettings {
+        let builtin = match check_type {
+            CheckType::Throughput => CheckSettings {
+                timeout: self.throughput.timeout(),
+                retries: 0,
+                interval: Duration::from_secs(self.throughput.interval_secs),
+            },
+            _ => CheckSettings {
+                timeout: if check_type == CheckType::Udp {
+                    self.udp.timeout()
+                } else {
+                    crate::TIMEOUT
+                },
+                retries: if self.confirm.enabled {
+                    self.confirm.retries
+                } else {
+                    0
+                },
+                interval: Duration::from_secs(crate::store::PERIOD_SECS),
+            },
+        };
+        let Some((_, set)) = self.checks.get(check_type) else {
+            return builtin;
+        };
+        CheckSettings {
+            timeout: set
+                .timeout_ms
+                .map_or(builtin.timeout, |ms| Duration::from_millis(ms.into())),
+            retries: set.retries.unwrap_or(builtin.retries),
+            interval: set
+                .interval_secs
+                .map_or(builtin.interval, Duration::from_secs),
+        }
+    }
+
+    /// Returns the path of the configuration file.
+    ///
+    /// The path is determined by:
+    /// 1. Checking [ENV_CONFIG] environment variable
+    /// 2. Falling back to [CONFIG_PATH] if not set
+    pub fn path() -> PathBuf {
+        match std::env::var_os(ENV_CONFIG) {
+            Some(var) => PathBuf::from(var),
+            None => PathBuf::from(CONFIG_PATH),
+        }
+    }
+
+    /// Returns the [scoped](ScopedAddr) host of [hosts](Config::hosts) with the address
+    /// `remote`, if there is one.
+    pub fn zone_of(&self, remote: IpAddr) -> Option<ScopedAddr> {
+        self.hosts
+            .iter()
+            .filter(|host| host.contains('%'))
+            .filter_map(|host| host.parse::<ScopedAddr>().ok())
+            .find(|scoped| IpAddr::V6(scoped.ip) == remote)
+    }
+
+    /// Returns whether `check` was made against a [critical](Config::critical) target.
+    ///
+    /// Every target is critical if none are configured.
+    pub fn is_critical(&self, check: &Check) -> bool {
+        self.critical.is_empty()
+            || self.critical.iter().any(|target| {
+                target.parse::<IpAddr>().ok() == Some(check.target())
+                    || check.details().contains(&CheckDetail::Resolved {
+                        host: target.clone(),
+                    })
+            })
+    }
+
+    /// Loads the configuration from [path](Config::path).
+    ///
+    /// A missing file results in the [default](Config::default) configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ConfigError] if the file can't be read, is not valid TOML or contains invalid
+    /// values.
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_from(&Self::path())
+    }
+
+    /// Loads the configuration from the file at `path`, see [load](Config::load).
+    ///
+    /// # Errors
+    ///
+    /// See [load](Config::load).
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(raw) => Self::from_toml(&raw),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Parses and validates a configuration from TOML.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ConfigError] if `raw` is not valid TOML or contains invalid values.
+    pub fn from_toml(raw: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(raw)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the values of the configuration for sanity.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ConfigError::Invalid] describing the first invalid value.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.backoff.after_failures == 0 {
+            return Err(ConfigError::Invalid(
+                "backoff.after_failures must be at least 1".into(),
+            ));
+        }
+        if self.backoff.max_factor == 0 {
+            return Err(ConfigError::Invalid(
+                "backoff.max_factor must be at least 1".into(),
+            ));
+        }
+        if self
+            .tags
+            .values()
+            .flat_map(|tags| tags.keys())
+            .any(|key| key.trim().is_empty())
+        {
+            return Err(ConfigError::Invalid("tags: names must not be empty".into()));
+        }
+        if self.hosts.iter().any(|host| host.trim().is_empty()) {
+            return Err(ConfigError::Invalid("hosts must not be empty".into()));
+        }
+        let mut zones: BTreeMap<Ipv6Addr, &str> = BTreeMap::new();
+        for host in &self.hosts {
+            let needs_zone = host.parse().is_ok_and(ScopedAddr::needs_zone);
+            if !host.contains('%') && !needs_zone {
+                continue;
+            }
+            let scoped: ScopedAddr = host.parse().map_err(|e| match e {
+                ConfigError::Invalid(reason) => ConfigError::Invalid(format!("hosts: {reason}")),
+                e => e,
+            })?;
+            if let Some(other) = zones.insert(scoped.ip, host) {
+                if other != host {
+                    return Err(ConfigError::Invalid(format!(
+                        "hosts: {other} and {host} have the same address, their checks can't be told apart"
+                    )));
+                }
+            }
+        }
+        if let Some(target) = self
+            .max_latency_ms
+            .iter()
+            .find_map(|(target, max)| (*max == 0).then_some(target))
+        {
+            return Err(ConfigError::Invalid(format!(
+                "max_latency_ms of {target} must be at least 1"
+            )));
+        }
+        if self.critical.iter().any(|target| target.trim().is_empty()) {
+            return Err(ConfigError::Invalid("critical must not be empty".into()));
+        }
+        for (target, source) in &self.source {
+            if let Source::Address(addr) = source {
+                if addr.is_ipv4() != target.is_ipv4() {
+                    return Err(ConfigError::Invalid(format!(
+                        "source: {target} can't be checked from {addr}, the IP versions differ"
+                    )));
+                }
+            }
+        }
+        for target in &self.http.targets {
+            if target.accept.as_ref().is_some_and(Vec::is_empty) {
+                return Err(ConfigError::Invalid(format!(
+                    "http.targets: accept of {} must not be empty",
+                    target.target
+                )));
+            }
+            if target.expect_body.is_some() && target.method == HttpMethod::Head {
+                return Err(ConfigError::Invalid(format!(
+                    "http.targets: expect_body of {} needs method = \"GET\"",
+                    target.target
+                )));
+            }
+        }
+        if let Some(window) = self.maintenance.iter().find(|w| w.start == w.end) {
+            return Err(ConfigError::Invalid(format!(
+                "maintenance: the window starting at {} must not end when it starts",
+                window.start
+            )));
+        }
+        if self.udp.count == 0 {
+            return Err(ConfigError::Invalid("udp.count must be at least 1".into()));
+        }
+        if self.throughput.max_bytes == 0
+            || self.throughput.timeout_secs == 0
+            || self.throughput.interval_secs == 0
+        {
+            return Err(ConfigError::Invalid(
+                "throughput.max_bytes, throughput.timeout_secs and throughput.interval_secs must be at least 1"
+                    .into(),
+            ));
+        }
+        if self.store.autosave_secs == Some(0) || self.store.autosave_checks == Some(0) {
+            return Err(ConfigError::Invalid(
+                "store.autosave_secs and store.autosave_checks must be at least 1".into(),
+            ));
+        }
+        if let Some(url) = &self.throughput.url {
+            if crate::checks::url_host(url).is_none() {
+                return Err(ConfigError::Invalid(format!(
+                    "throughput.url must be an http or https URL with a host, got {url}"
+                )));
+            }
+        }
+        if !(1..=100).contains(&self.udp.max_loss_percent) {
+            return Err(ConfigError::Invalid(
+                "udp.max_loss_percent must be between 1 and 100".into(),
+            ));
+        }
+        if self.outages.major_after_secs > self.outages.critical_after_secs {
+            return Err(ConfigError::Invalid(
+                "outages.major_after_secs must not be larger than outages.critical_after_secs"
+                    .into(),
+            ));
+        }
+        if self
+            .report
+            .width
+            .is_some_and(|width| width < crate::analyze::MIN_WIDTH)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "report.width must be at least {}",
+                crate::analyze::MIN_WIDTH
+            )));
+        }
+        if !(1..=MAX_CAPTURE_BYTES).contains(&self.capture.max_bytes) {
+            return Err(ConfigError::Invalid(format!(
+                "capture.max_bytes must be between 1 and {MAX_CAPTURE_BYTES}"
+            )));
+        }
+        if self.health.listen.is_some() && !cfg!(feature = "health-http") {
+            return Err(ConfigError::Invalid(
+                "health.listen needs the health-http feature".into(),
+            ));
+        }
+        if self.health.stale_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "health.stale_secs must be at least 1".into(),
+            ));
+        }
+        if self.confirm.retries == 0 {
+            return Err(ConfigError::Invalid(
+                "confirm.retries must be at least 1".into(),
+            ));
+        }
+        for slo in &self.slo {
+            if !(1..=100).contains(&slo.percentile) {
+                return Err(ConfigError::Invalid(format!(
+                    "slo.percentile of {} must be between 1 and 100",
+                    slo.check_type
+                )));
+            }
+        }
+        if self.outages.min_failures == 0 {
+            return Err(ConfigError::Invalid(
+                "outages.min_failures must be at least 1".into(),
+            ));
+        }
+        if self.outages.major_after_checks > self.outages.critical_after_checks {
+            return Err(ConfigError::Invalid(
+                "outages.major_after_checks must not be larger than outages.critical_after_checks"
+                    .into(),
+            ));
+        }
+        self.validate_checks()
+    }
+
+    /// Checks the [per type settings](Config::checks), see [validate](Config::validate).
+    fn validate_checks(&self) -> Result<(), ConfigError> {
+        for check_type in CheckType::default_enabled() {
+            let Some((name, set)) = self.checks.get(*check_type) else {
+                continue;
+            };
+            if set.timeout_ms == Some(0) {
+                return Err(ConfigError::Invalid(format!(
+                    "checks.{name}.timeout_ms must be at least 1"
+                )));
+            }
+            if set
+                .interval_secs
+                .is_some_and(|secs| secs < crate::store::PERIOD_SECS)
+            {
+                return Err(ConfigError::Invalid(format!(
+                    "checks.{name}.interval_secs must be at least {}, the period of the daemon",
+                    crate::store::PERIOD_SECS
+                )));
+            }
+            let settings = self.check_settings(*check_type);
+            // the datagrams of a UDP check are sent one after the other
+            let longest = if *check_type == CheckType::Udp {
+                settings.timeout * self.udp.count.into()
+            } else {
+                settings.timeout
+            };
+            if longest > settings.interval {
+                return Err(ConfigError::Invalid(format!(
+                    "checks.{name}: a check may take {}, longer than its interval of {}",
+                    humantime::format_duration(longest),
+                    humantime::format_duration(settings.interval)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+
+        let config = Config::from_toml("[udp]\ncount = 10\nmax_loss_percent = 1").unwrap();
+        assert_eq!(config.udp.count, 10);
+        assert_eq!(config.udp.max_loss_percent, 1);
+        assert_eq!(config.udp.port, UdpConfig::default().port);
+
+        assert!(Config::from_toml("[udp]\ncount = 0").is_err());
+        assert!(Config::from_toml("[udp]\nmax_loss_percent = 101").is_err());
+        // no loss would be below 0%
+        assert!(Config::from_toml("[udp]\nmax_loss_percent = 0").is_err());
+        assert!(Config::from_toml("[udp]\ncolor = \"blue\"").is_err());
+        assert!(Config::from_toml("[outages]\nmajor_after_secs = 7200").is_err());
+        assert!(Config::from_toml("[outages]\nmin_failures = 0").is_err());
+        assert!(Config::from_toml("[confirm]\nretries = 0").is_err());
+
+        let config =
+            Config::from_toml("[[slo]]\ncheck_type = \"icmpv4\"\npercentile = 95\nmax_ms = 50")
+                .unwrap();
+        assert_eq!(
+            config.slo,
+            [LatencySlo {
+                check_type: CheckType::IcmpV4,
+                percentile: 95,
+                max_ms: 50
+            }]
+        );
+        assert_eq!(
+            Config::from_toml(&toml::to_string(&config).unwrap()).unwrap(),
+            config
+        );
+        assert!(
+            Config::from_toml("[[slo]]\ncheck_type = \"icmp\"\npercentile = 95\nmax_ms = 50")
+                .is_err()
+        );
+        assert!(
+            Config::from_toml("[[slo]]\ncheck_type = \"udp\"\npercentile = 0\nmax_ms = 50")
+                .is_err()
+        );
+
+        let config = Config::from_toml("[report]\ntimezone = \"-05:30\"").unwrap();
+        assert_eq!(config.report.timezone, Timezone::Offset(-19800));
+        assert!(Config::from_toml("[report]\ntimezone = \"mars\"").is_err());
+        let config = Config::from_toml("[report]\nwidth = 60").unwrap();
+        assert_eq!(config.report.width, Some(60));
+        assert!(Config::from_toml("[report]\nwidth = 20").is_err());
+
+        let config = Config::from_toml("[syslog]\nenabled = true\nformat = \"rfc3164\"").unwrap();
+        assert_eq!(config.syslog.format, SyslogFormat::Rfc3164);
+        assert_eq!(config.syslog.socket, PathBuf::from("/dev/log"));
+
+        let config = Config::from_toml("[store]\nshard_by = \"tag:region\"").unwrap();
+        assert_eq!(config.store.shard_by, ShardBy::Tag("region".to_string()));
+
+        let config = Config::from_toml("[health]\nlisten = \"127.0.0.1:9464\"");
+        assert_eq!(
+            config.ok().and_then(|c| c.health.listen),
+            cfg!(feature = "health-http").then(|| "127.0.0.1:9464".parse().unwrap())
+        );
+        assert!(Config::from_toml("[health]\nstale_secs = 0").is_err());
+
+        let config =
+            Config::from_toml("[success]\n\"1.1.1.1\" = \"status == 200 && latency < 200ms\"")
+                .unwrap();
+        assert_eq!(
+            config.success["1.1.1.1"].to_string(),
+            "status == 200 && latency < 200ms"
+        );
+        assert_eq!(
+            Config::from_toml(&toml::to_string(&config).unwrap()).unwrap(),
+            config
+        );
+        assert!(Config::from_toml("[success]\n\"1.1.1.1\" = \"status = 200\"").is_err());
+
+        let config = Config::from_toml("[max_latency_ms]\n\"example.com\" = 2000").unwrap();
+        assert_eq!(config.max_latency_ms["example.com"], 2000);
+        assert!(Config::from_toml("[max_latency_ms]\n\"example.com\" = 0").is_err());
+        assert!(Config::from_toml("[health]\nlisten = \"localhost\"").is_err());
+    }
+
+    #[test]
+    fn test_check_settings() {
+        let config = Config::default();
+        assert_eq!(
+            config.check_settings(CheckType::Http),
+            CheckSettings {
+                timeout: crate::TIMEOUT,
+                retries: 0,
+                interval: Duration::from_secs(60),
+            }
+        );
+        assert_eq!(
+            config.check_settings(CheckType::Udp).timeout,
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            config.check_settings(CheckType::Throughput).interval,
+            Duration::from_secs(3600)
+        );
+
+        let config = Config::from_toml(
+            "[confirm]\nenabled = true\n[udp]\ntimeout_ms = 500\n\
+             [checks.icmpv6]\ntimeout_ms = 2500\nretries = 0\ninterval_secs = 300\n\
+             [checks.udp]\ntimeout_ms = 1000",
+        )
+        .unwrap();
+        assert_eq!(
+            config.check_settings(CheckType::IcmpV6),
+            CheckSettings {
+                timeout: Duration::from_millis(2500),
+                retries: 0,
+                interval: Duration::from_secs(300),
+            }
+        );
+        // the other types keep the defaults
+        assert_eq!(config.check_settings(CheckType::IcmpV4).retries, 2);
+        assert_eq!(
+            config.check_settings(CheckType::Udp).timeout,
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            config.check_settings(CheckType::Http).to_string(),
+            "timeout 10s, 2 retries, every 1m"
+        );
+
+        assert!(Config::from_toml("[checks.http]\ntimeout_ms = 0").is_err());
+        assert!(Config::from_toml("[checks.http]\ninterval_secs = 30").is_err());
+        assert!(Config::from_toml("[checks.http]\ntimeout_ms = 65000").is_err());
+        assert!(
+            Config::from_toml("[checks.http]\ntimeout_ms = 65000\ninterval_secs = 120").is_ok()
+        );
+        // five datagrams of 15 seconds are longer than a minute
+        assert!(Config::from_toml("[checks.udp]\ntimeout_ms = 15000").is_err());
+        assert!(Config::from_toml("[udp]\ntimeout_ms = 15000").is_err());
+        assert!(Config::from_toml("[checks.dns]\ntimeout_ms = 100").is_err());
+        assert!(Config::from_toml("[checks.http]\ntimeout = 100").is_err());
+    }
+
+    #[test]
+    fn test_http_accepted_status() {
+        let config =
+            Config::from_toml("[[http.targets]]\ntarget = \"10.0.0.1\"\naccept = [200, 204, 302]")
+                .unwrap();
+        let configured: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert!(config.http.accepts(configured, 302));
+        assert!(config.http.accepts(configured, 204));
+        assert!(!config.http.accepts(configured, 201));
+        assert!(config.http.accepts(other, 302));
+        assert!(config.http.accepts(other, 201));
+        assert!(!config.http.accepts(other, 404));
+        // http://1.1.1.1 redirects to https
+        assert!(Config::default().http.accepts(configured, 301));
+        assert!(Config::default().http.accepts(configured, 200));
+        assert!(!Config::default().http.accepts(configured, 199));
+        assert!(!Config::default().http.accepts(configured, 400));
+
+        assert!(Config::from_toml("[[http.targets]]\ntarget = \"10.0.0.1\"\naccept = []").is_err());
+
+        let config = Config::from_toml(
+            "[[http.targets]]\ntarget = \"10.0.0.1\"\nmethod = \"HEAD\"\nheaders = { Host = \"example.com\" }",
+        )
+        .unwrap();
+        let target = config.http.target(configured).unwrap();
+        assert_eq!(target.method, HttpMethod::Head);
+        assert_eq!(HttpMethod::default(), HttpMethod::Get);
+        assert_eq!(target.headers["Host"], "example.com");
+        // only an accept list narrows the accepted status codes
+        assert!(config.http.accepts(configured, 204));
+        assert!(config.http.accepts(configured, 302));
+        assert!(!config.http.accepts(configured, 500));
+        assert!(
+            Config::from_toml("[[http.targets]]\ntarget = \"10.0.0.1\"\nmethod = \"TRACE\"")
+                .is_err()
+        );
+
+        let config = Config::from_toml("[http]\nconnection = \"reuse\"\nphases = true").unwrap();
+        assert_eq!(config.http.connection, HttpConnection::Reuse);
+        assert!(config.http.phases);
+        assert_eq!(Config::default().http.connection, HttpConnection::Fresh);
+        assert!(Config::from_toml("[http]\nconnection = \"keepalive\"").is_err());
+
+        let config =
+            Config::from_toml("[[http.targets]]\ntarget = \"10.0.0.1\"\nexpect_body = \"ok\"")
+                .unwrap();
+        assert_eq!(
+            config
+                .http
+                .target(configured)
+                .unwrap()
+                .expect_body
+                .as_deref(),
+            Some("ok")
+        );
+        assert!(Config::from_toml(
+            "[[http.targets]]\ntarget = \"10.0.0.1\"\nmethod = \"HEAD\"\nexpect_body = \"ok\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_maintenance_windows() {
+        let config = Config::from_toml(
+            "[[maintenance]]\nstart = \"02:00\"\nend = \"02:30\"\ntimezone = \"local\"",
+        )
+        .unwrap();
+        assert_eq!(config.maintenance[0].start.minutes(), 120);
+        assert_eq!(config.maintenance[0].timezone, Timezone::Local);
+        assert!(!config.outages.count_maintenance);
+        assert!(Config::from_toml("[[maintenance]]\nstart = \"02:00\"\nend = \"02:00\"").is_err());
+        assert!(Config::from_toml("[[maintenance]]\nstart = \"2am\"\nend = \"3am\"").is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let config = Config::from_toml(
+            "[resolve.\"example.com\"]\nfamily = \"v6\"\nresolver = \"1.1.1.1\"\n\
+             [resolve.\"example.org\"]\nresolver = \"[2606:4700:4700::1111]:5353\"",
+        )
+        .unwrap();
+        let com = config.resolve["example.com"];
+        assert_eq!(com.family, AddressFamily::V6);
+        assert_eq!(com.resolver, Some("1.1.1.1:53".parse().unwrap()));
+        let org = config.resolve["example.org"];
+        assert_eq!(org.family, AddressFamily::Any);
+        assert_eq!(
+            org.resolver,
+            Some("[2606:4700:4700::1111]:5353".parse().unwrap())
+        );
+        assert!(!com.family.allows("1.1.1.1".parse().unwrap()));
+        assert!(Config::from_toml("[resolve.\"example.com\"]\nresolver = \"dns\"").is_err());
+    }
+
+    #[test]
+    fn test_dscp() {
+        let config = Config::from_toml("[dscp]\n\"1.1.1.1\" = 46\n\"::1\" = 0").unwrap();
+        let ef = config.dscp[&"1.1.1.1".parse::<IpAddr>().unwrap()];
+        assert_eq!(ef.value(), 46);
+        assert_eq!(ef.tos(), 0xb8);
+        assert_eq!(config.dscp[&"::1".parse::<IpAddr>().unwrap()], Dscp(0));
+
+        let err = Config::from_toml("[dscp]\n\"1.1.1.1\" = 64")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("invalid DSCP value 64"), "{err}");
+        assert!(Config::from_toml("[dscp]\n\"1.1.1.1\" = -1").is_err());
+        assert!(Config::from_toml("[dscp]\n\"example.com\" = 8").is_err());
+    }
+
+    #[test]
+    fn test_source() {
+        let config =
+            Config::from_toml("[source]\n\"1.1.1.1\" = \"192.0.2.10\"\n\"8.8.8.8\" = \"wan1\"")
+                .unwrap();
+        assert_eq!(
+            config.source[&"1.1.1.1".parse::<IpAddr>().unwrap()],
+            Source::Address("192.0.2.10".parse().unwrap())
+        );
+        assert_eq!(
+            config.source[&"8.8.8.8".parse::<IpAddr>().unwrap()],
+            Source::Interface("wan1".to_string())
+        );
+
+        for bad in ["\"\"", "\"eth 1\"", "\"averyveryverylongif\"", "\"::1\""] {
+            assert!(
+                Config::from_toml(&format!("[source]\n\"1.1.1.1\" = {bad}")).is_err(),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_scoped_hosts() {
+        let scoped: ScopedAddr = "fe80::1%eth0".parse().unwrap();
+        assert_eq!(scoped.ip, "fe80::1".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(scoped.zone, "eth0");
+        assert_eq!(scoped.to_string(), "fe80::1%eth0");
+        assert_eq!("fe80::1%2".parse::<ScopedAddr>().unwrap().zone, "2");
+
+        for (bad, reason) in [
+            (
+                "fe80::1",
+                "a link-local address needs a zone, like fe80::1%eth0",
+            ),
+            ("febf::1", "a link-local address needs a zone"),
+            ("10.0.0.1%eth0", "only IPv6 addresses have a zone"),
+            ("fe80::1%", "the zone is empty"),
+            (
+                "fe80::1%eth 0",
+                "the zone is not an interface name or index",
+            ),
+            ("router%eth0", "not an IP address"),
+        ] {
+            let err = bad.parse::<ScopedAddr>().unwrap_err().to_string();
+            assert!(err.contains(reason), "{bad}: {err}");
+        }
+        assert!(ScopedAddr::needs_zone("fe80::1".parse().unwrap()));
+        assert!(!ScopedAddr::needs_zone("fec0::1".parse().unwrap()));
+        assert!(!ScopedAddr::needs_zone("2001:db8::1".parse().unwrap()));
+
+        let config =
+            Config::from_toml("hosts = [\"example.com\", \"::1\", \"fe80::1%eth0\"]").unwrap();
+        assert_eq!(config.zone_of("fe80::1".parse().unwrap()), Some(scoped));
+        assert_eq!(config.zone_of("::1".parse().unwrap()), None);
+        let err = Config::from_toml("hosts = [\"fe80::1\"]")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("hosts: fe80::1: a link-local address needs a zone"),
+            "{err}"
+        );
+        assert!(Config::from_toml("hosts = [\"fe80::1%eth0\", \"fe80::1%eth1\"]").is_err());
+    }
+
+    #[test]
+    fn test_capture() {
+        assert_eq!(Config::default().capture.limit(), 0);
+        let config = Config::from_toml("[capture]\nenabled = true\nmax_bytes = 256").unwrap();
+        assert_eq!(config.capture.limit(), 256);
+        assert!(Config::from_toml("[capture]\nmax_bytes = 0").is_err());
+        assert!(Config::from_toml("[capture]\nmax_bytes = 1000000").is_err());
+    }
+
+    #[test]
+    fn test_http_version() {
+        let config =
+            Config::from_toml("[[http.targets]]\ntarget = \"10.0.0.1\"\nversion = \"2\"").unwrap();
+        let configured: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(
+            config.http.target(configured).unwrap().version,
+            Some(HttpVersion::V2)
+        );
+
+        assert!(config
+            .http
+            .accepts_version(configured, Some(HttpVersion::V2)));
+        assert!(!config
+            .http
+            .accepts_version(configured, Some(HttpVersion::V1_1)));
+        assert!(!config.http.accepts_version(configured, None));
+        assert!(config.http.accepts_version(other, Some(HttpVersion::V1_1)));
+        assert!(config.http.accepts_version(other, None));
+        assert!(
+            Config::from_toml("[[http.targets]]\ntarget = \"10.0.0.1\"\nversion = \"4\"").is_err()
+        );
+
+        for (line, version) in [
+            ("HTTP/1.1 200 OK\r\n", Some(HttpVersion::V1_1)),
+            ("HTTP/2 204\r\n", Some(HttpVersion::V2)),
+            ("HTTP/3 200\r\n", Some(HttpVersion::V3)),
+            ("Content-Length: 0\r\n", None),
+        ] {
+            assert_eq!(HttpVersion::from_status_line(line), version);
+        }
+    }
+}
@@ -0,0 +1,494 @@
+//! Control socket for talking to a running daemon.
+//!
+//! The daemon listens on a unix socket at [control_socket](crate::control_socket).
+//! The protocol is line based: the client sends a single [Command] as one line, and the daemon
+//! answers with one line, starting with either `ok` or `error`.
+//!
+//! # Commands
+//!
+//! - `snapshot <path>` - Write a consistent copy of the store to `<path>`, see
+//!   [Store::snapshot]. The path must be in the directory of the store file, see
+//!   [with_snapshot_dir](ControlServer::with_snapshot_dir).
+//! - `report` - Answer with the [report](crate::analyze::analyze_with) of the store file, from an
+//!   [AnalysisCache]. Since the report has multiple lines, it is encoded as a JSON string, see
+//!   [request_report].
+//! - `status` - Answer with the [Status](crate::recent::Status) of the last check cycle, from
+//!   the [RecentChecks] kept in memory, see [request_status]. This does not touch the store.
+//!
+//! The socket is only accessible to the daemon user and its group, see [ControlServer::bind].
+//! Requests are served one after another, so a command has to arrive within [CLIENT_TIMEOUT]
+//! and [MAX_COMMAND_BYTES], and [request] gives up on an answer after [ANSWER_TIMEOUT].
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use netpulse::control::{self, Command};
+//!
+//! let answer = control::request(
+//!     &netpulse::control_socket(),
+//!     &Command::Snapshot("/var/lib/netpulse/netpulse.snapshot".into()),
+//! )
+//! .unwrap();
+//! println!("{answer}");
+//! ```
+
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::cache::AnalysisCache;
+use crate::errors::ControlError;
+use crate::recent::RecentChecks;
+use crate::store::Store;
+
+/// A command that can be sent to the daemon over the control socket.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Command {
+    /// Write a consistent copy of the store to this path
+    Snapshot(PathBuf),
+    /// Answer with the cached report of the store file
+    Report,
+    /// Answer with the status of the last check cycle
+    Status,
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Snapshot(path) => write!(f, "snapshot {}", path.display()),
+            Self::Report => write!(f, "report"),
+            Self::Status => write!(f, "status"),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = ControlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.trim().split_once(' ') {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (s.trim(), ""),
+        };
+        match name {
+            "snapshot" if !arg.is_empty() => Ok(Self::Snapshot(PathBuf::from(arg))),
+            "report" if arg.is_empty() => Ok(Self::Report),
+            "status" if arg.is_empty() => Ok(Self::Status),
+            _ => Err(ControlError::InvalidCommand(s.trim().to_string())),
+        }
+    }
+}
+
+/// Mode of the control socket: read and write for the daemon user and its group
+pub const SOCKET_MODE: u32 = 0o660;
+/// How long a client may take to send its command or read the answer, so a stuck client does
+/// not block the socket
+pub const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a command line may be, longer ones are answered with an error
+pub const MAX_COMMAND_BYTES: usize = 4 * 1024;
+/// How long [request] waits for the answer, the daemon may have to make a report first
+pub const ANSWER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Server side of the control socket, owned by the daemon.
+#[derive(Debug)]
+pub struct ControlServer {
+    /// The bound socket
+    listener: UnixListener,
+    /// Where the socket is, removed when the server is dropped
+    path: PathBuf,
+    /// Directory that [Command::Snapshot] may write to
+    snapshot_dir: PathBuf,
+    /// Answers [Command::Report], if set
+    cache: Option<AnalysisCache>,
+    /// Answers [Command::Status], if set
+    recent: Option<Arc<Mutex<RecentChecks>>>,
+}
+
+impl ControlServer {
+    /// Binds the control socket at `path`.
+    ///
+    /// A stale socket file at `path`, left over from a daemon that was killed, is removed first.
+    /// The socket gets the mode [SOCKET_MODE], so other local users can't send commands.
+    ///
+    /// Snapshots may only be written to the directory of the [store file](Store::path).
+    ///
+    /// # Errors
+    ///
+    /// Returns [ControlError] if the socket can't be bound.
+    pub fn bind(path: &Path) -> Result<Self, ControlError> {
+        if let Err(err) = fs::remove_file(path) {
+            if err.kind() != ErrorKind::NotFound {
+                return Err(err.into());
+            }
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(SOCKET_MODE))?;
+        let store_path = Store::path();
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+            snapshot_dir: store_path
+                .parent()
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+            cache: None,
+            recent: None,
+        })
+    }
+
+    /// Only allows [Command::Snapshot] to write files directly in `dir`.
+    pub fn with_snapshot_dir(mut self, dir: &Path) -> Self {
+        self.snapshot_dir = dir.to_path_buf();
+        self
+    }
+
+    /// Serves [Command::Report] from `cache`.
+    ///
+    /// Without a cache, report requests are answered with an error.
+    pub fn with_cache(mut self, cache: AnalysisCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Serves [Command::Status] from `recent`, which the daemon fills after every check cycle.
+    ///
+    /// Without it, status requests are answered with an error.
+    pub fn with_recent(mut self, recent: Arc<Mutex<RecentChecks>>) -> Self {
+        self.recent = Some(recent);
+        self
+    }
+
+    /// Serves requests in a background thread.
+    ///
+    /// The `store` is locked for the duration of each command, so a snapshot can never contain a
+    /// half finished check cycle as long as the daemon also holds the lock while changing it.
+    pub fn spawn(mut self, store: Arc<Mutex<Store>>) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let mut cache = self.cache.take();
+            let recent = self.recent.take();
+            for stream in self.listener.incoming() {
+                let result = stream.map_err(ControlError::from).and_then(|stream| {
+                    handle_client(
+                        stream,
+                        &store,
+                        &self.snapshot_dir,
+                        cache.as_mut(),
+                        recent.as_deref(),
+                    )
+                });
+                if let Err(err) = result {
+                    crate::logging::error(format!("error on the control socket: {err}"));
+                }
+            }
+        })
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Reads the command line from `stream`.
+///
+/// Returns [None] if it is longer than [MAX_COMMAND_BYTES].
+///
+/// # Errors
+///
+/// Returns an [io::Error] if reading fails or the client takes longer than [CLIENT_TIMEOUT] in
+/// total.
+fn read_command(mut stream: &UnixStream) -> io::Result<Option<String>> {
+    let deadline = Instant::now() + CLIENT_TIMEOUT;
+    let mut line = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !line.ends_with(b"\n") {
+        if line.len() > MAX_COMMAND_BYTES {
+            return Ok(None);
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(ErrorKind::TimedOut.into());
+        }
+        stream.set_read_timeout(Some(left))?;
+        let len = stream.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        line.extend_from_slice(&buf[..len]);
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+/// Reads a single command from `stream`, executes it and writes the answer.
+fn handle_client(
+    stream: UnixStream,
+    store: &Mutex<Store>,
+    snapshot_dir: &Path,
+    cache: Option<&mut AnalysisCache>,
+    recent: Option<&Mutex<RecentChecks>>,
+) -> Result<(), ControlError> {
+    let answer = match read_command(&stream)? {
+        Some(line) => line
+            .parse::<Command>()
+            .and_then(|command| execute(&command, store, snapshot_dir, cache, recent)),
+        None => Err(ControlError::InvalidCommand(format!(
+            "longer than {MAX_COMMAND_BYTES} bytes"
+        ))),
+    };
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut stream = stream;
+    match answer {
+        Ok(msg) => writeln!(stream, "ok {msg}")?,
+        Err(err) => writeln!(stream, "error {err}")?,
+    }
+    Ok(())
+}
+
+/// Executes a [Command], holding the lock on the store if the command needs it.
+fn execute(
+    command: &Command,
+    store: &Mutex<Store>,
+    snapshot_dir: &Path,
+    cache: Option<&mut AnalysisCache>,
+    recent: Option<&Mutex<RecentChecks>>,
+) -> Result<String, ControlError> {
+    match command {
+        Command::Snapshot(path) => {
+            let path = snapshot_path(snapshot_dir, path)?;
+            let store = store.lock().expect("the store lock is poisoned");
+            store.snapshot(&path)?;
+            Ok(format!(
+                "wrote snapshot with {} checks to {}",
+                store.checks().len(),
+                path.display()
+            ))
+        }
+        Command::Report => {
+            let cache = cache.ok_or_else(|| {
+                ControlError::Daemon("the daemon does not serve reports".to_string())
+            })?;
+            let report = cache
+                .report()
+                .map_err(|err| ControlError::Daemon(err.to_string()))?;
+            Ok(serde_json::to_string(&report).expect("a string can always be serialized"))
+        }
+        Command::Status => {
+            let recent = recent.ok_or_else(|| {
+                ControlError::Daemon("the daemon does not serve its status".to_string())
+            })?;
+            let status = recent
+                .lock()
+                .expect("the recent checks lock is poisoned")
+                .status();
+            Ok(serde_json::to_string(&status.to_string())
+                .expect("a string can always be serialized"))
+        }
+    }
+}
+
+/// Returns where to write the snapshot requested for `path`, which has to be a file directly in
+/// `dir`. Relative paths are relative to `dir`.
+///
+/// # Errors
+///
+/// Returns [ControlError::Daemon] if `path` is somewhere else, and [ControlError::Io] if `dir`
+/// or the parent of `path` can't be resolved.
+fn snapshot_path(dir: &Path, path: &Path) -> Result<PathBuf, ControlError> {
+    let path = dir.join(path);
+    let outside = || {
+        ControlError::Daemon(format!(
+            "snapshots can only be written to {}",
+            dir.display()
+        ))
+    };
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(outside());
+    };
+    // symlinks and .. must not lead out of the directory
+    let parent = fs::canonicalize(parent)?;
+    if parent != fs::canonicalize(dir)? {
+        return Err(outside());
+    }
+    Ok(parent.join(name))
+}
+
+/// Sends a [Command] to the daemon listening at `socket` and returns its answer.
+///
+/// # Errors
+///
+/// Returns [ControlError] if the daemon can't be reached, does not answer within
+/// [ANSWER_TIMEOUT], or if it answered with an error.
+pub fn request(socket: &Path, command: &Command) -> Result<String, ControlError> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_read_timeout(Some(ANSWER_TIMEOUT))?;
+    writeln!(stream, "{command}")?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let line = line.trim_end();
+    if let Some(msg) = line.strip_prefix("ok") {
+        Ok(msg.trim_start().to_string())
+    } else if let Some(msg) = line.strip_prefix("error") {
+        Err(ControlError::Daemon(msg.trim_start().to_string()))
+    } else {
+        Err(ControlError::Daemon(format!("unexpected answer: '{line}'")))
+    }
+}
+
+/// Asks the daemon listening at `socket` for the report of the store, see [Command::Report].
+///
+/// # Errors
+///
+/// Returns [ControlError] if the daemon can't be reached, answered with an error, or the answer
+/// is not a report.
+pub fn request_report(socket: &Path) -> Result<String, ControlError> {
+    request_text(socket, &Command::Report)
+}
+
+/// Asks the daemon listening at `socket` for its [Status](crate::recent::Status), see
+/// [Command::Status].
+///
+/// # Errors
+///
+/// Returns [ControlError] if the daemon can't be reached, answered with an error, or the answer
+/// is not a status.
+pub fn request_status(socket: &Path) -> Result<String, ControlError> {
+    request_text(socket, &Command::Status)
+}
+
+/// Sends `command` and decodes the answer, a multi line text encoded as JSON string.
+fn request_text(socket: &Path, command: &Command) -> Result<String, ControlError> {
+    let answer = request(socket, command)?;
+    serde_json::from_str(&answer)
+        .map_err(|_| ControlError::Daemon(format!("unexpected answer: '{answer}'")))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::records::{Check, CheckFlag};
+
+    use super::*;
+
+    #[test]
+    fn test_command_roundtrip() {
+        let cmd = Command::Snapshot("/tmp/some where.store".into());
+        assert_eq!(cmd.to_string().parse::<Command>().unwrap(), cmd);
+        assert!("snapshot".parse::<Command>().is_err());
+        assert_eq!("report".parse::<Command>().unwrap(), Command::Report);
+        assert!("report now".parse::<Command>().is_err());
+        assert_eq!("status".parse::<Command>().unwrap(), Command::Status);
+        assert!("explode now".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn test_snapshot_mid_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("netpulse.sock");
+        let snapshot = dir.path().join("snapshot.store");
+
+        let store = Arc::new(Mutex::new(Store::new()));
+        ControlServer::bind(&socket)
+            .unwrap()
+            .with_snapshot_dir(dir.path())
+            .spawn(store.clone());
+        let mode = fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, SOCKET_MODE);
+
+        let writer_store = store.clone();
+        let writer = std::thread::spawn(move || {
+            for secs in 0..2000 {
+                writer_store.lock().unwrap().add_check(Check::new(
+                    UNIX_EPOCH + Duration::from_secs(secs),
+                    CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                    Some(12),
+                    "1.1.1.1".parse().unwrap(),
+                ));
+            }
+        });
+
+        let answer = request(&socket, &Command::Snapshot(snapshot.clone())).unwrap();
+        assert!(answer.starts_with("wrote snapshot"));
+        writer.join().unwrap();
+
+        let loaded = Store::load_from(&snapshot).unwrap();
+        assert!(loaded.checks().len() <= 2000);
+        // only in the directory of the store
+        let outside = tempfile::tempdir().unwrap();
+        for path in [
+            outside.path().join("snapshot.store"),
+            dir.path().join("../snapshot.store"),
+            dir.path().join(".."),
+        ] {
+            let err = request(&socket, &Command::Snapshot(path.clone())).unwrap_err();
+            assert!(err.to_string().contains("can only be written to"), "{err}");
+        }
+        assert!(!outside.path().join("snapshot.store").exists());
+        assert_eq!(
+            loaded.checks(),
+            &store.lock().unwrap().checks()[..loaded.checks().len()]
+        );
+    }
+
+    #[test]
+    fn test_status_from_recent_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("netpulse.sock");
+
+        let recent = Arc::new(Mutex::new(RecentChecks::new(4)));
+        ControlServer::bind(&socket)
+            .unwrap()
+            .with_recent(recent.clone())
+            .spawn(Arc::new(Mutex::new(Store::new())));
+        assert!(request_status(&socket)
+            .unwrap()
+            .starts_with("Last Cycle: none yet"));
+
+        let cycle = [Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            None,
+            "1.1.1.1".parse().unwrap(),
+        )];
+        recent
+            .lock()
+            .unwrap()
+            .push_cycle(&cycle.iter().collect::<Vec<_>>());
+        let status = request_status(&socket).unwrap();
+        assert_eq!(status, recent.lock().unwrap().status().to_string());
+        assert!(status.contains("Cycle Checks: 1 (1 failed)"));
+    }
+
+    #[test]
+    fn test_stuck_and_long_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("netpulse.sock");
+        ControlServer::bind(&socket)
+            .unwrap()
+            .with_recent(Arc::new(Mutex::new(RecentChecks::new(4))))
+            .spawn(Arc::new(Mutex::new(Store::new())));
+
+        // never sends a newline
+        let mut stuck = UnixStream::connect(&socket).unwrap();
+        stuck.write_all(b"stat").unwrap();
+        let start = Instant::now();
+        assert!(request_status(&socket).is_ok());
+        assert!(start.elapsed() < CLIENT_TIMEOUT + Duration::from_secs(2));
+
+        let mut long = UnixStream::connect(&socket).unwrap();
+        long.write_all("x".repeat(MAX_COMMAND_BYTES * 2).as_bytes())
+            .unwrap();
+        let mut answer = String::new();
+        BufReader::new(&long).read_line(&mut answer).unwrap();
+        assert!(answer.starts_with("error"), "{answer}");
+        assert!(answer.contains("longer than"), "{answer}");
+    }
+}
@@ -0,0 +1,259 @@
+//! Encryption of the store at rest.
+//!
+//! The store names the targets that are checked, which may be internal hostnames and addresses.
+//! With the `encryption` feature, the store file is encrypted with XChaCha20-Poly1305 if a
+//! [StoreKey] is set in the environment:
+//!
+//! - [ENV_KEY] - the key as 64 hexadecimal digits
+//! - [ENV_KEY_FILE] - path of a file containing the key as 64 hexadecimal digits
+//!
+//! Encryption is layered over the compression: the store is serialized, compressed and then
+//! encrypted. An encrypted file starts with [MAGIC], followed by a random nonce and the
+//! ciphertext. [Loading](crate::store::Store::load_from) detects encrypted files by their
+//! [MAGIC], so unencrypted stores are still loaded after a key was set, and are written
+//! encrypted on the next save.
+//!
+//! A key can be made with `openssl rand -hex 32`.
+//!
+//! The daemon [preloads](StoreKey::preload) the key before it drops its privileges, so the key
+//! file only has to be readable by root.
+
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::errors::StoreError;
+
+/// Environment variable with the [StoreKey] as 64 hexadecimal digits
+///
+/// Takes precedence over [ENV_KEY_FILE].
+pub const ENV_KEY: &str = "NETPULSE_STORE_KEY";
+/// Environment variable with the path of a file containing the [StoreKey]
+pub const ENV_KEY_FILE: &str = "NETPULSE_STORE_KEY_FILE";
+
+/// First bytes of every encrypted store file
+pub const MAGIC: [u8; 8] = *b"NPSTENC1";
+
+/// The key read by [StoreKey::preload], if it was called
+static PRELOADED: OnceLock<Option<StoreKey>> = OnceLock::new();
+
+/// Length of the XChaCha20 nonce that follows the [MAGIC]
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 24;
+
+/// A 256 bit key for encrypting the store, see the [module documentation](self).
+#[derive(Clone, PartialEq, Eq)]
+pub struct StoreKey([u8; 32]);
+
+impl StoreKey {
+    /// Creates a key from its raw bytes.
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Parses a key from 64 hexadecimal digits, surrounding whitespace is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError::BadKey] if `hex` is not 64 hexadecimal digits.
+    pub fn from_hex(hex: &str) -> Result<Self, StoreError> {
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(StoreError::BadKey(format!(
+                "expected 64 hexadecimal digits, got {} characters",
+                hex.chars().count()
+            )));
+        }
+        let mut bytes = [0; 32];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            // the input is ASCII, so every pair is valid UTF-8
+            let pair = std::str::from_utf8(pair).unwrap_or_default();
+            *byte = u8::from_str_radix(pair, 16)
+                .map_err(|_| StoreError::BadKey(format!("{pair:?} are not hexadecimal digits")))?;
+        }
+        Ok(Self(bytes))
+    }
+
+    /// Returns the key set in [ENV_KEY] or [ENV_KEY_FILE], [None] if neither is set.
+    ///
+    /// Empty variables count as unset. After [preload](Self::preload), the preloaded key is
+    /// returned and the environment is not read again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError::BadKey] if the key is malformed or its file can't be read, and
+    /// [StoreError::EncryptionNotEnabled] if a key is set without the `encryption` feature.
+    pub fn from_env() -> Result<Option<Self>, StoreError> {
+        match PRELOADED.get() {
+            Some(key) => Ok(key.clone()),
+            None => Self::read_env(),
+        }
+    }
+
+    /// Reads the key from the environment once, for all later calls of [from_env](Self::from_env).
+    ///
+    /// The daemon calls this before it drops its privileges, so the key file can stay readable
+    /// by root only. Calling it again has no effect.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [from_env](Self::from_env).
+    pub fn preload() -> Result<(), StoreError> {
+        if PRELOADED.get().is_none() {
+            let _ = PRELOADED.set(Self::read_env()?);
+        }
+        Ok(())
+    }
+
+    fn read_env() -> Result<Option<Self>, StoreError> {
+        let var = |key| std::env::var_os(key).filter(|value| !value.is_empty());
+        let key = if let Some(hex) = var(ENV_KEY) {
+            Self::from_hex(&hex.to_string_lossy())?
+        } else if let Some(path) = var(ENV_KEY_FILE) {
+            let path = PathBuf::from(path);
+            let hex = std::fs::read_to_string(&path).map_err(|err| {
+                StoreError::BadKey(format!("could not read {}: {err}", path.display()))
+            })?;
+            Self::from_hex(&hex)?
+        } else {
+            return Ok(None);
+        };
+        if !cfg!(feature = "encryption") {
+            return Err(StoreError::EncryptionNotEnabled);
+        }
+        Ok(Some(key))
+    }
+}
+
+impl Debug for StoreKey {
+    // the key does not end up in logs or error messages
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StoreKey(..)")
+    }
+}
+
+/// Returns whether `data` starts like an encrypted store.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Encrypts `plain` with `key`, see the [module documentation](self) for the format.
+///
+/// # Errors
+///
+/// Returns [StoreError::Encrypt] if encryption fails.
+#[cfg(feature = "encryption")]
+pub fn seal(key: &StoreKey, plain: &[u8]) -> Result<Vec<u8>, StoreError> {
+    use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+    use chacha20poly1305::XChaCha20Poly1305;
+
+    let cipher = XChaCha20Poly1305::new(&key.0.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    // the header is authenticated too
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plain,
+                aad: &MAGIC,
+            },
+        )
+        .map_err(|_| StoreError::Encrypt)?;
+    let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&MAGIC);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Fails, encrypting the store needs the `encryption` feature.
+#[cfg(not(feature = "encryption"))]
+pub fn seal(_key: &StoreKey, _plain: &[u8]) -> Result<Vec<u8>, StoreError> {
+    Err(StoreError::EncryptionNotEnabled)
+}
+
+/// Decrypts the `sealed` data made by [seal] with `key`.
+///
+/// # Errors
+///
+/// Returns [StoreError::Decrypt] if `key` is wrong or `sealed` was changed or cut off.
+#[cfg(feature = "encryption")]
+pub fn open(key: &StoreKey, sealed: &[u8]) -> Result<Vec<u8>, StoreError> {
+    use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+    let Some(rest) = sealed.strip_prefix(&MAGIC) else {
+        return Err(StoreError::Decrypt);
+    };
+    if rest.len() < NONCE_LEN {
+        return Err(StoreError::Decrypt);
+    }
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    XChaCha20Poly1305::new(&key.0.into())
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &MAGIC,
+            },
+        )
+        .map_err(|_| StoreError::Decrypt)
+}
+
+/// Fails, decrypting the store needs the `encryption` feature.
+#[cfg(not(feature = "encryption"))]
+pub fn open(_key: &StoreKey, _sealed: &[u8]) -> Result<Vec<u8>, StoreError> {
+    Err(StoreError::EncryptionNotEnabled)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_from_hex() {
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+        let key = StoreKey::from_hex(&format!("{hex}\n")).unwrap();
+        assert_eq!(key.0[..4], [0x00, 0x11, 0x22, 0x33]);
+        assert_eq!(key.0[31], 0xff);
+        assert_eq!(format!("{key:?}"), "StoreKey(..)");
+
+        assert!(matches!(
+            StoreKey::from_hex(&hex[..62]),
+            Err(StoreError::BadKey(_))
+        ));
+        assert!(matches!(
+            StoreKey::from_hex(&hex.replace('a', "g")),
+            Err(StoreError::BadKey(_))
+        ));
+        // 64 bytes, but not 64 characters
+        assert!(matches!(
+            StoreKey::from_hex(&format!("{}ü", &hex[..62])),
+            Err(StoreError::BadKey(_))
+        ));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_seal_and_open() {
+        let key = StoreKey::new([7; 32]);
+        let sealed = seal(&key, b"checks").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(6).any(|w| w == b"checks"));
+        assert_eq!(open(&key, &sealed).unwrap(), b"checks");
+        // every seal gets its own nonce
+        assert_ne!(seal(&key, b"checks").unwrap(), sealed);
+
+        assert!(matches!(
+            open(&StoreKey::new([8; 32]), &sealed),
+            Err(StoreError::Decrypt)
+        ));
+        let mut damaged = sealed.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        assert!(matches!(open(&key, &damaged), Err(StoreError::Decrypt)));
+        assert!(matches!(
+            open(&key, &sealed[..MAGIC.len() + 10]),
+            Err(StoreError::Decrypt)
+        ));
+    }
+}
@@ -0,0 +1,149 @@
+//! A bundled store of made-up checks, for `netpulse --demo` and tests.
+//!
+//! Trying netpulse out would otherwise mean running the daemon for a while and waiting for the
+//! network to fail. The [store] here covers one day, 2024-12-08 in UTC, with checks every five
+//! minutes, and has a bit of everything the report shows:
+//!
+//! - 09:00 to 09:25: an outage of the uplink, every check fails
+//! - 14:10: a single failed HTTP check
+//! - 18:00 to 19:10: the IPv6 ICMP checks fail, a critical outage
+//! - 19:00 to 23:00: evening congestion, the latencies go up
+//!
+//! The checks are always the same, so tests can rely on them.
+//!
+//! # Example
+//!
+//! ```rust
+//! use netpulse::{analyze, config::Config, demo};
+//!
+//! let mut config = Config::default();
+//! // the demo store has no file
+//! config.report.skip_store_file = true;
+//! let report = analyze::analyze_with(&demo::store(), &config).unwrap();
+//! assert!(report.contains("Outages"));
+//! ```
+
+use std::net::IpAddr;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::records::{Check, CheckDetail, CheckFlag};
+use crate::store::Store;
+
+/// Start of the day of the demo checks, 2024-12-08T00:00:00Z, in seconds since the unix epoch
+pub const START: u64 = 1_733_616_000;
+/// Time between the checks of the demo, in seconds
+pub const PERIOD: u64 = 5 * 60;
+
+/// Returns the store of the demo checks, see [checks].
+pub fn store() -> Store {
+    Store::from_fixture(checks())
+}
+
+/// Returns the demo checks, ordered by time, see the [module documentation](self).
+///
+/// Each period has an HTTP and an ICMP check of `1.1.1.1` and an ICMP check of
+/// `2606:4700:4700::1111`.
+pub fn checks() -> Vec<Check> {
+    let v4: IpAddr = "1.1.1.1".parse().expect("is an IP address");
+    let v6: IpAddr = "2606:4700:4700::1111".parse().expect("is an IP address");
+    // a fixed seed, so the jitter is the same for every run
+    let mut jitter = Jitter(0x5eed);
+    let mut checks = Vec::new();
+    for period in 0..24 * 60 * 60 / PERIOD {
+        let secs = START + period * PERIOD;
+        let minute = period * PERIOD / 60;
+        let uplink_down = (9 * 60..9 * 60 + 25).contains(&minute);
+        let v6_down = (18 * 60..19 * 60 + 10).contains(&minute);
+        let congestion = if (19 * 60..23 * 60).contains(&minute) {
+            25
+        } else {
+            0
+        };
+
+        for (flags, target, base, ok) in [
+            (
+                CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                v4,
+                32,
+                !uplink_down && minute != 14 * 60 + 10,
+            ),
+            (CheckFlag::TypeIcmp | CheckFlag::IPv4, v4, 11, !uplink_down),
+            (
+                CheckFlag::TypeIcmp | CheckFlag::IPv6,
+                v6,
+                14,
+                !uplink_down && !v6_down,
+            ),
+        ] {
+            let latency = base + congestion + jitter.next(8);
+            let mut check = Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                if ok {
+                    flags | CheckFlag::Success
+                } else {
+                    flags
+                },
+                ok.then_some(latency),
+                target,
+            );
+            if ok && flags.contains(CheckFlag::TypeHTTP) {
+                check.add_detail(CheckDetail::Http { status: 200 });
+            }
+            checks.push(check);
+        }
+    }
+    checks
+}
+
+/// A small pseudo random generator for the jitter of the latencies.
+struct Jitter(u32);
+
+impl Jitter {
+    /// Returns the next number below `bound`.
+    fn next(&mut self, bound: u16) -> u16 {
+        // the constants of Numerical Recipes
+        self.0 = self.0.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        ((self.0 >> 16) % u32::from(bound)) as u16
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analyze;
+    use crate::config::Config;
+    use crate::records::CheckType;
+
+    use super::*;
+
+    #[test]
+    fn test_demo_store() {
+        let store = store();
+        assert_eq!(store.checks().len(), 3 * 24 * 60 * 60 / PERIOD as usize);
+        assert_eq!(checks(), checks());
+
+        let mut config = Config::default();
+        config.report.skip_store_file = true;
+        let report = analyze::analyze_with(&store, &config).unwrap();
+        assert!(report.contains("Outages"));
+
+        let outages = analyze::outages_markdown(&store);
+        let rows: Vec<&str> = outages.lines().skip(2).collect();
+        // the uplink outage shows up for each check type
+        let uplink: Vec<&str> = rows
+            .iter()
+            .copied()
+            .filter(|row| row.starts_with("| 2024-12-08T09:00:00Z | 2024-12-08T09:20:00Z |"))
+            .collect();
+        assert_eq!(uplink.len(), 3, "{outages}");
+        for check_type in [CheckType::Http, CheckType::IcmpV4, CheckType::IcmpV6] {
+            let column = format!("| {check_type} |");
+            assert!(uplink.iter().any(|row| row.contains(&column)), "{outages}");
+        }
+        assert!(rows
+            .iter()
+            .any(|row| row.starts_with("| 2024-12-08T14:10:00Z | 2024-12-08T14:10:00Z |")));
+        assert!(rows
+            .iter()
+            .any(|row| row.starts_with("| 2024-12-08T18:00:00Z | 2024-12-08T19:05:00Z |")));
+    }
+}
@@ -0,0 +1,296 @@
+//! Self-test of the environment of the daemon, for `netpulsed --doctor`.
+//!
+//! Many problems of a new setup only show up as silent failures of the daemon later on, like a
+//! missing `netpulse` user or ICMP checks that are skipped without `CAP_NET_RAW`. Each function
+//! here checks one of these things, [diagnose] runs all of them. Checks of what the daemon can do
+//! are run [as the daemon user](as_user), since it drops its privileges after starting.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! let findings = netpulse::doctor::diagnose(false);
+//! for finding in &findings {
+//!     println!("{finding}");
+//! }
+//! let healthy = findings.iter().all(|f| f.passed());
+//! ```
+
+use std::ffi::CString;
+use std::fmt::Display;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use nix::sys::socket::{socket, AddressFamily, SockFlag, SockProtocol, SockType};
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::{self, ForkResult, Uid, User};
+
+use crate::config::Config;
+use crate::store::Store;
+use crate::{pid_file, DAEMON_USER};
+
+/// The result of one check of [diagnose].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Finding {
+    /// What was checked
+    pub name: String,
+    /// Why the check failed, [None] if it passed
+    pub problem: Option<String>,
+}
+
+impl Finding {
+    /// Creates a finding named `name` from the `result` of a check.
+    pub fn new(name: impl Into<String>, result: Result<(), String>) -> Self {
+        Self {
+            name: name.into(),
+            problem: result.err(),
+        }
+    }
+
+    /// Returns whether the check passed.
+    pub fn passed(&self) -> bool {
+        self.problem.is_none()
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.problem {
+            None => write!(f, "[ ok ] {}", self.name),
+            Some(problem) => write!(f, "[FAIL] {}: {problem}", self.name),
+        }
+    }
+}
+
+/// Runs all checks and returns their findings, in the order they are best shown.
+///
+/// With `user_mode`, like `netpulsed --start --user`, the daemon runs as the current user, so
+/// the [DAEMON_USER] is not needed. Otherwise, the directories and raw sockets are checked
+/// [as](as_user) the [DAEMON_USER], if it exists.
+///
+/// Must be called before any threads are started, see [as_user].
+pub fn diagnose(user_mode: bool) -> Vec<Finding> {
+    let store_path = Store::path();
+    let store_dir = store_path.parent().unwrap_or(Path::new("."));
+    let pid_path = pid_file();
+    let pid_dir = pid_path.parent().unwrap_or(Path::new("."));
+    let daemon_user = (!user_mode)
+        .then(|| crate::lookup_user(DAEMON_USER).ok())
+        .flatten();
+    let run = |check: &dyn Fn() -> Result<(), String>| match &daemon_user {
+        Some(user) => as_user(user, check),
+        None => check(),
+    };
+
+    let mut findings = vec![Finding::new(
+        "config parses",
+        config_parses(&Config::path()).map_err(|e| format!("{}: {e}", Config::path().display())),
+    )];
+    if !user_mode {
+        findings.push(Finding::new(
+            format!("user {DAEMON_USER} exists"),
+            user_exists(DAEMON_USER),
+        ));
+    }
+    findings.push(Finding::new(
+        "store directory writable",
+        run(&|| writable_dir(store_dir)),
+    ));
+    findings.push(Finding::new(
+        "pid directory writable",
+        run(&|| writable_dir(pid_dir)),
+    ));
+    findings.push(Finding::new("raw sockets for ICMP", run(&raw_socket)));
+    findings
+}
+
+/// Runs `check` in a child process as `user`, so it sees the files and capabilities like the
+/// daemon does after dropping its privileges.
+///
+/// Only root can switch to another user, so `check` runs in this process if it is not root or
+/// already is `user`.
+///
+/// The process is forked, which is only sound while it has a single thread. The daemon never
+/// calls this, `netpulsed --doctor` does before doing anything else.
+///
+/// # Errors
+///
+/// Returns the problem found by `check`, or describes why it could not be run as `user`.
+pub fn as_user(user: &User, check: &dyn Fn() -> Result<(), String>) -> Result<(), String> {
+    if !Uid::effective().is_root() || Uid::effective() == user.uid {
+        return check();
+    }
+    let (reader, writer) = unistd::pipe().map_err(|e| format!("could not make a pipe: {e}"))?;
+    // SAFETY: the child only switches its user, runs the check and exits, see above
+    match unsafe { unistd::fork() }.map_err(|e| format!("could not fork: {e}"))? {
+        ForkResult::Child => {
+            drop(reader);
+            let code = match switch_user(user).and_then(|()| check()) {
+                Ok(()) => 0,
+                Err(problem) => {
+                    let _ = fs::File::from(writer).write_all(problem.as_bytes());
+                    1
+                }
+            };
+            std::process::exit(code)
+        }
+        ForkResult::Parent { child } => {
+            drop(writer);
+            let mut problem = String::new();
+            // the problem is empty if it can't be read, the status still tells if it failed
+            let _ = fs::File::from(reader).read_to_string(&mut problem);
+            match waitpid(child, None) {
+                Ok(WaitStatus::Exited(_, 0)) => Ok(()),
+                Ok(_) if !problem.is_empty() => Err(problem),
+                Ok(status) => Err(format!("the check as {} failed: {status:?}", user.name)),
+                Err(e) => Err(format!("the check as {} failed: {e}", user.name)),
+            }
+        }
+    }
+}
+
+/// Switches this process to `user` and its groups, like the daemon does when it drops its
+/// privileges.
+fn switch_user(user: &User) -> Result<(), String> {
+    let describe = |e: nix::Error| format!("could not switch to user {}: {e}", user.name);
+    let name = CString::new(user.name.as_str()).map_err(|e| e.to_string())?;
+    unistd::initgroups(&name, user.gid).map_err(describe)?;
+    unistd::setgid(user.gid).map_err(describe)?;
+    unistd::setuid(user.uid).map_err(describe)
+}
+
+/// Checks that files can be created in the directory at `path`.
+///
+/// A missing directory passes if it can be created, since the daemon creates its directories.
+/// Nothing is left behind.
+///
+/// # Errors
+///
+/// Describes why no file can be created.
+pub fn writable_dir(path: &Path) -> Result<(), String> {
+    let describe = |e: std::io::Error| format!("{}: {e}", path.display());
+    if !path.exists() {
+        return match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            Some(parent) => writable_dir(parent).map_err(|e| {
+                format!(
+                    "{} does not exist and can't be created: {e}",
+                    path.display()
+                )
+            }),
+            None => Err(format!("{} does not exist", path.display())),
+        };
+    }
+    if !path.is_dir() {
+        return Err(format!("{} is not a directory", path.display()));
+    }
+    let probe = path.join(format!(".netpulse-doctor-{}", std::process::id()));
+    fs::write(&probe, b"").map_err(describe)?;
+    fs::remove_file(&probe).map_err(describe)
+}
+
+/// Checks that the user `name` exists.
+///
+/// # Errors
+///
+/// Describes why the user can't be found.
+pub fn user_exists(name: &str) -> Result<(), String> {
+    crate::lookup_user(name)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Checks that a raw ICMP socket can be created, which needs `CAP_NET_RAW`.
+///
+/// # Errors
+///
+/// Describes why the socket can't be created.
+pub fn raw_socket() -> Result<(), String> {
+    // the socket is closed right away when it is dropped
+    socket(
+        AddressFamily::Inet,
+        SockType::Raw,
+        SockFlag::empty(),
+        SockProtocol::Icmp,
+    )
+    .map(drop)
+    .map_err(|e| format!("{e}, ICMP checks will be skipped"))
+}
+
+/// Checks that the configuration file at `path` can be loaded, see
+/// [Config::load_from].
+///
+/// # Errors
+///
+/// Describes why the configuration can't be loaded.
+pub fn config_parses(path: &Path) -> Result<(), String> {
+    Config::load_from(path)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checks_detect_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(writable_dir(dir.path()), Ok(()));
+        assert_eq!(writable_dir(&dir.path().join("new/nested")), Ok(()));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        // a file where a directory should be fails, even for root
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        assert!(writable_dir(&file).unwrap_err().contains("not a directory"));
+        assert!(writable_dir(&file.join("store"))
+            .unwrap_err()
+            .contains("can't be created"));
+
+        assert_eq!(user_exists("root"), Ok(()));
+        assert!(user_exists("netpulse-no-such-user").is_err());
+
+        let config = dir.path().join("netpulse.toml");
+        assert_eq!(config_parses(&config), Ok(()));
+        fs::write(&config, "[udp]\ncount = 0\n").unwrap();
+        assert!(config_parses(&config).unwrap_err().contains("udp.count"));
+        fs::write(&config, "hosts = [").unwrap();
+        assert!(config_parses(&config).is_err());
+
+        let failed = Finding::new("user exists", user_exists("netpulse-no-such-user"));
+        assert!(!failed.passed());
+        assert!(failed.to_string().starts_with("[FAIL] user exists: "));
+        assert_eq!(
+            Finding::new("raw sockets", Ok(())).to_string(),
+            "[ ok ] raw sockets"
+        );
+    }
+
+    #[test]
+    fn test_checks_as_user() {
+        let current = User::from_uid(Uid::effective()).unwrap().unwrap();
+        assert_eq!(as_user(&current, &|| Ok(())), Ok(()));
+        assert_eq!(
+            as_user(&current, &|| Err("problem".to_string())),
+            Err("problem".to_string())
+        );
+
+        let Ok(Some(nobody)) = User::from_name("nobody") else {
+            return;
+        };
+        if !Uid::effective().is_root() {
+            return;
+        }
+        // a directory only root can write to is not writable for the daemon user
+        let dir = tempfile::tempdir().unwrap();
+        fs::set_permissions(
+            dir.path(),
+            std::os::unix::fs::PermissionsExt::from_mode(0o755),
+        )
+        .unwrap();
+        assert_eq!(writable_dir(dir.path()), Ok(()));
+        let problem = as_user(&nobody, &|| writable_dir(dir.path())).unwrap_err();
+        assert!(problem.contains("Permission denied"), "{problem}");
+        assert!(as_user(&nobody, &raw_socket).is_err());
+    }
+}
@@ -5,6 +5,12 @@
 //! - [`CheckError`] - Errors that occur during network checks (HTTP, ICMP)
 //! - [`DaemonError`] - Errors specific to daemon operations
 //! - [`AnalysisError`] - Errors that occur during analysis and report generation
+//! - [`RecordError`] - Errors for invalid [Checks](crate::records::Check)
+//! - [`ConfigError`] - Errors while loading the [Config](crate::config::Config)
+//! - [`ControlError`] - Errors that occur when talking to the daemon over the control socket
+//! - [`AnnotationError`] - Errors while reading or writing the
+//!   [Annotations](crate::annotations::Annotations)
+//! - [`UserError`] - Errors while looking up the user the daemon drops to
 //!
 //! All error types implement the standard Error trait and provide detailed error information.
 //!
@@ -23,6 +29,9 @@
 //! }
 //! ```
 
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
 use flagset::FlagSet;
 use thiserror::Error;
 
@@ -41,9 +50,28 @@ pub enum StoreError {
     /// This typically occurs on first run or if the store file was deleted.
     #[error("The store does not exist")]
     DoesNotExist,
+    /// The directory of the store file could not be created.
+    #[error("Could not create the store directory {}: {source}", path.display())]
+    CreateDir {
+        /// The directory that could not be created
+        path: std::path::PathBuf,
+        /// Underlying error
+        source: std::io::Error,
+    },
+    /// An I/O error occurred while accessing a store file.
+    #[error("Could not {operation} the store at {}: {source}", path.display())]
+    File {
+        /// What was done with the file
+        operation: StoreOperation,
+        /// The store file
+        path: PathBuf,
+        /// Underlying error
+        source: std::io::Error,
+    },
     /// An I/O error occurred during store operations.
     ///
-    /// This can happen during file reading, writing, or filesystem operations.
+    /// This can happen during file reading, writing, or filesystem operations. Errors while
+    /// accessing a known store file are [StoreError::File] instead.
     #[error("IO Error: {source}")]
     Io {
         /// Underlying error
@@ -59,18 +87,54 @@ pub enum StoreError {
         #[from]
         source: bincode::Error,
     },
-    /// Failed to convert data to UTF-8.
+    /// Failed to parse a [TOML store](crate::store::StoreFormat::Toml).
+    #[cfg(feature = "toml-store")]
+    #[error("Could not parse the TOML store: {source}")]
+    TomlLoad {
+        /// Underlying error
+        #[from]
+        source: toml::de::Error,
+    },
+    /// Failed to serialize a [TOML store](crate::store::StoreFormat::Toml).
+    #[cfg(feature = "toml-store")]
+    #[error("Could not serialize the TOML store: {source}")]
+    TomlSave {
+        /// Underlying error
+        #[from]
+        source: toml::ser::Error,
+    },
+    /// A store file is [TOML](crate::store::StoreFormat::Toml), but the `toml-store` feature
+    /// is not enabled.
+    #[error("The store is a TOML file, which needs the toml-store feature")]
+    TomlNotEnabled,
+    /// The store is encrypted or a key is set, but the `encryption` feature is not enabled.
+    #[error("Store encryption needs the encryption feature")]
+    EncryptionNotEnabled,
+    /// The store is encrypted, but no [key](crate::crypt::StoreKey) is set.
+    #[error(
+        "The store is encrypted, but no key is set in {} or {}",
+        crate::crypt::ENV_KEY,
+        crate::crypt::ENV_KEY_FILE
+    )]
+    KeyMissing,
+    /// The [key](crate::crypt::StoreKey) in the environment is malformed or can't be read.
+    #[error("Invalid store key: {0}")]
+    BadKey(String),
+    /// The store could not be decrypted.
     ///
-    /// This can occur when reading store metadata like file hashes.
-    #[error("Could not convert data to Utf8")]
-    Str {
+    /// Either the key is not the one the store was encrypted with, or the file was changed.
+    #[error("Could not decrypt the store, the key is wrong or the file is damaged")]
+    Decrypt,
+    /// The store could not be encrypted.
+    #[error("Could not encrypt the store")]
+    Encrypt,
+    /// The user owning the store directory could not be found.
+    #[error("{source}")]
+    User {
         /// Underlying error
         #[from]
-        source: std::str::Utf8Error,
+        source: UserError,
     },
-    /// A subprocess (like sha256sum) exited with non-zero status.
-    #[error("A subprocess ended non successfully")]
-    ProcessEndedWithoutSuccess,
     /// Attempted to load a store with an unsupported version number.
     ///
     /// This occurs when the store file version is newer or older than what this version
@@ -96,6 +160,65 @@ pub enum StoreError {
     MissingFlag(FlagSet<CheckFlag>),
 }
 
+impl StoreError {
+    /// Adds the `operation` on the store file at `path` to an [StoreError::Io], turning it into
+    /// a [StoreError::File]. Other errors are returned unchanged.
+    pub(crate) fn in_file(self, operation: StoreOperation, path: &Path) -> Self {
+        match self {
+            Self::Io { source } => Self::File {
+                operation,
+                path: path.to_path_buf(),
+                source,
+            },
+            other => other,
+        }
+    }
+}
+
+/// What was done with a store file when a [StoreError::File] occurred.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum StoreOperation {
+    /// Reading the store
+    Load,
+    /// Writing the store
+    Save,
+    /// Creating a new store file
+    Create,
+}
+
+impl Display for StoreOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Load => "load",
+            Self::Save => "save",
+            Self::Create => "create",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Errors for [Checks](crate::records::Check) that describe an impossible state.
+///
+/// These are returned by the validating constructor
+/// [Check::try_new](crate::records::Check::try_new), and when parsing a
+/// [CheckType](crate::records::CheckType) from a string.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RecordError {
+    /// More than one check type flag is set.
+    ///
+    /// This variant contains a [FlagSet] with only the conflicting type flags set.
+    #[error("Check has conflicting type flags: {0:?}")]
+    ConflictingTypes(FlagSet<CheckFlag>),
+    /// The IP flags contradict each other or the target address.
+    ///
+    /// This variant contains a [FlagSet] with only the conflicting IP flags set.
+    #[error("Check has conflicting IP flags: {0:?}")]
+    ConflictingIp(FlagSet<CheckFlag>),
+    /// The string does not name a [CheckType](crate::records::CheckType) that can be checked.
+    #[error("Unknown check type: '{0}', expected one of http, icmpv4, icmpv6, udp, throughput")]
+    UnknownCheckType(String),
+}
+
 /// Errors that can occur during network checks.
 ///
 /// These errors handle failures during the actual network connectivity tests,
@@ -131,6 +254,42 @@ pub enum CheckError {
         #[from]
         source: curl::Error,
     },
+    /// A hostname did not resolve to an address usable for the check.
+    ///
+    /// This variant contains the hostname.
+    #[error("{0} did not resolve to a usable address")]
+    NoAddress(String),
+    /// The probes could not be sent from the configured [Source](crate::config::Source), like
+    /// from an interface that is down.
+    #[error("Could not bind to {local}: {reason}")]
+    Bind {
+        /// The source, as configured
+        local: String,
+        /// Why it could not be used
+        reason: String,
+    },
+}
+
+/// Errors that can occur while loading the [Config](crate::config::Config).
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    #[error("Could not read the config file: {source}")]
+    Io {
+        /// Underlying error
+        #[from]
+        source: std::io::Error,
+    },
+    /// The configuration file is not valid TOML or does not fit the expected structure.
+    #[error("Could not parse the config file: {source}")]
+    Parse {
+        /// Underlying error
+        #[from]
+        source: toml::de::Error,
+    },
+    /// The configuration contains an invalid value.
+    #[error("Invalid configuration: {0}")]
+    Invalid(String),
 }
 
 /// Errors that can occur during daemon operations.
@@ -160,6 +319,20 @@ pub enum RunError {
         #[from]
         source: std::fmt::Error,
     },
+    /// The configuration could not be loaded.
+    #[error("Something went wrong with the config: {source}")]
+    Config {
+        /// Underlying error
+        #[from]
+        source: ConfigError,
+    },
+    /// The annotations could not be read or written.
+    #[error("Something went wrong with the annotations: {source}")]
+    Annotation {
+        /// Underlying error
+        #[from]
+        source: AnnotationError,
+    },
 }
 
 /// Errors that can occur during analysis and report generation.
@@ -182,4 +355,103 @@ pub enum AnalysisError {
         #[from]
         source: std::fmt::Error,
     },
+    /// No check has a target matching the [filter](crate::analyze::filter_targets).
+    #[error("no matching targets for '{0}'")]
+    NoMatchingTargets(String),
+    /// The annotations of the outages could not be read.
+    #[error("Something went wrong with the annotations: {source}")]
+    Annotation {
+        /// Underlying error
+        #[from]
+        source: AnnotationError,
+    },
+    /// The string does not name a [Format](crate::export::Format).
+    #[error(
+        "Unknown format '{0}', expected one of: {names}",
+        names = crate::export::Format::NAMES.join(", ")
+    )]
+    UnknownFormat(String),
+    /// The report could not be written to a file.
+    #[error("IO Error: {source}")]
+    Io {
+        /// Underlying error
+        #[from]
+        source: std::io::Error,
+    },
+}
+
+/// Errors that can occur while reading or writing the
+/// [Annotations](crate::annotations::Annotations).
+#[derive(Error, Debug)]
+pub enum AnnotationError {
+    /// The annotations file could not be read or written.
+    #[error("IO Error: {source}")]
+    Io {
+        /// Underlying error
+        #[from]
+        source: std::io::Error,
+    },
+    /// The annotations file is not valid JSON or does not fit the expected structure.
+    #[error("Could not parse the annotations file: {source}")]
+    Parse {
+        /// Underlying error
+        #[from]
+        source: serde_json::Error,
+    },
+    /// The given outage start is not a valid timestamp.
+    #[error("Invalid outage start '{0}', expected a timestamp like 2024-12-08T14:00:00Z")]
+    InvalidStart(String),
+}
+
+/// Errors that can occur when talking to the daemon over the control socket.
+///
+/// See the [control](crate::control) module.
+#[derive(Error, Debug)]
+pub enum ControlError {
+    /// An I/O error occurred on the socket.
+    ///
+    /// This typically means that the daemon is not running or the socket is not accessible.
+    #[error("IO Error: {source}")]
+    Io {
+        /// Underlying error
+        #[from]
+        source: std::io::Error,
+    },
+    /// An error occurred while operating on the store.
+    #[error("Something went wrong with the store: {source}")]
+    StoreError {
+        /// Underlying error
+        #[from]
+        source: StoreError,
+    },
+    /// The received line is not a valid [Command](crate::control::Command).
+    #[error("Invalid command: '{0}'")]
+    InvalidCommand(String),
+    /// The daemon answered with an error.
+    #[error("The daemon reported an error: {0}")]
+    Daemon(String),
+}
+
+/// Errors while looking up the user the daemon drops to, see [lookup_user](crate::lookup_user).
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum UserError {
+    /// There is no user with that name.
+    ///
+    /// This is common on the first run, the [DAEMON_USER](crate::DAEMON_USER) has to be created
+    /// before the daemon is started.
+    #[error(
+        "The user {name} does not exist. Create it with `useradd -r -s /usr/sbin/nologin {name}`, or start the daemon with --user to run it as the current user"
+    )]
+    Missing {
+        /// Name of the user
+        name: String,
+    },
+    /// The user database could not be read.
+    #[error("Could not look up the user {name}: {source}")]
+    Lookup {
+        /// Name of the user
+        name: String,
+        /// Underlying error
+        source: nix::errno::Errno,
+    },
 }
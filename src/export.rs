@@ -0,0 +1,493 @@
+//! Reports in machine readable formats, for spreadsheets and monitoring systems.
+//!
+//! Besides the human readable [text report](crate::analyze::analyze_with), the statistics of
+//! each check type and target can be rendered as JSON, CSV or in the Prometheus text format,
+//! see [Format]. `netpulse --output PATH --format FORMAT` writes them to a file with
+//! [write_to].
+//!
+//! The JSON is wrapped in an envelope with the [SCHEMA_VERSION], so downstream tools can tell
+//! whether they understand it:
+//!
+//! ```json
+//! {"schema_version": 1, "generated_at": "2024-12-08T14:00:00Z", "data": {"checks": 3, ...}}
+//! ```
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use netpulse::config::Config;
+//! use netpulse::export::{self, Format};
+//! use netpulse::store::Store;
+//!
+//! let store = Store::load_all().unwrap();
+//! let csv = export::render(&store, &Config::default(), Format::Csv).unwrap();
+//! export::write_to("/tmp/netpulse.csv".as_ref(), &csv).unwrap();
+//! ```
+//!
+//! # Custom Formats
+//!
+//! Each format is made by a [Reporter]. Other formats can be added by implementing it, and
+//! rendered with [render_with] like the built-in ones:
+//!
+//! ```rust,no_run
+//! use std::io::Write;
+//!
+//! use netpulse::errors::AnalysisError;
+//! use netpulse::export::{self, Reporter};
+//! use netpulse::store::Store;
+//!
+//! struct Count;
+//!
+//! impl Reporter for Count {
+//!     fn report(&self, store: &Store, w: &mut impl Write) -> Result<(), AnalysisError> {
+//!         writeln!(w, "{} checks", store.checks().len())?;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let store = Store::load_all().unwrap();
+//! print!("{}", export::render_with(&Count, &store).unwrap());
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::analyze;
+use crate::annotations::Annotations;
+use crate::config::Config;
+use crate::errors::AnalysisError;
+use crate::records::{Check, CheckType};
+use crate::store::Store;
+
+/// Version of the layout of the [JSON export](Format::Json)
+///
+/// Bumped on every change that could break a consumer, like removing or renaming a field.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Makes a report of a [Store] in some format.
+///
+/// The built-in formats are [TextReporter], [JsonReporter], [CsvReporter] and
+/// [PrometheusReporter], see [Format]. See the [module documentation](self) for a custom one.
+pub trait Reporter {
+    /// Writes the report of `store` to `w`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [AnalysisError] if the report can't be made or written to `w`.
+    fn report(&self, store: &Store, w: &mut impl Write) -> Result<(), AnalysisError>;
+}
+
+/// Format of a rendered report, see [render].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub enum Format {
+    /// The human readable report of [analyze_with](analyze::analyze_with)
+    #[default]
+    Text,
+    /// A JSON object with the totals and the [Stats] of each check type and target, in a
+    /// versioned envelope, see [SCHEMA_VERSION]
+    Json,
+    /// One line of [Stats] per check type and target, with a header
+    Csv,
+    /// The Prometheus text exposition format, with the check type and target as labels
+    Prometheus,
+}
+
+impl Format {
+    /// Names of all formats, as accepted by [from_str](Format::from_str)
+    pub const NAMES: &[&str] = &["text", "json", "csv", "prometheus"];
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Text => "text",
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Prometheus => "prometheus",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Format {
+    type Err = AnalysisError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "text" => Self::Text,
+            "json" => Self::Json,
+            "csv" => Self::Csv,
+            "prometheus" => Self::Prometheus,
+            _ => return Err(AnalysisError::UnknownFormat(s.to_string())),
+        })
+    }
+}
+
+/// Statistics of the checks of one type against one target.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Stats {
+    /// Type of the checks, lowercase
+    pub check_type: String,
+    /// Target of the checks
+    pub target: IpAddr,
+    /// Amount of checks
+    pub checks: usize,
+    /// Amount of successful checks
+    pub successes: usize,
+    /// Mean latency of the checks with a latency in milliseconds, [None] if there are none
+    pub mean_latency_ms: Option<f64>,
+}
+
+impl Stats {
+    /// Ratio of successful checks, between 0.0 and 1.0.
+    pub fn success_ratio(&self) -> f64 {
+        self.successes as f64 / self.checks as f64
+    }
+}
+
+/// The envelope of [Format::Json].
+#[derive(Debug, PartialEq, Clone, Serialize)]
+struct Envelope<T> {
+    /// Always [SCHEMA_VERSION]
+    schema_version: u32,
+    /// When the export was rendered, in RFC 3339
+    generated_at: String,
+    data: T,
+}
+
+impl<T> Envelope<T> {
+    fn new(data: T) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            generated_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            data,
+        }
+    }
+}
+
+/// The totals and statistics of a store, the data of [Format::Json].
+#[derive(Debug, PartialEq, Clone, Serialize)]
+struct Summary {
+    checks: usize,
+    successes: usize,
+    stats: Vec<Stats>,
+}
+
+/// Calculates the [Stats] of each check type and target, ordered by check type and target.
+pub fn stats(store: &Store) -> Vec<Stats> {
+    let mut groups: BTreeMap<(String, IpAddr), Vec<&Check>> = BTreeMap::new();
+    for check in store.checks() {
+        let check_type = check.calc_type().unwrap_or(CheckType::Unknown);
+        groups
+            .entry((check_type.to_string().to_lowercase(), check.target()))
+            .or_default()
+            .push(check);
+    }
+    groups
+        .into_iter()
+        .map(|((check_type, target), checks)| {
+            let latencies: Vec<f64> = checks
+                .iter()
+                .filter_map(|c| c.latency().map(f64::from))
+                .collect();
+            Stats {
+                check_type,
+                target,
+                checks: checks.len(),
+                successes: checks.iter().filter(|c| c.is_success()).count(),
+                mean_latency_ms: (!latencies.is_empty())
+                    .then(|| latencies.iter().sum::<f64>() / latencies.len() as f64),
+            }
+        })
+        .collect()
+}
+
+/// The human readable report of [Format::Text].
+#[derive(Debug, Clone, Copy)]
+pub struct TextReporter<'config> {
+    config: &'config Config,
+    annotations: Option<&'config Annotations>,
+}
+
+impl<'config> TextReporter<'config> {
+    /// Creates a reporter for the report of [analyze_with](analyze::analyze_with) with
+    /// `config`.
+    pub fn new(config: &'config Config) -> Self {
+        Self {
+            config,
+            annotations: None,
+        }
+    }
+
+    /// Shows `annotations` with their outages, see
+    /// [analyze_annotated](analyze::analyze_annotated). Without them, none are shown.
+    pub fn annotated(mut self, annotations: &'config Annotations) -> Self {
+        self.annotations = Some(annotations);
+        self
+    }
+}
+
+impl Reporter for TextReporter<'_> {
+    fn report(&self, store: &Store, w: &mut impl Write) -> Result<(), AnalysisError> {
+        match self.annotations {
+            Some(annotations) => {
+                analyze::write_report_annotated(store, self.config, annotations, w)
+            }
+            None => analyze::write_report_with(store, self.config, w),
+        }
+    }
+}
+
+/// The JSON object of [Format::Json].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct JsonReporter;
+
+impl Reporter for JsonReporter {
+    fn report(&self, store: &Store, w: &mut impl Write) -> Result<(), AnalysisError> {
+        let summary = Summary {
+            checks: store.checks().len(),
+            successes: store.checks().iter().filter(|c| c.is_success()).count(),
+            stats: stats(store),
+        };
+        serde_json::to_writer_pretty(&mut *w, &Envelope::new(summary)).map_err(io::Error::from)?;
+        writeln!(w)?;
+        Ok(())
+    }
+}
+
+/// The lines of [Format::Csv].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct CsvReporter;
+
+impl Reporter for CsvReporter {
+    fn report(&self, store: &Store, w: &mut impl Write) -> Result<(), AnalysisError> {
+        writeln!(
+            w,
+            "check_type,target,checks,successes,success_ratio,mean_latency_ms"
+        )?;
+        for s in stats(store) {
+            writeln!(
+                w,
+                "{},{},{},{},{:.4},{}",
+                s.check_type,
+                s.target,
+                s.checks,
+                s.successes,
+                s.success_ratio(),
+                s.mean_latency_ms
+                    .map(|l| format!("{l:.1}"))
+                    .unwrap_or_default()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The metrics of [Format::Prometheus].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct PrometheusReporter;
+
+impl Reporter for PrometheusReporter {
+    fn report(&self, store: &Store, w: &mut impl Write) -> Result<(), AnalysisError> {
+        let stats = stats(store);
+        for (name, kind, help) in [
+            ("netpulse_checks_total", "counter", "Amount of checks"),
+            (
+                "netpulse_checks_ok_total",
+                "counter",
+                "Amount of successful checks",
+            ),
+            (
+                "netpulse_latency_mean_ms",
+                "gauge",
+                "Mean latency in milliseconds",
+            ),
+        ] {
+            writeln!(w, "# HELP {name} {help}")?;
+            writeln!(w, "# TYPE {name} {kind}")?;
+            for s in &stats {
+                let value = match name {
+                    "netpulse_checks_total" => s.checks.to_string(),
+                    "netpulse_checks_ok_total" => s.successes.to_string(),
+                    _ => match s.mean_latency_ms {
+                        Some(latency) => latency.to_string(),
+                        None => continue,
+                    },
+                };
+                writeln!(
+                    w,
+                    "{name}{{check_type=\"{}\",target=\"{}\"}} {value}",
+                    s.check_type, s.target
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders the report of `store` in `format`.
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if the [text report](analyze::analyze_with) fails.
+pub fn render(store: &Store, config: &Config, format: Format) -> Result<String, AnalysisError> {
+    match format {
+        Format::Text => render_with(&TextReporter::new(config), store),
+        Format::Json => render_with(&JsonReporter, store),
+        Format::Csv => render_with(&CsvReporter, store),
+        Format::Prometheus => render_with(&PrometheusReporter, store),
+    }
+}
+
+/// Renders the report of `store` made by `reporter`.
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if the reporter fails or its report is not UTF-8.
+pub fn render_with(reporter: &impl Reporter, store: &Store) -> Result<String, AnalysisError> {
+    let mut buf = Vec::new();
+    reporter.report(store, &mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into())
+}
+
+/// Writes `report` to `path`, replacing it atomically like a [snapshot](Store::snapshot).
+///
+/// # Errors
+///
+/// Returns [AnalysisError] if the file can't be written.
+pub fn write_to(path: &Path, report: &str) -> Result<(), AnalysisError> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    fs::write(&tmp_path, report)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::records::CheckFlag;
+
+    use super::*;
+
+    fn store() -> Store {
+        let mut store = Store::new();
+        for (secs, flags, latency) in [
+            (60, CheckFlag::TypeHTTP | CheckFlag::Success, Some(20)),
+            (120, CheckFlag::TypeHTTP.into(), None),
+            (60, CheckFlag::TypeUdp | CheckFlag::Success, Some(8)),
+        ] {
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                flags | CheckFlag::IPv4,
+                latency,
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+        store
+    }
+
+    #[test]
+    fn test_format_names() {
+        for name in Format::NAMES {
+            assert_eq!(name.parse::<Format>().unwrap().to_string(), *name);
+        }
+        let err = "xml".parse::<Format>().unwrap_err().to_string();
+        assert!(err.contains("'xml'"));
+        assert!(err.contains("text, json, csv, prometheus"));
+    }
+
+    #[test]
+    fn test_formats_are_well_formed() {
+        let store = store();
+        let dir = tempfile::tempdir().unwrap();
+
+        for format in [Format::Json, Format::Csv, Format::Prometheus] {
+            let path = dir.path().join(format!("report.{format}"));
+            write_to(&path, &render(&store, &Config::default(), format).unwrap()).unwrap();
+            let written = fs::read_to_string(&path).unwrap();
+            assert!(!written.is_empty());
+
+            match format {
+                Format::Json => {
+                    let json: serde_json::Value = serde_json::from_str(&written).unwrap();
+                    assert_eq!(json["schema_version"], SCHEMA_VERSION);
+                    humantime::parse_rfc3339(json["generated_at"].as_str().unwrap()).unwrap();
+                    let data = &json["data"];
+                    assert_eq!(data["checks"], 3);
+                    assert_eq!(data["stats"][0]["check_type"], "http(s)");
+                    assert_eq!(data["stats"][0]["successes"], 1);
+                }
+                Format::Csv => {
+                    let lines: Vec<&str> = written.lines().collect();
+                    assert_eq!(lines.len(), 3);
+                    assert!(lines.iter().all(|l| l.split(',').count() == 6));
+                    assert_eq!(lines[2], "udp,1.1.1.1,1,1,1.0000,8.0");
+                }
+                Format::Prometheus => {
+                    assert!(written.contains(
+                        "netpulse_checks_total{check_type=\"http(s)\",target=\"1.1.1.1\"} 2"
+                    ));
+                    for line in written.lines().filter(|l| !l.starts_with('#')) {
+                        let (metric, value) = line.rsplit_once(' ').unwrap();
+                        assert!(metric.ends_with('}'));
+                        value.parse::<f64>().unwrap();
+                    }
+                }
+                Format::Text => unreachable!(),
+            }
+        }
+        assert!(!dir.path().join("report.csv.tmp").exists());
+    }
+
+    #[test]
+    fn test_custom_reporter() {
+        /// Lists the targets of the failed checks
+        struct Failures;
+
+        impl Reporter for Failures {
+            fn report(&self, store: &Store, w: &mut impl Write) -> Result<(), AnalysisError> {
+                for check in store.checks().iter().filter(|c| !c.is_success()) {
+                    writeln!(w, "{} failed", check.target())?;
+                }
+                Ok(())
+            }
+        }
+
+        let store = store();
+        assert_eq!(render_with(&Failures, &store).unwrap(), "1.1.1.1 failed\n");
+        // the built-in formats are rendered the same way
+        assert_eq!(
+            render_with(&CsvReporter, &store).unwrap(),
+            render(&store, &Config::default(), Format::Csv).unwrap()
+        );
+
+        let mut config = Config::default();
+        config.report.skip_store_file = true;
+        let annotations = Annotations::default();
+        // the time it took to make the report and how long the ongoing outage lasts differ
+        let timeless = |report: String| {
+            report
+                .lines()
+                .filter(|l| !l.starts_with("Report Time") && !l.starts_with("Duration"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        assert_eq!(
+            timeless(
+                render_with(&TextReporter::new(&config).annotated(&annotations), &store).unwrap()
+            ),
+            timeless(analyze::analyze_annotated(&store, &config, &annotations).unwrap())
+        );
+    }
+}
@@ -0,0 +1,425 @@
+//! HTTP health endpoints of the daemon, for container orchestration.
+//!
+//! Liveness and readiness probes, like those of Kubernetes, want to talk HTTP. If
+//! [listen](crate::config::HealthConfig::listen) is set, the daemon runs a [HealthServer] with
+//! these endpoints:
+//!
+//! - `/healthz` - `200` as long as the daemon is alive
+//! - `/readyz` - `200` if the last check cycle is recent and had a successful check, `503`
+//!   otherwise, see [is_ready]
+//! - `/metrics` - the [Prometheus export](crate::export::PrometheusReporter) of the store, as
+//!   of the last [update](Metrics::update)
+//!
+//! Other paths are answered with `404`. The server only speaks enough HTTP/1.1 for probes and
+//! scrapers, one request per connection. Each connection is served on a thread of its own, and
+//! a request has to arrive within [CLIENT_TIMEOUT] and [MAX_REQUEST_BYTES].
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::sync::{Arc, Mutex};
+//!
+//! use netpulse::config::HealthConfig;
+//! use netpulse::health::{HealthServer, Metrics};
+//! use netpulse::recent::RecentChecks;
+//! use netpulse::store::Store;
+//!
+//! let recent = Arc::new(Mutex::new(RecentChecks::default()));
+//! let metrics = Arc::new(Metrics::default());
+//! metrics.update(&Store::load().unwrap());
+//! let server = HealthServer::bind("127.0.0.1:9464".parse().unwrap(), &HealthConfig::default())
+//!     .unwrap()
+//!     .with_recent(recent)
+//!     .with_metrics(metrics);
+//! server.spawn();
+//! ```
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::HealthConfig;
+use crate::export::{self, PrometheusReporter};
+use crate::recent::{RecentChecks, Status};
+use crate::store::Store;
+
+/// How long a client may take to send its request, so a stuck client does not keep its thread
+pub const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the request line and headers of a request may be, longer ones are answered with
+/// `431`
+pub const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Content type of the Prometheus text exposition format
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Server of the health endpoints, see the [module documentation](self).
+#[derive(Debug)]
+pub struct HealthServer {
+    /// The bound socket
+    listener: TcpListener,
+    /// How old the last cycle may be for `/readyz`
+    stale_after: Duration,
+    /// The checks `/readyz` looks at, if set
+    recent: Option<Arc<Mutex<RecentChecks>>>,
+    /// What `/metrics` serves, if set
+    metrics: Option<Arc<Metrics>>,
+}
+
+/// The [Prometheus export](crate::export::PrometheusReporter) of the store that `/metrics`
+/// serves.
+///
+/// The daemon [updates](Metrics::update) it after every check cycle, so a scrape does not wait
+/// for the store, which is locked while the checks are made.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// The rendered export, [None] before the first update
+    rendered: Mutex<Option<Result<String, String>>>,
+}
+
+impl Metrics {
+    /// Renders the export of `store` for the following scrapes.
+    pub fn update(&self, store: &Store) {
+        let rendered =
+            export::render_with(&PrometheusReporter, store).map_err(|err| err.to_string());
+        *self.rendered.lock().expect("the metrics lock is poisoned") = Some(rendered);
+    }
+
+    /// Returns the export as of the last update, or why it could not be rendered, [None]
+    /// before the first update.
+    pub fn get(&self) -> Option<Result<String, String>> {
+        self.rendered
+            .lock()
+            .expect("the metrics lock is poisoned")
+            .clone()
+    }
+}
+
+impl HealthServer {
+    /// Binds the server to `addr`, with the thresholds of `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [io::Error] if the address can't be bound.
+    pub fn bind(addr: SocketAddr, config: &HealthConfig) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            stale_after: Duration::from_secs(config.stale_secs),
+            recent: None,
+            metrics: None,
+        })
+    }
+
+    /// Answers `/readyz` from `recent`, which the daemon fills after every check cycle.
+    ///
+    /// Without it, the daemon is never ready.
+    pub fn with_recent(mut self, recent: Arc<Mutex<RecentChecks>>) -> Self {
+        self.recent = Some(recent);
+        self
+    }
+
+    /// Answers `/metrics` from `metrics`, which the daemon updates after every check cycle.
+    ///
+    /// Without it, `/metrics` is answered with `503`.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Returns the address the server is bound to, useful when binding to port 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [io::Error] if the address of the socket can't be read.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections in a background thread, and serves each on a thread of its own.
+    pub fn spawn(self) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            for stream in self.listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        crate::logging::error(format!("error on the health endpoint: {err}"));
+                        continue;
+                    }
+                };
+                let (recent, metrics) = (self.recent.clone(), self.metrics.clone());
+                let stale_after = self.stale_after;
+                std::thread::spawn(move || {
+                    let result =
+                        handle_client(stream, recent.as_deref(), metrics.as_deref(), stale_after);
+                    if let Err(err) = result {
+                        crate::logging::error(format!("error on the health endpoint: {err}"));
+                    }
+                });
+            }
+        })
+    }
+}
+
+/// Returns whether the daemon is ready: the last cycle ended at most `stale_after` before
+/// `now`, and at least one of its checks succeeded.
+pub fn is_ready(status: &Status, now: SystemTime, stale_after: Duration) -> bool {
+    let Some(last_cycle) = status.last_cycle else {
+        return false;
+    };
+    // a cycle in the future is as good as a current one
+    let age = now.duration_since(last_cycle).unwrap_or_default();
+    age <= stale_after && status.cycle_failed < status.cycle_checks
+}
+
+/// An answer of the server.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Response {
+    /// HTTP status code
+    status: u16,
+    /// Value of the `Content-Type` header
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    /// Creates a plain text answer.
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+        }
+    }
+}
+
+/// Reads the request line and the headers of a request from `stream`.
+///
+/// The headers are not needed, but the client may wait until they are read. Returns [None] if
+/// they are longer than [MAX_REQUEST_BYTES].
+///
+/// # Errors
+///
+/// Returns an [io::Error] if reading fails or the client takes longer than [CLIENT_TIMEOUT] in
+/// total.
+fn read_head(mut stream: &TcpStream) -> io::Result<Option<String>> {
+    let deadline = Instant::now() + CLIENT_TIMEOUT;
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.ends_with(b"\r\n\r\n") && !head.ends_with(b"\n\n") {
+        if head.len() >= MAX_REQUEST_BYTES {
+            return Ok(None);
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        stream.set_read_timeout(Some(left))?;
+        let take = buf.len().min(MAX_REQUEST_BYTES - head.len());
+        let len = stream.read(&mut buf[..take])?;
+        if len == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..len]);
+    }
+    Ok(Some(String::from_utf8_lossy(&head).into_owned()))
+}
+
+/// Reads a single request from `stream` and writes the answer.
+fn handle_client(
+    stream: TcpStream,
+    recent: Option<&Mutex<RecentChecks>>,
+    metrics: Option<&Metrics>,
+    stale_after: Duration,
+) -> io::Result<()> {
+    let head = read_head(&stream)?;
+    let request_line = head.as_deref().and_then(|h| h.lines().next()).unwrap_or("");
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let response = match method {
+        _ if head.is_none() => Response::text(431, "the request is too large\n"),
+        "GET" | "HEAD" => respond(path, recent, metrics, stale_after),
+        _ => Response::text(405, "only GET and HEAD are supported\n"),
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(response.body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+/// Answers a request for `path`.
+fn respond(
+    path: &str,
+    recent: Option<&Mutex<RecentChecks>>,
+    metrics: Option<&Metrics>,
+    stale_after: Duration,
+) -> Response {
+    // probes may add a query, like ?verbose
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    match path {
+        "/healthz" => Response::text(200, "ok\n"),
+        "/readyz" => {
+            let status = recent.map(|recent| {
+                recent
+                    .lock()
+                    .expect("the recent checks lock is poisoned")
+                    .status()
+            });
+            match status {
+                Some(status) if is_ready(&status, SystemTime::now(), stale_after) => {
+                    Response::text(200, status.to_string())
+                }
+                Some(status) => Response::text(503, status.to_string()),
+                None => Response::text(503, "the daemon does not track its checks\n"),
+            }
+        }
+        "/metrics" => match metrics.and_then(Metrics::get) {
+            Some(Ok(metrics)) => Response {
+                status: 200,
+                content_type: METRICS_CONTENT_TYPE,
+                body: metrics,
+            },
+            Some(Err(err)) => Response::text(500, format!("{err}\n")),
+            None => Response::text(503, "no metrics yet\n"),
+        },
+        _ => Response::text(404, "not found\n"),
+    }
+}
+
+/// Returns the reason phrase of the status codes the server uses.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::time::UNIX_EPOCH;
+
+    use crate::records::{Check, CheckFlag};
+
+    use super::*;
+
+    /// Sends a GET request for `path` to `addr`, returns the status code and the body.
+    fn get(addr: SocketAddr, path: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, body.to_string())
+    }
+
+    fn cycle(recent: &Mutex<RecentChecks>, time: SystemTime, success: bool) {
+        let mut flags = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+        if success {
+            flags |= CheckFlag::Success;
+        }
+        let check = Check::new(time, flags, Some(12), "1.1.1.1".parse().unwrap());
+        recent.lock().unwrap().push_cycle(&[&check]);
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let recent = Arc::new(Mutex::new(RecentChecks::new(8)));
+        let metrics = Arc::new(Metrics::default());
+        let server = HealthServer::bind("127.0.0.1:0".parse().unwrap(), &HealthConfig::default())
+            .unwrap()
+            .with_recent(recent.clone())
+            .with_metrics(metrics.clone());
+        let addr = server.local_addr().unwrap();
+        server.spawn();
+
+        assert_eq!(get(addr, "/healthz"), (200, "ok\n".to_string()));
+        assert_eq!(get(addr, "/nope").0, 404);
+
+        // no cycle yet
+        assert_eq!(get(addr, "/readyz").0, 503);
+        // a stale cycle
+        cycle(&recent, UNIX_EPOCH + Duration::from_secs(60), true);
+        assert_eq!(get(addr, "/readyz").0, 503);
+        // a recent cycle without a successful check
+        cycle(&recent, SystemTime::now(), false);
+        assert_eq!(get(addr, "/readyz").0, 503);
+        // a recent, healthy cycle
+        cycle(&recent, SystemTime::now(), true);
+        let (status, body) = get(addr, "/readyz?verbose");
+        assert_eq!(status, 200);
+        assert!(body.starts_with("Last Cycle: "));
+
+        // nothing to serve before the first update
+        assert_eq!(get(addr, "/metrics").0, 503);
+        let mut store = Store::new();
+        store.add_check(Check::new(
+            SystemTime::now(),
+            CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            Some(12),
+            "1.1.1.1".parse().unwrap(),
+        ));
+        metrics.update(&store);
+        let (status, rendered) = get(addr, "/metrics");
+        assert_eq!(status, 200);
+        assert!(rendered.contains("netpulse_checks_total{"), "{rendered}");
+    }
+
+    #[test]
+    fn test_stuck_and_large_requests() {
+        let server =
+            HealthServer::bind("127.0.0.1:0".parse().unwrap(), &HealthConfig::default()).unwrap();
+        let addr = server.local_addr().unwrap();
+        server.spawn();
+
+        // a client that never sends its request does not hold up the others
+        let stuck = TcpStream::connect(addr).unwrap();
+        let start = Instant::now();
+        assert_eq!(get(addr, "/healthz").0, 200);
+        assert!(start.elapsed() < CLIENT_TIMEOUT);
+        drop(stuck);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let header = format!("X-Padding: {}\r\n", "x".repeat(MAX_REQUEST_BYTES));
+        write!(stream, "GET /healthz HTTP/1.1\r\n{header}\r\n").unwrap();
+        let mut response = String::new();
+        // the server may hang up before it read all of the request
+        let _ = stream.read_to_string(&mut response);
+        assert!(response.starts_with("HTTP/1.1 431 "), "{response}");
+    }
+
+    #[test]
+    fn test_is_ready() {
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        let stale_after = Duration::from_secs(180);
+        let status = |secs, failed| Status {
+            last_cycle: Some(UNIX_EPOCH + Duration::from_secs(secs)),
+            cycle_checks: 2,
+            cycle_failed: failed,
+            recent_checks: 2,
+            recent_failed: failed,
+        };
+        assert!(is_ready(&status(900, 1), now, stale_after));
+        assert!(is_ready(&status(820, 0), now, stale_after));
+        assert!(!is_ready(&status(819, 0), now, stale_after));
+        assert!(!is_ready(&status(900, 2), now, stale_after));
+        // clock skew
+        assert!(is_ready(&status(1100, 0), now, stale_after));
+        assert!(!is_ready(&RecentChecks::new(1).status(), now, stale_after));
+    }
+}
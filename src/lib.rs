@@ -6,20 +6,39 @@
 //! - [`store`] - Handles persistence of check results
 //! - [`records`] - Defines core types for representing checks and their results
 //! - [`checks`] - Implements the actual connectivity checks
+//! - [`annotations`] - Notes on outages, kept next to the store
+//! - [`backoff`] - Checks targets that keep failing less often
+//! - [`build_info`] - Metadata of the build, like the git commit
+//! - [`cache`] - Cached analysis of the store file, served by the daemon
+//! - [`config`] - Configuration file
 //! - [`analyze`] - Provides analysis of check results
+//! - [`control`] - Control socket to talk to the running daemon
+//! - [`crypt`] - Encryption of the store at rest, with the `encryption` feature
+//! - [`demo`] - A bundled store of made-up checks, for trying netpulse out
+//! - [`doctor`] - Self-test of the environment of the daemon
 //! - [`errors`] - Error types
+//! - [`export`] - Reports in machine readable formats
+//! - [`health`] - HTTP health endpoints of the daemon, with the `health-http` feature
+//! - [`logging`] - Log rotation and de-duplication for the daemon
+//! - [`maintenance`] - Recurring maintenance windows, left out of the outages
+//! - [`predicate`] - Custom success conditions of the checks of a target
+//! - [`recent`] - The most recent checks of the daemon, kept in memory
+//! - [`run`] - Runs all configured checks once, without a store or daemon
+//! - [`syslog`] - Outage events for the local syslog
+//! - [`timezone`] - Timezones for rendering timestamps in reports
 //! - [`analyze`] - Analysis functionalities for extrapolating the data in the [Store](store)
 //!
 //! # Example Usage
 //!
 //! ```rust,no_run
+//! use netpulse::config::Config;
 //! use netpulse::store::Store;
 //!
 //! // Load or create store
 //! let mut store = Store::load_or_create().unwrap();
 //!
 //! // Add checks for configured targets
-//! store.make_checks();
+//! store.make_checks(&Config::load().unwrap());
 //!
 //! // Save results
 //! store.save().unwrap();
@@ -27,22 +46,258 @@
 
 #![warn(missing_docs)]
 
+use std::path::{Path, PathBuf};
+
+use errors::UserError;
+
 /// How long to wait until considering a connection as timed out, in milliseconds
 pub const TIMEOUT_MS: u16 = 10_000;
 /// How long to wait until considering a connection as timed out
 pub const TIMEOUT: std::time::Duration = std::time::Duration::new(TIMEOUT_MS as u64 / 1000, 0);
 
 /// Lockfile of the daemon containing it#s pid
+///
+/// Can be overridden with [ENV_PID_FILE], see [pid_file].
 pub const DAEMON_PID_FILE: &str = "/run/netpulse/netpulse.pid";
+/// Unix socket on which the daemon listens for [control commands](control::Command)
+///
+/// Can be overridden with [ENV_CONTROL_SOCKET], see [control_socket].
+pub const DAEMON_CONTROL_SOCKET: &str = "/run/netpulse/netpulse.sock";
 /// Redirect the stderr of the daemon here
+///
+/// Can be overridden with [ENV_LOG_ERR], see [log_err_file].
 pub const DAEMON_LOG_ERR: &str = "/var/log/netpulse.err";
 /// Redirect the stdout of the daemon here
+///
+/// Can be overridden with [ENV_LOG_INF], see [log_inf_file].
 pub const DAEMON_LOG_INF: &str = "/var/log/netpulse.log";
 /// username of the user the daemon should drop to after being started
 pub const DAEMON_USER: &str = "netpulse";
 
+/// Looks up the user `name`, like the [DAEMON_USER].
+///
+/// # Errors
+///
+/// Returns [UserError::Missing] with how to create the user if there is no such user, and
+/// [UserError::Lookup] if the user database can't be read.
+pub fn lookup_user(name: &str) -> Result<nix::unistd::User, UserError> {
+    match nix::unistd::User::from_name(name) {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(UserError::Missing {
+            name: name.to_string(),
+        }),
+        Err(source) => Err(UserError::Lookup {
+            name: name.to_string(),
+            source,
+        }),
+    }
+}
+
+/// Environment variable name for overriding [DAEMON_PID_FILE]
+pub const ENV_PID_FILE: &str = "NETPULSE_PID_FILE";
+/// Environment variable name for overriding [DAEMON_CONTROL_SOCKET]
+pub const ENV_CONTROL_SOCKET: &str = "NETPULSE_CONTROL_SOCKET";
+/// Environment variable name for overriding [DAEMON_LOG_ERR]
+pub const ENV_LOG_ERR: &str = "NETPULSE_LOG_ERR";
+/// Environment variable name for overriding [DAEMON_LOG_INF]
+pub const ENV_LOG_INF: &str = "NETPULSE_LOG_INF";
+
+/// Returns the path of the pid file, [ENV_PID_FILE] or [DAEMON_PID_FILE].
+pub fn pid_file() -> PathBuf {
+    env_path(ENV_PID_FILE, DAEMON_PID_FILE)
+}
+
+/// Returns the path of the control socket, [ENV_CONTROL_SOCKET] or [DAEMON_CONTROL_SOCKET].
+pub fn control_socket() -> PathBuf {
+    env_path(ENV_CONTROL_SOCKET, DAEMON_CONTROL_SOCKET)
+}
+
+/// Returns the path of the error log, [ENV_LOG_ERR] or [DAEMON_LOG_ERR].
+pub fn log_err_file() -> PathBuf {
+    env_path(ENV_LOG_ERR, DAEMON_LOG_ERR)
+}
+
+/// Returns the path of the info log, [ENV_LOG_INF] or [DAEMON_LOG_INF].
+pub fn log_inf_file() -> PathBuf {
+    env_path(ENV_LOG_INF, DAEMON_LOG_INF)
+}
+
+/// Whether a process is alive, see [process_liveness].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Liveness {
+    /// The process is running and we may send signals to it
+    Running,
+    /// A process with that pid is running, but belongs to another user
+    ///
+    /// After a reboot, the pid in a stale pid file may belong to an unrelated process.
+    RunningNotOurs,
+    /// No process with that pid exists
+    NotRunning,
+}
+
+/// Checks whether the process with `pid` is alive, without relying on `/proc`.
+///
+/// Sends the null signal with `kill(pid, 0)`, which only checks for existence and permissions.
+/// Note that a zombie process counts as running until it is reaped.
+///
+/// A `pid` of 0 or below names no single process, `kill` would check a process group or every
+/// process instead, so it is [Liveness::NotRunning].
+pub fn process_liveness(pid: i32) -> Liveness {
+    if pid <= 0 {
+        return Liveness::NotRunning;
+    }
+    match nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), None) {
+        Ok(()) => Liveness::Running,
+        Err(nix::errno::Errno::EPERM) => Liveness::RunningNotOurs,
+        Err(_) => Liveness::NotRunning,
+    }
+}
+
+/// Returns the pid of the running daemon, from the [pid file](pid_file).
+///
+/// See [running_daemon_at].
+pub fn running_daemon() -> Option<i32> {
+    running_daemon_at(&pid_file())
+}
+
+/// Returns the pid in the pid file at `path`, if a process with that pid is
+/// [running](Liveness::Running).
+///
+/// A missing or unreadable pid file, one with a pid of 0 or below, or a stale one with the pid
+/// of a process that is gone or belongs to another user, results in [None].
+pub fn running_daemon_at(path: &Path) -> Option<i32> {
+    let pid: i32 = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    (pid > 0 && process_liveness(pid) == Liveness::Running).then_some(pid)
+}
+
+/// Returns the path in the environment variable `key`, or `default` if it is unset or empty.
+fn env_path(key: &str, default: &str) -> PathBuf {
+    path_or(std::env::var_os(key), default)
+}
+
+/// Returns the path `var`, the value of an environment variable, or `default` if it is unset or
+/// empty.
+fn path_or(var: Option<std::ffi::OsString>, default: &str) -> PathBuf {
+    match var {
+        Some(var) if !var.is_empty() => PathBuf::from(var),
+        _ => PathBuf::from(default),
+    }
+}
+
 pub mod analyze;
+pub mod annotations;
+pub mod backoff;
+pub mod build_info;
+pub mod cache;
 pub mod checks;
+pub mod config;
+pub mod control;
+pub mod crypt;
+pub mod demo;
+pub mod doctor;
 pub mod errors;
+pub mod export;
+#[cfg(feature = "health-http")]
+pub mod health;
+pub mod logging;
+pub mod maintenance;
+pub mod predicate;
+pub mod recent;
 pub mod records;
+pub mod run;
 pub mod store;
+pub mod syslog;
+pub mod timezone;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_path_env_overrides() {
+        assert_eq!(
+            path_or(None, DAEMON_PID_FILE),
+            PathBuf::from(DAEMON_PID_FILE)
+        );
+        assert_eq!(
+            path_or(
+                Some("/tmp/netpulse-test/netpulse.sock".into()),
+                DAEMON_CONTROL_SOCKET
+            ),
+            PathBuf::from("/tmp/netpulse-test/netpulse.sock")
+        );
+        // empty means unset
+        assert_eq!(
+            path_or(Some("".into()), DAEMON_LOG_INF),
+            PathBuf::from(DAEMON_LOG_INF)
+        );
+    }
+
+    #[test]
+    fn test_process_liveness() {
+        assert_eq!(
+            process_liveness(std::process::id() as i32),
+            Liveness::Running
+        );
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let pid = child.id() as i32;
+        assert_eq!(process_liveness(pid), Liveness::Running);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(process_liveness(pid), Liveness::NotRunning);
+
+        // init always runs, but only root may signal it
+        if !nix::unistd::getuid().is_root() {
+            assert_eq!(process_liveness(1), Liveness::RunningNotOurs);
+        }
+        // our process group and every process are no pids
+        assert_eq!(process_liveness(0), Liveness::NotRunning);
+        assert_eq!(process_liveness(-1), Liveness::NotRunning);
+    }
+
+    #[test]
+    fn test_lookup_user() {
+        assert_eq!(lookup_user("root").unwrap().uid.as_raw(), 0);
+
+        let err = lookup_user("netpulse-no-such-user").unwrap_err();
+        assert_eq!(
+            err,
+            UserError::Missing {
+                name: "netpulse-no-such-user".to_string()
+            }
+        );
+        assert!(err
+            .to_string()
+            .contains("useradd -r -s /usr/sbin/nologin netpulse-no-such-user"));
+    }
+
+    #[test]
+    fn test_running_daemon_at() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("netpulsed.pid");
+        assert_eq!(running_daemon_at(&path), None);
+
+        let own = std::process::id() as i32;
+        std::fs::write(&path, format!("{own}\n")).unwrap();
+        assert_eq!(running_daemon_at(&path), Some(own));
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let stale = child.id();
+        child.wait().unwrap();
+        std::fs::write(&path, stale.to_string()).unwrap();
+        assert_eq!(running_daemon_at(&path), None);
+
+        std::fs::write(&path, "not a pid").unwrap();
+        assert_eq!(running_daemon_at(&path), None);
+
+        // kill(0, 0) and kill(-1, 0) succeed, but no daemon runs
+        for invalid in ["0", "-1"] {
+            std::fs::write(&path, invalid).unwrap();
+            assert_eq!(running_daemon_at(&path), None);
+        }
+    }
+}
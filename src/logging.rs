@@ -0,0 +1,571 @@
+//! Logging facilities, mainly used by the daemon.
+//!
+//! The daemon redirects its stderr to [log_err_file](crate::log_err_file). Without care, a
+//! flapping target would fill that file with the same error line over and over. This module
+//! provides:
+//! - [Rotation] - size based rotation of a log file, keeping a number of old files
+//! - [Dedup] - collapsing of identical consecutive lines into a "(repeated x times)" line
+//! - [error] - a global error log combining both, falling back to plain stderr if it was never
+//!   [initialized](init_error_log)
+//! - [warn], [debug] and [trace] - messages written to the same log if their [Level] is
+//!   enabled, see [enabled]
+//! - [CycleLog] - a structured JSON line per check cycle, used by the foreground mode of the
+//!   daemon
+//!
+//! # Configuration
+//!
+//! The rotation can be configured with environment variables:
+//! - [ENV_LOG_MAX_BYTES] - rotate when the log file is larger than this (default
+//!   [DEFAULT_LOG_MAX_BYTES])
+//! - [ENV_LOG_KEEP] - how many rotated files to keep (default [DEFAULT_LOG_KEEP])
+//!
+//! The verbosity is set with [ENV_LOG], like `NETPULSE_LOG=debug`. At the `debug` level, the
+//! daemon logs a line for every check it makes.
+
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::net::IpAddr;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::records::{Check, CheckType};
+
+/// Environment variable to set the size in bytes at which a log file is rotated
+pub const ENV_LOG_MAX_BYTES: &str = "NETPULSE_LOG_MAX_BYTES";
+/// Environment variable to set how many rotated log files are kept
+pub const ENV_LOG_KEEP: &str = "NETPULSE_LOG_KEEP";
+/// Default size in bytes at which a log file is rotated (10 MiB)
+pub const DEFAULT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+/// Default amount of rotated log files that are kept
+pub const DEFAULT_LOG_KEEP: usize = 5;
+/// Environment variable to set the [Level] of the log, like `debug`
+pub const ENV_LOG: &str = "NETPULSE_LOG";
+
+/// The global error log, see [init_error_log] and [error].
+static ERROR_LOG: Mutex<Option<ErrorLog>> = Mutex::new(None);
+
+/// Verbosity of the log, from the least to the most verbose.
+///
+/// A message is written if its level is at most the level of the log, so errors are always
+/// written.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default)]
+pub enum Level {
+    /// Only errors
+    Error,
+    /// Problems that don't keep the daemon from working
+    Warn,
+    /// What the daemon is doing, like starting a check cycle
+    #[default]
+    Info,
+    /// The result of every check
+    Debug,
+    /// Everything
+    Trace,
+}
+
+impl Level {
+    /// Names of all levels, as accepted by [parse](Level::parse)
+    pub const NAMES: &[&str] = &["error", "warn", "info", "debug", "trace"];
+
+    /// Parses the name of a level, ignoring case, like `debug`.
+    pub fn parse(raw: &str) -> Option<Self> {
+        Some(match raw.trim().to_lowercase().as_str() {
+            "error" => Self::Error,
+            "warn" => Self::Warn,
+            "info" => Self::Info,
+            "debug" => Self::Debug,
+            "trace" => Self::Trace,
+            _ => return None,
+        })
+    }
+
+    /// Reads the level from [ENV_LOG].
+    ///
+    /// An unset or unknown level falls back to the [default](Level::Info).
+    pub fn from_env() -> Self {
+        let Ok(raw) = std::env::var(ENV_LOG) else {
+            return Self::default();
+        };
+        Self::parse(&raw).unwrap_or_else(|| {
+            eprintln!(
+                "ignoring invalid value for {ENV_LOG}: '{raw}', expected one of: {}",
+                Self::NAMES.join(", ")
+            );
+            Self::default()
+        })
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::NAMES[*self as usize])
+    }
+}
+
+/// Size based rotation policy for a log file.
+///
+/// When the file is larger than [max_bytes](Rotation::max_bytes), it is moved to `<file>.1`,
+/// `<file>.1` is moved to `<file>.2` and so on. Only [keep](Rotation::keep) rotated files are
+/// kept, older ones are removed.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Rotation {
+    /// Rotate when the file is larger than this many bytes
+    pub max_bytes: u64,
+    /// How many rotated files to keep
+    pub keep: usize,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_LOG_MAX_BYTES,
+            keep: DEFAULT_LOG_KEEP,
+        }
+    }
+}
+
+impl Rotation {
+    /// Reads the rotation policy from [ENV_LOG_MAX_BYTES] and [ENV_LOG_KEEP].
+    ///
+    /// Unset or unparsable values fall back to the defaults.
+    pub fn from_env() -> Self {
+        let mut rotation = Self::default();
+        if let Some(max_bytes) = env_parse(ENV_LOG_MAX_BYTES) {
+            rotation.max_bytes = max_bytes;
+        }
+        if let Some(keep) = env_parse(ENV_LOG_KEEP) {
+            rotation.keep = keep;
+        }
+        rotation
+    }
+
+    /// Returns the path of the `n`th rotated file of `path`, like `netpulse.err.2`.
+    pub fn rotated_path(path: &Path, n: usize) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    }
+
+    /// Rotates the file at `path` if it is larger than [max_bytes](Rotation::max_bytes).
+    ///
+    /// Returns `true` if the file was rotated. A missing file is not rotated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file metadata can't be read or renaming/removing the files fails.
+    pub fn rotate_if_needed(&self, path: &Path) -> io::Result<bool> {
+        let len = match fs::metadata(path) {
+            Ok(meta) => meta.len(),
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        if len <= self.max_bytes {
+            return Ok(false);
+        }
+
+        if self.keep == 0 {
+            fs::remove_file(path)?;
+            return Ok(true);
+        }
+        let oldest = Self::rotated_path(path, self.keep);
+        if fs::exists(&oldest)? {
+            fs::remove_file(&oldest)?;
+        }
+        for n in (1..self.keep).rev() {
+            let from = Self::rotated_path(path, n);
+            if fs::exists(&from)? {
+                fs::rename(&from, Self::rotated_path(path, n + 1))?;
+            }
+        }
+        fs::rename(path, Self::rotated_path(path, 1))?;
+        Ok(true)
+    }
+}
+
+/// Collapses identical consecutive lines.
+///
+/// The first occurrence of a line is passed through, repetitions are counted and emitted as a
+/// single `<line> (repeated x times)` as soon as a different line comes in or the [Dedup] is
+/// [flushed](Dedup::flush).
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Dedup {
+    /// The last line that was seen
+    last: Option<String>,
+    /// How often the last line was repeated after its first occurrence
+    repeated: usize,
+}
+
+impl Dedup {
+    /// Feeds a line into the [Dedup], returning the lines that should be written now.
+    pub fn push(&mut self, line: &str) -> Vec<String> {
+        if self.last.as_deref() == Some(line) {
+            self.repeated += 1;
+            return Vec::new();
+        }
+        let mut out: Vec<String> = self.flush().into_iter().collect();
+        out.push(line.to_string());
+        self.last = Some(line.to_string());
+        out
+    }
+
+    /// Returns the pending "(repeated x times)" line, if the last line was repeated.
+    pub fn flush(&mut self) -> Option<String> {
+        let repeated = std::mem::take(&mut self.repeated);
+        if repeated == 0 {
+            return None;
+        }
+        self.last
+            .as_ref()
+            .map(|line| format!("{line} (repeated {repeated} times)"))
+    }
+}
+
+/// An error log writing to stderr, with de-duplication and optional rotation.
+///
+/// If a path is given, stderr is expected to be redirected to that file. After the file is
+/// rotated, a fresh file is opened at the path and stderr is redirected to it.
+#[derive(Debug)]
+pub struct ErrorLog {
+    /// File that stderr is redirected to, if any
+    path: Option<PathBuf>,
+    /// Rotation policy for [path](ErrorLog::path)
+    rotation: Rotation,
+    /// Collapses repeated lines
+    dedup: Dedup,
+    /// Messages above this level are not written
+    level: Level,
+}
+
+impl ErrorLog {
+    /// Creates a new [ErrorLog] with the [default](Level::default) [Level].
+    pub fn new(path: Option<PathBuf>, rotation: Rotation) -> Self {
+        Self {
+            path,
+            rotation,
+            dedup: Dedup::default(),
+            level: Level::default(),
+        }
+    }
+
+    /// Sets the [Level] of this log.
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Returns the [Level] of this log.
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// Writes a message of `level` like [log](ErrorLog::log), if the level is enabled.
+    ///
+    /// Messages that are not errors are prefixed with their level, like `[debug] `. Returns
+    /// whether the message was written.
+    pub fn log_at(&mut self, level: Level, msg: impl Display) -> bool {
+        if level > self.level {
+            return false;
+        }
+        match level {
+            Level::Error => self.log(msg),
+            _ => self.log(format!("[{level}] {msg}")),
+        }
+        true
+    }
+
+    /// Writes a message, line by line, to stderr and rotates the log file if needed.
+    pub fn log(&mut self, msg: impl Display) {
+        for line in msg.to_string().lines() {
+            for out in self.dedup.push(line) {
+                eprintln!("{out}");
+            }
+        }
+        if let Err(err) = self.rotate() {
+            eprintln!("could not rotate the error log: {err}");
+        }
+    }
+
+    /// Writes the pending "(repeated x times)" line, if any.
+    pub fn flush(&mut self) {
+        if let Some(out) = self.dedup.flush() {
+            eprintln!("{out}");
+        }
+    }
+
+    /// Rotates the log file and redirects stderr to the new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if self.rotation.rotate_if_needed(path)? {
+            let file = fs::File::options().create(true).append(true).open(path)?;
+            nix::unistd::dup2(file.as_raw_fd(), io::stderr().as_raw_fd())?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets up the global error log used by [error] and the other levels.
+///
+/// `path` should be the file stderr is redirected to, or [None] if stderr is not a file. The
+/// [Level] is read from [ENV_LOG].
+pub fn init_error_log(path: Option<PathBuf>, rotation: Rotation) {
+    *ERROR_LOG.lock().expect("the error log lock is poisoned") =
+        Some(ErrorLog::new(path, rotation).with_level(Level::from_env()));
+}
+
+/// Returns whether messages of `level` are written by the global log.
+///
+/// Before [init_error_log], this is the [default](Level::default) level.
+pub fn enabled(level: Level) -> bool {
+    let max = ERROR_LOG
+        .lock()
+        .expect("the error log lock is poisoned")
+        .as_ref()
+        .map_or(Level::default(), ErrorLog::level);
+    level <= max
+}
+
+/// Logs a message of `level` through the global [ErrorLog], see [ErrorLog::log_at].
+///
+/// Falls back to plain stderr if [init_error_log] was not called.
+fn log_at(level: Level, msg: impl Display) {
+    match ERROR_LOG
+        .lock()
+        .expect("the error log lock is poisoned")
+        .as_mut()
+    {
+        Some(log) => {
+            log.log_at(level, msg);
+        }
+        None if level <= Level::default() => eprintln!("{msg}"),
+        None => (),
+    }
+}
+
+/// Logs a warning through the global [ErrorLog], if [Level::Warn] is enabled.
+pub fn warn(msg: impl Display) {
+    log_at(Level::Warn, msg)
+}
+
+/// Logs a debug message through the global [ErrorLog], if [Level::Debug] is enabled.
+pub fn debug(msg: impl Display) {
+    log_at(Level::Debug, msg)
+}
+
+/// Logs a trace message through the global [ErrorLog], if [Level::Trace] is enabled.
+pub fn trace(msg: impl Display) {
+    log_at(Level::Trace, msg)
+}
+
+/// Logs an error message through the global [ErrorLog].
+///
+/// Falls back to plain stderr if [init_error_log] was not called.
+pub fn error(msg: impl Display) {
+    match ERROR_LOG
+        .lock()
+        .expect("the error log lock is poisoned")
+        .as_mut()
+    {
+        Some(log) => log.log(msg),
+        None => eprintln!("{msg}"),
+    }
+}
+
+/// Writes out pending repetitions of the global error log, see [ErrorLog::flush].
+pub fn flush() {
+    if let Some(log) = ERROR_LOG
+        .lock()
+        .expect("the error log lock is poisoned")
+        .as_mut()
+    {
+        log.flush()
+    }
+}
+
+/// Structured log entry for one check cycle of the daemon.
+///
+/// Serialized as a single JSON line with [to_json_line](CycleLog::to_json_line), for example:
+///
+/// ```json
+/// {"time":1733700000,"duration_ms":154,"checks":[{"type":"Http","target":"1.1.1.1","success":true,"latency_ms":42}]}
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct CycleLog {
+    /// When the cycle started, in seconds since the UNIX epoch
+    pub time: u64,
+    /// How long the cycle took, in milliseconds
+    pub duration_ms: u64,
+    /// Results of the checks made in this cycle
+    pub checks: Vec<CheckLog>,
+}
+
+/// Result of a single [Check] in a [CycleLog].
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct CheckLog {
+    /// Type of the check
+    #[serde(rename = "type")]
+    pub check_type: CheckType,
+    /// Target of the check
+    pub target: IpAddr,
+    /// Whether the check was successful
+    pub success: bool,
+    /// Latency of successful checks, in milliseconds
+    pub latency_ms: Option<u16>,
+}
+
+impl From<&Check> for CheckLog {
+    fn from(check: &Check) -> Self {
+        Self {
+            check_type: check.calc_type().unwrap_or(CheckType::Unknown),
+            target: check.target(),
+            success: check.is_success(),
+            latency_ms: check.latency(),
+        }
+    }
+}
+
+impl CycleLog {
+    /// Creates the log entry for a cycle that started at `start` and took `duration`.
+    pub fn new(start: SystemTime, duration: Duration, checks: &[&Check]) -> Self {
+        Self {
+            time: start
+                .duration_since(UNIX_EPOCH)
+                .expect("time is before the UNIX_EPOCH")
+                .as_secs(),
+            duration_ms: duration.as_millis() as u64,
+            checks: checks.iter().map(|c| CheckLog::from(*c)).collect(),
+        }
+    }
+
+    /// Serializes the entry as a single line of JSON, without a trailing newline.
+    pub fn to_json_line(&self) -> String {
+        serde_json::to_string(self).expect("a cycle log can always be serialized")
+    }
+}
+
+/// Parses an environment variable, [None] if it is unset or invalid.
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    let raw = std::env::var(key).ok()?;
+    match raw.trim().parse() {
+        Ok(v) => Some(v),
+        Err(_) => {
+            eprintln!("ignoring invalid value for {key}: '{raw}'");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rotation_trigger() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("netpulse.err");
+        let rotation = Rotation {
+            max_bytes: 10,
+            keep: 2,
+        };
+
+        fs::write(&path, "short").unwrap();
+        assert!(!rotation.rotate_if_needed(&path).unwrap());
+
+        for content in ["first rotation", "second rotation", "third rotation"] {
+            fs::write(&path, content).unwrap();
+            assert!(rotation.rotate_if_needed(&path).unwrap());
+            assert!(!path.exists());
+        }
+        assert_eq!(
+            fs::read_to_string(Rotation::rotated_path(&path, 1)).unwrap(),
+            "third rotation"
+        );
+        assert_eq!(
+            fs::read_to_string(Rotation::rotated_path(&path, 2)).unwrap(),
+            "second rotation"
+        );
+        assert!(!Rotation::rotated_path(&path, 3).exists());
+    }
+
+    #[test]
+    fn test_cycle_log_json_line() {
+        let ok = Check::new(
+            UNIX_EPOCH + Duration::from_secs(1000),
+            crate::records::CheckFlag::Success
+                | crate::records::CheckFlag::TypeHTTP
+                | crate::records::CheckFlag::IPv4,
+            Some(42),
+            "1.1.1.1".parse().unwrap(),
+        );
+        let failed = Check::new(
+            UNIX_EPOCH + Duration::from_secs(1000),
+            crate::records::CheckFlag::TypeUdp | crate::records::CheckFlag::IPv6,
+            None,
+            "2606:4700:4700::1111".parse().unwrap(),
+        );
+        let line = CycleLog::new(
+            UNIX_EPOCH + Duration::from_secs(1000),
+            Duration::from_millis(154),
+            &[&ok, &failed],
+        )
+        .to_json_line();
+        assert!(!line.contains('\n'));
+
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["time"], 1000);
+        assert_eq!(value["duration_ms"], 154);
+        assert_eq!(value["checks"][0]["type"], "Http");
+        assert_eq!(value["checks"][0]["target"], "1.1.1.1");
+        assert_eq!(value["checks"][0]["success"], true);
+        assert_eq!(value["checks"][0]["latency_ms"], 42);
+        assert_eq!(value["checks"][1]["type"], "Udp");
+        assert_eq!(value["checks"][1]["success"], false);
+        assert!(value["checks"][1]["latency_ms"].is_null());
+    }
+
+    #[test]
+    fn test_debug_only_at_debug_level() {
+        let mut log = ErrorLog::new(None, Rotation::default());
+        assert_eq!(log.level(), Level::Info);
+        assert!(!log.log_at(Level::Debug, "HTTP check of 1.1.1.1: ok"));
+        assert_eq!(log.dedup.last, None);
+        assert!(log.log_at(Level::Info, "waking up!"));
+        assert_eq!(log.dedup.last.as_deref(), Some("[info] waking up!"));
+
+        let mut log = log.with_level(Level::Debug);
+        assert!(log.log_at(Level::Debug, "HTTP check of 1.1.1.1: ok"));
+        assert_eq!(
+            log.dedup.last.as_deref(),
+            Some("[debug] HTTP check of 1.1.1.1: ok")
+        );
+        assert!(!log.log_at(Level::Trace, "details"));
+        assert!(log.with_level(Level::Error).log_at(Level::Error, "broken"));
+
+        for name in Level::NAMES {
+            assert_eq!(Level::parse(name).unwrap().to_string(), *name);
+        }
+        assert_eq!(Level::parse(" DEBUG "), Some(Level::Debug));
+        assert_eq!(Level::parse("verbose"), None);
+    }
+
+    #[test]
+    fn test_dedup_collapses_repeats() {
+        let mut dedup = Dedup::default();
+        assert_eq!(dedup.push("a"), vec!["a"]);
+        assert!(dedup.push("a").is_empty());
+        assert!(dedup.push("a").is_empty());
+        assert_eq!(dedup.push("b"), vec!["a (repeated 2 times)", "b"]);
+        assert_eq!(dedup.push("a"), vec!["a"]);
+        assert_eq!(dedup.flush(), None);
+        dedup.push("a");
+        assert_eq!(dedup.flush(), Some("a (repeated 1 times)".to_string()));
+        assert_eq!(dedup.flush(), None);
+    }
+}
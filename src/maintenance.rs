@@ -0,0 +1,173 @@
+//! Recurring maintenance windows, like a nightly deployment.
+//!
+//! Checks made during a [MaintenanceWindow] are still recorded, but marked with
+//! [CheckDetail::Maintenance](crate::records::CheckDetail::Maintenance). Their failures don't
+//! make [outages](crate::analyze::Outage) and don't count against the success ratios of the
+//! report, unless [count_maintenance](crate::config::OutageConfig::count_maintenance) is set.
+//!
+//! # Example
+//!
+//! ```toml
+//! [[maintenance]]
+//! start = "02:00"
+//! end = "02:30"
+//! timezone = "local"
+//! ```
+
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ConfigError;
+use crate::timezone::Timezone;
+
+/// Seconds in a day
+const DAY_SECS: i64 = 24 * 3600;
+
+/// A time of the day, with minutes, like `02:30`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    /// Minutes since midnight
+    minutes: u16,
+}
+
+impl TimeOfDay {
+    /// Creates a time of the day, [None] if `hours` or `minutes` are out of range.
+    pub fn new(hours: u16, minutes: u16) -> Option<Self> {
+        (hours < 24 && minutes < 60).then_some(Self {
+            minutes: hours * 60 + minutes,
+        })
+    }
+
+    /// Minutes since midnight.
+    pub fn minutes(&self) -> u16 {
+        self.minutes
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ConfigError::Invalid(format!("invalid time '{s}', expected HH:MM"));
+        let (hours, minutes) = s.trim().split_once(':').ok_or_else(invalid)?;
+        Self::new(
+            hours.parse().map_err(|_| invalid())?,
+            minutes.parse().map_err(|_| invalid())?,
+        )
+        .ok_or_else(invalid)
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = ConfigError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(value: TimeOfDay) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.minutes / 60, self.minutes % 60)
+    }
+}
+
+/// A maintenance window that recurs every day, from [start](MaintenanceWindow::start) up to
+/// [end](MaintenanceWindow::end).
+///
+/// A window with an end before its start spans midnight, like `23:30` to `00:30`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceWindow {
+    /// When the window starts
+    pub start: TimeOfDay,
+    /// When the window ends, not part of the window anymore
+    pub end: TimeOfDay,
+    /// Timezone of [start](MaintenanceWindow::start) and [end](MaintenanceWindow::end), UTC by
+    /// default
+    #[serde(default)]
+    pub timezone: Timezone,
+}
+
+impl MaintenanceWindow {
+    /// Returns whether `time` is in this window.
+    pub fn contains(&self, time: SystemTime) -> bool {
+        let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+        let local = secs + i64::from(self.timezone.offset_at(time));
+        let minute = (local.rem_euclid(DAY_SECS) / 60) as u16;
+        let (start, end) = (self.start.minutes, self.end.minutes);
+        if start <= end {
+            (start..end).contains(&minute)
+        } else {
+            minute >= start || minute < end
+        }
+    }
+}
+
+impl Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} to {} ({})", self.start, self.end, self.timezone)
+    }
+}
+
+/// Returns whether `time` is in any of `windows`.
+pub fn in_maintenance(windows: &[MaintenanceWindow], time: SystemTime) -> bool {
+    windows.iter().any(|window| window.contains(time))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(hours: u64, minutes: u64) -> SystemTime {
+        // some day in december 2024
+        UNIX_EPOCH + Duration::from_secs(1_733_616_000 + hours * 3600 + minutes * 60)
+    }
+
+    #[test]
+    fn test_window_contains() {
+        let nightly = MaintenanceWindow {
+            start: "02:00".parse().unwrap(),
+            end: "02:30".parse().unwrap(),
+            timezone: Timezone::Utc,
+        };
+        assert!(!nightly.contains(at(1, 59)));
+        assert!(nightly.contains(at(2, 0)));
+        assert!(nightly.contains(at(2, 29)));
+        assert!(!nightly.contains(at(2, 30)));
+        assert!(nightly.contains(at(26, 15)));
+
+        // 02:00 at +02:00 is midnight in UTC
+        let shifted = MaintenanceWindow {
+            timezone: "+02:00".parse().unwrap(),
+            ..nightly
+        };
+        assert!(shifted.contains(at(0, 10)));
+        assert!(!shifted.contains(at(2, 10)));
+
+        let midnight = MaintenanceWindow {
+            start: "23:30".parse().unwrap(),
+            end: "00:30".parse().unwrap(),
+            timezone: Timezone::Utc,
+        };
+        assert!(midnight.contains(at(23, 45)));
+        assert!(midnight.contains(at(0, 15)));
+        assert!(!midnight.contains(at(12, 0)));
+
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert!("2am".parse::<TimeOfDay>().is_err());
+        assert_eq!("2:05".parse::<TimeOfDay>().unwrap().to_string(), "02:05");
+    }
+}
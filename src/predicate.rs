@@ -0,0 +1,551 @@
+//! Custom success conditions of the checks of a target.
+//!
+//! Normally, a check succeeds if the target answered, and for HTTP checks, if the status was
+//! [accepted](crate::config::HttpTargetConfig::accept). A [Predicate] configured in
+//! [success](crate::config::Config::success) decides instead, from the recorded fields of the
+//! check:
+//!
+//! - `success` - whether the check succeeded without the predicate
+//! - `latency` - the latency in milliseconds
+//! - `status` - the status of an HTTP check
+//! - `loss` - the lost datagrams of a UDP check, in percent
+//!
+//! Numbers can be compared with `==`, `!=`, `<`, `<=`, `>` and `>=`, and conditions combined
+//! with `&&`, `||`, `!` and parentheses, nested at most [MAX_DEPTH] deep. Latencies can be
+//! written with a unit, like `200ms` or `1s`.
+//!
+//! A predicate applies to all check types of its target. A comparison with a field the check
+//! does not have, like the `status` of an ICMP check or the `latency` of a check without
+//! answer, does not apply: it is left out of its `&&` or `||`, and if nothing is left, the
+//! check keeps its `success`. So `status == 200 && latency < 200ms` only looks at the latency
+//! of the ICMP checks of a target.
+//!
+//! Predicates are only evaluated, never run: there are no other names, functions or loops.
+//!
+//! # Example
+//!
+//! ```toml
+//! [success]
+//! "1.1.1.1" = "status == 200 && latency < 200ms"
+//! "9.9.9.9" = "success && (latency <= 50ms || loss == 0)"
+//! ```
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ConfigError;
+use crate::records::{Check, CheckDetail, CheckFlag};
+
+/// How deep parentheses and `!` may be nested in a predicate
+pub const MAX_DEPTH: usize = 32;
+
+/// A success condition, see the [module documentation](self).
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Predicate {
+    /// The predicate as written
+    source: String,
+    /// The parsed predicate
+    expr: Expr,
+}
+
+impl Predicate {
+    /// Returns whether `check` succeeded, as this predicate sees it.
+    ///
+    /// If no comparison of the predicate applies to the check, it keeps its own result.
+    pub fn eval(&self, check: &Check) -> bool {
+        self.expr.eval(check).unwrap_or(check.is_success())
+    }
+
+    /// Sets or removes the [Success](CheckFlag::Success) flag of `check` as this predicate
+    /// decides.
+    pub fn apply(&self, check: &mut Check) {
+        if self.eval(check) {
+            check.add_flag(CheckFlag::Success);
+        } else {
+            *check.flags_mut() -= CheckFlag::Success;
+        }
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| {
+            ConfigError::Invalid(format!("invalid success condition {s:?}: {reason}"))
+        };
+        let tokens = tokenize(s).map_err(invalid)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let expr = parser.or().map_err(invalid)?;
+        if let Some(token) = parser.peek() {
+            return Err(invalid(format!("unexpected {token}")));
+        }
+        Ok(Self {
+            source: s.to_string(),
+            expr,
+        })
+    }
+}
+
+impl TryFrom<String> for Predicate {
+    type Error = ConfigError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Predicate> for String {
+    fn from(value: Predicate) -> Self {
+        value.source
+    }
+}
+
+impl Display for Predicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// A number field of a check.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+enum Field {
+    Latency,
+    Status,
+    Loss,
+}
+
+impl Field {
+    /// Returns the value of the field in `check`, [None] if the check does not have it.
+    fn of(self, check: &Check) -> Option<u64> {
+        match self {
+            Self::Latency => check.latency().map(u64::from),
+            Self::Status => check.details().iter().find_map(|detail| match detail {
+                CheckDetail::Http { status } => Some(u64::from(*status)),
+                _ => None,
+            }),
+            Self::Loss => check.details().iter().find_map(|detail| match detail {
+                CheckDetail::Udp { sent, received } if *sent > 0 => {
+                    Some(u64::from(sent.saturating_sub(*received)) * 100 / u64::from(*sent))
+                }
+                _ => None,
+            }),
+        }
+    }
+}
+
+/// A number in a comparison.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+enum Operand {
+    Number(u64),
+    Field(Field),
+}
+
+impl Operand {
+    fn of(self, check: &Check) -> Option<u64> {
+        match self {
+            Self::Number(n) => Some(n),
+            Self::Field(field) => field.of(check),
+        }
+    }
+}
+
+/// A comparison operator.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A parsed condition.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+enum Expr {
+    Literal(bool),
+    /// The `success` of the check without the predicate
+    Success,
+    Compare(Operand, Cmp, Operand),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluates the condition for `check`, [None] if it does not apply, see the
+    /// [module documentation](self).
+    fn eval(&self, check: &Check) -> Option<bool> {
+        // a side that does not apply is left out
+        let combine = |left: Option<bool>, right: Option<bool>, op: fn(bool, bool) -> bool| match (
+            left, right,
+        ) {
+            (Some(left), Some(right)) => Some(op(left, right)),
+            (side, None) | (None, side) => side,
+        };
+        match self {
+            Self::Literal(value) => Some(*value),
+            Self::Success => Some(check.is_success()),
+            Self::Compare(left, cmp, right) => {
+                let (left, right) = (left.of(check)?, right.of(check)?);
+                Some(match cmp {
+                    Cmp::Eq => left == right,
+                    Cmp::Ne => left != right,
+                    Cmp::Lt => left < right,
+                    Cmp::Le => left <= right,
+                    Cmp::Gt => left > right,
+                    Cmp::Ge => left >= right,
+                })
+            }
+            Self::Not(expr) => expr.eval(check).map(|value| !value),
+            Self::And(left, right) => combine(left.eval(check), right.eval(check), |l, r| l && r),
+            Self::Or(left, right) => combine(left.eval(check), right.eval(check), |l, r| l || r),
+        }
+    }
+}
+
+/// A token of a predicate.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Token {
+    Number(u64),
+    Name(String),
+    Cmp(Cmp),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "number {n}"),
+            Self::Name(name) => write!(f, "'{name}'"),
+            Self::Cmp(cmp) => write!(
+                f,
+                "'{}'",
+                match cmp {
+                    Cmp::Eq => "==",
+                    Cmp::Ne => "!=",
+                    Cmp::Lt => "<",
+                    Cmp::Le => "<=",
+                    Cmp::Gt => ">",
+                    Cmp::Ge => ">=",
+                }
+            ),
+            Self::And => write!(f, "'&&'"),
+            Self::Or => write!(f, "'||'"),
+            Self::Not => write!(f, "'!'"),
+            Self::Open => write!(f, "'('"),
+            Self::Close => write!(f, "')'"),
+        }
+    }
+}
+
+/// Splits `s` into tokens.
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        let token = match (c, next) {
+            _ if c.is_whitespace() => continue,
+            ('&', Some('&')) => Token::And,
+            ('|', Some('|')) => Token::Or,
+            ('=', Some('=')) => Token::Cmp(Cmp::Eq),
+            ('!', Some('=')) => Token::Cmp(Cmp::Ne),
+            ('<', Some('=')) => Token::Cmp(Cmp::Le),
+            ('>', Some('=')) => Token::Cmp(Cmp::Ge),
+            ('<', _) => Token::Cmp(Cmp::Lt),
+            ('>', _) => Token::Cmp(Cmp::Gt),
+            ('!', _) => Token::Not,
+            ('(', _) => Token::Open,
+            (')', _) => Token::Close,
+            _ if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) =
+                    chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    end = i + c.len_utf8();
+                }
+                tokens.push(word(&s[start..end])?);
+                continue;
+            }
+            _ => return Err(format!("unexpected '{c}'")),
+        };
+        // the two character operators
+        if matches!(
+            token,
+            Token::And | Token::Or | Token::Cmp(Cmp::Eq | Cmp::Ne | Cmp::Le | Cmp::Ge)
+        ) {
+            chars.next();
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Turns a word into a name, or into a number with an optional unit of time.
+fn word(word: &str) -> Result<Token, String> {
+    if !word.starts_with(|c: char| c.is_ascii_digit()) {
+        return Ok(Token::Name(word.to_string()));
+    }
+    let digits = word
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(word.len());
+    let number: u64 = word[..digits]
+        .parse()
+        .map_err(|_| format!("number {word} is too large"))?;
+    let factor = match &word[digits..] {
+        "" | "ms" => 1,
+        "s" => 1000,
+        unit => return Err(format!("unknown unit '{unit}' in {word}, expected ms or s")),
+    };
+    number
+        .checked_mul(factor)
+        .map(Token::Number)
+        .ok_or_else(|| format!("number {word} is too large"))
+}
+
+/// A recursive descent parser over the tokens.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// How deep the current [unary](Parser::unary) is nested, at most [MAX_DEPTH]
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| "unexpected end".to_string())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    /// `and ("||" and)*`
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    /// `unary ("&&" unary)*`
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    /// `"!" unary | "(" or ")" | "true" | "false" | "success" | operand cmp operand`
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            Token::Not => Ok(Expr::Not(Box::new(self.nested(Self::unary)?))),
+            Token::Open => {
+                let expr = self.nested(Self::or)?;
+                match self.next()? {
+                    Token::Close => Ok(expr),
+                    token => Err(format!("expected ')', found {token}")),
+                }
+            }
+            Token::Name(name) if name == "true" => Ok(Expr::Literal(true)),
+            Token::Name(name) if name == "false" => Ok(Expr::Literal(false)),
+            Token::Name(name) if name == "success" => Ok(Expr::Success),
+            token => {
+                let left = operand(token)?;
+                let cmp = match self.next()? {
+                    Token::Cmp(cmp) => cmp,
+                    token => return Err(format!("expected a comparison, found {token}")),
+                };
+                let right = operand(self.next()?)?;
+                Ok(Expr::Compare(left, cmp, right))
+            }
+        }
+    }
+}
+
+impl Parser {
+    /// Parses with `parse` one level deeper, failing beyond [MAX_DEPTH].
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("nested deeper than {MAX_DEPTH}"));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+}
+
+/// Turns a token into a number to compare.
+fn operand(token: Token) -> Result<Operand, String> {
+    match token {
+        Token::Number(n) => Ok(Operand::Number(n)),
+        Token::Name(name) => match name.as_str() {
+            "latency" => Ok(Operand::Field(Field::Latency)),
+            "status" => Ok(Operand::Field(Field::Status)),
+            "loss" => Ok(Operand::Field(Field::Loss)),
+            _ => Err(format!(
+                "unknown name '{name}', expected success, latency, status or loss"
+            )),
+        },
+        token => Err(format!("expected a number or a field, found {token}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    fn http(success: bool, status: u16, latency: Option<u16>) -> Check {
+        let mut flags = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+        if success {
+            flags |= CheckFlag::Success;
+        }
+        let mut check = Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            flags,
+            latency,
+            "1.1.1.1".parse().unwrap(),
+        );
+        check.add_detail(CheckDetail::Http { status });
+        check
+    }
+
+    fn eval(predicate: &str, check: &Check) -> bool {
+        predicate.parse::<Predicate>().unwrap().eval(check)
+    }
+
+    #[test]
+    fn test_eval() {
+        let fast = http(true, 200, Some(40));
+        let slow = http(true, 200, Some(250));
+        let redirect = http(true, 301, Some(40));
+
+        let predicate = "status == 200 && latency < 200ms";
+        assert!(eval(predicate, &fast));
+        assert!(!eval(predicate, &slow));
+        assert!(!eval(predicate, &redirect));
+
+        assert!(eval("!(status >= 300) || latency >= 1s", &fast));
+        assert!(!eval("!(status >= 300) || latency >= 1s", &redirect));
+        assert!(eval("success && latency <= 40", &fast));
+        assert!(!eval("!success", &fast));
+        assert!(eval("true || false && false", &fast));
+
+        // comparisons with missing fields do not apply, the check keeps its result
+        let mut timeout = Check::new(
+            UNIX_EPOCH,
+            CheckFlag::TypeIcmp | CheckFlag::IPv4,
+            None,
+            "1.1.1.1".parse().unwrap(),
+        );
+        assert!(!eval("latency < 200ms", &timeout));
+        assert!(!eval("status == 200", &timeout));
+        assert!(!eval("!(status == 200)", &timeout));
+        timeout.add_detail(CheckDetail::Udp {
+            sent: 4,
+            received: 3,
+        });
+        assert!(eval("loss == 25", &timeout));
+        assert!(eval("status == 200 || loss == 25", &timeout));
+    }
+
+    #[test]
+    fn test_other_check_types() {
+        let icmp = |latency: Option<u16>| {
+            let mut flags = CheckFlag::TypeIcmp | CheckFlag::IPv4;
+            if latency.is_some() {
+                flags |= CheckFlag::Success;
+            }
+            Check::new(UNIX_EPOCH, flags, latency, "1.1.1.1".parse().unwrap())
+        };
+        // the predicate of an HTTP target also judges its ICMP checks, by their latency only
+        let predicate = "status == 200 && latency < 200ms";
+        assert!(eval(predicate, &icmp(Some(40))));
+        assert!(!eval(predicate, &icmp(Some(250))));
+        assert!(!eval(predicate, &icmp(None)));
+        // nothing applies, so they keep their result
+        assert!(eval("status == 200", &icmp(Some(40))));
+        assert!(!eval("status != 200 || !(status == 200)", &icmp(None)));
+        // a literal always applies
+        assert!(!eval("status == 200 && false", &icmp(Some(40))));
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut slow = http(true, 200, Some(250));
+        "latency < 200ms"
+            .parse::<Predicate>()
+            .unwrap()
+            .apply(&mut slow);
+        assert!(!slow.is_success());
+
+        // a predicate can also make a failed check successful
+        let mut not_found = http(false, 404, Some(20));
+        "status == 404"
+            .parse::<Predicate>()
+            .unwrap()
+            .apply(&mut not_found);
+        assert!(not_found.is_success());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for invalid in [
+            "",
+            "status",
+            "status = 200",
+            "status == 200 &&",
+            "(latency < 200",
+            "latency < 200 )",
+            "latency < 200h",
+            "latency < 2.5s",
+            "ping == 1",
+            "success == 1",
+            "latency < 99999999999999999999",
+            "std::process::exit(1)",
+        ] {
+            let err = invalid.parse::<Predicate>().unwrap_err().to_string();
+            assert!(
+                err.contains("invalid success condition"),
+                "{invalid}: {err}"
+            );
+        }
+        let nested = |depth: usize| format!("{}true{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(nested(MAX_DEPTH).parse::<Predicate>().is_ok());
+        let err = nested(MAX_DEPTH + 1).parse::<Predicate>().unwrap_err();
+        assert!(err.to_string().contains("nested deeper than"), "{err}");
+        let err = format!("{}true", "!".repeat(100_000))
+            .parse::<Predicate>()
+            .unwrap_err();
+        assert!(err.to_string().contains("nested deeper than"), "{err}");
+
+        let predicate: Predicate = "status==200&&latency<1s".parse().unwrap();
+        assert_eq!(predicate.to_string(), "status==200&&latency<1s");
+    }
+}
@@ -0,0 +1,221 @@
+//! The most recent checks of the daemon, kept in memory.
+//!
+//! Answering `netpulsed --info` from the store would mean loading and going through all checks
+//! ever made. Instead, the daemon pushes the checks of every cycle into a [RecentChecks] ring of
+//! a fixed size, and answers [Command::Status](crate::control::Command::Status) from it. How long
+//! that takes does not depend on the size of the store.
+//!
+//! # Example
+//!
+//! ```rust
+//! use netpulse::recent::RecentChecks;
+//!
+//! let recent = RecentChecks::new(2);
+//! assert!(recent.is_empty());
+//! assert_eq!(recent.status().last_cycle, None);
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::time::SystemTime;
+
+use crate::records::Check;
+
+/// How many checks the daemon keeps in memory by default
+pub const DEFAULT_CAPACITY: usize = 512;
+
+/// The last checks of the daemon, at most [capacity](RecentChecks::capacity) of them.
+///
+/// When the ring is full, pushing new checks drops the oldest ones.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RecentChecks {
+    /// How many checks are kept at most
+    capacity: usize,
+    /// The checks, oldest first
+    checks: VecDeque<Check>,
+    /// How many of the newest checks belong to the last cycle
+    last_cycle: usize,
+}
+
+impl RecentChecks {
+    /// Creates an empty ring that keeps the last `capacity` checks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "the ring must be able to keep at least one check"
+        );
+        Self {
+            capacity,
+            checks: VecDeque::with_capacity(capacity),
+            last_cycle: 0,
+        }
+    }
+
+    /// Adds the checks of a check cycle, dropping the oldest checks if the ring is full.
+    pub fn push_cycle(&mut self, cycle: &[&Check]) {
+        for check in cycle {
+            if self.checks.len() == self.capacity {
+                self.checks.pop_front();
+            }
+            self.checks.push_back((*check).clone());
+        }
+        self.last_cycle = cycle.len().min(self.capacity);
+    }
+
+    /// Iterates over the kept checks, oldest first.
+    pub fn checks(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter()
+    }
+
+    /// Iterates over the checks of the last cycle.
+    ///
+    /// If the last cycle had more checks than the ring can keep, only the newest are returned.
+    pub fn last_cycle(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().skip(self.checks.len() - self.last_cycle)
+    }
+
+    /// How many checks are kept at most.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many checks are kept right now.
+    pub fn len(&self) -> usize {
+        self.checks.len()
+    }
+
+    /// Returns whether no checks were pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.checks.is_empty()
+    }
+
+    /// Summarizes the last cycle and the kept checks.
+    pub fn status(&self) -> Status {
+        Status {
+            last_cycle: self.last_cycle().map(Check::timestamp_parsed).max(),
+            cycle_checks: self.last_cycle,
+            cycle_failed: self.last_cycle().filter(|c| !c.is_success()).count(),
+            recent_checks: self.checks.len(),
+            recent_failed: self.checks.iter().filter(|c| !c.is_success()).count(),
+        }
+    }
+}
+
+impl Default for RecentChecks {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// Status of the daemon, made by [RecentChecks::status].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Status {
+    /// Time of the newest check of the last cycle, [None] if there was no cycle yet
+    pub last_cycle: Option<SystemTime>,
+    /// How many checks were made in the last cycle
+    pub cycle_checks: usize,
+    /// How many checks of the last cycle failed
+    pub cycle_failed: usize,
+    /// How many checks are kept in memory
+    pub recent_checks: usize,
+    /// How many of the kept checks failed
+    pub recent_failed: usize,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(last_cycle) = self.last_cycle else {
+            return writeln!(f, "Last Cycle: none yet");
+        };
+        writeln!(
+            f,
+            "Last Cycle: {}",
+            humantime::format_rfc3339_seconds(last_cycle)
+        )?;
+        writeln!(
+            f,
+            "Cycle Checks: {} ({} failed)",
+            self.cycle_checks, self.cycle_failed
+        )?;
+        writeln!(
+            f,
+            "Recent Checks: {} ({} failed)",
+            self.recent_checks, self.recent_failed
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::records::CheckFlag;
+
+    use super::*;
+
+    fn check(secs: u64, success: bool) -> Check {
+        let mut flags = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+        if success {
+            flags |= CheckFlag::Success;
+        }
+        Check::new(
+            UNIX_EPOCH + Duration::from_secs(secs),
+            flags,
+            Some(12),
+            "1.1.1.1".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_ring_keeps_last_n() {
+        let mut recent = RecentChecks::new(3);
+        let all: Vec<Check> = (0..5).map(|secs| check(secs, true)).collect();
+        recent.push_cycle(&all.iter().take(2).collect::<Vec<_>>());
+        assert_eq!(recent.len(), 2);
+        recent.push_cycle(&all.iter().skip(2).collect::<Vec<_>>());
+        assert_eq!(recent.len(), 3);
+        assert_eq!(
+            recent.checks().collect::<Vec<_>>(),
+            all[2..].iter().collect::<Vec<_>>()
+        );
+
+        // a cycle larger than the ring only keeps its newest checks
+        let big: Vec<Check> = (10..15).map(|secs| check(secs, true)).collect();
+        recent.push_cycle(&big.iter().collect::<Vec<_>>());
+        assert_eq!(
+            recent.checks().collect::<Vec<_>>(),
+            big[2..].iter().collect::<Vec<_>>()
+        );
+        assert_eq!(recent.last_cycle().count(), 3);
+    }
+
+    #[test]
+    fn test_status_reflects_latest_cycle() {
+        let mut recent = RecentChecks::new(8);
+        assert_eq!(recent.status().last_cycle, None);
+
+        let first = [check(60, false), check(60, false)];
+        recent.push_cycle(&first.iter().collect::<Vec<_>>());
+        let second = [check(120, true), check(121, false), check(120, true)];
+        recent.push_cycle(&second.iter().collect::<Vec<_>>());
+
+        let status = recent.status();
+        assert_eq!(
+            status,
+            Status {
+                last_cycle: Some(UNIX_EPOCH + Duration::from_secs(121)),
+                cycle_checks: 3,
+                cycle_failed: 1,
+                recent_checks: 5,
+                recent_failed: 3,
+            }
+        );
+        assert!(status
+            .to_string()
+            .starts_with("Last Cycle: 1970-01-01T00:02:01Z\nCycle Checks: 3 (1 failed)"));
+    }
+}
@@ -4,12 +4,16 @@
 //! - [`Check`] - Result of a single connectivity check
 //! - [`CheckType`] - Different types of checks (HTTP, ICMP, DNS)
 //! - [`CheckFlag`] - Flags indicating check status and metadata
+//! - [`CheckDetail`] - Additional data that only some checks have
+//! - [`Aggregate`] - Summary of old checks, see [downsample](crate::store::Store::downsample)
 //!
 //! # Check Types
 //!
 //! The following check types are supported:
 //! - HTTP(S) - Web connectivity checks
 //! - ICMPv4/v6 - Ping checks
+//! - UDP - Packet loss and latency with a series of DNS queries
+//! - Throughput - Download speed of a configured URL
 //! - DNS - Domain name resolution (planned)
 //!
 //! # Check Flags
@@ -18,7 +22,7 @@
 //! - Success/failure status
 //! - Failure reasons (timeout, unreachable)
 //! - Protocol used (IPv4/IPv6)
-//! - Check type (HTTP, ICMP, DNS)
+//! - Check type (HTTP, ICMP, UDP, DNS)
 //!
 //! This system may be expanded in future versions
 //!
@@ -36,15 +40,18 @@
 //! }
 //! ```
 
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::hash::{Hash, Hasher};
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
 use std::time::{self};
 
 use flagset::{flags, FlagSet};
 use serde::{Deserialize, Serialize};
 
-use crate::errors::StoreError;
+use crate::config::{Config, ResolveConfig, Source};
+use crate::errors::{CheckError, RecordError, StoreError};
 
 /// List of target IP addresses used for connectivity checks.
 ///
@@ -59,8 +66,8 @@ flags! {
     ///
     /// Uses a bitflag system to efficiently store multiple properties:
     /// - Result flags (bits 0-7): Success, failure reasons
-    /// - Protocol flags (bits 8-11): IPv4/IPv6
-    /// - Type flags (bits 12-15): Check type (HTTP, ICMP, DNS)
+    /// - Protocol flags (bits 8-10): IPv4/IPv6
+    /// - Type flags (bits 11-15): Check type (HTTP, UDP, ICMP, DNS, throughput)
     #[derive(Hash, Deserialize, Serialize)]
     pub enum CheckFlag: u16 {
         /// If this is not set, the check will be considered failed
@@ -75,8 +82,18 @@ flags! {
         /// The Check used IPv6
         IPv6        =   0b0000_0010_0000_0000,
 
+        /// The Check downloaded a file to measure the throughput
+        ///
+        /// See [CheckDetail::Throughput] for the measurement. This is below the other type flags
+        /// because they took up all of their bits.
+        TypeThroughput = 0b0000_1000_0000_0000,
+
         /// The Check used HTTP/HTTPS
         TypeHTTP    =   0b0001_0000_0000_0000,
+        /// The Check used a series of UDP datagrams
+        ///
+        /// See [CheckDetail::Udp] for the packet counts.
+        TypeUdp     =   0b0010_0000_0000_0000,
         /// Check type was ICMP (ping)
         ///
         /// Must be combined with either [IPv4](CheckFlag::IPv4) or [IPv6](CheckFlag::IPv6)
@@ -101,8 +118,15 @@ pub enum CheckType {
     IcmpV4,
     /// ICMP ping using IPv6
     IcmpV6,
+    /// Series of UDP datagrams (DNS queries), measuring packet loss
+    Udp,
     /// Unknown or invalid check type
     Unknown,
+    /// Download of the [configured URL](crate::config::ThroughputConfig::url), measuring the
+    /// throughput
+    ///
+    /// Stores encode the check types by position, so new ones go last.
+    Throughput,
 }
 impl CheckType {
     /// Creates and performs a new network check of this type.
@@ -122,11 +146,18 @@ impl CheckType {
     ///
     /// # Panics
     ///
-    /// - If HTTP check is attempted without `http` feature
+    /// - If HTTP or throughput check is attempted without `http` feature
     /// - If ICMP check is attempted without `ping` feature
     /// - If check type is `Unknown`
     /// - If check type is `Dns` (not yet implemented)
     pub fn make(&self, remote: IpAddr) -> Check {
+        self.make_with(remote, &Config::default())
+    }
+
+    /// Creates and performs a new network check of this type, using the given [Config].
+    ///
+    /// See [make](CheckType::make) for details.
+    pub fn make_with(&self, remote: IpAddr, config: &Config) -> Check {
         let mut check = Check::new(
             std::time::SystemTime::now(),
             FlagSet::default(),
@@ -138,20 +169,22 @@ impl CheckType {
             IpAddr::V4(_) => check.add_flag(CheckFlag::IPv4),
             IpAddr::V6(_) => check.add_flag(CheckFlag::IPv6),
         }
+        let source = config.source.get(&remote);
 
         match self {
             #[cfg(feature = "http")]
             Self::Http => {
                 check.add_flag(CheckFlag::TypeHTTP);
-                match crate::checks::check_http(remote) {
-                    Err(err) => {
-                        eprintln!("error while performing an Http check: {err}")
-                    }
-                    Ok(lat) => {
-                        check.add_flag(CheckFlag::Success);
-                        check.latency = Some(lat);
-                    }
+                let options = crate::checks::HttpOptions::of(config, remote);
+                // only a method other than the default is worth recording in every check
+                if options.method != crate::config::HttpMethod::default() {
+                    check.details.push(CheckDetail::HttpMethod {
+                        method: options.method.to_string(),
+                    });
                 }
+                let response = crate::checks::check_http_with(remote, &options);
+                record_source(&mut check, source, &response);
+                record_http(&mut check, response, config);
             }
             #[cfg(not(feature = "http"))]
             Self::Http => {
@@ -161,9 +194,13 @@ impl CheckType {
             #[cfg(feature = "ping")]
             Self::IcmpV4 => {
                 check.add_flag(CheckFlag::TypeIcmp);
-                match crate::checks::just_fucking_ping(remote) {
+                let timeout = config.check_settings(Self::IcmpV4).timeout;
+                match crate::checks::ping_with_timeout(remote, timeout) {
                     Err(err) => {
-                        eprintln!("error while performing an ICMPv4 check: {err}")
+                        crate::logging::error(format!(
+                            "error while performing an ICMPv4 check: {err}"
+                        ));
+                        capture(&mut check, config, err.to_string().into_bytes());
                     }
                     Ok(lat) => {
                         check.add_flag(CheckFlag::Success);
@@ -178,9 +215,22 @@ impl CheckType {
             #[cfg(feature = "ping")]
             Self::IcmpV6 => {
                 check.add_flag(CheckFlag::TypeIcmp);
-                match crate::checks::just_fucking_ping(remote) {
+                let timeout = config.check_settings(Self::IcmpV6).timeout;
+                let result = match config.zone_of(remote) {
+                    Some(scoped) => {
+                        check.details.push(CheckDetail::Zone {
+                            zone: scoped.zone.clone(),
+                        });
+                        crate::checks::ping_scoped(&scoped, timeout)
+                    }
+                    None => crate::checks::ping_with_timeout(remote, timeout),
+                };
+                match result {
                     Err(err) => {
-                        eprintln!("error while performing an ICMPv6 check: {err}")
+                        crate::logging::error(format!(
+                            "error while performing an ICMPv6 check: {err}"
+                        ));
+                        capture(&mut check, config, err.to_string().into_bytes());
                     }
                     Ok(lat) => {
                         check.add_flag(CheckFlag::Success);
@@ -192,6 +242,64 @@ impl CheckType {
             Self::IcmpV6 => {
                 panic!("Trying to make a ICMPv6 check, but the ping feature is not enabled")
             }
+            Self::Udp => {
+                check.add_flag(CheckFlag::TypeUdp);
+                let udp = &config.udp;
+                let dscp = config.dscp.get(&remote).copied();
+                let stats = crate::checks::check_udp_with(
+                    std::net::SocketAddr::new(remote, udp.port),
+                    udp.count,
+                    config.check_settings(Self::Udp).timeout,
+                    dscp,
+                    source,
+                );
+                record_source(&mut check, source, &stats);
+                match stats {
+                    Err(err) => {
+                        crate::logging::error(format!("error while performing an UDP check: {err}"))
+                    }
+                    Ok(stats) => {
+                        if stats.loss_below(udp.max_loss_percent) {
+                            check.add_flag(CheckFlag::Success);
+                        }
+                        check.latency = stats.mean_latency;
+                        check.details.push(CheckDetail::Udp {
+                            sent: stats.sent,
+                            received: stats.received,
+                        });
+                        if let Some(dscp) = dscp {
+                            check.details.push(CheckDetail::Dscp {
+                                value: dscp.value(),
+                            });
+                        }
+                    }
+                }
+            }
+            #[cfg(feature = "http")]
+            Self::Throughput => {
+                check.add_flag(CheckFlag::TypeThroughput);
+                let throughput = &config.throughput;
+                match &throughput.url {
+                    None => crate::logging::error(
+                        "tried to make a throughput check, but no throughput.url is configured",
+                    ),
+                    Some(url) => {
+                        let download = crate::checks::check_throughput(
+                            url,
+                            Some(remote),
+                            throughput.max_bytes,
+                            throughput.timeout(),
+                            source,
+                        );
+                        record_source(&mut check, source, &download);
+                        record_throughput(&mut check, download, config);
+                    }
+                }
+            }
+            #[cfg(not(feature = "http"))]
+            Self::Throughput => {
+                panic!("Trying to make a throughput check, but the http feature is not enabled")
+            }
             Self::Unknown => {
                 panic!("tried to make an Unknown check");
             }
@@ -203,20 +311,124 @@ impl CheckType {
         check
     }
 
+    /// Records that `host` could not be resolved for a check of this type, because of `error`.
+    ///
+    /// The returned check failed without being made. It has the unspecified address of the IP
+    /// version of this type as [target](Check::target), a [CheckDetail::Resolved] with `host` and
+    /// a [CheckDetail::Unresolved] with `error`, so outages of the name resolution show up like
+    /// any other.
+    pub fn unresolved(&self, host: &str, error: impl Display) -> Check {
+        let (target, ip_flag): (IpAddr, CheckFlag) = match self {
+            Self::IcmpV6 => (Ipv6Addr::UNSPECIFIED.into(), CheckFlag::IPv6),
+            _ => (Ipv4Addr::UNSPECIFIED.into(), CheckFlag::IPv4),
+        };
+        let mut check = Check::new(std::time::SystemTime::now(), ip_flag, None, target);
+        match self {
+            Self::Http => check.add_flag(CheckFlag::TypeHTTP),
+            Self::IcmpV4 | Self::IcmpV6 => check.add_flag(CheckFlag::TypeIcmp),
+            Self::Udp => check.add_flag(CheckFlag::TypeUdp),
+            Self::Throughput => check.add_flag(CheckFlag::TypeThroughput),
+            Self::Dns => check.add_flag(CheckFlag::TypeDns),
+            Self::Unknown => (),
+        }
+        check.details.push(CheckDetail::Resolved {
+            host: host.to_string(),
+        });
+        check.details.push(CheckDetail::Unresolved {
+            error: error.to_string(),
+        });
+        check
+    }
+
+    /// Resolves `host` to an address usable for this type of check.
+    ///
+    /// The first address of the fitting IP version is used for ICMP checks, the first address
+    /// of any version for the other checks.
+    ///
+    /// # Errors
+    ///
+    /// Returns [CheckError] if `host` can't be resolved or has no address of the fitting IP
+    /// version.
+    pub fn resolve(&self, host: &str) -> Result<IpAddr, CheckError> {
+        self.resolve_with(host, &ResolveConfig::default())
+    }
+
+    /// Resolves `host` like [resolve](CheckType::resolve), but with the DNS server and only the
+    /// addresses of the IP version of `options`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [CheckError] if `host` can't be resolved or has no address of the fitting IP
+    /// version.
+    pub fn resolve_with(&self, host: &str, options: &ResolveConfig) -> Result<IpAddr, CheckError> {
+        self.pick(host, &crate::checks::resolve_with(host, options)?, options)
+    }
+
+    /// Picks the address to use for this type of check from `addrs`, the addresses `host`
+    /// resolved to, see [resolve_with](CheckType::resolve_with).
+    ///
+    /// Meant for resolving a host once for all check types.
+    ///
+    /// # Errors
+    ///
+    /// Returns [CheckError::NoAddress] if none of `addrs` has the fitting IP version.
+    pub fn pick(
+        &self,
+        host: &str,
+        addrs: &[IpAddr],
+        options: &ResolveConfig,
+    ) -> Result<IpAddr, CheckError> {
+        addrs
+            .iter()
+            .copied()
+            .filter(|ip| options.family.allows(*ip))
+            .find(|ip| match self {
+                Self::IcmpV4 => ip.is_ipv4(),
+                Self::IcmpV6 => ip.is_ipv6(),
+                _ => true,
+            })
+            .ok_or_else(|| CheckError::NoAddress(host.to_string()))
+    }
+
     /// Returns a slice containing all possible check types.
     ///
     /// Used for iterating over available check types, e.g., during analysis.
     pub const fn all() -> &'static [Self] {
-        &[Self::Dns, Self::Http, Self::IcmpV4, Self::IcmpV6]
+        &[
+            Self::Dns,
+            Self::Http,
+            Self::IcmpV4,
+            Self::IcmpV6,
+            Self::Udp,
+            Self::Throughput,
+        ]
     }
 
     /// Returns a slice of check types enabled by default.
     ///
-    /// Currently only includes HTTP checks because ICMP requires special
-    /// privileges (CAP_NET_RAW) which are lost when the daemon drops privileges, and DNS is not
-    /// implemented.
+    /// Includes HTTP, ICMP and UDP checks. DNS checks are not implemented, and throughput checks
+    /// are made on their own schedule, see [ThroughputConfig](crate::config::ThroughputConfig).
     pub const fn default_enabled() -> &'static [Self] {
-        &[Self::Http, Self::IcmpV4, Self::IcmpV6]
+        &[Self::Http, Self::IcmpV4, Self::IcmpV6, Self::Udp]
+    }
+}
+
+impl FromStr for CheckType {
+    type Err = RecordError;
+
+    /// Parses the [Display] representation of a [CheckType], ignoring case.
+    ///
+    /// `http` and `https` are accepted for [Http](CheckType::Http) too. [Dns](CheckType::Dns)
+    /// and [Unknown](CheckType::Unknown) are rejected, no checks of them can be made.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "http" | "https" | "http(s)" => Self::Http,
+            "icmpv4" => Self::IcmpV4,
+            "icmpv6" => Self::IcmpV6,
+            "udp" => Self::Udp,
+            "throughput" => Self::Throughput,
+            _ => return Err(RecordError::UnknownCheckType(s.to_string())),
+        })
     }
 }
 
@@ -230,12 +442,316 @@ impl Display for CheckType {
                 Self::Http => "HTTP(S)",
                 Self::IcmpV4 => "ICMPv4",
                 Self::IcmpV6 => "ICMPv6",
+                Self::Udp => "UDP",
+                Self::Throughput => "Throughput",
                 Self::Unknown => "Unknown",
             }
         )
     }
 }
 
+/// Additional data of a [Check] that only some kinds of checks have.
+///
+/// A [Check] holds a list of these. New variants may be added in future versions, but existing
+/// variants must not be changed, since that would break loading older stores.
+#[derive(Debug, PartialEq, Eq, Hash, Deserialize, Serialize, Clone)]
+pub enum CheckDetail {
+    /// Packet counts of a [UDP check](CheckType::Udp)
+    Udp {
+        /// Amount of datagrams that were sent
+        sent: u8,
+        /// Amount of answers that were received in time
+        received: u8,
+    },
+    /// Response of an [HTTP check](CheckType::Http)
+    Http {
+        /// Status code of the response
+        status: u16,
+    },
+    /// The target was given as hostname and resolved to the [target](Check::target) of the
+    /// check
+    Resolved {
+        /// The hostname that was resolved
+        host: String,
+    },
+    /// The target was [backed off](crate::backoff) because it kept failing
+    Backoff {
+        /// The target was checked only every `factor` periods
+        factor: u32,
+    },
+    /// A label of the target, like `region=eu`, see [Check::tags]
+    Tag {
+        /// Name of the tag
+        key: String,
+        /// Value of the tag
+        value: String,
+    },
+    /// The check failed at first, but succeeded when it was
+    /// [retried](crate::config::ConfirmConfig)
+    Retried {
+        /// How many retries were needed
+        retries: u8,
+    },
+    /// Method of the request of an [HTTP check](CheckType::Http), see
+    /// [HttpMethod](crate::config::HttpMethod)
+    ///
+    /// Only recorded if it is not the default `GET`.
+    HttpMethod {
+        /// The method, like `HEAD`
+        method: String,
+    },
+    /// HTTP version of the response of an [HTTP check](CheckType::Http), see
+    /// [HttpVersion](crate::config::HttpVersion)
+    HttpVersion {
+        /// The version, like `2`
+        version: String,
+    },
+    /// The response body of an [HTTP check](CheckType::Http) did not contain the
+    /// [expected text](crate::config::HttpTargetConfig::expect_body)
+    BodyMismatch,
+    /// The check was made in a [maintenance window](crate::maintenance)
+    Maintenance,
+    /// The probes of the check were marked with a [DSCP](crate::config::Config::dscp)
+    Dscp {
+        /// The code point, between 0 and 63
+        value: u8,
+    },
+    /// Raw response of a failed check, if [capturing](crate::config::CaptureConfig) is enabled
+    ///
+    /// For HTTP checks, this is the head and the first bytes of the body, for checks without a
+    /// response the error.
+    Captured {
+        /// The captured bytes, at most [max_bytes](crate::config::CaptureConfig::max_bytes)
+        data: Vec<u8>,
+    },
+    /// Download of a [throughput check](CheckType::Throughput)
+    Throughput {
+        /// Amount of bytes that were downloaded
+        bytes: u64,
+        /// How long the download took in microseconds
+        micros: u64,
+    },
+    /// The probes of the check were sent from a configured [Source]
+    Source {
+        /// The address or interface, as configured
+        source: String,
+        /// Whether the probes could be sent from it, the check failed if not
+        bound: bool,
+    },
+    /// How long each phase of the request of an [HTTP check](CheckType::Http) took, in
+    /// microseconds, if [enabled](crate::config::HttpConfig::phases)
+    HttpPhases {
+        /// Resolving the host
+        dns: u32,
+        /// Connecting with TCP
+        connect: u32,
+        /// The TLS handshake, zero for plain HTTP
+        tls: u32,
+        /// Sending the request and waiting for the first byte of the response
+        ttfb: u32,
+        /// The whole request
+        total: u32,
+        /// Whether an open connection was
+        /// [reused](crate::config::HttpConnection::Reuse), skipping the first three phases
+        reused: bool,
+    },
+    /// The [target](Check::target) is a [link-local address](crate::config::ScopedAddr) that
+    /// was reached through this zone
+    Zone {
+        /// Name or index of the interface, as configured
+        zone: String,
+    },
+    /// The check got an answer, but slower than the
+    /// [maximum latency](crate::config::Config::max_latency_ms) of its target, so it is
+    /// recorded as soft failure
+    TooSlow {
+        /// How long the check took in milliseconds
+        latency: u16,
+        /// The maximum latency of the target in milliseconds
+        max: u16,
+    },
+    /// The hostname of the target could not be resolved, so the check was not made, see
+    /// [CheckType::unresolved]
+    Unresolved {
+        /// Why resolving failed
+        error: String,
+    },
+}
+
+impl Display for CheckDetail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Udp { sent, received } => write!(f, "Packets: {received}/{sent} answered"),
+            Self::Http { status } => write!(f, "HTTP status: {status}"),
+            Self::Resolved { host } => write!(f, "Host: {host}"),
+            Self::Backoff { factor } => write!(f, "Backed off: checked every {factor} periods"),
+            Self::Tag { key, value } => write!(f, "Tag: {key}={value}"),
+            Self::Retried { retries } => write!(f, "Required {retries} retries"),
+            Self::HttpMethod { method } => write!(f, "HTTP method: {method}"),
+            Self::HttpVersion { version } => write!(f, "HTTP version: {version}"),
+            Self::BodyMismatch => write!(f, "Body mismatch: the expected text is missing"),
+            Self::Maintenance => write!(f, "Made in a maintenance window"),
+            Self::Dscp { value } => write!(f, "DSCP: {value}"),
+            Self::Captured { data } => write!(f, "Captured: {} bytes", data.len()),
+            Self::Throughput { bytes, micros } => write!(
+                f,
+                "Throughput: {:.2} MB/s, {bytes} bytes in {} ms",
+                *bytes as f64 / (*micros).max(1) as f64,
+                micros / 1000
+            ),
+            Self::Source {
+                source,
+                bound: true,
+            } => write!(f, "Source: {source}"),
+            Self::Source {
+                source,
+                bound: false,
+            } => write!(f, "Source: {source}, could not bind"),
+            Self::HttpPhases {
+                dns,
+                connect,
+                tls,
+                ttfb,
+                total,
+                reused,
+            } => {
+                let ms = |micros: &u32| *micros as f64 / 1000.0;
+                write!(
+                    f,
+                    "HTTP phases: DNS {:.1}ms, connect {:.1}ms, TLS {:.1}ms, TTFB {:.1}ms, total {:.1}ms",
+                    ms(dns),
+                    ms(connect),
+                    ms(tls),
+                    ms(ttfb),
+                    ms(total)
+                )?;
+                if *reused {
+                    write!(f, ", reused connection")?;
+                }
+                Ok(())
+            }
+            Self::Zone { zone } => write!(f, "Zone: {zone}"),
+            Self::TooSlow { latency, max } => {
+                write!(f, "Too slow: {latency} ms, the maximum is {max} ms")
+            }
+            Self::Unresolved { error } => write!(f, "Could not resolve: {error}"),
+        }
+    }
+}
+
+/// Records the `source` the probes of `check` were sent from, and whether it could be used
+/// going by the `result` of the check.
+fn record_source<T>(check: &mut Check, source: Option<&Source>, result: &Result<T, CheckError>) {
+    if let Some(source) = source {
+        check.details.push(CheckDetail::Source {
+            source: source.to_string(),
+            bound: !matches!(result, Err(CheckError::Bind { .. })),
+        });
+    }
+}
+
+/// Records the `response` of an [HTTP check](CheckType::Http) in `check`.
+///
+/// The check is successful if the status and the HTTP version of the response are
+/// [accepted](crate::config::HttpConfig::accepts) for the target, and the body contained the
+/// expected text, if there is one.
+#[cfg(feature = "http")]
+fn record_http(
+    check: &mut Check,
+    response: Result<crate::checks::HttpResponse, CheckError>,
+    config: &Config,
+) {
+    let response = match response {
+        Ok(response) => response,
+        Err(err) => {
+            crate::logging::error(format!("error while performing an Http check: {err}"));
+            capture(check, config, err.to_string().into_bytes());
+            return;
+        }
+    };
+    let target = check.target;
+    if response.body_matches == Some(false) {
+        check.details.push(CheckDetail::BodyMismatch);
+    } else if !config.http.accepts_version(target, response.version) {
+        crate::logging::error(format!(
+            "Http check of {target} did not use the configured HTTP version, got {}",
+            response
+                .version
+                .map_or("an unknown version".to_string(), |v| format!("HTTP/{v}"))
+        ));
+    } else if config.http.accepts(target, response.status) {
+        check.add_flag(CheckFlag::Success);
+    }
+    check.latency = Some(response.latency);
+    check.details.push(CheckDetail::Http {
+        status: response.status,
+    });
+    if let Some(version) = response.version {
+        check.details.push(CheckDetail::HttpVersion {
+            version: version.to_string(),
+        });
+    }
+    if config.http.phases {
+        let phases = response.phases;
+        let micros = |phase: time::Duration| phase.as_micros().min(u32::MAX as u128) as u32;
+        check.details.push(CheckDetail::HttpPhases {
+            dns: micros(phases.dns),
+            connect: micros(phases.connect),
+            tls: micros(phases.tls),
+            ttfb: micros(phases.ttfb),
+            total: micros(phases.total),
+            reused: phases.reused,
+        });
+    }
+    capture(check, config, response.raw);
+}
+
+/// Records the `throughput` of a [throughput check](CheckType::Throughput) in `check`.
+///
+/// The check is successful if the response had a 2xx status and the throughput is
+/// [accepted](crate::config::ThroughputConfig::accepts).
+#[cfg(feature = "http")]
+fn record_throughput(
+    check: &mut Check,
+    throughput: Result<crate::checks::Throughput, CheckError>,
+    config: &Config,
+) {
+    let throughput = match throughput {
+        Ok(throughput) => throughput,
+        Err(err) => {
+            crate::logging::error(format!("error while performing a throughput check: {err}"));
+            capture(check, config, err.to_string().into_bytes());
+            return;
+        }
+    };
+    if (200..300).contains(&throughput.status)
+        && config.throughput.accepts(throughput.bytes_per_sec())
+    {
+        check.add_flag(CheckFlag::Success);
+    }
+    check.latency = Some(throughput.latency);
+    check.details.push(CheckDetail::Http {
+        status: throughput.status,
+    });
+    check.details.push(CheckDetail::Throughput {
+        bytes: throughput.bytes,
+        micros: throughput.duration.as_micros() as u64,
+    });
+}
+
+/// Adds the raw response `data` to `check` as [CheckDetail::Captured], if the check failed and
+/// [capturing](crate::config::CaptureConfig) is enabled.
+///
+/// The data is cut off at the [limit](crate::config::CaptureConfig::limit).
+#[cfg_attr(not(any(feature = "http", feature = "ping")), allow(dead_code))]
+fn capture(check: &mut Check, config: &Config, mut data: Vec<u8>) {
+    if check.is_success() || data.is_empty() || !config.capture.enabled {
+        return;
+    }
+    data.truncate(config.capture.limit());
+    check.details.push(CheckDetail::Captured { data });
+}
+
 /// Result of a single network connectivity check.
 ///
 /// Contains all information about a check attempt including:
@@ -244,7 +760,7 @@ impl Display for CheckType {
 /// - Whether it succeeded
 /// - Measured latency (if successful)
 /// - Target address
-#[derive(Debug, PartialEq, Eq, Hash, Deserialize, Serialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Deserialize, Serialize, Clone)]
 pub struct Check {
     /// Unix timestamp when check was performed (seconds since UNIX_EPOCH)
     timestamp: u64,
@@ -259,6 +775,12 @@ pub struct Check {
     latency: Option<u16>,
     /// Target IP address that was checked
     target: IpAddr,
+    /// Additional data depending on the kind of check
+    #[serde(default)]
+    details: Vec<CheckDetail>,
+    /// Position of the check in its [Store](crate::store::Store), see [seq](Check::seq)
+    #[serde(default)]
+    seq: u64,
 }
 
 impl Check {
@@ -267,9 +789,14 @@ impl Check {
     /// Uses [DefaultHasher](std::hash::DefaultHasher) to create a 16-character hexadecimal hash
     /// of the [Check] that can be used to identify this [Check]. Useful for detecting changes.
     pub fn get_hash(&self) -> String {
+        format!("{:016X}", self.hash_value())
+    }
+
+    /// The hash of [get_hash](Check::get_hash) as number.
+    pub(crate) fn hash_value(&self) -> u64 {
         let mut hasher = std::hash::DefaultHasher::default();
         self.hash(&mut hasher);
-        format!("{:016X}", hasher.finish())
+        hasher.finish()
     }
 
     /// Creates a new check result with the specified properties.
@@ -301,7 +828,62 @@ impl Check {
             flags: flags.into(),
             latency,
             target,
+            details: Vec::new(),
+            seq: 0,
+        }
+    }
+
+    /// Creates a new check result, rejecting contradictory flags.
+    ///
+    /// Like [new](Check::new), but meant for data that does not come from netpulse itself, like
+    /// user input or imports. Use [new](Check::new) for trusted internal paths.
+    ///
+    /// # Errors
+    ///
+    /// * Returns [RecordError::ConflictingTypes] if more than one check type flag is set, like
+    ///   both [TypeHTTP](CheckFlag::TypeHTTP) and [TypeIcmp](CheckFlag::TypeIcmp).
+    /// * Returns [RecordError::ConflictingIp] if both [IPv4](CheckFlag::IPv4) and
+    ///   [IPv6](CheckFlag::IPv6) are set, or if the set IP flag does not match the `target`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if timestamp is before UNIX_EPOCH.
+    pub fn try_new(
+        time: time::SystemTime,
+        flags: impl Into<FlagSet<CheckFlag>>,
+        latency: Option<u16>,
+        target: IpAddr,
+    ) -> Result<Self, RecordError> {
+        let check = Self::new(time, flags, latency, target);
+        check.validate()?;
+        Ok(check)
+    }
+
+    /// Checks that the flags of this [Check] do not contradict each other or the target.
+    ///
+    /// # Errors
+    ///
+    /// See [try_new](Check::try_new).
+    pub fn validate(&self) -> Result<(), RecordError> {
+        let types = self.flags
+            & (CheckFlag::TypeHTTP
+                | CheckFlag::TypeIcmp
+                | CheckFlag::TypeDns
+                | CheckFlag::TypeUdp
+                | CheckFlag::TypeThroughput);
+        if types.into_iter().count() > 1 {
+            return Err(RecordError::ConflictingTypes(types));
+        }
+
+        let ips = self.flags & (CheckFlag::IPv4 | CheckFlag::IPv6);
+        let target_ip = match self.target {
+            IpAddr::V4(_) => CheckFlag::IPv4,
+            IpAddr::V6(_) => CheckFlag::IPv6,
+        };
+        if ips.into_iter().count() > 1 || (!ips.is_empty() && !ips.contains(target_ip)) {
+            return Err(RecordError::ConflictingIp(ips | target_ip));
         }
+        Ok(())
     }
 
     /// Returns whether this check was successful.
@@ -335,6 +917,38 @@ impl Check {
         self.timestamp
     }
 
+    /// Returns whether `other` has the same result as this [`Check`], made at the same time
+    /// against the same target, ignoring the [sequence number](Check::seq).
+    pub fn is_duplicate_of(&self, other: &Check) -> bool {
+        (
+            self.timestamp,
+            self.flags,
+            self.latency,
+            self.target,
+            &self.details,
+        ) == (
+            other.timestamp,
+            other.flags,
+            other.latency,
+            other.target,
+            &other.details,
+        )
+    }
+
+    /// Returns the sequence number of this [`Check`].
+    ///
+    /// The [Store](crate::store::Store) numbers its checks in the order they were added,
+    /// starting at 1. Unlike the timestamp, this never goes backwards, even if the system
+    /// clock does. A check that was not added to a store yet has the sequence number 0.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Sets the sequence number of this [`Check`], see [seq](Check::seq).
+    pub(crate) fn set_seq(&mut self, seq: u64) {
+        self.seq = seq;
+    }
+
     /// Returns the timestamp of this [`Check`] as [SystemTime](std::time::SystemTime).
     pub fn timestamp_parsed(&self) -> time::SystemTime {
         time::UNIX_EPOCH + time::Duration::from_secs(self.timestamp())
@@ -357,10 +971,14 @@ impl Check {
     ///
     /// Returns [CheckType::Unknown] if flags indicate an invalid combination.
     pub fn calc_type(&self) -> Result<CheckType, StoreError> {
-        Ok(if self.flags.contains(CheckFlag::TypeHTTP) {
+        Ok(if self.flags.contains(CheckFlag::TypeThroughput) {
+            CheckType::Throughput
+        } else if self.flags.contains(CheckFlag::TypeHTTP) {
             CheckType::Http
         } else if self.flags.contains(CheckFlag::TypeDns) {
             CheckType::Dns
+        } else if self.flags.contains(CheckFlag::TypeUdp) {
+            CheckType::Udp
         } else if self.flags.contains(CheckFlag::TypeIcmp) {
             match self.ip_type()? {
                 CheckFlag::IPv4 => CheckType::IcmpV4,
@@ -372,6 +990,90 @@ impl Check {
         })
     }
 
+    /// Returns the target IP address of this [`Check`].
+    pub fn target(&self) -> IpAddr {
+        self.target
+    }
+
+    /// Returns the [additional data](CheckDetail) of this [`Check`].
+    pub fn details(&self) -> &[CheckDetail] {
+        &self.details
+    }
+
+    /// Adds [additional data](CheckDetail) to this [`Check`].
+    pub fn add_detail(&mut self, detail: CheckDetail) {
+        self.details.push(detail)
+    }
+
+    /// Returns the [captured](CheckDetail::Captured) raw response of this [`Check`], [None] if
+    /// nothing was captured.
+    pub fn captured(&self) -> Option<&[u8]> {
+        self.details.iter().find_map(|detail| match detail {
+            CheckDetail::Captured { data } => Some(data.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// Records this successful [`Check`] as soft failure if it took longer than `max`
+    /// milliseconds, see [CheckDetail::TooSlow].
+    ///
+    /// Failed checks and checks without latency are left alone.
+    pub fn fail_if_slower(&mut self, max: u16) {
+        match self.latency() {
+            Some(latency) if latency > max => {
+                self.flags -= CheckFlag::Success;
+                self.details.push(CheckDetail::TooSlow { latency, max });
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns whether this [`Check`] is a soft failure, that got an answer too slowly, see
+    /// [CheckDetail::TooSlow].
+    pub fn is_too_slow(&self) -> bool {
+        self.details
+            .iter()
+            .any(|detail| matches!(detail, CheckDetail::TooSlow { .. }))
+    }
+
+    /// Returns whether this [`Check`] was made in a [maintenance window](crate::maintenance).
+    pub fn in_maintenance(&self) -> bool {
+        self.details.contains(&CheckDetail::Maintenance)
+    }
+
+    /// Returns the tags of this [`Check`], like `region=eu`.
+    ///
+    /// Tags are stored as [CheckDetail::Tag] and configured per target, see
+    /// [Config::tags](crate::config::Config::tags).
+    pub fn tags(&self) -> BTreeMap<String, String> {
+        self.details
+            .iter()
+            .filter_map(|detail| match detail {
+                CheckDetail::Tag { key, value } => Some((key.clone(), value.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the value of the tag `key` of this [`Check`], if it has that tag.
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.details.iter().find_map(|detail| match detail {
+            CheckDetail::Tag { key: k, value } if k == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Sets the tag `key` of this [`Check`] to `value`, replacing an earlier value.
+    pub fn set_tag(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let key = key.into();
+        self.details
+            .retain(|detail| !matches!(detail, CheckDetail::Tag { key: k, .. } if *k == key));
+        self.details.push(CheckDetail::Tag {
+            key,
+            value: value.into(),
+        });
+    }
+
     /// Updates the target IP address of this check.
     pub fn set_target(&mut self, target: IpAddr) {
         self.target = target;
@@ -448,7 +1150,168 @@ impl Display for Check {
                 Some(l) => format!("{l} ms"),
                 None => "(Error)".to_string(),
             }
-        })
+        })?;
+        for detail in &self.details {
+            match detail {
+                CheckDetail::Captured { .. } => {
+                    write!(f, "\n{detail}, see netpulse --inspect {}", self.get_hash())?
+                }
+                _ => write!(f, "\n{detail}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks are ordered by their [timestamp](Check::timestamp), then by their
+/// [sequence number](Check::seq), then by their [hash](Check::get_hash).
+///
+/// The hash only decides between checks made at the same time that were not added to a store,
+/// so sorting checks from different sources always gives the same order.
+impl Ord for Check {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.timestamp
+            .cmp(&other.timestamp)
+            .then(self.seq.cmp(&other.seq))
+            .then_with(|| self.get_hash().cmp(&other.get_hash()))
+    }
+}
+
+impl PartialOrd for Check {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Summary of the [Checks](Check) of one type and target within a time bucket.
+///
+/// Made by [downsample](crate::store::Store::downsample), which replaces old checks with their
+/// aggregates, so that the success ratios and latencies of old checks are kept without keeping
+/// every single check.
+#[derive(Debug, PartialEq, Eq, Hash, Deserialize, Serialize, Clone)]
+pub struct Aggregate {
+    /// Unix timestamp of the start of the bucket
+    start: u64,
+    /// Length of the bucket in seconds
+    length: u64,
+    /// Type of the checks
+    check_type: CheckType,
+    /// Target of the checks
+    target: IpAddr,
+    /// Amount of checks
+    count: u64,
+    /// Amount of successful checks
+    successes: u64,
+    /// Sum of the latencies of the checks with a latency, in milliseconds
+    latency_sum: u64,
+    /// Amount of checks with a latency
+    latencies: u64,
+    /// 95th percentile of the latencies, in milliseconds
+    p95_latency: Option<u16>,
+    /// Highest [sequence number](Check::seq) of the checks
+    last_seq: u64,
+}
+
+impl Aggregate {
+    /// Summarizes `checks`, which should all be of `check_type` and `target`, for the bucket
+    /// starting at `start`.
+    pub fn of(
+        start: time::SystemTime,
+        length: time::Duration,
+        check_type: CheckType,
+        target: IpAddr,
+        checks: &[&Check],
+    ) -> Self {
+        let latencies: Vec<u64> = checks
+            .iter()
+            .filter_map(|c| c.latency().map(u64::from))
+            .collect();
+        Self {
+            start: start
+                .duration_since(time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            length: length.as_secs(),
+            check_type,
+            target,
+            count: checks.len() as u64,
+            successes: checks.iter().filter(|c| c.is_success()).count() as u64,
+            latency_sum: latencies.iter().sum(),
+            latencies: latencies.len() as u64,
+            p95_latency: crate::analyze::percentile(&latencies, 95).map(|l| l as u16),
+            last_seq: checks.iter().map(|c| c.seq()).max().unwrap_or_default(),
+        }
+    }
+
+    /// Adds the checks summarized by `other` to this aggregate.
+    ///
+    /// The percentile of merged aggregates can't be calculated exactly anymore, the higher of
+    /// the two is kept.
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        self.successes += other.successes;
+        self.latency_sum += other.latency_sum;
+        self.latencies += other.latencies;
+        self.p95_latency = self.p95_latency.max(other.p95_latency);
+        self.last_seq = self.last_seq.max(other.last_seq);
+    }
+
+    /// Returns whether `other` summarizes the same bucket, type and target.
+    pub(crate) fn same_bucket(&self, other: &Self) -> bool {
+        (self.start, self.length, self.check_type, self.target)
+            == (other.start, other.length, other.check_type, other.target)
+    }
+
+    /// Returns the start of the bucket.
+    pub fn start(&self) -> time::SystemTime {
+        time::UNIX_EPOCH + time::Duration::from_secs(self.start)
+    }
+
+    /// Returns the length of the bucket.
+    pub fn length(&self) -> time::Duration {
+        time::Duration::from_secs(self.length)
+    }
+
+    /// Returns the type of the summarized checks.
+    pub fn check_type(&self) -> CheckType {
+        self.check_type
+    }
+
+    /// Returns the target of the summarized checks.
+    pub fn target(&self) -> IpAddr {
+        self.target
+    }
+
+    /// Returns the amount of summarized checks.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the amount of successful checks.
+    pub fn successes(&self) -> u64 {
+        self.successes
+    }
+
+    /// Returns the ratio of successful checks, between 0.0 and 1.0.
+    pub fn success_ratio(&self) -> f64 {
+        self.successes as f64 / self.count as f64
+    }
+
+    /// Returns the mean latency of the checks with a latency, in milliseconds.
+    pub fn mean_latency(&self) -> Option<f64> {
+        match self.latencies {
+            0 => None,
+            n => Some(self.latency_sum as f64 / n as f64),
+        }
+    }
+
+    /// Returns the 95th percentile of the latencies, in milliseconds.
+    pub fn p95_latency(&self) -> Option<u16> {
+        self.p95_latency
+    }
+
+    /// Returns the highest [sequence number](Check::seq) of the summarized checks.
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
     }
 }
 
@@ -458,6 +1321,236 @@ mod test {
 
     use super::*;
 
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_version_mismatch_fails() {
+        use crate::checks::HttpResponse;
+        use crate::config::HttpVersion;
+
+        let config =
+            Config::from_toml("[[http.targets]]\ntarget = \"10.0.0.1\"\nversion = \"3\"").unwrap();
+        let make = |version| {
+            let mut check = Check::new(
+                time::SystemTime::now(),
+                CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                None,
+                "10.0.0.1".parse().unwrap(),
+            );
+            let response = HttpResponse {
+                latency: 30,
+                status: 200,
+                version,
+                body_matches: None,
+                raw: Vec::new(),
+                phases: Default::default(),
+            };
+            record_http(&mut check, Ok(response), &config);
+            check
+        };
+
+        let check = make(Some(HttpVersion::V3));
+        assert!(check.is_success());
+        assert!(check.details().contains(&CheckDetail::HttpVersion {
+            version: "3".to_string()
+        }));
+
+        let check = make(Some(HttpVersion::V1_1));
+        assert!(!check.is_success());
+        assert!(check.details().contains(&CheckDetail::HttpVersion {
+            version: "1.1".to_string()
+        }));
+        assert!(!make(None).is_success());
+
+        let mut check = Check::new(
+            time::SystemTime::now(),
+            CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            None,
+            "10.0.0.2".parse().unwrap(),
+        );
+        let response = HttpResponse {
+            latency: 30,
+            status: 200,
+            version: Some(HttpVersion::V1_1),
+            body_matches: Some(false),
+            raw: Vec::new(),
+            phases: Default::default(),
+        };
+        record_http(&mut check, Ok(response), &config);
+        assert!(!check.is_success());
+        assert!(check.details().contains(&CheckDetail::BodyMismatch));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_phases_recorded() {
+        use crate::checks::{HttpPhases, HttpResponse};
+
+        let make = |config: &Config| {
+            let mut check = Check::new(
+                time::SystemTime::now(),
+                CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                None,
+                "10.0.0.1".parse().unwrap(),
+            );
+            let ms = time::Duration::from_millis;
+            let response = HttpResponse {
+                latency: 70,
+                status: 200,
+                version: None,
+                body_matches: None,
+                raw: Vec::new(),
+                phases: HttpPhases {
+                    dns: ms(2),
+                    connect: ms(5),
+                    tls: ms(30),
+                    ttfb: ms(25),
+                    total: ms(63),
+                    reused: false,
+                },
+            };
+            record_http(&mut check, Ok(response), config);
+            check
+        };
+
+        let check = make(&Config::default());
+        assert!(!check
+            .details()
+            .iter()
+            .any(|d| matches!(d, CheckDetail::HttpPhases { .. })));
+
+        let config = Config::from_toml("[http]\nphases = true").unwrap();
+        let check = make(&config);
+        let detail = CheckDetail::HttpPhases {
+            dns: 2000,
+            connect: 5000,
+            tls: 30_000,
+            ttfb: 25_000,
+            total: 63_000,
+            reused: false,
+        };
+        assert!(check.details().contains(&detail));
+        assert_eq!(
+            detail.to_string(),
+            "HTTP phases: DNS 2.0ms, connect 5.0ms, TLS 30.0ms, TTFB 25.0ms, total 63.0ms"
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_capture_failed_checks() {
+        use crate::checks::HttpResponse;
+        use crate::store::Store;
+
+        let make = |config: &Config, status| {
+            let mut check = Check::new(
+                time::SystemTime::now(),
+                CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                None,
+                "10.0.0.1".parse().unwrap(),
+            );
+            let response = HttpResponse {
+                latency: 30,
+                status,
+                version: None,
+                body_matches: None,
+                raw: b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 60\r\n".to_vec(),
+                phases: Default::default(),
+            };
+            record_http(&mut check, Ok(response), config);
+            check
+        };
+
+        let config = Config::from_toml("[capture]\nenabled = true\nmax_bytes = 32").unwrap();
+        let mut store = Store::new();
+        store.add_check(make(&config, 503));
+        let hash = store.checks()[0].get_hash();
+        let check = store.check_by_hash(&hash).unwrap();
+        assert_eq!(
+            check.captured(),
+            Some(&b"HTTP/1.1 503 Service Unavailable"[..])
+        );
+        assert!(check.to_string().contains(&format!(
+            "Captured: 32 bytes, see netpulse --inspect {hash}"
+        )));
+
+        // nothing is captured for successful checks or if capturing is off
+        assert_eq!(make(&config, 200).captured(), None);
+        assert_eq!(make(&Config::default(), 503).captured(), None);
+        let mut check = Check::new(
+            time::SystemTime::now(),
+            CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            None,
+            "10.0.0.1".parse().unwrap(),
+        );
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        record_http(&mut check, Err(refused.into()), &config);
+        assert!(String::from_utf8_lossy(check.captured().unwrap()).contains("refused"));
+    }
+
+    #[test]
+    fn test_unusable_source() {
+        let remote: IpAddr = "127.0.0.1".parse().unwrap();
+        let mut config = Config::default();
+        config
+            .source
+            .insert(remote, Source::Interface("nosuchif0".to_string()));
+        let check = CheckType::Udp.make_with(remote, &config);
+        assert!(!check.is_success());
+        assert!(check.details().contains(&CheckDetail::Source {
+            source: "nosuchif0".to_string(),
+            bound: false
+        }));
+        assert_eq!(
+            check.details().last().unwrap().to_string(),
+            "Source: nosuchif0, could not bind"
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_throughput_threshold() {
+        use std::time::Duration;
+
+        use crate::checks::Throughput;
+
+        let make = |min_kb_per_sec, status| {
+            let mut config = Config::default();
+            config.throughput.min_kb_per_sec = min_kb_per_sec;
+            let mut check = Check::new(
+                time::SystemTime::now(),
+                CheckFlag::TypeThroughput | CheckFlag::IPv4,
+                None,
+                "10.0.0.1".parse().unwrap(),
+            );
+            let throughput = Throughput {
+                bytes: 2_000_000,
+                duration: Duration::from_secs(1),
+                latency: 40,
+                status,
+            };
+            record_throughput(&mut check, Ok(throughput), &config);
+            check
+        };
+
+        let check = make(1500, 200);
+        assert!(check.is_success());
+        assert_eq!(check.calc_type().unwrap(), CheckType::Throughput);
+        assert_eq!(check.latency(), Some(40));
+        let detail = CheckDetail::Throughput {
+            bytes: 2_000_000,
+            micros: 1_000_000,
+        };
+        assert!(check.details().contains(&detail));
+        assert_eq!(
+            detail.to_string(),
+            "Throughput: 2.00 MB/s, 2000000 bytes in 1000 ms"
+        );
+
+        // too slow, or not the file
+        assert!(!make(2500, 200).is_success());
+        assert!(!make(0, 404).is_success());
+    }
+
     #[test]
     fn test_max_time_fits_in_latency_field() {
         let _c = Check::new(
@@ -469,4 +1562,137 @@ mod test {
         // if it can be created, that's good enough for me, I'm just worried that I'll change the
         // timeout ms some day and this will break
     }
+
+    #[test]
+    fn test_try_new_rejects_contradictions() {
+        let v4: IpAddr = "1.1.1.1".parse().unwrap();
+        let v6: IpAddr = "2606:4700:4700::1111".parse().unwrap();
+        let now = time::SystemTime::now();
+
+        for types in [
+            CheckFlag::TypeHTTP | CheckFlag::TypeIcmp,
+            CheckFlag::TypeHTTP | CheckFlag::TypeDns,
+            CheckFlag::TypeIcmp | CheckFlag::TypeDns,
+            CheckFlag::TypeThroughput | CheckFlag::TypeHTTP,
+            CheckFlag::TypeThroughput | CheckFlag::TypeUdp,
+        ] {
+            assert_eq!(
+                Check::try_new(now, types | CheckFlag::IPv4, None, v4),
+                Err(RecordError::ConflictingTypes(types))
+            );
+        }
+        assert_eq!(
+            Check::try_new(
+                now,
+                CheckFlag::TypeIcmp | CheckFlag::IPv4 | CheckFlag::IPv6,
+                None,
+                v4
+            ),
+            Err(RecordError::ConflictingIp(
+                CheckFlag::IPv4 | CheckFlag::IPv6
+            ))
+        );
+        assert_eq!(
+            Check::try_new(now, CheckFlag::TypeIcmp | CheckFlag::IPv4, None, v6),
+            Err(RecordError::ConflictingIp(
+                CheckFlag::IPv4 | CheckFlag::IPv6
+            ))
+        );
+
+        let valid = Check::try_new(
+            now,
+            CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv6,
+            Some(20),
+            v6,
+        )
+        .unwrap();
+        assert_eq!(valid.calc_type().unwrap(), CheckType::Http);
+    }
+
+    #[test]
+    fn test_tags_roundtrip() {
+        let mut check = Check::new(
+            time::UNIX_EPOCH,
+            CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            Some(20),
+            "1.1.1.1".parse().unwrap(),
+        );
+        check.set_tag("region", "us");
+        check.set_tag("tier", "prod");
+        check.set_tag("region", "eu");
+        assert_eq!(check.tag("region"), Some("eu"));
+        assert_eq!(check.tag("rack"), None);
+        assert_eq!(
+            check.tags(),
+            BTreeMap::from([
+                ("region".to_string(), "eu".to_string()),
+                ("tier".to_string(), "prod".to_string())
+            ])
+        );
+
+        let raw = bincode::serialize(&check).unwrap();
+        assert_eq!(bincode::deserialize::<Check>(&raw).unwrap(), check);
+    }
+
+    #[test]
+    fn test_check_type_display_roundtrip() {
+        for check_type in CheckType::all()
+            .iter()
+            .filter(|check_type| **check_type != CheckType::Dns)
+        {
+            assert_eq!(
+                check_type.to_string().parse::<CheckType>().unwrap(),
+                *check_type
+            );
+        }
+        // making these checks would panic
+        for name in ["dns", "DNS", "unknown"] {
+            let err = name.parse::<CheckType>().unwrap_err();
+            assert_eq!(err, RecordError::UnknownCheckType(name.to_string()));
+            assert!(err.to_string().contains("http, icmpv4, icmpv6, udp"));
+        }
+        assert_eq!("http".parse::<CheckType>().unwrap(), CheckType::Http);
+        assert_eq!(" ICMPV6 ".parse::<CheckType>().unwrap(), CheckType::IcmpV6);
+        assert_eq!(
+            "carrier pigeon".parse::<CheckType>(),
+            Err(RecordError::UnknownCheckType("carrier pigeon".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_sort_checks() {
+        let check = |secs, flags, seq| {
+            let mut check = Check::new(
+                time::UNIX_EPOCH + time::Duration::from_secs(secs),
+                flags,
+                None,
+                "1.1.1.1".parse().unwrap(),
+            );
+            check.set_seq(seq);
+            check
+        };
+        let sorted = {
+            let mut tied = [
+                check(20, CheckFlag::TypeHTTP | CheckFlag::IPv4, 0),
+                check(20, CheckFlag::TypeUdp | CheckFlag::IPv4, 0),
+            ];
+            tied.sort_by_key(Check::get_hash);
+            let [a, b] = tied;
+            vec![
+                check(10, CheckFlag::TypeHTTP | CheckFlag::IPv4, 3),
+                a,
+                b,
+                check(20, CheckFlag::TypeHTTP | CheckFlag::IPv4, 1),
+                check(20, CheckFlag::TypeHTTP | CheckFlag::IPv4, 2),
+                // the timestamp decides before the sequence number
+                check(30, CheckFlag::TypeHTTP | CheckFlag::IPv4, 0),
+            ]
+        };
+
+        for shuffle in [[5, 3, 0, 2, 4, 1], [2, 1, 4, 5, 3, 0], [1, 2, 3, 4, 5, 0]] {
+            let mut shuffled: Vec<Check> = shuffle.iter().map(|&i| sorted[i].clone()).collect();
+            shuffled.sort();
+            assert_eq!(shuffled, sorted);
+        }
+    }
 }
@@ -0,0 +1,574 @@
+//! Running all configured checks once, without a store or daemon.
+//!
+//! This is what the daemon does every period, available for other tools that want to embed
+//! netpulse. Nothing is written to the store or the log files; the checks are just returned.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use netpulse::config::Config;
+//!
+//! for check in netpulse::run::run_once(&Config::load().unwrap()) {
+//!     println!("{check}");
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use crate::backoff::Backoff;
+use crate::config::Config;
+use crate::errors::CheckError;
+use crate::maintenance::in_maintenance;
+use crate::records::{Check, CheckDetail, CheckType, TARGETS};
+
+/// Runs every [default check type](CheckType::default_enabled) once against the built-in
+/// [TARGETS] and the configured [hosts](Config::hosts), and returns the checks.
+///
+/// ICMP checks are skipped without `CAP_NET_RAW`. Hosts that can't be resolved get a failed
+/// check, see [run_once_with].
+pub fn run_once(config: &Config) -> Vec<Check> {
+    run_once_with(
+        &targets(config),
+        &usable_check_types(CheckType::default_enabled()),
+        config,
+        &mut Backoff::new(&config.backoff),
+        |check_type, ip| check_type.make_with(ip, config),
+    )
+}
+
+/// Returns the built-in [TARGETS] followed by the configured [hosts](Config::hosts).
+pub fn targets(config: &Config) -> Vec<&str> {
+    TARGETS
+        .iter()
+        .copied()
+        .chain(config.hosts.iter().map(String::as_str))
+        .collect()
+}
+
+/// Runs each of `check_types` once against each of `targets`, with the checks made by `make`.
+///
+/// `targets` can be IP addresses or hostnames. Hostnames are resolved once for all check types
+/// as [configured](Config::resolve), see [CheckType::resolve_with], and their checks get a
+/// [CheckDetail::Resolved]. If resolving a hostname fails, a failed check made by
+/// [CheckType::unresolved] takes the place of the check, while hostnames without an address of
+/// the IP version of a check type are skipped. [Scoped addresses](crate::config::ScopedAddr)
+/// only get ICMPv6 checks. The [configured tags](Config::tags) of a target are added to its
+/// checks, and checks made in a [maintenance window](Config::maintenance) get a
+/// [CheckDetail::Maintenance]. Targets that `backoff` does not consider due are skipped. The
+/// [success condition](Config::success) and [maximum latency](Config::max_latency_ms) of a
+/// target decide the success of its checks. Failed checks are retried as often as the
+/// [settings](Config::check_settings) of their type allow.
+///
+/// The checks are ordered by check type first, then by target.
+pub fn run_once_with(
+    targets: &[&str],
+    check_types: &[CheckType],
+    config: &Config,
+    backoff: &mut Backoff,
+    make: impl FnMut(CheckType, IpAddr) -> Check,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+    run_each_with(targets, check_types, config, backoff, make, |check| {
+        checks.push(check)
+    });
+    checks
+}
+
+/// Like [run_once_with], but each check is passed to `each` as soon as it is made, instead of
+/// returning all of them at the end.
+pub fn run_each_with(
+    targets: &[&str],
+    check_types: &[CheckType],
+    config: &Config,
+    backoff: &mut Backoff,
+    mut make: impl FnMut(CheckType, IpAddr) -> Check,
+    mut each: impl FnMut(Check),
+) {
+    // each target is resolved once, for all check types
+    let mut resolved: HashMap<&str, Result<Vec<IpAddr>, CheckError>> = HashMap::new();
+    for check_type in check_types {
+        for target in targets {
+            let is_scoped = crate::checks::scoped(target).is_some();
+            if is_scoped && *check_type != CheckType::IcmpV6 {
+                crate::logging::debug(format!(
+                    "skipping the {check_type} check of {target}, only ICMPv6 checks use a zone"
+                ));
+                continue;
+            }
+            let is_host = target.parse::<IpAddr>().is_err() && !is_scoped;
+            let resolve = config.resolve.get(*target).copied().unwrap_or_default();
+            let addrs = resolved.entry(target).or_insert_with(|| {
+                match crate::checks::resolve_with(target, &resolve) {
+                    // like a host without an address of the IP version of a check
+                    Err(CheckError::NoAddress(_)) => Ok(Vec::new()),
+                    result => result,
+                }
+            });
+            let picked = match addrs {
+                Ok(addrs) => check_type.pick(target, addrs, &resolve),
+                Err(err) => {
+                    crate::logging::error(format!(
+                        "could not resolve {target} for a {check_type} check: {err}"
+                    ));
+                    if is_host {
+                        let mut check = check_type.unresolved(target, &*err);
+                        tag(&mut check, config, target);
+                        if in_maintenance(&config.maintenance, check.timestamp_parsed()) {
+                            check.add_detail(CheckDetail::Maintenance);
+                        }
+                        each(check);
+                    }
+                    continue;
+                }
+            };
+            let ip = match picked {
+                Ok(ip) => ip,
+                // a host without an address of the IP version of the check is no outage
+                Err(err) => {
+                    crate::logging::error(format!(
+                        "could not resolve {target} for a {check_type} check: {err}"
+                    ));
+                    continue;
+                }
+            };
+            if !backoff.due(*check_type, ip) {
+                crate::logging::debug(format!(
+                    "skipping the {check_type} check of {target}, it is backed off"
+                ));
+                continue;
+            }
+            let mut check = judge(make(*check_type, ip), config, target);
+            let retries = config.check_settings(*check_type).retries;
+            if retries > 0 && !check.is_success() {
+                check = confirm(check, retries, || {
+                    judge(make(*check_type, ip), config, target)
+                });
+            }
+            if is_host {
+                check.add_detail(CheckDetail::Resolved {
+                    host: target.to_string(),
+                });
+            }
+            tag(&mut check, config, target);
+            if in_maintenance(&config.maintenance, check.timestamp_parsed()) {
+                check.add_detail(CheckDetail::Maintenance);
+            }
+            crate::logging::debug(format!(
+                "{check_type} check of {}: {}{}",
+                if is_host {
+                    format!("{target} ({ip})")
+                } else {
+                    target.to_string()
+                },
+                if check.is_success() { "ok" } else { "failed" },
+                check
+                    .latency()
+                    .map(|l| format!(" in {l}ms"))
+                    .unwrap_or_default()
+            ));
+            backoff.record(&mut check);
+            each(check);
+        }
+    }
+}
+
+/// Makes a [throughput check](CheckType::Throughput) of the
+/// [configured URL](crate::config::ThroughputConfig::url).
+///
+/// The host of the URL is resolved like the targets of [run_once_with], and the check gets its
+/// [success condition](Config::success), tags and [maintenance](Config::maintenance) mark like
+/// them. The [maximum latency](Config::max_latency_ms) is not applied, a download takes longer
+/// than a single answer. If the host can't be resolved, the check is made by
+/// [CheckType::unresolved]. Returns [None] if no URL is configured.
+#[cfg(feature = "http")]
+pub fn run_throughput(config: &Config) -> Option<Check> {
+    let url = config.throughput.url.as_deref()?;
+    let (host, _port) = crate::checks::url_host(url)?;
+    let resolve = config.resolve.get(host).copied().unwrap_or_default();
+    let mut check = match CheckType::Throughput.resolve_with(host, &resolve) {
+        Ok(ip) => {
+            let mut check = judge(CheckType::Throughput.make_with(ip, config), config, host);
+            if host.parse::<IpAddr>().is_err() {
+                check.add_detail(CheckDetail::Resolved {
+                    host: host.to_string(),
+                });
+            }
+            check
+        }
+        Err(err) => {
+            crate::logging::error(format!(
+                "could not resolve {host} for a throughput check: {err}"
+            ));
+            CheckType::Throughput.unresolved(host, &err)
+        }
+    };
+    tag(&mut check, config, host);
+    if in_maintenance(&config.maintenance, check.timestamp_parsed()) {
+        check.add_detail(CheckDetail::Maintenance);
+    }
+    Some(check)
+}
+
+/// Makes no throughput check, they need the `http` feature.
+#[cfg(not(feature = "http"))]
+pub fn run_throughput(_config: &Config) -> Option<Check> {
+    None
+}
+
+/// Applies the [success condition](Config::success) and then the
+/// [maximum latency](Config::max_latency_ms) of `target` to its `check`, if it has them.
+///
+/// The maximum latency is not applied to [throughput checks](CheckType::Throughput), their
+/// latency is the time of the whole download.
+fn judge(mut check: Check, config: &Config, target: &str) -> Check {
+    if let Some(predicate) = config.success.get(target) {
+        predicate.apply(&mut check);
+    }
+    let is_throughput = matches!(check.calc_type(), Ok(CheckType::Throughput));
+    if let (Some(max), false) = (config.max_latency_ms.get(target), is_throughput) {
+        check.fail_if_slower(*max);
+    }
+    check
+}
+
+/// Retries the `failed` check with `retry` up to `retries` times.
+///
+/// Returns the first successful retry, with a [CheckDetail::Retried], or `failed` if all
+/// retries fail.
+fn confirm(failed: Check, retries: u8, mut retry: impl FnMut() -> Check) -> Check {
+    for retries in 1..=retries {
+        let mut check = retry();
+        if check.is_success() {
+            check.add_detail(CheckDetail::Retried { retries });
+            return check;
+        }
+    }
+    failed
+}
+
+/// Returns `check_types` without the ones that can't be used by this process.
+///
+/// ICMP checks need `CAP_NET_RAW`.
+pub(crate) fn usable_check_types(check_types: &[CheckType]) -> Vec<CheckType> {
+    check_types
+        .iter()
+        .filter(|check_type| {
+            if [CheckType::IcmpV4, CheckType::IcmpV6].contains(check_type) && !has_cap_net_raw() {
+                eprintln!("Does not have CAP_NET_RAW, can't use {check_type}, skipping");
+                return false;
+            }
+            true
+        })
+        .copied()
+        .collect()
+}
+
+/// Adds the [configured tags](Config::tags) of `target` to `check`.
+fn tag(check: &mut Check, config: &Config, target: &str) {
+    for (key, value) in config.tags.get(target).into_iter().flatten() {
+        check.set_tag(key, value);
+    }
+}
+
+fn has_cap_net_raw() -> bool {
+    // First check if we're root (which implies all capabilities)
+    if nix::unistd::getuid().is_root() {
+        return true;
+    }
+
+    // Check current process capabilities
+    if let Ok(caps) = caps::read(None, caps::CapSet::Effective) {
+        caps.contains(&caps::Capability::CAP_NET_RAW)
+    } else {
+        eprintln!("Could not read capabilities");
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::config::{AddressFamily, ResolveConfig};
+    use crate::records::CheckFlag;
+
+    use super::*;
+
+    fn mock(check_type: CheckType, ip: IpAddr) -> Check {
+        let mut flags = match check_type {
+            CheckType::Http => CheckFlag::TypeHTTP | CheckFlag::Success,
+            _ => CheckFlag::TypeUdp.into(),
+        };
+        flags |= match ip {
+            IpAddr::V4(_) => CheckFlag::IPv4,
+            IpAddr::V6(_) => CheckFlag::IPv6,
+        };
+        Check::new(UNIX_EPOCH + Duration::from_secs(60), flags, Some(7), ip)
+    }
+
+    #[test]
+    fn test_run_once_with_mock_targets() {
+        let mut config = Config::default();
+        config.tags.insert(
+            "localhost".to_string(),
+            BTreeMap::from([("region".to_string(), "home".to_string())]),
+        );
+        let checks = run_once_with(
+            &["192.0.2.1", "localhost"],
+            &[CheckType::Http, CheckType::Udp],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            mock,
+        );
+
+        assert_eq!(checks.len(), 4);
+        let types: Vec<CheckType> = checks.iter().map(|c| c.calc_type().unwrap()).collect();
+        assert_eq!(
+            types,
+            [
+                CheckType::Http,
+                CheckType::Http,
+                CheckType::Udp,
+                CheckType::Udp
+            ]
+        );
+        assert!(checks[0].is_success());
+        assert!(!checks[2].is_success());
+
+        assert_eq!(checks[0].target(), "192.0.2.1".parse::<IpAddr>().unwrap());
+        assert!(checks[0].details().is_empty());
+        assert!(checks[1].target().is_loopback());
+        assert_eq!(checks[1].tag("region"), Some("home"));
+        assert!(checks[1].details().contains(&CheckDetail::Resolved {
+            host: "localhost".to_string()
+        }));
+    }
+
+    /// Makes checks that fail the first time, then succeed like [mock] does for HTTP.
+    fn flaky() -> impl FnMut(CheckType, IpAddr) -> Check {
+        let mut attempts = 0;
+        move |check_type, ip| {
+            attempts += 1;
+            match attempts {
+                1 => mock(CheckType::Udp, ip),
+                _ => mock(check_type, ip),
+            }
+        }
+    }
+
+    #[test]
+    fn test_confirm_failures() {
+        let mut config = Config::default();
+        config.confirm.enabled = true;
+        let checks = run_once_with(
+            &["192.0.2.1"],
+            &[CheckType::Http],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            flaky(),
+        );
+        assert!(checks[0].is_success());
+        assert_eq!(checks[0].details(), [CheckDetail::Retried { retries: 1 }]);
+
+        // without confirmation, the first failure is recorded
+        config.confirm.enabled = false;
+        let checks = run_once_with(
+            &["192.0.2.1"],
+            &[CheckType::Http],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            flaky(),
+        );
+        assert!(!checks[0].is_success());
+        assert!(checks[0].details().is_empty());
+
+        // a check that keeps failing is recorded as failed after all retries
+        let mut tries = 0;
+        let failing = confirm(
+            mock(CheckType::Udp, "192.0.2.1".parse().unwrap()),
+            3,
+            || {
+                tries += 1;
+                mock(CheckType::Udp, "192.0.2.1".parse().unwrap())
+            },
+        );
+        assert_eq!(tries, 3);
+        assert!(!failing.is_success());
+        assert!(failing.details().is_empty());
+    }
+
+    #[test]
+    fn test_success_condition_of_target() {
+        let mut config = Config::from_toml("[success]\n\"192.0.2.1\" = \"latency < 5ms\"").unwrap();
+        let checks = run_once_with(
+            &["192.0.2.1", "192.0.2.2"],
+            &[CheckType::Http],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            mock,
+        );
+        // the mock checks take 7ms
+        assert!(!checks[0].is_success());
+        assert!(checks[1].is_success());
+
+        // the condition also decides the success of the retries
+        config.confirm.enabled = true;
+        let mut tries = 0;
+        let checks = run_once_with(
+            &["192.0.2.1"],
+            &[CheckType::Http],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            |check_type, ip| {
+                tries += 1;
+                mock(check_type, ip)
+            },
+        );
+        assert!(!checks[0].is_success());
+        assert_eq!(tries, 1 + usize::from(config.confirm.retries));
+    }
+
+    #[test]
+    fn test_max_latency_of_target() {
+        let mut config = Config::from_toml(
+            "[max_latency_ms]\n\"192.0.2.1\" = 5\n\"192.0.2.2\" = 7\n\"192.0.2.3\" = 5",
+        )
+        .unwrap();
+        let checks = run_once_with(
+            &["192.0.2.1", "192.0.2.2"],
+            &[CheckType::Http, CheckType::Udp],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            mock,
+        );
+        // the mock checks take 7ms
+        assert!(!checks[0].is_success());
+        assert!(checks[0].is_too_slow());
+        assert!(checks[0]
+            .details()
+            .contains(&CheckDetail::TooSlow { latency: 7, max: 5 }));
+        assert!(checks[1].is_success());
+        assert!(!checks[1].is_too_slow());
+        // hard failures are no soft failures
+        assert!(checks[2..]
+            .iter()
+            .all(|check| !check.is_success() && !check.is_too_slow()));
+
+        // retries that are too slow don't confirm the success either
+        config.confirm.enabled = true;
+        let checks = run_once_with(
+            &["192.0.2.3"],
+            &[CheckType::Http],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            mock,
+        );
+        assert!(checks[0].is_too_slow());
+
+        // downloads take longer than answers, throughput checks are left alone
+        let throughput = Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            CheckFlag::TypeThroughput | CheckFlag::Success | CheckFlag::IPv4,
+            Some(7),
+            "192.0.2.1".parse().unwrap(),
+        );
+        let judged = judge(throughput, &config, "192.0.2.1");
+        assert!(judged.is_success());
+        assert!(!judged.is_too_slow());
+    }
+
+    #[test]
+    fn test_v4_only_resolution() {
+        let mut config = Config::default();
+        config.resolve.insert(
+            "localhost".to_string(),
+            ResolveConfig {
+                family: AddressFamily::V4,
+                resolver: None,
+            },
+        );
+        let checks = run_once_with(
+            &["localhost"],
+            &[CheckType::Http, CheckType::Udp, CheckType::IcmpV6],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            mock,
+        );
+        // the ICMPv6 check has no address to use
+        assert_eq!(checks.len(), 2);
+        assert!(checks.iter().all(|c| c.target().is_ipv4()));
+        assert!(checks.iter().all(|c| c.flags().contains(CheckFlag::IPv4)));
+    }
+
+    #[test]
+    fn test_unresolvable_hosts_fail() {
+        // nothing answers DNS queries there
+        let resolver = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut config = Config::from_toml("[tags.\"example.com\"]\nregion = \"eu\"").unwrap();
+        config.resolve.insert(
+            "example.com".to_string(),
+            ResolveConfig {
+                family: AddressFamily::Any,
+                resolver: Some(resolver),
+            },
+        );
+        let checks = run_once_with(
+            &["example.com"],
+            &[CheckType::Http, CheckType::IcmpV6],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            |_, _| unreachable!("unresolved checks are not made"),
+        );
+        assert_eq!(checks.len(), 2);
+        for check in &checks {
+            assert!(!check.is_success());
+            assert!(check.details().contains(&CheckDetail::Resolved {
+                host: "example.com".to_string()
+            }));
+            assert!(check
+                .details()
+                .iter()
+                .any(|d| matches!(d, CheckDetail::Unresolved { .. })));
+            assert_eq!(check.tag("region"), Some("eu"));
+        }
+        assert_eq!(checks[0].calc_type().unwrap(), CheckType::Http);
+        assert_eq!(checks[1].calc_type().unwrap(), CheckType::IcmpV6);
+        assert!(checks[1].target().is_ipv6());
+    }
+
+    #[test]
+    fn test_scoped_targets_only_get_icmpv6_checks() {
+        let config = Config::default();
+        let checks = run_once_with(
+            &["fe80::1%eth0"],
+            &[CheckType::Http, CheckType::IcmpV6, CheckType::Udp],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            mock,
+        );
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].target(), "fe80::1".parse::<IpAddr>().unwrap());
+        // the zone is recorded by the check itself, a scoped address is no hostname
+        assert!(checks[0].details().is_empty());
+    }
+
+    #[test]
+    fn test_run_once_with_skips_unresolvable() {
+        let config = Config::default();
+        // an ICMPv6 check needs an IPv6 address
+        let checks = run_once_with(
+            &["127.0.0.1", "::1"],
+            &[CheckType::IcmpV6],
+            &config,
+            &mut Backoff::new(&config.backoff),
+            mock,
+        );
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].target(), "::1".parse::<IpAddr>().unwrap());
+    }
+}
@@ -7,32 +7,49 @@
 //! # Store Location
 //!
 //! The store location can be configured via:
-//! - Environment variable: `NETPULSE_STORE_PATH` (for debugging)
+//! - Environment variable: `NETPULSE_STORE_PATH`, either the store file itself or the directory
+//!   of the store file, see [Store::path]
 //! - Default path: `/var/lib/netpulse/netpulse.store`
 //!
+//! # Sharding
+//!
+//! For high check volumes, the checks can be split into multiple files next to the store file,
+//! like `netpulse.http.store`, see [ShardBy]. [Store::load_all] merges them again.
+//!
+//! # Downsampling
+//!
+//! Old checks can be replaced with hourly (or otherwise bucketed) [Aggregates](Aggregate) with
+//! [Store::downsample], which keeps their success ratios and latencies in much less space.
+//!
 //! # Versioning
 //!
 //! The store uses a simple version number to track format changes. [Version::CURRENT] is the current version.
 //! When loading a store, the version is checked and migration is performed if needed.
 
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::Display;
 use std::fs::{self};
 use std::hash::{Hash, Hasher};
-use std::io::{ErrorKind, Write};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::IpAddr;
 use std::os::unix::fs::OpenOptionsExt;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use nix::fcntl::{Flock, FlockArg};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use crate::errors::StoreError;
-use crate::records::{Check, CheckType, TARGETS};
+use crate::backoff::Backoff;
+use crate::config::{Config, StoreConfig};
+use crate::crypt::{self, StoreKey};
+use crate::errors::{CheckError, ConfigError, StoreError, StoreOperation};
+use crate::records::{Aggregate, Check, CheckDetail, CheckType};
+use crate::run;
 use crate::DAEMON_USER;
 
-#[cfg(feature = "compression")]
-use zstd;
-
 /// The filename of the netpulse store database
 ///
 /// Used in combination with [DB_PATH] to form the complete store path.
@@ -49,15 +66,25 @@ pub const DB_PATH: &str = "/var/lib/netpulse";
 ///
 /// Higher values provide better compression but slower performance.
 /// Default value: 4 (balanced between compression and speed)
-#[cfg(feature = "compression")]
 pub const ZSTD_COMPRESSION_LEVEL: i32 = 4;
 
+/// Whether stores are written compressed, set by the "compression" feature
+///
+/// Stores are always read in both formats, see [load_from](Store::load_from).
+pub const COMPRESS: bool = cfg!(feature = "compression");
+
+/// Magic number at the start of every zstd frame, used to detect compressed stores
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
 /// Environment variable name for overriding the store path
 ///
-/// If set, its value will be used instead of [DB_PATH] to locate the store.
-/// Primarily intended for development and testing.
+/// If set, its value will be used instead of [DB_PATH] to locate the store, see
+/// [path_from](Store::path_from). Primarily intended for development and testing.
 pub const ENV_PATH: &str = "NETPULSE_STORE_PATH";
 
+/// Seconds between two check cycles of the daemon, see [period_seconds](Store::period_seconds)
+pub const PERIOD_SECS: u64 = 60;
+
 /// Version information for the store format.
 ///
 /// The [Store] definition might change over time as netpulse is developed. To work with older or
@@ -69,6 +96,7 @@ pub const ENV_PATH: &str = "NETPULSE_STORE_PATH";
 ///
 /// This only describes the version of the [Store], not of [Netpulse](crate) itself.
 #[derive(Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(transparent)]
 pub struct Version {
     /// Raw version number as u8
     inner: u8,
@@ -85,6 +113,329 @@ pub struct Store {
     version: Version,
     /// Collection of all recorded checks
     checks: Vec<Check>,
+    /// Summaries of old checks that were [downsampled](Store::downsample), ordered by start
+    aggregates: Vec<Aggregate>,
+    /// Positions of the checks by their hash, see [check_by_hash](Store::check_by_hash)
+    #[serde(skip)]
+    index: CheckIndex,
+    /// Hash of the checks when they were last loaded from or saved to the store file, see
+    /// [in_sync](Store::in_sync)
+    #[serde(skip)]
+    synced: SyncedHash,
+}
+
+/// Positions of the checks of a [Store] by their [hash](Check::get_hash), the time of the
+/// last check of each [CheckType], and the highest [sequence number](Check::seq) given out so
+/// far.
+///
+/// Derived from the checks, so it is left out of comparisons, hashes and the store file.
+#[derive(Debug, Default)]
+struct CheckIndex {
+    /// Position of the first check with each hash
+    by_hash: HashMap<u64, usize>,
+    /// Time of the last added check of each type, see [Store::check_type_due]
+    last_of_type: HashMap<CheckType, SystemTime>,
+    /// Highest sequence number of the checks and aggregates, see [Store::last_seq]
+    last_seq: u64,
+}
+
+impl CheckIndex {
+    /// Builds the index of `checks` and `aggregates`.
+    ///
+    /// If checks have the same hash, the first one is indexed.
+    fn of(checks: &[Check], aggregates: &[Aggregate]) -> Self {
+        let mut index = Self {
+            by_hash: HashMap::new(),
+            last_of_type: HashMap::new(),
+            last_seq: checks
+                .iter()
+                .map(Check::seq)
+                .chain(aggregates.iter().map(Aggregate::last_seq))
+                .max()
+                .unwrap_or_default(),
+        };
+        index.reindex(checks);
+        index
+    }
+
+    /// Rebuilds the positions and last checks of each type of `checks`, after some were
+    /// removed.
+    ///
+    /// The highest sequence number stays, so the numbers of removed checks are not given out
+    /// again.
+    fn reindex(&mut self, checks: &[Check]) {
+        self.by_hash = HashMap::with_capacity(checks.len());
+        self.last_of_type.clear();
+        for (idx, check) in checks.iter().enumerate() {
+            self.by_hash.entry(check.hash_value()).or_insert(idx);
+            self.typed(check);
+        }
+    }
+
+    /// Remembers `check` as the last check of its type.
+    fn typed(&mut self, check: &Check) {
+        if let Ok(check_type) = check.calc_type() {
+            self.last_of_type
+                .insert(check_type, check.timestamp_parsed());
+        }
+    }
+}
+
+impl PartialEq for CheckIndex {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for CheckIndex {}
+
+impl Hash for CheckIndex {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+/// The [display_hash](Store::display_hash) of a [Store] when it was last loaded from or saved
+/// to its file, or [None] if it never was.
+///
+/// Like the [CheckIndex], it is left out of comparisons, hashes and the store file. Saving only
+/// borrows the store, so the hash is behind a [Mutex].
+#[derive(Debug, Default)]
+struct SyncedHash(Mutex<Option<u64>>);
+
+impl SyncedHash {
+    /// Returns the recorded hash.
+    fn get(&self) -> Option<u64> {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Records `hash` as the hash of the store file.
+    fn set(&self, hash: u64) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(hash);
+    }
+}
+
+impl PartialEq for SyncedHash {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for SyncedHash {}
+
+impl Hash for SyncedHash {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+/// Which checks [gc](Store::gc) keeps.
+///
+/// A check is kept if it is within both limits, so the newest checks are kept. Without any
+/// limit, all checks are kept.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Retention {
+    /// Remove the checks made longer ago than this
+    pub max_age: Option<Duration>,
+    /// Keep at most this many checks, the newest ones
+    pub max_count: Option<usize>,
+}
+
+impl Retention {
+    /// Returns the time and [sequence number](Check::seq) of the oldest check of `checks` that
+    /// is kept at `now`.
+    ///
+    /// A check is kept if its time and sequence number are at least as large. Checks made at
+    /// the same second are ordered by their sequence number.
+    fn oldest_kept<'check>(
+        &self,
+        checks: impl Iterator<Item = &'check Check>,
+        now: SystemTime,
+    ) -> (u64, u64) {
+        let by_age = (
+            self.max_age.map_or(0, |age| {
+                now.checked_sub(age)
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_secs())
+            }),
+            0,
+        );
+        let Some(max_count) = self.max_count else {
+            return by_age;
+        };
+        let mut kept: Vec<(u64, u64)> = checks
+            .map(|check| (check.timestamp(), check.seq()))
+            .filter(|key| *key >= by_age)
+            .collect();
+        if kept.len() <= max_count {
+            return by_age;
+        }
+        if max_count == 0 {
+            return (u64::MAX, u64::MAX);
+        }
+        kept.sort_unstable();
+        kept[kept.len() - max_count]
+    }
+}
+
+/// Result of an [import](Store::import_jsonl) into a [Store].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct ImportStats {
+    /// Amount of checks that were added to the store
+    pub imported: usize,
+    /// Amount of lines that were skipped because they were malformed or contained an invalid
+    /// [Check]
+    pub skipped: usize,
+    /// Amount of checks that were skipped because they duplicate a recent check, see
+    /// [add_check_with](Store::add_check_with)
+    pub duplicates: usize,
+}
+
+impl Display for ImportStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "imported {} checks, skipped {} lines",
+            self.imported, self.skipped
+        )?;
+        if self.duplicates > 0 {
+            write!(f, " and {} duplicates", self.duplicates)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the checks of a [Store] are split into multiple files, see [Store::save_sharded].
+///
+/// Parsed from and displayed as `none`, `type` or `tag:<key>`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ShardBy {
+    /// All checks are in the store file
+    #[default]
+    None,
+    /// One file per [CheckType], like `netpulse.http.store`
+    Type,
+    /// One file per value of this [tag](Check::tags), like `netpulse.region-eu.store`
+    ///
+    /// Checks without the tag are in the store file.
+    Tag(String),
+}
+
+impl ShardBy {
+    /// Returns the name of the shard `check` belongs to, [None] for the store file itself.
+    pub fn shard_of(&self, check: &Check) -> Option<String> {
+        let name = match self {
+            Self::None => return None,
+            Self::Type => match check.calc_type().unwrap_or(CheckType::Unknown) {
+                CheckType::Dns => "dns".to_string(),
+                CheckType::Http => "http".to_string(),
+                CheckType::IcmpV4 => "icmpv4".to_string(),
+                CheckType::IcmpV6 => "icmpv6".to_string(),
+                CheckType::Udp => "udp".to_string(),
+                CheckType::Throughput => "throughput".to_string(),
+                CheckType::Unknown => "unknown".to_string(),
+            },
+            Self::Tag(key) => format!("{key}-{}", check.tag(key)?),
+        };
+        // the name ends up in a file name
+        Some(
+            name.chars()
+                .map(|c| match c {
+                    'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                    _ => '_',
+                })
+                .collect(),
+        )
+    }
+}
+
+impl FromStr for ShardBy {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "none" => Ok(Self::None),
+            "type" => Ok(Self::Type),
+            other => match other.strip_prefix("tag:") {
+                Some(key) if !key.trim().is_empty() => Ok(Self::Tag(key.trim().to_string())),
+                _ => Err(ConfigError::Invalid(format!(
+                    "invalid shard_by '{s}', expected none, type or tag:<key>"
+                ))),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for ShardBy {
+    type Error = ConfigError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<ShardBy> for String {
+    fn from(value: ShardBy) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for ShardBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Type => write!(f, "type"),
+            Self::Tag(key) => write!(f, "tag:{key}"),
+        }
+    }
+}
+
+/// How a store file is encoded, chosen by its extension, see [StoreFormat::of].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub enum StoreFormat {
+    /// Compact binary with bincode, compressed with the `compression` feature
+    #[default]
+    Bincode,
+    /// TOML that can be read and edited by hand, with the `toml-store` feature
+    ///
+    /// Much larger and slower to load and save than [Bincode](StoreFormat::Bincode), and never
+    /// compressed, so it only suits small stores. Only the [current version](Version::CURRENT)
+    /// of the store can be loaded from TOML.
+    Toml,
+}
+
+impl StoreFormat {
+    /// Returns the format of the store file at `path`: [Toml](StoreFormat::Toml) for files
+    /// ending in `.toml`, [Bincode](StoreFormat::Bincode) for all others.
+    pub fn of(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext == "toml" => Self::Toml,
+            _ => Self::Bincode,
+        }
+    }
+}
+
+/// What was written by [save](Store::save) or [snapshot](Store::snapshot).
+///
+/// Lets a caller log and verify the file without reading it again.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct SaveReport {
+    /// Size of the written file in bytes, after compression
+    pub bytes_written: u64,
+    /// SHA-256 of the written file in lowercase hex, the same as
+    /// [display_hash_of_file](Store::display_hash_of_file)
+    pub hash: String,
+}
+
+/// Aggregate counts of the checks in a [Store], see [Store::stats].
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct StoreStats {
+    /// Amount of checks in the store
+    pub total: usize,
+    /// Amount of successful checks
+    pub ok: usize,
+    /// Amount of failed checks
+    pub bad: usize,
+    /// Amount of checks per [CheckType], checks without a valid type count as
+    /// [CheckType::Unknown]
+    pub by_type: HashMap<CheckType, usize>,
 }
 
 impl Display for Version {
@@ -107,12 +458,24 @@ impl From<Version> for u8 {
 
 impl Version {
     /// Current version of the store format
-    pub const CURRENT: Self = Version::new(0);
+    ///
+    /// # History
+    ///
+    /// - `0` - Initial format
+    /// - `1` - [Checks](Check) have a list of [CheckDetails](crate::records::CheckDetail)
+    /// - `2` - [Checks](Check) have a [sequence number](Check::seq)
+    /// - `3` - The store has [Aggregates](Aggregate) of [downsampled](Store::downsample) checks
+    pub const CURRENT: Self = Version::new(3);
 
     /// List of supported store format versions
     ///
     /// Used for compatibility checking when loading stores.
-    pub const SUPPROTED: &[Self] = &[Version::new(0)];
+    pub const SUPPROTED: &[Self] = &[
+        Version::new(0),
+        Version::new(1),
+        Version::new(2),
+        Version::new(3),
+    ];
 
     /// Creates a new Version with the given raw version number
     pub(crate) const fn new(raw: u8) -> Self {
@@ -124,7 +487,7 @@ impl Store {
     /// Returns the full path to the store file.
     ///
     /// The path is determined by:
-    /// 1. Checking [ENV_PATH] environment variable
+    /// 1. Checking [ENV_PATH] environment variable, see [path_from](Store::path_from)
     /// 2. Falling back to [DB_PATH]/[DB_NAME] if not set
     ///
     /// # Examples
@@ -137,21 +500,82 @@ impl Store {
     /// ```
     pub fn path() -> PathBuf {
         if let Some(var) = std::env::var_os(ENV_PATH) {
-            let mut p = PathBuf::from(var);
-            p.push(DB_NAME);
-            p
+            Self::path_from(Path::new(&var))
         } else {
             PathBuf::from(format!("{DB_PATH}/{DB_NAME}"))
         }
     }
 
+    /// Returns the path of the store file for `value`, the value of [ENV_PATH].
+    ///
+    /// The value is the store file itself if it ends in `.store` or `.toml`, see [StoreFormat],
+    /// or is an existing file, like `~/.local/share/netpulse/netpulse.store`. Otherwise, it is the
+    /// directory of the store file, which is named [DB_NAME] in it.
+    pub fn path_from(value: &Path) -> PathBuf {
+        if value
+            .extension()
+            .is_some_and(|ext| ext == "store" || ext == "toml")
+            || value.is_file()
+        {
+            value.to_path_buf()
+        } else {
+            value.join(DB_NAME)
+        }
+    }
+
     /// Creates a new empty store with current version.
     ///
     /// Used internally by [create](Store::create) when initializing a new store.
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
+        Self::of(Vec::new(), Vec::new())
+    }
+
+    /// Creates a store with `checks`, in the given order, without a file.
+    ///
+    /// Meant for tests and demos that need a store with known checks, like the
+    /// [demo](crate::demo) store. The checks get [sequence numbers](Check::seq) as if they were
+    /// [added](Store::add_check) one after another. The store can still be
+    /// [saved](Store::save_to) to a file.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// use netpulse::records::{Check, CheckFlag};
+    /// use netpulse::store::Store;
+    ///
+    /// let checks = (0..3)
+    ///     .map(|minute| {
+    ///         Check::new(
+    ///             UNIX_EPOCH + Duration::from_secs(minute * 60),
+    ///             CheckFlag::TypeHTTP | CheckFlag::IPv4 | CheckFlag::Success,
+    ///             Some(20),
+    ///             "1.1.1.1".parse().unwrap(),
+    ///         )
+    ///     })
+    ///     .collect();
+    /// let store = Store::from_fixture(checks);
+    /// assert_eq!(store.checks().len(), 3);
+    /// assert_eq!(store.checks()[2].seq(), 3);
+    /// ```
+    pub fn from_fixture(checks: Vec<Check>) -> Self {
+        let mut store = Self::new();
+        for check in checks {
+            store.add_check(check);
+        }
+        store
+    }
+
+    /// Creates a store of the current version with `checks` and `aggregates`, and indexes the
+    /// checks.
+    fn of(checks: Vec<Check>, aggregates: Vec<Aggregate>) -> Self {
         Self {
             version: Version::CURRENT,
-            checks: Vec::new(),
+            index: CheckIndex::of(&checks, &aggregates),
+            checks,
+            aggregates,
+            synced: SyncedHash::default(),
         }
     }
 
@@ -204,13 +628,7 @@ impl Store {
         let parent_path = path
             .parent()
             .expect("the store path has no parent directory");
-        let user = nix::unistd::User::from_name(DAEMON_USER)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-            .expect("could not get user for netpulse")
-            .ok_or_else(|| {
-                std::io::Error::new(std::io::ErrorKind::NotFound, "netpulse user not found")
-            })
-            .expect("could not get user for netpulse");
+        let user = crate::lookup_user(DAEMON_USER)?;
 
         fs::create_dir_all(parent_path)?;
         std::os::unix::fs::chown(parent_path, Some(user.uid.into()), Some(user.gid.into()))
@@ -231,35 +649,46 @@ impl Store {
     /// # Errors
     ///
     /// Returns [StoreError] if:
-    /// - Directory creation fails
+    /// - Directory creation fails ([StoreError::CreateDir])
     /// - File creation fails
     /// - Serialization fails
     /// - Write fails
     pub fn create() -> Result<Self, StoreError> {
+        Self::create_at(&Self::path())
+    }
+
+    /// Creates a new store file at `path`, see [create](Store::create).
+    ///
+    /// # Errors
+    ///
+    /// See [create](Store::create).
+    pub fn create_at(path: &Path) -> Result<Self, StoreError> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|source| StoreError::CreateDir {
+                path: parent.to_path_buf(),
+                source,
+            })?;
+        }
         let file = match fs::File::options()
             .read(false)
             .write(true)
             .append(false)
             .create_new(true)
             .mode(0o644)
-            .open(Self::path())
+            .open(path)
         {
             Ok(file) => file,
             Err(err) => {
                 eprintln!("opening the store file for writing failed: {err}");
-                return Err(err.into());
+                return Err(StoreError::from(err).in_file(StoreOperation::Create, path));
             }
         };
 
         let store = Store::new();
-
-        #[cfg(feature = "compression")]
-        let mut writer = zstd::Encoder::new(file, ZSTD_COMPRESSION_LEVEL)?;
-        #[cfg(not(feature = "compression"))]
-        let mut writer = file;
-
-        writer.write_all(&bincode::serialize(&store)?)?;
-        writer.flush()?;
+        store
+            .write_as(StoreFormat::of(path), file)
+            .map_err(|e| e.in_file(StoreOperation::Create, path))?;
+        store.synced.set(store.hash_value());
         Ok(store)
     }
 
@@ -276,10 +705,11 @@ impl Store {
     /// # Examples
     ///
     /// ```rust,no_run
+    /// use netpulse::config::Config;
     /// use netpulse::store::Store;
     ///
     /// let mut store = Store::load_or_create().unwrap();
-    /// store.make_checks();
+    /// store.make_checks(&Config::default());
     /// store.save().unwrap();
     /// ```
     pub fn load_or_create() -> Result<Self, StoreError> {
@@ -329,11 +759,151 @@ impl Store {
     /// - Read/parse fails
     /// - Version unsupported
     pub fn load() -> Result<Self, StoreError> {
+        Self::load_from(&Self::path())
+    }
+
+    /// Like [load_or_create](Store::load_or_create), but loads all shards if the store is
+    /// [sharded](crate::config::StoreConfig::shard_by).
+    ///
+    /// A sharded store without any files results in an empty store, the files are created when
+    /// it is [saved](Store::save_with).
+    ///
+    /// # Errors
+    ///
+    /// See [load_or_create](Store::load_or_create) and [load_all](Store::load_all).
+    pub fn load_or_create_with(config: &Config) -> Result<Self, StoreError> {
+        match config.store.shard_by {
+            ShardBy::None => Self::load_or_create(),
+            _ => match Self::load_all() {
+                Err(StoreError::DoesNotExist) => Ok(Self::new()),
+                other => other,
+            },
+        }
+    }
+
+    /// Loads the store file at [path](Store::path) together with all of its shards.
+    ///
+    /// See [load_all_from](Store::load_all_from).
+    ///
+    /// # Errors
+    ///
+    /// See [load_all_from](Store::load_all_from).
+    pub fn load_all() -> Result<Self, StoreError> {
+        Self::load_all_from(&Self::path())
+    }
+
+    /// Loads the store file at `path` and all of its [shards](ShardBy), merged into one store.
+    ///
+    /// The checks are ordered by their [sequence number](Check::seq). Works for stores that are
+    /// not sharded too, and for a missing store file if there are shards.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError::DoesNotExist] if neither the store file nor any shard exist, and the
+    /// errors of [load_from](Store::load_from) for each file.
+    pub fn load_all_from(path: &Path) -> Result<Self, StoreError> {
+        let files = Self::files_of(path)?;
+        if files.is_empty() {
+            return Err(StoreError::DoesNotExist);
+        }
+        let mut checks = Vec::new();
+        let mut aggregates = Vec::new();
+        for file in files {
+            let store = Self::load_from(&file)?;
+            checks.extend(store.checks);
+            aggregates.extend(store.aggregates);
+        }
+        checks.sort_by_key(|check| check.seq());
+        // a check can be in the store file and a shard after sharding was turned on
+        checks.dedup();
+        aggregates.sort_by_key(|aggregate| aggregate.start());
+        aggregates.dedup();
+        let store = Store::of(checks, aggregates);
+        store.synced.set(store.hash_value());
+        Ok(store)
+    }
+
+    /// Returns the existing files of the store at `path`: the store file itself and its shards.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError] if the directory of the store can't be read.
+    pub fn files_of(path: &Path) -> Result<Vec<PathBuf>, StoreError> {
+        let mut files = Vec::new();
+        if fs::exists(path)? {
+            files.push(path.to_path_buf());
+        }
+        let (Some(dir), Some((stem, ext))) = (
+            path.parent(),
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split_once('.')),
+        ) else {
+            return Ok(files);
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let mut shards: Vec<PathBuf> = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            let is_shard = name
+                .strip_prefix(stem)
+                .and_then(|rest| rest.strip_prefix('.'))
+                .and_then(|rest| rest.strip_suffix(ext))
+                .and_then(|shard| shard.strip_suffix('.'))
+                .is_some_and(|shard| !shard.is_empty());
+            if is_shard {
+                shards.push(entry.path());
+            }
+        }
+        shards.sort();
+        files.extend(shards);
+        Ok(files)
+    }
+
+    /// Returns the path of the file of `shard` for the store file at `path`.
+    ///
+    /// `/var/lib/netpulse/netpulse.store` with the shard `http` results in
+    /// `/var/lib/netpulse/netpulse.http.store`.
+    pub fn shard_path(path: &Path, shard: &str) -> PathBuf {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(DB_NAME);
+        let name = match name.split_once('.') {
+            Some((stem, ext)) => format!("{stem}.{shard}.{ext}"),
+            None => format!("{name}.{shard}"),
+        };
+        path.with_file_name(name)
+    }
+
+    /// Loads a store from an arbitrary path, like a [snapshot](Store::snapshot).
+    ///
+    /// Works just like [load](Store::load), but does not use [path](Store::path).
+    ///
+    /// Compressed and uncompressed stores are both loaded, regardless of the "compression"
+    /// feature, by looking at the first bytes of the file. Encrypted stores are decrypted with
+    /// the key in the environment, see [crypt].
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError] if:
+    /// - Store file doesn't exist
+    /// - Read/parse fails
+    /// - Version unsupported
+    pub fn load_from(path: &Path) -> Result<Self, StoreError> {
         let file = match fs::File::options()
             .read(true)
             .write(false)
             .create_new(false)
-            .open(Self::path())
+            .open(path)
         {
             Ok(file) => file,
             Err(err) => {
@@ -343,186 +913,2342 @@ impl Store {
                     _ => (),
                 };
 
-                return Err(err.into());
+                return Err(StoreError::from(err).in_file(StoreOperation::Load, path));
             }
         };
+        let store = Self::decode_as(StoreFormat::of(path), file)
+            .map_err(|e| e.in_file(StoreOperation::Load, path))?;
+        store.synced.set(store.hash_value());
+        Ok(store)
+    }
+
+    /// Reads a store from `reader`, like a store file piped to stdin.
+    ///
+    /// Works just like [load_from](Store::load_from), but does not use the filesystem. The
+    /// store has to be in the [Bincode](StoreFormat::Bincode) format.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError] if reading or parsing fails, or the version is unsupported.
+    pub fn load_from_reader(reader: impl Read) -> Result<Self, StoreError> {
+        Self::decode_as(StoreFormat::Bincode, reader)
+    }
+
+    /// Reads a store in `format` from the start of `file`, decrypted with the key in the
+    /// environment if it is encrypted, see [crypt].
+    fn decode_as(format: StoreFormat, file: impl Read) -> Result<Self, StoreError> {
+        Self::decode_keyed(format, file, StoreKey::from_env()?.as_ref())
+    }
 
-        #[cfg(feature = "compression")]
-        let reader = zstd::Decoder::new(file)?;
-        #[cfg(not(feature = "compression"))]
-        let mut reader = file;
+    /// Reads a store in `format` from the start of `file`, decrypted with `key` if it is
+    /// encrypted.
+    fn decode_keyed(
+        format: StoreFormat,
+        file: impl Read,
+        key: Option<&StoreKey>,
+    ) -> Result<Self, StoreError> {
+        let mut file = BufReader::new(file);
+        if !crypt::is_encrypted(file.fill_buf()?) {
+            return Self::decode_plain(format, file);
+        }
+        let key = key.ok_or(StoreError::KeyMissing)?;
+        let mut sealed = Vec::new();
+        file.read_to_end(&mut sealed)?;
+        Self::decode_plain(format, crypt::open(key, &sealed)?.as_slice())
+    }
 
-        let mut store: Store = bincode::deserialize_from(reader)?;
+    /// Reads an unencrypted store in `format` from the start of `file`.
+    fn decode_plain(format: StoreFormat, file: impl Read) -> Result<Self, StoreError> {
+        match format {
+            StoreFormat::Bincode => Self::decode(file),
+            StoreFormat::Toml => Self::decode_toml(file),
+        }
+    }
 
-        // TODO: somehow account for old versions that are not compatible with the store struct
+    /// Reads a [TOML](StoreFormat::Toml) store from `file`.
+    #[cfg(feature = "toml-store")]
+    fn decode_toml(mut file: impl Read) -> Result<Self, StoreError> {
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+        let store: Self = toml::from_str(&text)?;
+        // TOML stores were added with the current version, there is nothing to migrate
         if store.version != Version::CURRENT {
-            eprintln!("The store that was loaded is not of the current version:\nstore has {} but the current version is {}", store.version, Version::CURRENT);
-            if Version::SUPPROTED.contains(&store.version) {
+            return Err(StoreError::UnsupportedVersion);
+        }
+        Ok(Self::of(store.checks, store.aggregates))
+    }
+
+    /// Fails, reading [TOML](StoreFormat::Toml) stores needs the `toml-store` feature.
+    #[cfg(not(feature = "toml-store"))]
+    fn decode_toml(_file: impl Read) -> Result<Self, StoreError> {
+        Err(StoreError::TomlNotEnabled)
+    }
+
+    /// Reads a store from the start of `file`, migrating older [versions](Version).
+    fn decode(file: impl Read) -> Result<Self, StoreError> {
+        let mut file = BufReader::new(file);
+        let mut reader: Box<dyn Read> = if file.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+            Box::new(zstd::Decoder::with_buffer(file)?)
+        } else {
+            Box::new(file)
+        };
+
+        // the version is the first field, so we know how to read the rest before reading it
+        let version: Version = bincode::deserialize_from(&mut reader)?;
+        if version != Version::CURRENT {
+            eprintln!("The store that was loaded is not of the current version:\nstore has {} but the current version is {}", version, Version::CURRENT);
+            if Version::SUPPROTED.contains(&version) {
                 eprintln!("The old store version is still supported, migrating to newer version");
-                store.version = Version::CURRENT;
             } else {
                 eprintln!("The store version is not supported");
                 return Err(StoreError::UnsupportedVersion);
             }
         }
-        Ok(store)
+
+        let mut checks: Vec<Check> = match version.inner {
+            0 => bincode::deserialize_from::<_, Vec<legacy::CheckV0>>(&mut reader)?
+                .into_iter()
+                .map(Check::from)
+                .collect(),
+            1 => bincode::deserialize_from::<_, Vec<legacy::CheckV1>>(&mut reader)?
+                .into_iter()
+                .map(Check::from)
+                .collect(),
+            _ => bincode::deserialize_from(&mut reader)?,
+        };
+        if version.inner < 2 {
+            // older stores are in the order the checks were made
+            for (idx, check) in checks.iter_mut().enumerate() {
+                check.set_seq(idx as u64 + 1);
+            }
+        }
+        let aggregates = match version.inner {
+            0..=2 => Vec::new(),
+            _ => bincode::deserialize_from(&mut reader)?,
+        };
+
+        Ok(Store::of(checks, aggregates))
     }
 
     /// Saves the store to disk.
     ///
     /// # File Handling
     ///
-    /// - Truncates existing file
+    /// - Replaces the existing file atomically, like a [snapshot](Store::snapshot), so a crash
+    ///   while saving leaves the old store intact
+    /// - Locks the directory of the store while writing, like
+    ///   [append_check_locked](Store::append_check_locked)
     /// - Optionally compresses if feature enabled
+    /// - Encrypts if a key is set, see [crypt]
     /// - Maintains original permissions
     ///
+    /// Returns the size and hash of the written file.
+    ///
     /// # Errors
     ///
     /// Returns [StoreError] if:
     /// - File doesn't exist
-    /// - Write fails
+    /// - Write fails, as [StoreError::File]
     /// - Serialization fails
-    pub fn save(&self) -> Result<(), StoreError> {
-        let file = match fs::File::options()
-            .read(false)
-            .write(true)
-            .append(false)
-            .create_new(false)
-            .truncate(true)
-            .create(false)
-            .open(Self::path())
-        {
-            Ok(file) => file,
+    pub fn save(&self) -> Result<SaveReport, StoreError> {
+        self.save_to(&Self::path())
+    }
+
+    /// Saves the store to the existing store file at `path`, see [save](Store::save).
+    ///
+    /// # Errors
+    ///
+    /// See [save](Store::save).
+    pub fn save_to(&self, path: &Path) -> Result<SaveReport, StoreError> {
+        let _lock = Self::lock(path)?;
+        let permissions = match fs::metadata(path) {
+            Ok(meta) => meta.permissions(),
             Err(err) => match err.kind() {
                 ErrorKind::NotFound => return Err(StoreError::DoesNotExist),
-                _ => return Err(err.into()),
+                _ => return Err(StoreError::from(err).in_file(StoreOperation::Save, path)),
             },
         };
-
-        #[cfg(feature = "compression")]
-        let mut writer = zstd::Encoder::new(file, ZSTD_COMPRESSION_LEVEL)?;
-        #[cfg(not(feature = "compression"))]
-        let mut writer = file;
-
-        writer.write_all(&bincode::serialize(&self)?)?;
-        writer.flush()?;
-        Ok(())
+        let report = self.replace(path, Some(permissions))?;
+        self.synced.set(self.hash_value());
+        Ok(report)
     }
 
-    /// Adds a new check to the store.
-    pub fn add_check(&mut self, check: impl Into<Check>) {
-        self.checks.push(check.into());
+    /// Writes a consistent copy of the store to `path`.
+    ///
+    /// The copy is first written to a temporary file next to `path` and then renamed, so a reader
+    /// of `path` will never see a partially written store. An existing file at `path` is replaced.
+    ///
+    /// The snapshot can be loaded with [load_from](Store::load_from). Returns the size and hash
+    /// of the written file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError] if:
+    /// - The temporary file can't be created
+    /// - Serialization fails
+    /// - Write or rename fails, as [StoreError::File]
+    pub fn snapshot(&self, path: &Path) -> Result<SaveReport, StoreError> {
+        self.replace(path, None)
     }
 
-    /// Returns a reference to the checks of this [`Store`].
-    pub fn checks(&self) -> &[Check] {
-        &self.checks
+    /// Writes the store to a temporary file next to `path` and renames it to `path`.
+    ///
+    /// The file gets `permissions` if given. The temporary file is removed if anything fails.
+    fn replace(
+        &self,
+        path: &Path,
+        permissions: Option<fs::Permissions>,
+    ) -> Result<SaveReport, StoreError> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let write = || -> Result<SaveReport, StoreError> {
+            let file = fs::File::options()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o644)
+                .open(&tmp_path)?;
+            if let Some(permissions) = permissions {
+                file.set_permissions(permissions)?;
+            }
+            let report = self.write_as(StoreFormat::of(path), file)?;
+            fs::rename(&tmp_path, path)?;
+            Ok(report)
+        };
+        write()
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&tmp_path);
+            })
+            .map_err(|e| e.in_file(StoreOperation::Save, path))
     }
 
-    /// Returns the check interval in seconds.
+    /// Serializes the store into `file` in `format`, encrypted if a key is set in the
+    /// environment, see [crypt].
     ///
-    /// This determines how frequently the daemon performs checks.
-    /// Currently fixed at 60 seconds.
-    pub const fn period_seconds(&self) -> u64 {
-        60
+    /// [Bincode](StoreFormat::Bincode) is compressed if the feature is enabled.
+    fn write_as(&self, format: StoreFormat, file: fs::File) -> Result<SaveReport, StoreError> {
+        self.write_keyed(format, file, StoreKey::from_env()?.as_ref())
     }
 
-    /// Generates a hash of the in-memory store data.
-    ///
-    /// Uses [DefaultHasher](std::hash::DefaultHasher) to create a 16-character hexadecimal hash
-    /// of the entire store contents. Useful for detecting changes.
-    pub fn display_hash(&self) -> String {
-        let mut hasher = std::hash::DefaultHasher::default();
-        self.hash(&mut hasher);
-        format!("{:016X}", hasher.finish())
+    /// Serializes the store into `file` in `format`, encrypted with `key` if given.
+    fn write_keyed(
+        &self,
+        format: StoreFormat,
+        file: fs::File,
+        key: Option<&StoreKey>,
+    ) -> Result<SaveReport, StoreError> {
+        match format {
+            StoreFormat::Bincode => encode(file, &bincode::serialize(&self)?, COMPRESS, key),
+            #[cfg(feature = "toml-store")]
+            StoreFormat::Toml => encode(file, toml::to_string(self)?.as_bytes(), false, key),
+            #[cfg(not(feature = "toml-store"))]
+            StoreFormat::Toml => Err(StoreError::TomlNotEnabled),
+        }
     }
 
-    /// Generates SHA-256 hash of the store file on disk.
+    /// Appends `check` to the store file at `path`, safe against other processes doing the same.
     ///
-    /// This calls `sha256sum` on the store file.
+    /// The directory of the store is locked exclusively with `flock`, the store is loaded,
+    /// written with the added check and unlocked again. Concurrent callers wait for the lock,
+    /// so no check is lost. A missing or empty file
+    /// is started as a new store.
     ///
-    /// # External Dependencies
+    /// Every call reads and writes the whole store, which gets slow for large stores. Loading the
+    /// store once and [saving](Store::save) a batch of checks is much cheaper, if only one
+    /// process writes the store, like the daemon does. The file is replaced atomically, like with
+    /// [save](Store::save), so readers never see a partially written store.
+    /// [Sharding](ShardBy) is not applied, the check always goes into the file at `path`.
     ///
-    /// Requires `sha256sum` command to be available in PATH.
+    /// # Errors
+    ///
+    /// Returns [StoreError] if the store can't be locked, read or written.
+    pub fn append_check_locked(path: &Path, check: impl Into<Check>) -> Result<(), StoreError> {
+        let _lock = Self::lock(path)?;
+
+        let (mut store, permissions) = match fs::metadata(path) {
+            Ok(meta) if meta.len() > 0 => (Self::load_from(path)?, Some(meta.permissions())),
+            Ok(meta) => (Self::new(), Some(meta.permissions())),
+            Err(err) if err.kind() == ErrorKind::NotFound => (Self::new(), None),
+            Err(err) => return Err(StoreError::from(err).in_file(StoreOperation::Save, path)),
+        };
+        store.add_check(check);
+        store.replace(path, permissions)?;
+        // dropping the lock unlocks the store
+        Ok(())
+    }
+
+    /// Locks the store file at `path` and its shards exclusively with `flock`, until the returned
+    /// lock is dropped.
+    ///
+    /// The directory of the store is locked, not the file: the file is replaced on every write,
+    /// so a lock on it would only hold the old file. [save](Store::save),
+    /// [append_check_locked](Store::append_check_locked) and [gc_locked](Store::gc_locked) take
+    /// this lock, so they don't overwrite each other.
     ///
     /// # Errors
     ///
-    /// Returns [StoreError] if:
-    /// - sha256sum command fails
-    /// - Output parsing fails
-    pub fn display_hash_of_file(&self) -> Result<String, StoreError> {
-        let out = Command::new("sha256sum").arg(Self::path()).output()?;
+    /// Returns [StoreError] if the directory can't be opened or locked.
+    fn lock(path: &Path) -> Result<Flock<fs::File>, StoreError> {
+        let dir = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let locked = fs::File::open(dir).and_then(|dir| {
+            Flock::lock(dir, FlockArg::LockExclusive)
+                .map_err(|(_, errno)| std::io::Error::from(errno))
+        });
+        locked.map_err(|e| StoreError::from(e).in_file(StoreOperation::Save, path))
+    }
+
+    /// Removes the checks the [Retention] does not keep at `now`, see
+    /// [gc](Store::gc), from the store file at `path` and its [shards](ShardBy), and returns how
+    /// many were removed.
+    ///
+    /// The directory of the store is locked while the files are loaded and written, like with
+    /// [append_check_locked](Store::append_check_locked). The limits apply to the checks of
+    /// all files together. Files without removed checks are not written, the others are
+    /// replaced with a temporary file that keeps their permissions.
+    ///
+    /// The daemon keeps its checks in memory and writes all of them on every save, so it has to
+    /// be stopped first, or the removed checks come back.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError::DoesNotExist] if there is no store file or shard, and [StoreError]
+    /// if a file can't be opened, locked, read or written.
+    pub fn gc_locked(
+        path: &Path,
+        retention: &Retention,
+        now: SystemTime,
+    ) -> Result<usize, StoreError> {
+        let _lock = Self::lock(path)?;
+        let files = Self::files_of(path)?;
+        if files.is_empty() {
+            return Err(StoreError::DoesNotExist);
+        }
+        let mut loaded = Vec::with_capacity(files.len());
+        for file_path in files {
+            let file = fs::File::open(&file_path)
+                .map_err(|e| StoreError::from(e).in_file(StoreOperation::Load, &file_path))?;
+            let permissions = file.metadata()?.permissions();
+            let store = Self::decode_as(StoreFormat::of(&file_path), file)
+                .map_err(|e| e.in_file(StoreOperation::Load, &file_path))?;
+            loaded.push((file_path, permissions, store));
+        }
+
+        let oldest = retention.oldest_kept(
+            loaded.iter().flat_map(|(_, _, store)| store.checks.iter()),
+            now,
+        );
+        let mut removed = 0;
+        for (file_path, permissions, mut store) in loaded {
+            let removed_here = store.retain(|check| (check.timestamp(), check.seq()) >= oldest);
+            if removed_here == 0 {
+                continue;
+            }
+            // a crash while writing leaves the old file, not a truncated one
+            store.replace(&file_path, Some(permissions))?;
+            removed += removed_here;
+        }
+        // dropping the lock unlocks the store
+        Ok(removed)
+    }
+
+    /// Adds a new check to the store.
+    pub fn add_check(&mut self, check: impl Into<Check>) {
+        let mut check = check.into();
+        check.set_seq(self.next_seq());
+        self.index.last_seq = check.seq();
+        self.index
+            .by_hash
+            .entry(check.hash_value())
+            .or_insert(self.checks.len());
+        self.index.typed(&check);
+        self.checks.push(check);
+    }
+
+    /// Returns the check with the [hash](Check::get_hash) `hash`, [None] if there is none.
+    ///
+    /// The checks are indexed by their hash, so this does not search the checks.
+    pub fn check_by_hash(&self, hash: &str) -> Option<&Check> {
+        let hash = u64::from_str_radix(hash, 16).ok()?;
+        self.index.by_hash.get(&hash).map(|idx| &self.checks[*idx])
+    }
+
+    /// Removes the check with the [hash](Check::get_hash) `hash` and returns it, [None] if there
+    /// is none.
+    ///
+    /// The check is found with the index, but the checks after it are moved to keep their order,
+    /// which takes as long as going through them. The store has to be saved afterwards.
+    pub fn remove_check(&mut self, hash: &str) -> Option<Check> {
+        let hash = u64::from_str_radix(hash, 16).ok()?;
+        let removed = self.index.by_hash.remove(&hash)?;
+        for idx in self.index.by_hash.values_mut() {
+            if *idx > removed {
+                *idx -= 1;
+            }
+        }
+        Some(self.checks.remove(removed))
+    }
+
+    /// Keeps only the checks for which `keep` returns true, and returns how many were removed.
+    ///
+    /// The order of the kept checks stays the same. The store has to be saved afterwards.
+    pub fn retain(&mut self, keep: impl FnMut(&Check) -> bool) -> usize {
+        let before = self.checks.len();
+        self.checks.retain(keep);
+        self.checks.shrink_to_fit();
+        self.index.reindex(&self.checks);
+        before - self.checks.len()
+    }
+
+    /// Removes the checks that `retention` does not keep at `now`, and returns how many were
+    /// removed.
+    ///
+    /// Checks older than [max_age](Retention::max_age) are removed, then the oldest checks
+    /// until at most [max_count](Retention::max_count) are left. The
+    /// [aggregates](Store::aggregates) of downsampled checks are kept. The store has to be
+    /// saved afterwards, see [gc_locked](Store::gc_locked) to do this on the store file.
+    pub fn gc(&mut self, retention: &Retention, now: SystemTime) -> usize {
+        let oldest = retention.oldest_kept(self.checks.iter(), now);
+        self.retain(|check| (check.timestamp(), check.seq()) >= oldest)
+    }
+
+    /// Adds a new check to the store, unless it duplicates a recent one, and returns whether it
+    /// was added.
+    ///
+    /// With [dedup_window_secs](crate::config::StoreConfig::dedup_window_secs) set, a check
+    /// is skipped if a [duplicate](Check::is_duplicate_of) was made at most that many seconds
+    /// before it. Otherwise, this is just [add_check](Store::add_check).
+    pub fn add_check_with(&mut self, check: impl Into<Check>, config: &Config) -> bool {
+        let check = check.into();
+        if let Some(window) = config.store.dedup_window_secs {
+            let duplicate = self
+                .checks
+                .iter()
+                .rev()
+                .take_while(|old| old.timestamp().saturating_add(window) >= check.timestamp())
+                .any(|old| old.is_duplicate_of(&check));
+            if duplicate {
+                return false;
+            }
+        }
+        self.add_check(check);
+        true
+    }
+
+    /// Returns the [sequence number](Check::seq) for the next check added to this [`Store`].
+    fn next_seq(&self) -> u64 {
+        self.last_seq() + 1
+    }
+
+    /// Appends checks from JSON Lines, one serialized [Check] per line.
+    ///
+    /// Meant for backfilling the store with data from other tools. Every [Check] is
+    /// [validated](Check::validate). Lines that are not valid JSON or contain an invalid [Check]
+    /// are skipped with a warning on stderr and counted in [ImportStats::skipped]. Empty lines
+    /// are ignored.
+    ///
+    /// The store is not saved, use [save](Store::save) afterwards. To skip checks that are already
+    /// in the store, use [import_jsonl_with](Store::import_jsonl_with).
+    ///
+    /// # Example line
+    ///
+    /// ```json
+    /// {"timestamp":1733700000,"flags":4353,"latency":12,"target":"1.1.1.1"}
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError::Io] if reading from `reader` fails. Checks imported up to that
+    /// point stay in the store.
+    pub fn import_jsonl(&mut self, reader: impl BufRead) -> Result<ImportStats, StoreError> {
+        self.import_jsonl_with(reader, &Config::default())
+    }
+
+    /// Like [import_jsonl](Store::import_jsonl), but checks are added with
+    /// [add_check_with](Store::add_check_with), so duplicates can be skipped.
+    ///
+    /// # Errors
+    ///
+    /// See [import_jsonl](Store::import_jsonl).
+    pub fn import_jsonl_with(
+        &mut self,
+        reader: impl BufRead,
+        config: &Config,
+    ) -> Result<ImportStats, StoreError> {
+        let mut stats = ImportStats::default();
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let check = match serde_json::from_str::<Check>(&line) {
+                Ok(check) => check,
+                Err(err) => {
+                    eprintln!("skipping line {}: malformed check: {err}", idx + 1);
+                    stats.skipped += 1;
+                    continue;
+                }
+            };
+            if let Err(err) = check.validate() {
+                eprintln!("skipping line {}: invalid check: {err}", idx + 1);
+                stats.skipped += 1;
+                continue;
+            }
+            if self.add_check_with(check, config) {
+                stats.imported += 1;
+            } else {
+                stats.duplicates += 1;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Returns a reference to the checks of this [`Store`].
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    /// Returns up to `limit` checks of this [`Store`], starting with the check at `offset`.
+    ///
+    /// Meant for showing the checks page by page. The page is clamped to the checks, so an
+    /// `offset` past the end returns an empty page instead of panicking.
+    pub fn checks_page(&self, offset: usize, limit: usize) -> &[Check] {
+        let start = offset.min(self.checks.len());
+        let end = start.saturating_add(limit).min(self.checks.len());
+        &self.checks[start..end]
+    }
+
+    /// Returns the last `amount` checks of this [`Store`], in the order they were added.
+    ///
+    /// A [page](Store::checks_page) at the end of the checks, so all checks are returned if there
+    /// are fewer than `amount`.
+    pub fn tail(&self, amount: usize) -> &[Check] {
+        self.checks_page(self.checks.len().saturating_sub(amount), amount)
+    }
 
-        if !out.status.success() {
-            eprintln!(
-                "error while making the hash over the store file:\nStdout\n{:?}\n\nStdin\n{:?}",
-                out.stdout, out.stderr
+    /// Returns the amount of checks in this [`Store`].
+    pub fn len(&self) -> usize {
+        self.checks.len()
+    }
+
+    /// Returns whether this [`Store`] has no checks.
+    pub fn is_empty(&self) -> bool {
+        self.checks.is_empty()
+    }
+
+    /// Returns the time of the earliest check of this [`Store`], [None] if it has no checks.
+    ///
+    /// This is the earliest timestamp, not the timestamp of the first check in the store, which
+    /// can differ if the system clock was set back.
+    pub fn first_check_time(&self) -> Option<SystemTime> {
+        self.checks.iter().map(Check::timestamp_parsed).min()
+    }
+
+    /// Returns the time of the latest check of this [`Store`], [None] if it has no checks.
+    ///
+    /// See [first_check_time](Store::first_check_time).
+    pub fn last_check_time(&self) -> Option<SystemTime> {
+        self.checks.iter().map(Check::timestamp_parsed).max()
+    }
+
+    /// Returns the [aggregates](Aggregate) of the [downsampled](Store::downsample) checks.
+    pub fn aggregates(&self) -> &[Aggregate] {
+        &self.aggregates
+    }
+
+    /// Returns the highest [sequence number](Check::seq) of the checks, including the
+    /// downsampled and removed ones, or 0 for an empty store.
+    ///
+    /// Kept up to date when checks are added, so it is only computed once when loading.
+    fn last_seq(&self) -> u64 {
+        self.index.last_seq
+    }
+
+    /// Replaces the checks made before `older_than` with one [Aggregate] per `bucket`, check type
+    /// and target, and returns how many checks were replaced.
+    ///
+    /// Only complete buckets are downsampled: `older_than` is rounded down to a multiple of
+    /// `bucket` since the unix epoch. A bucket that already has an aggregate, because some of its
+    /// checks were added late, is merged into it. The store has to be saved afterwards.
+    pub fn downsample(&mut self, older_than: SystemTime, bucket: Duration) -> usize {
+        let length = bucket.as_secs().max(1);
+        let cutoff = older_than
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let cutoff = cutoff - cutoff % length;
+
+        let (old, kept): (Vec<Check>, Vec<Check>) = std::mem::take(&mut self.checks)
+            .into_iter()
+            .partition(|check| check.timestamp() < cutoff);
+        self.index.reindex(&kept);
+        self.checks = kept;
+
+        // start of the bucket, type and target
+        type Key = (u64, CheckType, IpAddr);
+        let mut groups: Vec<(Key, Vec<&Check>)> = Vec::new();
+        let mut index: HashMap<Key, usize> = HashMap::new();
+        for check in &old {
+            let key = (
+                check.timestamp() - check.timestamp() % length,
+                check.calc_type().unwrap_or(CheckType::Unknown),
+                check.target(),
             );
-            return Err(StoreError::ProcessEndedWithoutSuccess);
+            let idx = *index.entry(key).or_insert_with(|| {
+                groups.push((key, Vec::new()));
+                groups.len() - 1
+            });
+            groups[idx].1.push(check);
         }
 
-        Ok(std::str::from_utf8(&out.stdout)?
-            .split(" ")
-            .collect::<Vec<&str>>()[0]
-            .to_string())
+        for ((start, check_type, target), checks) in groups {
+            let aggregate = Aggregate::of(
+                UNIX_EPOCH + Duration::from_secs(start),
+                Duration::from_secs(length),
+                check_type,
+                target,
+                &checks,
+            );
+            match self
+                .aggregates
+                .iter_mut()
+                .find(|other| other.same_bucket(&aggregate))
+            {
+                Some(existing) => existing.merge(&aggregate),
+                None => self.aggregates.push(aggregate),
+            }
+        }
+        self.aggregates.sort_by_key(Aggregate::start);
+        old.len()
     }
 
-    /// Creates and adds checks for all configured targets.
+    /// Saves this [`Store`] with [save](Store::save), or split into shards with
+    /// [save_sharded](Store::save_sharded) if the store is
+    /// [sharded](crate::config::StoreConfig::shard_by).
     ///
-    /// Iterates through [CheckType::default_enabled] and [TARGETS] and creates a [Checks](Check).
+    /// `changed` are the checks that were added since the last save.
     ///
-    /// Only HTTP checks are done for now, as ICMP needs `CAP_NET_RAW` and DNS is not yet
-    /// implemented.
-    pub fn make_checks(&mut self) -> Vec<&Check> {
-        let last_old = self
-            .checks
+    /// # Errors
+    ///
+    /// See [save](Store::save) and [save_sharded](Store::save_sharded).
+    pub fn save_with(&self, config: &Config, changed: &[&Check]) -> Result<(), StoreError> {
+        match &config.store.shard_by {
+            ShardBy::None => self.save().map(|_| ()),
+            shard_by => self.save_sharded(shard_by, changed),
+        }
+    }
+
+    /// Saves the checks of this [`Store`] split into shards, see
+    /// [save_sharded_to](Store::save_sharded_to).
+    ///
+    /// # Errors
+    ///
+    /// See [save_sharded_to](Store::save_sharded_to).
+    pub fn save_sharded(&self, shard_by: &ShardBy, changed: &[&Check]) -> Result<(), StoreError> {
+        self.save_sharded_to(&Self::path(), shard_by, changed)
+    }
+
+    /// Saves the checks of this [`Store`] split into shards next to the store file at `path`.
+    ///
+    /// Only the files of the shards that `changed` checks belong to are written, the other
+    /// shards keep their files. Checks that belong to no shard go to the store file itself, as
+    /// do the [aggregates](Store::aggregates). Each file is replaced atomically, like a
+    /// [snapshot](Store::snapshot).
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError] if a file can't be written.
+    pub fn save_sharded_to(
+        &self,
+        path: &Path,
+        shard_by: &ShardBy,
+        changed: &[&Check],
+    ) -> Result<(), StoreError> {
+        let _lock = Self::lock(path)?;
+        let shards: BTreeSet<Option<String>> = changed
             .iter()
-            .enumerate()
-            .last()
-            .map(|a| a.0)
-            .unwrap_or(0);
+            .map(|check| shard_by.shard_of(check))
+            .collect();
+        for shard in shards {
+            let file = match &shard {
+                Some(name) => Self::shard_path(path, name),
+                None => path.to_path_buf(),
+            };
+            let mut part = self.filtered(|check| shard_by.shard_of(check) == shard);
+            if shard.is_none() {
+                part.aggregates.clone_from(&self.aggregates);
+            }
+            part.snapshot(&file)?;
+        }
+        self.synced.set(self.hash_value());
+        Ok(())
+    }
 
-        Self::primitive_make_checks(&mut self.checks);
+    /// Returns a new [`Store`] with only the checks of this one that match `predicate`.
+    ///
+    /// Meant for narrowing down reports. The new store has no file of its own, and no
+    /// [aggregates](Store::aggregates).
+    pub fn filtered(&self, predicate: impl Fn(&Check) -> bool) -> Self {
+        Self::of(
+            self.checks
+                .iter()
+                .filter(|c| predicate(c))
+                .cloned()
+                .collect(),
+            Vec::new(),
+        )
+    }
 
-        let mut made_checks = Vec::new();
-        for new_check in self.checks.iter().skip(last_old) {
-            made_checks.push(new_check);
+    /// Counts the checks of this [`Store`] in a single pass.
+    ///
+    /// This is much cheaper than a full [analysis](crate::analyze::analyze) when only the
+    /// amounts are of interest.
+    pub fn stats(&self) -> StoreStats {
+        let mut stats = StoreStats {
+            total: self.checks.len(),
+            ..Default::default()
+        };
+        for check in &self.checks {
+            if check.is_success() {
+                stats.ok += 1;
+            }
+            *stats
+                .by_type
+                .entry(check.calc_type().unwrap_or(CheckType::Unknown))
+                .or_default() += 1;
         }
+        stats.bad = stats.total - stats.ok;
+        stats
+    }
+
+    /// Returns the check interval in seconds.
+    ///
+    /// This determines how frequently the daemon performs checks.
+    /// Currently fixed at [PERIOD_SECS].
+    pub const fn period_seconds(&self) -> u64 {
+        PERIOD_SECS
+    }
+
+    /// Generates a hash of the in-memory store data.
+    ///
+    /// Uses [DefaultHasher](std::hash::DefaultHasher) to create a 16-character hexadecimal hash
+    /// of the entire store contents. Useful for detecting changes, see
+    /// [hash_matches_file](Store::hash_matches_file).
+    pub fn display_hash(&self) -> String {
+        format!("{:016X}", self.hash_value())
+    }
+
+    /// Returns the hash of [display_hash](Store::display_hash) as a number.
+    fn hash_value(&self) -> u64 {
+        let mut hasher = std::hash::DefaultHasher::default();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Generates SHA-256 hash of the store file on disk.
+    ///
+    /// The hash is in lowercase hex, like that of `sha256sum`. It identifies the file, but can't
+    /// be compared to [display_hash](Store::display_hash), use
+    /// [hash_matches_file](Store::hash_matches_file) for that.
+    ///
+    /// # Errors
+    ///
+    /// Returns [StoreError] if the file can't be read.
+    pub fn display_hash_of_file(&self) -> Result<String, StoreError> {
+        self.display_hash_of_file_at(&Self::path())
+    }
+
+    /// Generates SHA-256 hash of the store file at `path`, see
+    /// [display_hash_of_file](Store::display_hash_of_file).
+    ///
+    /// # Errors
+    ///
+    /// See [display_hash_of_file](Store::display_hash_of_file).
+    pub fn display_hash_of_file_at(&self, path: &Path) -> Result<String, StoreError> {
+        let mut hasher = Sha256::new();
+        fs::File::open(path)
+            .and_then(|mut file| std::io::copy(&mut file, &mut hasher))
+            .map_err(|e| StoreError::from(e).in_file(StoreOperation::Load, path))?;
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Returns whether this store has the same content as the store file, see
+    /// [hash_matches_file_at](Store::hash_matches_file_at).
+    ///
+    /// # Errors
+    ///
+    /// See [hash_matches_file_at](Store::hash_matches_file_at).
+    pub fn hash_matches_file(&self) -> Result<bool, StoreError> {
+        self.hash_matches_file_at(&Self::path())
+    }
+
+    /// Returns whether this store has the same content as the store file at `path` and its
+    /// [shards](ShardBy).
+    ///
+    /// The files are loaded and their [display_hash](Store::display_hash) is compared to the one
+    /// of this store. A mismatch means that this store has changes that were not saved, or that
+    /// the file was changed by someone else.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [load_all_from](Store::load_all_from).
+    pub fn hash_matches_file_at(&self, path: &Path) -> Result<bool, StoreError> {
+        Ok(Self::load_all_from(path)?.display_hash() == self.display_hash())
+    }
 
-        made_checks
+    /// Returns whether this store has the same content as when it was last loaded from or saved
+    /// to its file.
+    ///
+    /// Unlike [hash_matches_file](Store::hash_matches_file), nothing is read, so changes made to
+    /// the file by someone else are not noticed. Returns [None] for stores that were never
+    /// loaded or saved, like the [filtered](Store::filtered) ones of a report.
+    pub fn in_sync(&self) -> Option<bool> {
+        self.synced.get().map(|hash| hash == self.hash_value())
     }
 
     /// Creates and adds checks for all configured targets.
     ///
-    /// Iterates through [CheckType::default_enabled] and [TARGETS] and creates a [Checks](Check).
-    pub fn primitive_make_checks(buf: &mut Vec<Check>) {
-        for check_type in CheckType::default_enabled() {
-            if [CheckType::IcmpV4, CheckType::IcmpV6].contains(check_type) && !has_cap_net_raw() {
-                eprintln!("Does not have CAP_NET_RAW, can't use {check_type}, skipping");
-                continue;
+    /// Iterates through [CheckType::default_enabled] and [TARGETS](crate::records::TARGETS) and the
+    /// [hosts](Config::hosts) and creates a [Checks](Check).
+    ///
+    /// Only HTTP checks are done for now, as ICMP needs `CAP_NET_RAW` and DNS is not yet
+    /// implemented.
+    pub fn make_checks(&mut self, config: &Config) -> Vec<&Check> {
+        self.make_checks_with(config, &mut Backoff::new(&config.backoff))
+    }
+
+    /// Creates and adds checks for all configured targets that are not backed off.
+    ///
+    /// Like [make_checks](Store::make_checks), but targets that keep failing are checked less
+    /// often, as decided by `backoff`. The same [Backoff] should be used for every period.
+    ///
+    /// A [throughput check](CheckType::Throughput) is made too if it is
+    /// [due](Store::throughput_due).
+    pub fn make_checks_with(&mut self, config: &Config, backoff: &mut Backoff) -> Vec<&Check> {
+        self.make_checks_saving(config, backoff, &mut AutoSave::disabled())
+    }
+
+    /// Creates and adds checks like [make_checks_with](Store::make_checks_with), and saves the
+    /// store while they are made when `autosave` says so.
+    ///
+    /// Each check is added as soon as it is made. The store is not saved at the end, that is
+    /// left to the caller, who should then tell `autosave` that it was [saved](AutoSave::saved).
+    /// Errors while saving are logged, the checks stay in the store and are saved later.
+    pub fn make_checks_saving(
+        &mut self,
+        config: &Config,
+        backoff: &mut Backoff,
+        autosave: &mut AutoSave,
+    ) -> Vec<&Check> {
+        let last_old = self.checks.len();
+        let now = SystemTime::now();
+        let throughput_due = self.throughput_due(config, now);
+        let check_types: Vec<CheckType> = run::usable_check_types(CheckType::default_enabled())
+            .into_iter()
+            .filter(|check_type| self.check_type_due(*check_type, config, now))
+            .collect();
+
+        let mut unsaved = last_old;
+        let mut add = |check: Check| {
+            if !self.add_check_with(check, config) || !autosave.added(Instant::now()) {
+                return;
             }
-            for target in TARGETS {
-                let check = check_type.make(
-                    std::net::IpAddr::from_str(target)
-                        .expect("a target constant was not an Ip Address"),
-                );
-                buf.push(check);
+            let changed: Vec<&Check> = self.checks[unsaved..].iter().collect();
+            match self.save_with(config, &changed) {
+                Ok(()) => {
+                    unsaved = self.checks.len();
+                    autosave.saved(Instant::now());
+                }
+                Err(err) => crate::logging::error(format!("could not autosave the store: {err}")),
             }
+        };
+        run::run_each_with(
+            &run::targets(config),
+            &check_types,
+            config,
+            backoff,
+            |check_type, ip| check_type.make_with(ip, config),
+            &mut add,
+        );
+        if throughput_due {
+            if let Some(check) = run::run_throughput(config) {
+                add(check);
+            }
+        }
+
+        self.checks[last_old..].iter().collect()
+    }
+
+    /// Returns whether a [throughput check](CheckType::Throughput) should be made at `now`.
+    ///
+    /// That is if a [URL](crate::config::ThroughputConfig::url) is configured and the last
+    /// throughput check is at least [interval_secs](crate::config::ThroughputConfig::interval_secs)
+    /// old.
+    pub fn throughput_due(&self, config: &Config, now: SystemTime) -> bool {
+        if config.throughput.url.is_none() {
+            return false;
+        }
+        let interval = Duration::from_secs(config.throughput.interval_secs);
+        self.last_check_of_type(CheckType::Throughput)
+            .is_none_or(|last| now.duration_since(last).is_ok_and(|age| age >= interval))
+    }
+
+    /// Returns whether checks of `check_type` should be made at `now`, by its
+    /// [interval](Config::check_settings).
+    ///
+    /// That is if the [last check](Store::last_check_of_type) of that type is at least the
+    /// interval old. The cycles of the daemon don't start on the second, so half a
+    /// [period](PERIOD_SECS) of slack is allowed.
+    pub fn check_type_due(&self, check_type: CheckType, config: &Config, now: SystemTime) -> bool {
+        let interval = config.check_settings(check_type).interval;
+        if interval <= Duration::from_secs(PERIOD_SECS) {
+            return true;
+        }
+        let slack = Duration::from_secs(PERIOD_SECS) / 2;
+        self.last_check_of_type(check_type).is_none_or(|last| {
+            now.duration_since(last)
+                .is_ok_and(|age| age + slack >= interval)
+        })
+    }
+
+    /// Returns the time of the check of `check_type` that was added last, [None] if there is
+    /// none.
+    ///
+    /// The checks are indexed by their type, so this does not search the checks.
+    pub fn last_check_of_type(&self, check_type: CheckType) -> Option<SystemTime> {
+        self.index.last_of_type.get(&check_type).copied()
+    }
+
+    /// Creates and adds checks for all configured targets.
+    ///
+    /// Iterates through [CheckType::default_enabled] and [TARGETS](crate::records::TARGETS) and
+    /// creates a [Checks](Check).
+    pub fn primitive_make_checks(buf: &mut Vec<Check>, config: &Config) {
+        Self::primitive_make_checks_of(buf, CheckType::default_enabled(), config)
+    }
+
+    /// Creates and adds checks of the given types for all configured targets.
+    ///
+    /// Like [primitive_make_checks](Store::primitive_make_checks), but only for `check_types`.
+    pub fn primitive_make_checks_of(
+        buf: &mut Vec<Check>,
+        check_types: &[CheckType],
+        config: &Config,
+    ) {
+        Self::make_checks_into(buf, check_types, config, &mut Backoff::new(&config.backoff))
+    }
+
+    /// Checks a single target once with each of `check_types`, without a store or daemon.
+    ///
+    /// `target` can be an IP address or a hostname. Hostnames are resolved for each check type,
+    /// see [CheckType::resolve], and the checks get a [CheckDetail::Resolved]. ICMP checks are
+    /// skipped without `CAP_NET_RAW`.
+    ///
+    /// Returns the result for each check type, or the error if `target` could not be resolved
+    /// for it.
+    pub fn probe(
+        target: &str,
+        check_types: &[CheckType],
+        config: &Config,
+    ) -> Vec<Result<Check, CheckError>> {
+        let check_types = run::usable_check_types(check_types);
+        Self::probe_with(target, &check_types, |check_type, ip| {
+            check_type.make_with(ip, config)
+        })
+    }
+
+    /// Like [probe](Store::probe), but the checks are made by `make`.
+    fn probe_with(
+        target: &str,
+        check_types: &[CheckType],
+        mut make: impl FnMut(CheckType, IpAddr) -> Check,
+    ) -> Vec<Result<Check, CheckError>> {
+        let is_host = target.parse::<IpAddr>().is_err();
+        check_types
+            .iter()
+            .map(|check_type| {
+                let ip = check_type.resolve(target)?;
+                let mut check = make(*check_type, ip);
+                if is_host {
+                    check.add_detail(CheckDetail::Resolved {
+                        host: target.to_string(),
+                    });
+                }
+                Ok(check)
+            })
+            .collect()
+    }
+
+    /// Creates checks of `check_types` for all targets that `backoff` considers due.
+    fn make_checks_into(
+        buf: &mut Vec<Check>,
+        check_types: &[CheckType],
+        config: &Config,
+        backoff: &mut Backoff,
+    ) {
+        buf.extend(run::run_once_with(
+            &run::targets(config),
+            &run::usable_check_types(check_types),
+            config,
+            backoff,
+            |check_type, ip| check_type.make_with(ip, config),
+        ));
+    }
+}
+
+/// Decides when the store is saved while a check cycle is running.
+///
+/// A long cycle would lose all of its checks if the daemon crashed before saving at its end.
+/// With [autosave_secs](StoreConfig::autosave_secs) or
+/// [autosave_checks](StoreConfig::autosave_checks), the new checks are saved when either
+/// threshold is reached, see [make_checks_saving](Store::make_checks_saving).
+#[derive(Debug, Clone)]
+pub struct AutoSave {
+    interval: Option<Duration>,
+    checks: Option<usize>,
+    last_save: Instant,
+    /// Checks added since the last save
+    unsaved: usize,
+}
+
+impl AutoSave {
+    /// Creates the save policy of `config`, counting the time from `now`.
+    pub fn new(config: &StoreConfig, now: Instant) -> Self {
+        Self {
+            interval: config.autosave_secs.map(Duration::from_secs),
+            checks: config.autosave_checks,
+            last_save: now,
+            unsaved: 0,
         }
     }
+
+    /// Creates a policy that never saves.
+    pub fn disabled() -> Self {
+        Self::new(&StoreConfig::default(), Instant::now())
+    }
+
+    /// Notes that a check was added at `now` and returns whether the store should be saved.
+    pub fn added(&mut self, now: Instant) -> bool {
+        self.unsaved += 1;
+        self.checks.is_some_and(|checks| self.unsaved >= checks)
+            || self
+                .interval
+                .is_some_and(|interval| now.saturating_duration_since(self.last_save) >= interval)
+    }
+
+    /// Notes that the store was saved at `now`.
+    pub fn saved(&mut self, now: Instant) {
+        self.unsaved = 0;
+        self.last_save = now;
+    }
 }
 
-fn has_cap_net_raw() -> bool {
-    // First check if we're root (which implies all capabilities)
-    if nix::unistd::getuid().is_root() {
-        return true;
+/// Writes the serialized store `data` into `file`, compressed with zstd if `compress` is set
+/// and then encrypted if a `key` is given.
+fn encode(
+    file: fs::File,
+    data: &[u8],
+    compress: bool,
+    key: Option<&StoreKey>,
+) -> Result<SaveReport, StoreError> {
+    let mut file = Digesting::new(file);
+    match key {
+        None => compress_into(&mut file, data, compress)?,
+        Some(key) => {
+            let mut plain = Vec::new();
+            compress_into(&mut plain, data, compress)?;
+            file.write_all(&crypt::seal(key, &plain)?)?;
+        }
     }
+    file.flush()?;
+    // the data has to be on the disk before the file replaces the store
+    file.inner.sync_all()?;
+    Ok(file.report())
+}
 
-    // Check current process capabilities
-    if let Ok(caps) = caps::read(None, caps::CapSet::Effective) {
-        caps.contains(&caps::Capability::CAP_NET_RAW)
+/// Writes `data` into `writer`, compressed with zstd if `compress` is set.
+fn compress_into(writer: &mut impl Write, data: &[u8], compress: bool) -> std::io::Result<()> {
+    if compress {
+        let mut encoder = zstd::Encoder::new(writer, ZSTD_COMPRESSION_LEVEL)?;
+        encoder.write_all(data)?;
+        encoder.finish()?;
     } else {
-        eprintln!("Could not read capabilities");
-        false
+        writer.write_all(data)?;
+    }
+    Ok(())
+}
+
+/// Counts and hashes what is written to the inner writer, for a [SaveReport].
+struct Digesting<W> {
+    inner: W,
+    bytes: u64,
+    hasher: Sha256,
+}
+
+impl<W> Digesting<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            bytes: 0,
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn report(self) -> SaveReport {
+        SaveReport {
+            bytes_written: self.bytes,
+            hash: format!("{:x}", self.hasher.finalize()),
+        }
+    }
+}
+
+impl<W: Write> Write for Digesting<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Layouts of older store versions, used to migrate them to the current [Version].
+mod legacy {
+    use std::net::IpAddr;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use flagset::FlagSet;
+    use serde::Deserialize;
+
+    use crate::records::{Check, CheckDetail, CheckFlag};
+
+    /// A [Check] in store version 1, before [sequence numbers](Check::seq)
+    #[derive(Deserialize)]
+    pub(super) struct CheckV1 {
+        timestamp: u64,
+        flags: FlagSet<CheckFlag>,
+        latency: Option<u16>,
+        target: IpAddr,
+        details: Vec<CheckDetail>,
+    }
+
+    impl From<CheckV1> for Check {
+        fn from(value: CheckV1) -> Self {
+            let mut check = Check::new(
+                UNIX_EPOCH + Duration::from_secs(value.timestamp),
+                value.flags,
+                value.latency,
+                value.target,
+            );
+            for detail in value.details {
+                check.add_detail(detail);
+            }
+            check
+        }
+    }
+
+    /// A [Check] in store version 0, before [CheckDetails](crate::records::CheckDetail)
+    #[derive(Deserialize)]
+    pub(super) struct CheckV0 {
+        timestamp: u64,
+        flags: FlagSet<CheckFlag>,
+        latency: Option<u16>,
+        target: IpAddr,
+    }
+
+    impl From<CheckV0> for Check {
+        fn from(value: CheckV0) -> Self {
+            Check::new(
+                UNIX_EPOCH + Duration::from_secs(value.timestamp),
+                value.flags,
+                value.latency,
+                value.target,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::records::{CheckDetail, CheckFlag};
+
+    use super::*;
+
+    #[test]
+    fn test_errors_name_path_and_operation() {
+        let dir = tempfile::tempdir().unwrap();
+        // a directory can't be written like a file, even by root
+        let err = Store::new().save_to(dir.path()).unwrap_err();
+        assert!(matches!(
+            err,
+            StoreError::File {
+                operation: StoreOperation::Save,
+                ..
+            }
+        ));
+        let message = err.to_string();
+        assert!(message.contains("save"));
+        assert!(message.contains(&dir.path().display().to_string()));
+
+        let err = Store::create_at(dir.path()).unwrap_err().to_string();
+        assert!(err.starts_with(&format!(
+            "Could not create the store at {}",
+            dir.path().display()
+        )));
+        assert!(matches!(
+            Store::load_from(&dir.path().join("missing.store")),
+            Err(StoreError::DoesNotExist)
+        ));
+    }
+
+    #[test]
+    fn test_save_replaces_the_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("netpulse.store");
+        assert!(matches!(
+            Store::new().save_to(&path),
+            Err(StoreError::DoesNotExist)
+        ));
+        Store::new().snapshot(&path).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let mut store = Store::new();
+        store.add_check(Check::new(
+            UNIX_EPOCH,
+            CheckFlag::TypeHTTP | CheckFlag::IPv4 | CheckFlag::Success,
+            Some(10),
+            "1.1.1.1".parse().unwrap(),
+        ));
+        store.save_to(&path).unwrap();
+        assert_eq!(Store::load_from(&path).unwrap().checks(), store.checks());
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        // nothing but the store is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_in_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("netpulse.store");
+        let check = |secs| {
+            Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                CheckFlag::TypeHTTP | CheckFlag::IPv4 | CheckFlag::Success,
+                Some(10),
+                "1.1.1.1".parse().unwrap(),
+            )
+        };
+
+        let mut store = Store::from_fixture(vec![check(0)]);
+        assert_eq!(store.in_sync(), None);
+        store.snapshot(&path).unwrap();
+        assert_eq!(store.in_sync(), None);
+
+        let mut loaded = Store::load_from(&path).unwrap();
+        assert_eq!(loaded.in_sync(), Some(true));
+        loaded.add_check(check(60));
+        assert_eq!(loaded.in_sync(), Some(false));
+        loaded.save_to(&path).unwrap();
+        assert_eq!(loaded.in_sync(), Some(true));
+        assert_eq!(Store::load_all_from(&path).unwrap().in_sync(), Some(true));
+
+        // derived stores have no file to be in sync with
+        assert_eq!(loaded.filtered(|_| true).in_sync(), None);
+        store.add_check(check(120));
+        store.save_to(&path).unwrap();
+        assert_eq!(store.in_sync(), Some(true));
+    }
+
+    #[cfg(feature = "toml-store")]
+    #[test]
+    fn test_toml_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("netpulse.toml");
+        assert_eq!(StoreFormat::of(&path), StoreFormat::Toml);
+        assert_eq!(Store::path_from(&path), path);
+        Store::create_at(&path).unwrap();
+
+        let mut store = Store::new();
+        let mut check = Check::new(
+            UNIX_EPOCH + Duration::from_secs(3600),
+            CheckFlag::TypeHTTP | CheckFlag::IPv6,
+            None,
+            "2606:4700:4700::1111".parse().unwrap(),
+        );
+        check.set_tag("site", "\"Büro\" = 'main'\nfloor ☃ #2");
+        check.add_detail(CheckDetail::Resolved {
+            host: "xn--bro-hoa.example".to_string(),
+        });
+        check.add_detail(CheckDetail::Captured {
+            data: b"curl: (7) \"refused\"\r\n\0\xff".to_vec(),
+        });
+        check.add_detail(CheckDetail::BodyMismatch);
+        store.add_check(check);
+        for minute in 0..3 {
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(minute * 60),
+                CheckFlag::TypeUdp | CheckFlag::IPv4 | CheckFlag::Success,
+                Some(12),
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+        store.downsample(
+            UNIX_EPOCH + Duration::from_secs(600),
+            Duration::from_secs(600),
+        );
+        store.save_to(&path).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(&format!("version = {}", Version::CURRENT.inner)));
+        assert!(text.contains("Büro"));
+        let loaded = Store::load_from(&path).unwrap();
+        assert_eq!(loaded, store);
+        assert_eq!(loaded.aggregates().len(), 1);
+        let hash = store.checks()[0].get_hash();
+        assert!(loaded.check_by_hash(&hash).is_some());
+
+        // an edited store is picked up, and broken edits are reported
+        fs::write(&path, text.replace("1.1.1.1", "9.9.9.9")).unwrap();
+        let edited = Store::load_from(&path).unwrap();
+        assert_eq!(edited.aggregates()[0].target().to_string(), "9.9.9.9");
+        fs::write(&path, "version = 3\nchecks = [").unwrap();
+        assert!(matches!(
+            Store::load_from(&path),
+            Err(StoreError::TomlLoad { .. })
+        ));
+    }
+
+    #[test]
+    fn test_gc_policies() {
+        let store_of = |minutes: &[u64]| {
+            let mut store = Store::new();
+            for minute in minutes {
+                store.add_check(Check::new(
+                    UNIX_EPOCH + Duration::from_secs(minute * 60),
+                    CheckFlag::TypeHTTP | CheckFlag::IPv4 | CheckFlag::Success,
+                    Some(10),
+                    "1.1.1.1".parse().unwrap(),
+                ));
+            }
+            store
+        };
+        let minutes = |store: &Store| -> Vec<u64> {
+            store.checks().iter().map(|c| c.timestamp() / 60).collect()
+        };
+        let now = UNIX_EPOCH + Duration::from_secs(10 * 60);
+        // added out of order, like after the clock was set back
+        let all = [0, 1, 2, 3, 4, 5, 6, 7, 9, 8];
+
+        let mut store = store_of(&all);
+        assert_eq!(store.gc(&Retention::default(), now), 0);
+        assert_eq!(store.len(), 10);
+
+        let age = Retention {
+            max_age: Some(Duration::from_secs(5 * 60)),
+            max_count: None,
+        };
+        assert_eq!(store.gc(&age, now), 5);
+        assert_eq!(minutes(&store), [5, 6, 7, 9, 8]);
+
+        let count = Retention {
+            max_age: None,
+            max_count: Some(3),
+        };
+        let mut store = store_of(&all);
+        assert_eq!(store.gc(&count, now), 7);
+        assert_eq!(minutes(&store), [7, 9, 8]);
+
+        // both limits, the stricter one wins
+        let both = Retention {
+            max_age: Some(Duration::from_secs(5 * 60)),
+            max_count: Some(8),
+        };
+        let mut store = store_of(&all);
+        assert_eq!(store.gc(&both, now), 5);
+        assert_eq!(minutes(&store), [5, 6, 7, 9, 8]);
+        let both = Retention {
+            max_age: Some(Duration::from_secs(5 * 60)),
+            max_count: Some(2),
+        };
+        assert_eq!(store.gc(&both, now), 3);
+        assert_eq!(minutes(&store), [9, 8]);
+        // the index is rebuilt
+        let hash = store.checks()[1].get_hash();
+        assert_eq!(store.check_by_hash(&hash), Some(&store.checks()[1]));
+
+        // checks of the same second are kept by their sequence number
+        let mut store = store_of(&[1, 1, 1, 1]);
+        let last = store.checks()[3].clone();
+        let one = Retention {
+            max_age: None,
+            max_count: Some(1),
+        };
+        assert_eq!(store.gc(&one, now), 3);
+        assert_eq!(store.checks(), [last]);
+        let none = Retention {
+            max_age: None,
+            max_count: Some(0),
+        };
+        assert_eq!(store.gc(&none, now), 1);
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_gc_locked_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DB_NAME);
+        assert!(matches!(
+            Store::gc_locked(&path, &Retention::default(), SystemTime::now()),
+            Err(StoreError::DoesNotExist)
+        ));
+
+        let mut store = Store::new();
+        for minute in 0..6 {
+            let flags = match minute % 2 {
+                0 => CheckFlag::TypeHTTP,
+                _ => CheckFlag::TypeUdp,
+            };
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(minute * 60),
+                flags | CheckFlag::IPv4 | CheckFlag::Success,
+                Some(10),
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+        let all: Vec<&Check> = store.checks().iter().collect();
+        Store::new().snapshot(&path).unwrap();
+        store.save_sharded_to(&path, &ShardBy::Type, &all).unwrap();
+
+        let retention = Retention {
+            max_age: Some(Duration::from_secs(10 * 60)),
+            max_count: Some(3),
+        };
+        let now = UNIX_EPOCH + Duration::from_secs(10 * 60);
+        assert_eq!(Store::gc_locked(&path, &retention, now).unwrap(), 3);
+        // the count applies to all shards together
+        let left = Store::load_all_from(&path).unwrap();
+        assert_eq!(left.checks(), &store.checks()[3..]);
+        assert_eq!(Store::gc_locked(&path, &retention, now).unwrap(), 0);
+        // the files are replaced through temporary files, none are left behind
+        let files: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert!(
+            files
+                .iter()
+                .all(|name| !name.to_string_lossy().ends_with(".tmp")),
+            "{files:?}"
+        );
+    }
+
+    #[test]
+    fn test_autosave_thresholds() {
+        let start = Instant::now();
+        let mut config = StoreConfig::default();
+        let mut never = AutoSave::new(&config, start);
+        assert!((0..1000).all(|_| !never.added(start + Duration::from_secs(86400))));
+
+        config.autosave_checks = Some(3);
+        let mut autosave = AutoSave::new(&config, start);
+        assert!(!autosave.added(start));
+        assert!(!autosave.added(start));
+        assert!(autosave.added(start));
+        autosave.saved(start);
+        assert!(!autosave.added(start));
+
+        config.autosave_checks = None;
+        config.autosave_secs = Some(60);
+        let mut autosave = AutoSave::new(&config, start);
+        assert!(!autosave.added(start + Duration::from_secs(59)));
+        assert!(autosave.added(start + Duration::from_secs(60)));
+        autosave.saved(start + Duration::from_secs(61));
+        assert!(!autosave.added(start + Duration::from_secs(120)));
+        assert!(autosave.added(start + Duration::from_secs(121)));
+
+        // whichever threshold is reached first
+        config.autosave_checks = Some(2);
+        let mut autosave = AutoSave::new(&config, start);
+        assert!(autosave.added(start + Duration::from_secs(60)));
+        autosave.saved(start + Duration::from_secs(60));
+        assert!(!autosave.added(start + Duration::from_secs(61)));
+        assert!(autosave.added(start + Duration::from_secs(62)));
+    }
+
+    #[test]
+    fn test_checks_page() {
+        let mut store = Store::new();
+        assert!(store.is_empty());
+        assert!(store.checks_page(0, 10).is_empty());
+        for secs in 0..5 {
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs * 60),
+                CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                None,
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+        assert_eq!(store.len(), 5);
+
+        assert_eq!(store.checks_page(1, 2), &store.checks()[1..3]);
+        // the last page is shorter
+        assert_eq!(store.checks_page(4, 2), &store.checks()[4..]);
+        assert_eq!(store.checks_page(0, usize::MAX), store.checks());
+        assert!(store.checks_page(5, 2).is_empty());
+        assert!(store.checks_page(usize::MAX, usize::MAX).is_empty());
+        assert!(store.checks_page(2, 0).is_empty());
+
+        assert_eq!(store.tail(2), &store.checks()[3..]);
+        assert_eq!(store.tail(5), store.checks());
+        // fewer checks than asked for
+        assert_eq!(store.tail(20), store.checks());
+        assert!(store.tail(0).is_empty());
+        assert!(Store::new().tail(20).is_empty());
+    }
+
+    #[test]
+    fn test_throughput_due() {
+        let mut config = Config::default();
+        let mut store = Store::new();
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        // without a URL, there are no throughput checks
+        assert!(!store.throughput_due(&config, at(0)));
+
+        config.throughput.url = Some("http://speed.example.com/10MB.bin".to_string());
+        config.throughput.interval_secs = 3600;
+        assert!(store.throughput_due(&config, at(0)));
+        store.add_check(Check::new(
+            at(600),
+            CheckFlag::TypeThroughput | CheckFlag::IPv4 | CheckFlag::Success,
+            Some(40),
+            "192.0.2.1".parse().unwrap(),
+        ));
+        // other checks don't count
+        store.add_check(Check::new(
+            at(3000),
+            CheckFlag::TypeHTTP | CheckFlag::IPv4 | CheckFlag::Success,
+            Some(10),
+            "1.1.1.1".parse().unwrap(),
+        ));
+        assert!(!store.throughput_due(&config, at(600 + 3599)));
+        assert!(store.throughput_due(&config, at(600 + 3600)));
+    }
+
+    #[test]
+    fn test_check_type_due() {
+        let mut config = Config::default();
+        let mut store = Store::new();
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        store.add_check(Check::new(
+            at(600),
+            CheckFlag::TypeUdp | CheckFlag::IPv4 | CheckFlag::Success,
+            Some(10),
+            "1.1.1.1".parse().unwrap(),
+        ));
+        // by default, every cycle checks every type
+        assert!(store.check_type_due(CheckType::Udp, &config, at(600)));
+
+        config.checks.udp.interval_secs = Some(300);
+        assert!(!store.check_type_due(CheckType::Udp, &config, at(600 + 240)));
+        // a cycle that starts a little early is still due
+        assert!(store.check_type_due(CheckType::Udp, &config, at(600 + 299)));
+        assert!(store.check_type_due(CheckType::Udp, &config, at(600 + 300)));
+        // never checked
+        assert!(store.check_type_due(CheckType::Http, &config, at(600)));
+
+        // the last check of each type is kept track of as checks come and go
+        assert_eq!(store.last_check_of_type(CheckType::Udp), Some(at(600)));
+        assert_eq!(store.last_check_of_type(CheckType::Http), None);
+        store.retain(|_| false);
+        assert_eq!(store.last_check_of_type(CheckType::Udp), None);
+        assert!(store.check_type_due(CheckType::Udp, &config, at(600 + 240)));
+    }
+
+    #[test]
+    fn test_check_by_hash() {
+        let mut store = Store::new();
+        for minute in 0..6 {
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(minute * 60),
+                CheckFlag::TypeHTTP | CheckFlag::IPv4 | CheckFlag::Success,
+                Some(10),
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+        let assert_indexed = |store: &Store| {
+            for check in store.checks() {
+                assert_eq!(store.check_by_hash(&check.get_hash()), Some(check));
+            }
+        };
+        assert_indexed(&store);
+        assert_eq!(store.check_by_hash("0000000000000000"), None);
+        assert_eq!(store.check_by_hash("not a hash"), None);
+
+        let removed = store.checks()[2].clone();
+        assert_eq!(
+            store.remove_check(&removed.get_hash()),
+            Some(removed.clone())
+        );
+        assert_eq!(store.len(), 5);
+        assert_eq!(store.check_by_hash(&removed.get_hash()), None);
+        assert_eq!(store.remove_check(&removed.get_hash()), None);
+        assert_indexed(&store);
+
+        // the first two minutes are compacted into an aggregate
+        let compacted = store.checks()[0].clone();
+        store.downsample(
+            UNIX_EPOCH + Duration::from_secs(120),
+            Duration::from_secs(120),
+        );
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.check_by_hash(&compacted.get_hash()), None);
+        assert_indexed(&store);
+
+        // adding after removal and compaction keeps the index in order
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(600),
+            CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            None,
+            "1.1.1.1".parse().unwrap(),
+        ));
+        assert_indexed(&store);
+
+        // the index is rebuilt on load
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("netpulse.store");
+        fs::File::create(&path).unwrap();
+        store.save_to(&path).unwrap();
+        let loaded = Store::load_from(&path).unwrap();
+        assert_eq!(loaded, store);
+        assert_indexed(&loaded);
+        assert_eq!(loaded.last_seq(), 7);
+        assert_indexed(&store.filtered(|c| c.is_success()));
+
+        // the sequence number of a removed check is not given out again
+        let newest = store.checks().last().unwrap().clone();
+        store.remove_check(&newest.get_hash()).unwrap();
+        assert_eq!(store.last_seq(), newest.seq());
+        assert_indexed(&store);
+    }
+
+    #[test]
+    fn test_load_from_reader() {
+        let mut store = Store::new();
+        store.add_check(Check::new(
+            UNIX_EPOCH,
+            CheckFlag::TypeHTTP | CheckFlag::IPv4 | CheckFlag::Success,
+            Some(12),
+            "1.1.1.1".parse().unwrap(),
+        ));
+        let raw = bincode::serialize(&store).unwrap();
+        let piped = Store::load_from_reader(std::io::Cursor::new(&raw)).unwrap();
+        assert_eq!(piped, store);
+        assert_eq!(
+            piped.check_by_hash(&store.checks()[0].get_hash()),
+            Some(&store.checks()[0])
+        );
+
+        let compressed = zstd::encode_all(raw.as_slice(), 0).unwrap();
+        assert_eq!(
+            Store::load_from_reader(compressed.as_slice()).unwrap(),
+            store
+        );
+        assert!(Store::load_from_reader(&raw[..3]).is_err());
+    }
+
+    #[test]
+    fn test_check_times() {
+        let mut store = Store::new();
+        assert_eq!(store.first_check_time(), None);
+        assert_eq!(store.last_check_time(), None);
+
+        // the clock was set back between the second and third check
+        for secs in [120, 180, 60] {
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                None,
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+        assert_eq!(
+            store.first_check_time(),
+            Some(UNIX_EPOCH + Duration::from_secs(60))
+        );
+        assert_eq!(
+            store.last_check_time(),
+            Some(UNIX_EPOCH + Duration::from_secs(180))
+        );
+    }
+
+    #[test]
+    fn test_sharding() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DB_NAME);
+        let mut store = Store::new();
+        for (secs, flags, region) in [
+            (0, CheckFlag::TypeHTTP | CheckFlag::IPv4, Some("eu")),
+            (60, CheckFlag::TypeIcmp | CheckFlag::IPv4, None),
+            (120, CheckFlag::TypeHTTP | CheckFlag::IPv4, Some("us")),
+            (180, CheckFlag::TypeUdp | CheckFlag::IPv4, Some("eu")),
+        ] {
+            let mut check = Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                flags,
+                None,
+                "1.1.1.1".parse().unwrap(),
+            );
+            if let Some(region) = region {
+                check.set_tag("region", region);
+            }
+            store.add_check(check);
+        }
+        let shard_len = |path: &Path, shard: &str| {
+            Store::load_from(&Store::shard_path(path, shard))
+                .unwrap()
+                .checks()
+                .len()
+        };
+
+        // only the shard of the changed check is written
+        let changed = [&store.checks()[0]];
+        store
+            .save_sharded_to(&path, &ShardBy::Type, &changed)
+            .unwrap();
+        assert_eq!(
+            Store::files_of(&path).unwrap(),
+            [dir.path().join("netpulse.http.store")]
+        );
+        assert_eq!(shard_len(&path, "http"), 2);
+
+        let all: Vec<&Check> = store.checks().iter().collect();
+        store.save_sharded_to(&path, &ShardBy::Type, &all).unwrap();
+        assert_eq!(shard_len(&path, "icmpv4"), 1);
+        assert_eq!(shard_len(&path, "udp"), 1);
+        assert_eq!(Store::load_all_from(&path).unwrap(), store);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DB_NAME);
+        let by_region = ShardBy::Tag("region".to_string());
+        store.save_sharded_to(&path, &by_region, &all).unwrap();
+        assert_eq!(shard_len(&path, "region-eu"), 2);
+        assert_eq!(shard_len(&path, "region-us"), 1);
+        // untagged checks are in the store file itself
+        assert_eq!(Store::load_from(&path).unwrap().checks().len(), 1);
+        assert_eq!(Store::load_all_from(&path).unwrap(), store);
+
+        assert!(matches!(
+            Store::load_all_from(&dir.path().join("other.store")),
+            Err(StoreError::DoesNotExist)
+        ));
+    }
+
+    #[test]
+    fn test_append_check_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DB_NAME);
+        let writers: Vec<_> = (0..8u64)
+            .map(|writer| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for n in 0..10 {
+                        let check = Check::new(
+                            UNIX_EPOCH + Duration::from_secs(writer * 100 + n),
+                            CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                            None,
+                            "1.1.1.1".parse().unwrap(),
+                        );
+                        Store::append_check_locked(&path, check).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let store = Store::load_from(&path).unwrap();
+        assert_eq!(store.checks().len(), 80);
+        let times: BTreeSet<u64> = store.checks().iter().map(Check::timestamp).collect();
+        assert_eq!(times.len(), 80);
+        let seqs: BTreeSet<u64> = store.checks().iter().map(Check::seq).collect();
+        assert_eq!(seqs, (1..=80).collect());
+    }
+
+    #[test]
+    fn test_path_from() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(Store::path_from(dir.path()), dir.path().join(DB_NAME));
+
+        let file = dir.path().join("data").join("custom.store");
+        assert_eq!(Store::path_from(&file), file);
+        Store::create_at(&file).unwrap();
+        assert!(Store::load_from(&file).unwrap().checks().is_empty());
+
+        // an existing file is used as is, whatever its name
+        let existing = dir.path().join("netpulse.db");
+        fs::write(&existing, "").unwrap();
+        assert_eq!(Store::path_from(&existing), existing);
+
+        // a file is in the way of the directory
+        let blocked = existing.join(DB_NAME);
+        let err = Store::create_at(&blocked).unwrap_err();
+        assert!(matches!(err, StoreError::CreateDir { ref path, .. } if *path == existing));
+        assert!(err.to_string().contains(&existing.display().to_string()));
+    }
+
+    #[test]
+    fn test_parse_shard_by() {
+        for shard_by in [ShardBy::None, ShardBy::Type, ShardBy::Tag("region".into())] {
+            assert_eq!(shard_by.to_string().parse::<ShardBy>().unwrap(), shard_by);
+        }
+        assert!("tag:".parse::<ShardBy>().is_err());
+        assert!("target".parse::<ShardBy>().is_err());
+    }
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(Store::new().stats(), StoreStats::default());
+
+        let mut store = Store::new();
+        for (flags, secs) in [
+            (
+                CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                0,
+            ),
+            (CheckFlag::TypeHTTP | CheckFlag::IPv4, 60),
+            (
+                CheckFlag::Success | CheckFlag::TypeIcmp | CheckFlag::IPv6,
+                120,
+            ),
+            (
+                CheckFlag::Success | CheckFlag::TypeIcmp | CheckFlag::IPv4,
+                180,
+            ),
+            // no type flag
+            (CheckFlag::IPv4.into(), 240),
+        ] {
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                flags,
+                None,
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+
+        let stats = store.stats();
+        assert_eq!((stats.total, stats.ok, stats.bad), (5, 3, 2));
+        assert_eq!(
+            stats.by_type,
+            HashMap::from([
+                (CheckType::Http, 2),
+                (CheckType::IcmpV6, 1),
+                (CheckType::IcmpV4, 1),
+                (CheckType::Unknown, 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_import_jsonl() {
+        let ok = Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            Some(12),
+            "1.1.1.1".parse().unwrap(),
+        );
+        let failed = Check::new(
+            UNIX_EPOCH + Duration::from_secs(120),
+            CheckFlag::TypeIcmp | CheckFlag::IPv6,
+            None,
+            "2606:4700:4700::1111".parse().unwrap(),
+        );
+        let input = format!(
+            "{}\n{{\"timestamp\": 180, \"flags\": \n\n{}\n{}\n",
+            serde_json::to_string(&ok).unwrap(),
+            serde_json::to_string(&failed).unwrap(),
+            // IPv4 flag with an IPv6 target
+            r#"{"timestamp":240,"flags":8448,"latency":null,"target":"::1"}"#,
+        );
+
+        let mut store = Store::new();
+        let stats = store.import_jsonl(input.as_bytes()).unwrap();
+        assert_eq!(
+            stats,
+            ImportStats {
+                imported: 2,
+                skipped: 2,
+                duplicates: 0,
+            }
+        );
+        let mut expected = [ok, failed];
+        expected[0].set_seq(1);
+        expected[1].set_seq(2);
+        assert_eq!(store.checks(), &expected);
+    }
+
+    #[test]
+    fn test_dedup_window() {
+        let check = |secs| {
+            Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                Some(12),
+                "1.1.1.1".parse().unwrap(),
+            )
+        };
+        let input: String = [check(60), check(120), check(60), check(120)]
+            .iter()
+            .map(|c| serde_json::to_string(c).unwrap() + "\n")
+            .collect();
+
+        // disabled by default, the duplicates are kept
+        let mut store = Store::new();
+        let stats = store.import_jsonl(input.as_bytes()).unwrap();
+        assert_eq!((stats.imported, stats.duplicates), (4, 0));
+        assert_eq!(store.checks().len(), 4);
+
+        let mut config = Config::default();
+        config.store.dedup_window_secs = Some(300);
+        let mut store = Store::new();
+        let stats = store.import_jsonl_with(input.as_bytes(), &config).unwrap();
+        assert_eq!((stats.imported, stats.duplicates), (2, 2));
+        assert_eq!(
+            stats.to_string(),
+            "imported 2 checks, skipped 0 lines and 2 duplicates"
+        );
+        let seqs: Vec<u64> = store.checks().iter().map(Check::seq).collect();
+        assert_eq!(seqs, [1, 2]);
+
+        // only exact duplicates are skipped
+        assert!(!store.add_check_with(check(120), &config));
+        let mut other = check(120);
+        other.add_detail(CheckDetail::Retried { retries: 1 });
+        assert!(store.add_check_with(other, &config));
+    }
+
+    #[test]
+    fn test_probe_with_mock_checks() {
+        let mock = |check_type: CheckType, ip: IpAddr| {
+            let mut check = Check::new(
+                UNIX_EPOCH + Duration::from_secs(60),
+                match check_type {
+                    CheckType::Http => CheckFlag::TypeHTTP | CheckFlag::Success,
+                    _ => CheckFlag::TypeUdp.into(),
+                },
+                Some(7),
+                ip,
+            );
+            check.add_flag(match ip {
+                IpAddr::V4(_) => CheckFlag::IPv4,
+                IpAddr::V6(_) => CheckFlag::IPv6,
+            });
+            check
+        };
+
+        let results = Store::probe_with("192.0.2.1", &[CheckType::Http, CheckType::Udp], mock);
+        let results: Vec<Check> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].calc_type().unwrap(), CheckType::Http);
+        assert!(results[0].is_success());
+        assert_eq!(results[1].calc_type().unwrap(), CheckType::Udp);
+        assert!(!results[1].is_success());
+        assert!(results
+            .iter()
+            .all(|c| c.target() == "192.0.2.1".parse::<IpAddr>().unwrap()));
+        assert!(results.iter().all(|c| c.details().is_empty()));
+
+        // hostnames are resolved and recorded, an ICMPv6 check needs an IPv6 address
+        let results = Store::probe_with("127.0.0.1", &[CheckType::IcmpV6], mock);
+        assert!(matches!(results[..], [Err(CheckError::NoAddress(_))]));
+        let results = Store::probe_with("localhost", &[CheckType::Http], mock);
+        let check = results[0].as_ref().unwrap();
+        assert!(check.target().is_loopback());
+        assert_eq!(
+            check.details(),
+            [CheckDetail::Resolved {
+                host: "localhost".to_string()
+            }]
+        );
+    }
+
+    /// Writes `store` to `path` like [Store::save] would, for stores of older versions.
+    fn write_store(path: &Path, store: &impl Serialize) {
+        let file = fs::File::create(path).unwrap();
+        encode(file, &bincode::serialize(store).unwrap(), COMPRESS, None).unwrap();
+    }
+
+    #[test]
+    fn test_save_report_matches_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new();
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            Some(12),
+            "1.1.1.1".parse().unwrap(),
+        ));
+        let mut paths = vec![dir.path().join(DB_NAME)];
+        if cfg!(feature = "toml-store") {
+            paths.push(dir.path().join("netpulse.toml"));
+        }
+        for path in paths {
+            Store::create_at(&path).unwrap();
+            let report = store.save_to(&path).unwrap();
+            assert_eq!(report.bytes_written, fs::metadata(&path).unwrap().len());
+            assert_eq!(report.hash, store.display_hash_of_file_at(&path).unwrap());
+            assert_eq!(report.hash.len(), 64);
+
+            // the same store in the same format makes the same file
+            let snapshot = path
+                .with_file_name("snapshot")
+                .with_extension(path.extension().unwrap());
+            assert_eq!(store.snapshot(&snapshot).unwrap().hash, report.hash);
+        }
+    }
+
+    #[test]
+    fn test_hash_matches_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DB_NAME);
+        let mut store = Store::new();
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            Some(12),
+            "1.1.1.1".parse().unwrap(),
+        ));
+        assert!(matches!(
+            store.hash_matches_file_at(&path),
+            Err(StoreError::DoesNotExist)
+        ));
+
+        store.snapshot(&path).unwrap();
+        assert!(store.hash_matches_file_at(&path).unwrap());
+
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(120),
+            CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            None,
+            "1.1.1.1".parse().unwrap(),
+        ));
+        assert!(!store.hash_matches_file_at(&path).unwrap());
+
+        store.snapshot(&path).unwrap();
+        assert!(store.hash_matches_file_at(&path).unwrap());
+    }
+
+    #[test]
+    fn test_load_either_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new();
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            Some(12),
+            "1.1.1.1".parse().unwrap(),
+        ));
+        let data = bincode::serialize(&store).unwrap();
+
+        for compress in [true, false] {
+            let path = dir.path().join(format!("compressed-{compress}.store"));
+            encode(fs::File::create(&path).unwrap(), &data, compress, None).unwrap();
+            let raw = fs::read(&path).unwrap();
+            assert_eq!(raw.starts_with(&ZSTD_MAGIC), compress);
+            assert_eq!(Store::load_from(&path).unwrap(), store);
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new();
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            Some(12),
+            "10.20.30.40".parse().unwrap(),
+        ));
+        let key = StoreKey::new([42; 32]);
+        let wrong = StoreKey::new([43; 32]);
+        let read = |path: &Path, key| {
+            Store::decode_keyed(StoreFormat::of(path), fs::File::open(path).unwrap(), key)
+        };
+
+        for name in ["netpulse.store", "netpulse.toml"] {
+            let plain_path = dir.path().join(format!("plain-{name}"));
+            store
+                .write_keyed(
+                    StoreFormat::of(&plain_path),
+                    fs::File::create(&plain_path).unwrap(),
+                    None,
+                )
+                .unwrap();
+            let plain = fs::read(&plain_path).unwrap();
+
+            let path = dir.path().join(name);
+            let report = store
+                .write_keyed(
+                    StoreFormat::of(&path),
+                    fs::File::create(&path).unwrap(),
+                    Some(&key),
+                )
+                .unwrap();
+            let raw = fs::read(&path).unwrap();
+            assert_eq!(report.bytes_written, raw.len() as u64);
+            assert!(crypt::is_encrypted(&raw));
+            // the ciphertext does not contain the unencrypted store
+            assert!(!raw.windows(plain.len()).any(|w| w == plain));
+            assert_eq!(read(&path, Some(&key)).unwrap(), store);
+
+            assert!(matches!(
+                read(&path, Some(&wrong)),
+                Err(StoreError::Decrypt)
+            ));
+            let err = read(&path, None).unwrap_err();
+            assert!(matches!(err, StoreError::KeyMissing));
+            assert!(err.to_string().contains(crypt::ENV_KEY));
+        }
+
+        // unencrypted stores are still read with a key
+        let path = dir.path().join("plain.store");
+        store
+            .write_keyed(StoreFormat::Bincode, fs::File::create(&path).unwrap(), None)
+            .unwrap();
+        assert_eq!(read(&path, Some(&key)).unwrap(), store);
+    }
+
+    #[test]
+    fn test_load_version_0() {
+        #[derive(Serialize)]
+        struct StoreV0 {
+            version: Version,
+            checks: Vec<(
+                u64,
+                flagset::FlagSet<CheckFlag>,
+                Option<u16>,
+                std::net::IpAddr,
+            )>,
+        }
+        let flags = CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4;
+        let target = "1.1.1.1".parse().unwrap();
+        let old = StoreV0 {
+            version: Version::new(0),
+            checks: vec![(60, flags, Some(12), target)],
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.store");
+        write_store(&path, &old);
+
+        let store = Store::load_from(&path).unwrap();
+        assert_eq!(store.version, Version::CURRENT);
+        let mut expected = Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            flags,
+            Some(12),
+            target,
+        );
+        expected.set_seq(1);
+        assert_eq!(store.checks(), &[expected]);
+    }
+
+    #[test]
+    fn test_load_version_1() {
+        type CheckV1 = (
+            u64,
+            flagset::FlagSet<CheckFlag>,
+            Option<u16>,
+            std::net::IpAddr,
+            Vec<CheckDetail>,
+        );
+        #[derive(Serialize)]
+        struct StoreV1 {
+            version: Version,
+            checks: Vec<CheckV1>,
+        }
+        let flags = CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4;
+        let target = "1.1.1.1".parse().unwrap();
+        let detail = CheckDetail::Http { status: 204 };
+        let old = StoreV1 {
+            version: Version::new(1),
+            // the clock went backwards between the checks
+            checks: vec![
+                (120, flags, Some(12), target, vec![detail.clone()]),
+                (60, flags, Some(14), target, Vec::new()),
+            ],
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.store");
+        write_store(&path, &old);
+
+        let store = Store::load_from(&path).unwrap();
+        assert_eq!(store.version, Version::CURRENT);
+        let seqs: Vec<u64> = store.checks().iter().map(|c| c.seq()).collect();
+        assert_eq!(seqs, [1, 2]);
+        assert_eq!(store.checks()[0].details(), [detail]);
+        assert_eq!(store.checks()[1].latency(), Some(14));
+    }
+
+    #[test]
+    fn test_seq_continues() {
+        let mut store = Store::new();
+        for secs in [60, 0] {
+            store.add_check(Check::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+                CheckFlag::TypeHTTP | CheckFlag::IPv4,
+                None,
+                "1.1.1.1".parse().unwrap(),
+            ));
+        }
+        let mut check = store.checks()[0].clone();
+        // the store assigns the number, not the caller
+        check.set_seq(100);
+        store.add_check(check);
+        let seqs: Vec<u64> = store.checks().iter().map(|c| c.seq()).collect();
+        assert_eq!(seqs, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_downsample_preserves_totals() {
+        let http = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+        let udp = CheckFlag::TypeUdp | CheckFlag::IPv4;
+        let mut store = Store::new();
+        // two hours of checks every minute, every third one failed
+        for minute in 0..120u64 {
+            for flags in [http, udp] {
+                let ok = minute % 3 != 0;
+                store.add_check(Check::new(
+                    UNIX_EPOCH + Duration::from_secs(minute * 60),
+                    if ok {
+                        flags | CheckFlag::Success
+                    } else {
+                        flags
+                    },
+                    ok.then_some(10 + minute as u16),
+                    "1.1.1.1".parse().unwrap(),
+                ));
+            }
+        }
+        let seqs: Vec<u64> = (1..=store.checks().len() as u64).collect();
+        for (check, seq) in store.checks.iter_mut().zip(seqs) {
+            check.set_seq(seq);
+        }
+        store.index = CheckIndex::of(&store.checks, &store.aggregates);
+        let totals = |store: &Store, check_type: CheckType| {
+            let raw = store
+                .checks()
+                .iter()
+                .filter(|c| c.calc_type().unwrap() == check_type);
+            let aggregated = store
+                .aggregates()
+                .iter()
+                .filter(|a| a.check_type() == check_type);
+            (
+                raw.clone().count() as u64 + aggregated.clone().map(Aggregate::count).sum::<u64>(),
+                raw.filter(|c| c.is_success()).count() as u64
+                    + aggregated.map(Aggregate::successes).sum::<u64>(),
+            )
+        };
+        let before = [
+            totals(&store, CheckType::Http),
+            totals(&store, CheckType::Udp),
+        ];
+
+        // the cutoff is rounded down to the full hour
+        let replaced = store.downsample(
+            UNIX_EPOCH + Duration::from_secs(90 * 60),
+            Duration::from_secs(3600),
+        );
+        assert_eq!(replaced, 120);
+        assert_eq!(store.checks().len(), 120);
+        assert_eq!(store.aggregates().len(), 2);
+        assert_eq!(
+            [
+                totals(&store, CheckType::Http),
+                totals(&store, CheckType::Udp)
+            ],
+            before
+        );
+
+        let first_hour = &store.aggregates()[0];
+        assert_eq!(first_hour.start(), UNIX_EPOCH);
+        assert_eq!(first_hour.length(), Duration::from_secs(3600));
+        assert_eq!((first_hour.count(), first_hour.successes()), (60, 40));
+        assert!((first_hour.success_ratio() - 2.0 / 3.0).abs() < f64::EPSILON);
+        assert!(first_hour.p95_latency().unwrap() >= 66);
+        assert_eq!(store.last_seq(), 240);
+
+        // downsampling the same hour again changes nothing
+        assert_eq!(
+            store.downsample(
+                UNIX_EPOCH + Duration::from_secs(3600),
+                Duration::from_secs(3600)
+            ),
+            0
+        );
+        assert_eq!(store.aggregates().len(), 2);
+
+        store.downsample(
+            UNIX_EPOCH + Duration::from_secs(7200),
+            Duration::from_secs(3600),
+        );
+        assert!(store.checks().is_empty());
+        // new checks continue after the downsampled ones
+        store.add_check(Check::new(
+            UNIX_EPOCH + Duration::from_secs(7200),
+            http,
+            None,
+            "1.1.1.1".parse().unwrap(),
+        ));
+        assert_eq!(store.checks()[0].seq(), 241);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DB_NAME);
+        store.snapshot(&path).unwrap();
+        assert_eq!(Store::load_from(&path).unwrap(), store);
+    }
+
+    #[test]
+    fn test_load_version_2() {
+        #[derive(Serialize)]
+        struct StoreV2 {
+            version: Version,
+            checks: Vec<Check>,
+        }
+        let mut check = Check::new(
+            UNIX_EPOCH + Duration::from_secs(60),
+            CheckFlag::Success | CheckFlag::TypeHTTP | CheckFlag::IPv4,
+            Some(12),
+            "1.1.1.1".parse().unwrap(),
+        );
+        check.set_seq(1);
+        let old = StoreV2 {
+            version: Version::new(2),
+            checks: vec![check.clone()],
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.store");
+        write_store(&path, &old);
+
+        let store = Store::load_from(&path).unwrap();
+        assert_eq!(store.checks(), [check]);
+        assert!(store.aggregates().is_empty());
     }
 }
@@ -0,0 +1,364 @@
+//! Outage events for the local syslog.
+//!
+//! For environments that aggregate logs centrally, the daemon can send an event to the syslog
+//! socket (usually `/dev/log`) when a target starts failing and when it recovers. This is
+//! configured in [SyslogConfig].
+//!
+//! - [OutageTracker] - turns the checks of each cycle into [OutageEvents](OutageEvent), only
+//!   reporting changes, so that an ongoing outage is not logged again every cycle
+//! - [Syslog] - formats the events as [RFC 3164 or RFC 5424](SyslogFormat) messages and sends
+//!   them to the socket
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io;
+use std::net::IpAddr;
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::SyslogConfig;
+use crate::records::{Check, CheckType};
+use crate::timezone::Timezone;
+
+/// Syslog facility of the messages, `daemon`
+pub const FACILITY_DAEMON: u8 = 3;
+/// Name of the program in the messages
+pub const APP_NAME: &str = "netpulsed";
+
+/// Message format of the syslog protocol.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFormat {
+    /// The traditional BSD format, like `<28>Dec  8 14:00:00 host netpulsed[42]: msg`
+    Rfc3164,
+    /// The structured format, like `<28>1 2024-12-08T14:00:00Z host netpulsed 42 - - msg`
+    #[default]
+    Rfc5424,
+}
+
+/// Severity of a syslog message, a subset of the levels defined in RFC 5424.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub enum SyslogSeverity {
+    /// Error conditions
+    Error = 3,
+    /// Warning conditions
+    Warning = 4,
+    /// Normal but significant conditions
+    Notice = 5,
+    /// Informational messages
+    Info = 6,
+}
+
+/// A target started or stopped failing, see [OutageTracker].
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum OutageEvent {
+    /// The first failed check of a target after a successful one
+    Started {
+        /// Type of the failed check
+        check_type: CheckType,
+        /// Target of the failed check
+        target: IpAddr,
+        /// When the failed check was made
+        time: SystemTime,
+    },
+    /// The first successful check of a target after failed ones
+    Ended {
+        /// Type of the checks
+        check_type: CheckType,
+        /// Target of the checks
+        target: IpAddr,
+        /// When the first failed check was made
+        start: SystemTime,
+        /// When the successful check was made
+        end: SystemTime,
+        /// How many checks failed in between
+        failed: usize,
+    },
+}
+
+impl OutageEvent {
+    /// Returns the [SyslogSeverity] the event is logged with.
+    pub fn severity(&self) -> SyslogSeverity {
+        match self {
+            Self::Started { .. } => SyslogSeverity::Warning,
+            Self::Ended { .. } => SyslogSeverity::Notice,
+        }
+    }
+
+    /// Returns when the event happened.
+    pub fn time(&self) -> SystemTime {
+        match self {
+            Self::Started { time, .. } => *time,
+            Self::Ended { end, .. } => *end,
+        }
+    }
+}
+
+impl Display for OutageEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Started {
+                check_type, target, ..
+            } => write!(f, "outage started: {check_type} check of {target} failed"),
+            Self::Ended {
+                check_type,
+                target,
+                start,
+                end,
+                failed,
+            } => write!(
+                f,
+                "outage ended: {check_type} checks of {target} succeed again after {} ({failed} failed checks)",
+                humantime::format_duration(
+                    end.duration_since(*start).unwrap_or(Duration::ZERO)
+                )
+            ),
+        }
+    }
+}
+
+/// Detects when targets start and stop failing.
+///
+/// The state is kept per [CheckType] and target, and only in memory.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct OutageTracker {
+    /// Start time and amount of failed checks for each failing check type and target
+    failing: HashMap<(CheckType, IpAddr), (SystemTime, usize)>,
+}
+
+impl OutageTracker {
+    /// Creates a new [OutageTracker] that considers every target as working.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the checks of a cycle into the tracker, returning the events they caused.
+    ///
+    /// Further failures of a target that is already failing do not cause events.
+    pub fn update(&mut self, checks: &[&Check]) -> Vec<OutageEvent> {
+        let mut events = Vec::new();
+        for check in checks {
+            let check_type = check.calc_type().unwrap_or(CheckType::Unknown);
+            let target = check.target();
+            let time = check.timestamp_parsed();
+            let key = (check_type, target);
+            if check.is_success() {
+                if let Some((start, failed)) = self.failing.remove(&key) {
+                    events.push(OutageEvent::Ended {
+                        check_type,
+                        target,
+                        start,
+                        end: time,
+                        failed,
+                    });
+                }
+            } else if let Some((_, failed)) = self.failing.get_mut(&key) {
+                *failed += 1;
+            } else {
+                self.failing.insert(key, (time, 1));
+                events.push(OutageEvent::Started {
+                    check_type,
+                    target,
+                    time,
+                });
+            }
+        }
+        events
+    }
+}
+
+/// Sends messages to the syslog socket, see the [module documentation](self).
+#[derive(Debug)]
+pub struct Syslog {
+    /// Unbound socket the messages are sent from
+    socket: UnixDatagram,
+    /// Path of the syslog socket
+    path: PathBuf,
+    /// Format of the messages
+    format: SyslogFormat,
+    /// Name of this machine in the messages
+    hostname: String,
+    /// Timezone of the timestamps in the messages
+    timezone: Timezone,
+}
+
+impl Syslog {
+    /// Creates a [Syslog] sending to the socket configured in `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the local socket can't be created.
+    pub fn open(config: &SyslogConfig) -> io::Result<Self> {
+        let hostname = nix::unistd::gethostname()
+            .ok()
+            .and_then(|name| name.into_string().ok())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| "localhost".to_string());
+        Ok(Self {
+            socket: UnixDatagram::unbound()?,
+            path: config.socket.clone(),
+            format: config.format,
+            hostname,
+            timezone: Timezone::Local,
+        })
+    }
+
+    /// Sends a single message with `severity` to the syslog socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message can't be sent, for example if no syslog daemon listens
+    /// on the socket.
+    pub fn send(&self, severity: SyslogSeverity, time: SystemTime, msg: &str) -> io::Result<()> {
+        self.socket
+            .send_to(self.message(severity, time, msg).as_bytes(), &self.path)?;
+        Ok(())
+    }
+
+    /// Sends an [OutageEvent] with its [severity](OutageEvent::severity).
+    ///
+    /// # Errors
+    ///
+    /// See [send](Syslog::send).
+    pub fn send_event(&self, event: &OutageEvent) -> io::Result<()> {
+        self.send(event.severity(), event.time(), &event.to_string())
+    }
+
+    /// Formats a message in the configured [SyslogFormat].
+    fn message(&self, severity: SyslogSeverity, time: SystemTime, msg: &str) -> String {
+        let pri = FACILITY_DAEMON * 8 + severity as u8;
+        let pid = std::process::id();
+        let timestamp = self.timezone.format(time);
+        match self.format {
+            SyslogFormat::Rfc5424 => {
+                format!(
+                    "<{pri}>1 {timestamp} {} {APP_NAME} {pid} - - {msg}",
+                    self.hostname
+                )
+            }
+            SyslogFormat::Rfc3164 => {
+                // "2024-12-08T14:00:00Z" -> "Dec  8 14:00:00"
+                const MONTHS: [&str; 12] = [
+                    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov",
+                    "Dec",
+                ];
+                let month: usize = timestamp[5..7].parse().expect("rfc3339 has a month");
+                let day: u8 = timestamp[8..10].parse().expect("rfc3339 has a day");
+                format!(
+                    "<{pri}>{} {day:>2} {} {} {APP_NAME}[{pid}]: {msg}",
+                    MONTHS[month - 1],
+                    &timestamp[11..19],
+                    self.hostname
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::UNIX_EPOCH;
+
+    use crate::records::CheckFlag;
+
+    use super::*;
+
+    fn http_check(secs: u64, ok: bool) -> Check {
+        let mut flags = CheckFlag::TypeHTTP | CheckFlag::IPv4;
+        if ok {
+            flags |= CheckFlag::Success;
+        }
+        Check::new(
+            UNIX_EPOCH + Duration::from_secs(secs),
+            flags,
+            None,
+            "1.1.1.1".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_tracker_deduplicates() {
+        let mut tracker = OutageTracker::new();
+        let results = [true, false, false, false, true, true, false];
+        let events: Vec<Vec<OutageEvent>> = results
+            .iter()
+            .enumerate()
+            .map(|(idx, ok)| tracker.update(&[&http_check(idx as u64 * 60, *ok)]))
+            .collect();
+
+        let target: IpAddr = "1.1.1.1".parse().unwrap();
+        let started = |secs| {
+            vec![OutageEvent::Started {
+                check_type: CheckType::Http,
+                target,
+                time: UNIX_EPOCH + Duration::from_secs(secs),
+            }]
+        };
+        assert_eq!(
+            events,
+            [
+                vec![],
+                started(60),
+                vec![],
+                vec![],
+                vec![OutageEvent::Ended {
+                    check_type: CheckType::Http,
+                    target,
+                    start: UNIX_EPOCH + Duration::from_secs(60),
+                    end: UNIX_EPOCH + Duration::from_secs(240),
+                    failed: 3,
+                }],
+                vec![],
+                started(360),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_send_to_fake_syslog() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        let server = UnixDatagram::bind(&path).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut syslog = Syslog::open(&SyslogConfig {
+            enabled: true,
+            socket: path,
+            format: SyslogFormat::Rfc5424,
+        })
+        .unwrap();
+        syslog.hostname = "box".to_string();
+        syslog.timezone = Timezone::Utc;
+        let pid = std::process::id();
+        let mut buf = [0; 1024];
+
+        let mut tracker = OutageTracker::new();
+        for event in tracker.update(&[&http_check(1_733_666_400, false)]) {
+            syslog.send_event(&event).unwrap();
+        }
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf[..len]).unwrap(),
+            format!(
+                "<28>1 2024-12-08T14:00:00Z box netpulsed {pid} - - outage started: HTTP(S) check of 1.1.1.1 failed"
+            )
+        );
+
+        syslog.format = SyslogFormat::Rfc3164;
+        for event in tracker.update(&[&http_check(1_733_666_400 + 300, true)]) {
+            syslog.send_event(&event).unwrap();
+        }
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf[..len]).unwrap(),
+            format!(
+                "<29>Dec  8 14:05:00 box netpulsed[{pid}]: outage ended: HTTP(S) checks of 1.1.1.1 succeed again after 5m (1 failed checks)"
+            )
+        );
+    }
+}
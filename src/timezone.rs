@@ -0,0 +1,191 @@
+//! Timezones for rendering timestamps in reports.
+//!
+//! Netpulse always stores timestamps in UTC. For reports, they can be rendered in UTC, in the
+//! local time of the system, or with a fixed offset, see [Timezone].
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::{Duration, UNIX_EPOCH};
+//! use netpulse::timezone::Timezone;
+//!
+//! let time = UNIX_EPOCH + Duration::from_secs(3600);
+//! assert_eq!(Timezone::Utc.format(time), "1970-01-01T01:00:00Z");
+//! let tz: Timezone = "+02:00".parse().unwrap();
+//! assert_eq!(tz.format(time), "1970-01-01T03:00:00+02:00");
+//! ```
+
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ConfigError;
+
+/// Largest allowed offset from UTC, in seconds
+const MAX_OFFSET: i32 = 24 * 3600 - 60;
+
+/// Timezone in which timestamps are rendered.
+///
+/// Parsed from and displayed as `utc`, `local` or an offset like `+02:00` or `-05:30`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Timezone {
+    /// Coordinated Universal Time
+    #[default]
+    Utc,
+    /// The local time of the system, including daylight saving time
+    Local,
+    /// A fixed offset from UTC, in seconds
+    Offset(i32),
+}
+
+impl Timezone {
+    /// Renders `time` as RFC 3339 timestamp with seconds in this timezone.
+    ///
+    /// UTC is rendered with a `Z` suffix, everything else with its offset, like
+    /// `2024-12-08T14:00:00+02:00`.
+    pub fn format(&self, time: SystemTime) -> String {
+        if *self == Self::Utc {
+            return humantime::format_rfc3339_seconds(time).to_string();
+        }
+        let offset = self.offset_at(time);
+        let shift = Duration::from_secs(offset.unsigned_abs() as u64);
+        let shifted = if offset >= 0 {
+            time + shift
+        } else {
+            time.checked_sub(shift).unwrap_or(UNIX_EPOCH)
+        };
+        let utc = humantime::format_rfc3339_seconds(shifted).to_string();
+        format!("{}{}", utc.trim_end_matches('Z'), format_offset(offset))
+    }
+
+    /// Returns the offset of this timezone from UTC at `time`, in seconds.
+    ///
+    /// Only the local time can have different offsets, because of daylight saving time.
+    pub fn offset_at(&self, time: SystemTime) -> i32 {
+        match self {
+            Self::Utc => 0,
+            Self::Local => local_offset(time),
+            Self::Offset(offset) => *offset,
+        }
+    }
+}
+
+/// Formats an offset in seconds like `+02:00`.
+fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.unsigned_abs() / 60;
+    format!("{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Returns the offset of the local time of the system from UTC at `time`, in seconds.
+fn local_offset(time: SystemTime) -> i32 {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as libc::time_t;
+    // SAFETY: localtime_r only writes to the given tm struct, which is valid for writes
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i32
+}
+
+impl FromStr for Timezone {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            ConfigError::Invalid(format!(
+                "invalid timezone '{s}', expected utc, local or an offset like +02:00"
+            ))
+        };
+        let s = s.trim();
+        match s.to_lowercase().as_str() {
+            "utc" | "z" => return Ok(Self::Utc),
+            "local" => return Ok(Self::Local),
+            _ => (),
+        }
+
+        let (sign, rest) = match s.split_at_checked(1) {
+            Some(("+", rest)) => (1, rest),
+            Some(("-", rest)) => (-1, rest),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = rest.split_once(':').ok_or_else(invalid)?;
+        let hours: i32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+        if !(0..60).contains(&minutes) || hours < 0 {
+            return Err(invalid());
+        }
+        let offset = sign * (hours * 3600 + minutes * 60);
+        if offset.abs() > MAX_OFFSET {
+            return Err(invalid());
+        }
+        Ok(Self::Offset(offset))
+    }
+}
+
+impl TryFrom<String> for Timezone {
+    type Error = ConfigError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Timezone> for String {
+    fn from(value: Timezone) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for Timezone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Utc => write!(f, "utc"),
+            Self::Local => write!(f, "local"),
+            Self::Offset(offset) => write!(f, "{}", format_offset(*offset)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_in_offsets() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_733_666_400);
+        assert_eq!(Timezone::Utc.format(time), "2024-12-08T14:00:00Z");
+        assert_eq!(
+            Timezone::Offset(2 * 3600).format(time),
+            "2024-12-08T16:00:00+02:00"
+        );
+        assert_eq!(
+            Timezone::Offset(-(5 * 3600 + 30 * 60)).format(time),
+            "2024-12-08T08:30:00-05:30"
+        );
+        // crossing the date line
+        assert_eq!(
+            Timezone::Offset(11 * 3600).format(time),
+            "2024-12-09T01:00:00+11:00"
+        );
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        for (raw, tz) in [
+            ("UTC", Timezone::Utc),
+            ("local", Timezone::Local),
+            ("+02:00", Timezone::Offset(7200)),
+            ("-05:30", Timezone::Offset(-19800)),
+        ] {
+            assert_eq!(raw.parse::<Timezone>().unwrap(), tz);
+            assert_eq!(tz.to_string().parse::<Timezone>().unwrap(), tz);
+        }
+        for raw in ["", "02:00", "+2", "+25:00", "+02:60", "mars"] {
+            assert!(raw.parse::<Timezone>().is_err(), "{raw}");
+        }
+    }
+}